@@ -0,0 +1,1360 @@
+// Generated from the server's message types, don't edit by hand.
+
+/**
+ * Where an ace can sit in a run.
+ */
+export type Aces =
+  /**
+   * Only below the two, A-2-3.
+   */
+  | "Low"
+  /**
+   * Only above the king, Q-K-A.
+   */
+  | "High"
+  /**
+   * Either end, but a run can't go around the corner from king to two.
+   */
+  | "Either";
+
+export type BlackjackPhase = "Betting" | "Playing" | "RoundOver";
+
+export type BlackjackSeatView = {
+  hands: Array<HandView>;
+  player_id: string;
+};
+
+export type BlackjackView = {
+  chips: Array<ChipsView>;
+  dealer: DealerView;
+  dealer_hits_soft_17: boolean;
+  phase: BlackjackPhase;
+  seats: Array<BlackjackSeatView>;
+  shoe_size: number;
+  turn?: TurnView | null;
+};
+
+export type BotKind = "Random" | "Heuristic";
+
+/**
+ * What a bot is sent instead of the game state: the same view a player would get, plus every
+ * move the rules allow it to make and the move a sensible player would make.
+ */
+export type BotTurn = {
+  game_state: GameState;
+  hint?: GameMove | null;
+  legal_moves: Array<GameMove>;
+};
+
+export type Card = {
+  suite: Suite;
+  value: Value;
+  visible: boolean;
+};
+
+export type CardData = {
+  suite?: Suite | null;
+  value?: Value | null;
+  visible: boolean;
+};
+
+export type ChipsData = {
+  players: Array<PlayerChips>;
+  pots: Array<SidePot>;
+};
+
+export type ChipsView = {
+  bankroll: number;
+  bet?: number | null;
+  player_id: string;
+  won?: number | null;
+};
+
+/**
+ * A message from a client. The `request_id` is whatever the client wants to use to match
+ * replies up with the request, the server only echoes it back.
+ */
+export type ClientMessage = {
+  request_id?: string | null;
+} & (
+  /**
+   * Has to be the first thing a connection sends, the server answers with `Welcome`. It's
+   * always sent as JSON, everything after the `Welcome` uses the encoding asked for here.
+   */
+  | {
+    action: "Hello";
+    data: {
+      encoding?: Encoding | null;
+      max_version: number;
+      min_version: number;
+    };
+  }
+  | {
+    action: "CreateGame";
+    data: CreateGame;
+  }
+  | {
+    action: "JoinRoom";
+    data: {
+      invite_token?: string | null;
+      password?: string | null;
+      player_name: string;
+      room_id: number;
+    };
+  }
+  /**
+   * Sent to everyone in the room under the name of the player's seat.
+   */
+  | {
+    action: "Chat";
+    data: {
+      message: string;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "DrawCard";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "ToggleVisibilityOfCard";
+    data: {
+      card: Card;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "DiscardCard";
+    data: {
+      card: Card;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "ResetDeck";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "GameMove";
+    data: {
+      game_move: GameMove;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "Quit";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "RotatePassword";
+    data: {
+      password?: string | null;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "CreateInviteToken";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "ListRooms";
+    data: {
+      game_type?: GameType | null;
+      open_seats?: boolean | null;
+      search?: string | null;
+    };
+  }
+  | {
+    action: "SubscribeLobby";
+  }
+  | {
+    action: "UnsubscribeLobby";
+  }
+  | {
+    action: "EnqueueMatch";
+    data: {
+      game_type?: GameType | null;
+      player_count: number;
+      player_name: string;
+    };
+  }
+  | {
+    action: "CancelMatch";
+  }
+  | {
+    action: "RecordScores";
+    data: {
+      player_id: string;
+      room_id: number;
+      scores: Array<PlayerScore>;
+    };
+  }
+  | {
+    action: "Bet";
+    data: {
+      amount: number;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "CollectPot";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "TransferChips";
+    data: {
+      amount: number;
+      player_id: string;
+      room_id: number;
+      to_player_id: string;
+    };
+  }
+  | {
+    action: "DefineTeams";
+    data: {
+      player_id: string;
+      room_id: number;
+      team_names: Array<string>;
+    };
+  }
+  /**
+   * Leaving out the team takes the player off their team.
+   */
+  | {
+    action: "AssignTeam";
+    data: {
+      player_id: string;
+      room_id: number;
+      team?: string | null;
+      to_player_id: string;
+    };
+  }
+  | {
+    action: "TeamChat";
+    data: {
+      message: string;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "PlayToTeamPile";
+    data: {
+      card: Card;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "TakeFromTeamPile";
+    data: {
+      card: Card;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "AddBot";
+    data: {
+      bot_kind?: BotKind | null;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "RemoveBot";
+    data: {
+      player_id: string;
+      room_id: number;
+      to_player_id: string;
+    };
+  }
+  /**
+   * Asks for every room event numbered after `seq`, the last one the client saw.
+   */
+  | {
+    action: "SyncFrom";
+    data: {
+      player_id: string;
+      room_id: number;
+      seq: number;
+    };
+  }
+  /**
+   * Takes back a seat kept from before a restart, using the token from `CreateGame` or
+   * `JoinRoom`.
+   */
+  | {
+    action: "ReclaimSeat";
+    data: {
+      room_id: number;
+      session_token: string;
+    };
+  }
+  | {
+    action: "GetHistory";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  /**
+   * Takes back the last change made in a free play room, if the room's undo setting allows
+   * it.
+   */
+  | {
+    action: "Undo";
+    data: {
+      player_id: string;
+      room_id: number;
+    };
+  }
+  /**
+   * Answers an `UndoRequested` in a room where undoing takes a vote.
+   */
+  | {
+    action: "VoteUndo";
+    data: {
+      approve: boolean;
+      player_id: string;
+      room_id: number;
+    };
+  });
+
+export type CrazyEightsPhase = "Waiting" | "Playing" | "RoundOver";
+
+export type CrazyEightsSeatView = {
+  cards_in_hand: number;
+  player_id: string;
+};
+
+export type CrazyEightsView = {
+  clockwise: boolean;
+  hand: Array<CardData>;
+  phase: CrazyEightsPhase;
+  round_points: Array<PlayerScore>;
+  seats: Array<CrazyEightsSeatView>;
+  stock_size: number;
+  suit_to_follow?: Suite | null;
+  top_card?: CardData | null;
+  turn?: string | null;
+  winner?: string | null;
+};
+
+/**
+ * The room a `CreateGame` asks for, anything left out gets the default for the chosen game.
+ */
+export type CreateGame = {
+  aces?: Aces | null;
+  big_blind?: number | null;
+  dealer_hits_soft_17?: boolean | null;
+  deck?: DeckSpec | null;
+  game_type?: GameType | null;
+  max_players?: number | null;
+  password?: string | null;
+  player_name: string;
+  private?: boolean | null;
+  seed?: number | null;
+  small_blind?: number | null;
+  starting_chips?: number | null;
+  target_score?: number | null;
+  undo?: UndoPolicy | null;
+};
+
+export type DealerView = {
+  cards: Array<CardData>;
+  value?: number | null;
+};
+
+/**
+ * Describes which cards make up a room's draw deck.
+ */
+export type DeckSpec = {
+  decks: number;
+};
+
+/**
+ * How messages are written on a connection, picked in the `Hello` handshake. JSON goes out in
+ * text frames, the binary encodings in binary frames.
+ */
+export type Encoding = "Json" | "MessagePack" | "Cbor";
+
+export type EuchrePhase =
+  | "Waiting" | "Playing" | "RoundOver" | "GameOver"
+  /**
+   * Going around once to see whether anyone orders up the turned up card as trump.
+   */
+  | "OrderingUp"
+  /**
+   * The dealer picked up the turned up card and has to discard one.
+   */
+  | "DealerDiscard"
+  /**
+   * Going around a second time to name any other suit as trump.
+   */
+  | "NamingTrump";
+
+export type EuchreView = {
+  alone: boolean;
+  current_trick: Array<PlayedCard>;
+  dealer?: string | null;
+  hand: Array<CardData>;
+  last_trick: Array<PlayedCard>;
+  maker?: string | null;
+  phase: EuchrePhase;
+  round_points: Array<PlayerScore>;
+  seats: Array<TrickSeatView>;
+  target_score: number;
+  team_scores: Array<number>;
+  trump?: Suite | null;
+  turn?: string | null;
+  upcard?: CardData | null;
+};
+
+/**
+ * Something a player wants to do at the table. The first few are the sandbox moves every room
+ * has always had, the rest only mean something to the game they belong to.
+ */
+export type GameMove =
+  | {
+    type: "StartRound";
+  }
+  | {
+    type: "DrawCard";
+  }
+  | {
+    card: Card;
+    type: "DiscardCard";
+  }
+  | {
+    card: Card;
+    type: "ToggleVisibilityOfCard";
+  }
+  | {
+    type: "ResetDeck";
+  }
+  | {
+    amount: number;
+    type: "PlaceBet";
+  }
+  | {
+    type: "Hit";
+  }
+  | {
+    type: "Stand";
+  }
+  | {
+    type: "DoubleDown";
+  }
+  | {
+    type: "Split";
+  }
+  | {
+    type: "Check";
+  }
+  | {
+    type: "Call";
+  }
+  | {
+    type: "Fold";
+  }
+  | {
+    type: "AllIn";
+  }
+  | {
+    amount: number;
+    type: "Bet";
+  }
+  | {
+    amount: number;
+    type: "Raise";
+  }
+  | {
+    card: Card;
+    type: "PlayCard";
+  }
+  | {
+    cards: Array<Card>;
+    type: "PassCards";
+  }
+  | {
+    tricks: number;
+    type: "Bid";
+  }
+  | {
+    alone: boolean;
+    type: "OrderUp";
+  }
+  | {
+    alone: boolean;
+    suite: Suite;
+    type: "NameTrump";
+  }
+  | {
+    type: "Pass";
+  }
+  | {
+    type: "DrawDiscard";
+  }
+  | {
+    cards: Array<Card>;
+    type: "LayMeld";
+  }
+  | {
+    cards: Array<Card>;
+    meld: number;
+    type: "ExtendMeld";
+  }
+  | {
+    melds: Array<Array<Card>>;
+    type: "RearrangeMelds";
+  }
+  | {
+    card: Card;
+    type: "Knock";
+  }
+  | {
+    card: Card;
+    suite: Suite;
+    type: "PlayWild";
+  }
+  | {
+    count: number;
+    from: Zone;
+    to: Zone;
+    type: "MoveCards";
+  }
+  | {
+    type: "DrawStock";
+  }
+  | {
+    type: "UndoMove";
+  }
+  | {
+    type: "CheckSolvable";
+  };
+
+/**
+ * What one player is allowed to see of the game being played, tagged with the game it's from.
+ */
+export type GameState =
+  | {
+    game: "Blackjack";
+  } & BlackjackView
+  | {
+    game: "Holdem";
+  } & HoldemView
+  | {
+    game: "Hearts";
+  } & HeartsView
+  | {
+    game: "Spades";
+  } & SpadesView
+  | {
+    game: "Euchre";
+  } & EuchreView
+  | {
+    game: "Rummy";
+  } & RummyView
+  | {
+    game: "Gin";
+  } & GinView
+  | {
+    game: "CrazyEights";
+  } & CrazyEightsView
+  | {
+    game: "Solitaire";
+  } & SolitaireView;
+
+export type GameType = "FreePlay" | "Blackjack" | "Holdem" | "Hearts" | "Spades" | "Euchre" | "Rummy" | "Gin" | "CrazyEights" | "Klondike" | "FreeCell" | "Spider";
+
+export type GinPhase = "Waiting" | "Draw" | "Discard" | "RoundOver" | "GameOver";
+
+export type GinView = {
+  aces: Aces;
+  deadwood_points: number;
+  discard_top?: CardData | null;
+  hand: Array<CardData>;
+  opponent_cards: number;
+  phase: GinPhase;
+  round_points: Array<PlayerScore>;
+  showdown?: Showdown | null;
+  stock_size: number;
+  target_score: number;
+  totals: Record<string, number>;
+  turn?: string | null;
+};
+
+export type HandResult = "Blackjack" | "Win" | "Push" | "Lose" | "Bust";
+
+export type HandSize = {
+  cards: number;
+  player_id: string;
+};
+
+export type HandView = {
+  bet: number;
+  cards: Array<CardData>;
+  doubled: boolean;
+  finished: boolean;
+  result?: HandResult | null;
+  soft: boolean;
+  value: number;
+};
+
+export type HeartsPhase = "Waiting" | "Passing" | "Playing" | "RoundOver" | "GameOver";
+
+export type HeartsView = {
+  current_trick: Array<PlayedCard>;
+  hand: Array<CardData>;
+  hearts_broken: boolean;
+  last_trick: Array<PlayedCard>;
+  pass_direction: PassDirection;
+  passed: boolean;
+  phase: HeartsPhase;
+  round_points: Array<PlayerScore>;
+  seats: Array<TrickSeatView>;
+  target_score: number;
+  totals: Record<string, number>;
+  trump?: Suite | null;
+  turn?: string | null;
+};
+
+export type HistoryEntry = {
+  /**
+   * Milliseconds since the Unix epoch.
+   */
+  at: number;
+  /**
+   * The cards the event put somewhere new, and where.
+   */
+  cards: Array<PlacedCard>;
+  event: RoomEvent;
+  /**
+   * Counts up from 1 for each room.
+   */
+  number: number;
+  player_id: string;
+  zones: ZoneSizes;
+};
+
+export type HoldemSeatView = {
+  all_in: boolean;
+  button: boolean;
+  folded: boolean;
+  hole_cards: Array<CardData>;
+  player_id: string;
+  stack: number;
+  street_bet: number;
+};
+
+export type HoldemView = {
+  big_blind: number;
+  board: Array<CardData>;
+  current_bet: number;
+  min_raise_to: number;
+  pot: number;
+  seats: Array<HoldemSeatView>;
+  small_blind: number;
+  street: Street;
+  to_call?: number | null;
+  turn?: string | null;
+  winners: Array<Winner>;
+};
+
+export type MeldData = {
+  cards: Array<CardData>;
+  kind: MeldKind;
+};
+
+export type MeldKind = "Set" | "Run";
+
+export type Outcome = "Gin" | "Knock" | "Undercut" | "Draw";
+
+export type PassDirection = "Left" | "Right" | "Across" | "Hold";
+
+/**
+ * Where a card on the table is.
+ */
+export type Place =
+  | {
+    player_id: string;
+    zone: "Hand";
+  }
+  | {
+    zone: "DiscardPile";
+  }
+  | {
+    team: string;
+    zone: "TeamPile";
+  };
+
+export type PlacedCard = {
+  card: Card;
+  place: Place;
+};
+
+export type PlayedCard = {
+  card: CardData;
+  player_id: string;
+};
+
+export type PlayerChips = {
+  chips: number;
+  player_id: string;
+};
+
+export type PlayerData = {
+  bot: boolean;
+  chips: number;
+  hand: Array<CardData>;
+  id: string;
+  name: string;
+  team?: string | null;
+};
+
+export type PlayerScore = {
+  player_id: string;
+  points: number;
+};
+
+/**
+ * Something that changed the room. Together with the seed the table shuffles from, the events
+ * are enough to build the room again from nothing.
+ */
+export type RoomEvent =
+  | {
+    bot?: BotKind | null;
+    player_name: string;
+    type: "Joined";
+  }
+  | {
+    type: "Left";
+  }
+  | {
+    game_move: GameMove;
+    type: "Moved";
+  }
+  | {
+    scores: Array<PlayerScore>;
+    type: "ScoresRecorded";
+  }
+  | {
+    amount: number;
+    type: "Bet";
+  }
+  | {
+    type: "PotCollected";
+  }
+  | {
+    amount: number;
+    to_player_id: string;
+    type: "ChipsTransferred";
+  }
+  | {
+    team_names: Array<string>;
+    type: "TeamsDefined";
+  }
+  | {
+    team?: string | null;
+    to_player_id: string;
+    type: "TeamAssigned";
+  }
+  /**
+   * The card is left out of the public history when it's face down.
+   */
+  | {
+    card?: Card | null;
+    type: "PlayedToTeamPile";
+  }
+  | {
+    card?: Card | null;
+    type: "TookFromTeamPile";
+  }
+  /**
+   * Took back entry `number`, leaving the room as if it never happened.
+   */
+  | {
+    number: number;
+    type: "Undone";
+  }
+  /**
+   * Stands in for a discard in the public history when the card went down face down. It's
+   * never recorded itself.
+   */
+  | {
+    type: "DiscardedFaceDown";
+  };
+
+/**
+ * What the lobby shows about a public room.
+ */
+export type RoomSummary = {
+  capacity: number;
+  deck: DeckSpec;
+  game_type: GameType;
+  host_name: string;
+  in_progress: boolean;
+  player_count: number;
+  room_id: number;
+};
+
+export type RoundScores = {
+  deltas: Array<PlayerScore>;
+  round: number;
+};
+
+export type RummyPhase = "Waiting" | "Draw" | "Meld" | "RoundOver" | "GameOver";
+
+export type RummyView = {
+  aces: Aces;
+  cards_in_hand: Record<string, number>;
+  discard_top?: CardData | null;
+  hand: Array<CardData>;
+  melds: Array<MeldData>;
+  phase: RummyPhase;
+  round_points: Array<PlayerScore>;
+  stock_size: number;
+  target_score: number;
+  totals: Record<string, number>;
+  turn?: string | null;
+};
+
+export type ScoreboardData = {
+  rounds: Array<RoundScores>;
+  target_score?: number | null;
+  totals: Array<PlayerScore>;
+  winners: Array<string>;
+};
+
+/**
+ * A message from the server. Direct replies and errors carry the `request_id` of the request
+ * that caused them, everything else leaves it out.
+ */
+export type ServerMessage = {
+  request_id?: string | null;
+  /**
+   * Events sent to everyone in a room are numbered one after another within that room, so
+   * a gap means something was missed and `SyncFrom` can fetch it.
+   */
+  seq?: number | null;
+} & (
+  /**
+   * The protocol version and encoding both sides agreed on.
+   */
+  | {
+    action: "Welcome";
+    data: {
+      encoding: Encoding;
+      version: number;
+    };
+  }
+  /**
+   * A request that couldn't be handled at all, as opposed to a move the rules didn't allow.
+   */
+  | {
+    action: "Error";
+    data: {
+      message: string;
+    };
+  }
+  /**
+   * `seq` is the number of the last room event, the next one the client sees follows it.
+   */
+  | {
+    action: "CreateGame";
+    data: {
+      chips: ChipsData;
+      deck: DeckSpec;
+      draw_deck_size: number;
+      game_type: GameType;
+      max_players: number;
+      player_id: string;
+      player_name: string;
+      private: boolean;
+      room_id: number;
+      seq: number;
+      session_token: string;
+    };
+  }
+  | {
+    action: "JoinRoom";
+    data: {
+      chips: ChipsData;
+      deck: DeckSpec;
+      discard_pile: Array<CardData>;
+      draw_deck_size: number;
+      game_type: GameType;
+      max_players: number;
+      other_players: Array<PlayerData>;
+      player_id: string;
+      player_name: string;
+      private: boolean;
+      room_id: number;
+      scoreboard: ScoreboardData;
+      seq: number;
+      session_token: string;
+      teams: Array<TeamData>;
+    };
+  }
+  | {
+    action: "PlayerJoinedRoomInSession";
+    data: {
+      player_id: string;
+      player_name: string;
+    };
+  }
+  | {
+    action: "WrongPassword";
+    data: {
+      message: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "RoomFull";
+    data: {
+      message: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "Chat";
+    data: {
+      message: string;
+      player_name: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "DrawCard";
+    data: {
+      card: Card;
+      draw_deck_size: number;
+    };
+  }
+  | {
+    action: "DrawDeckUpdated";
+    data: {
+      draw_deck_size: number;
+      player_id: string;
+    };
+  }
+  | {
+    action: "ToggleVisibilityOfCard";
+    data: {
+      card: Card;
+      player_id: string;
+    };
+  }
+  | {
+    action: "DiscardCard";
+    data: {
+      card: Card;
+      hand: Array<CardData>;
+      player_id: string;
+    };
+  }
+  | {
+    action: "ResetDeck";
+    data: {
+      discard_pile: Array<CardData>;
+      draw_deck_size: number;
+      message: string;
+    };
+  }
+  | {
+    action: "Quit";
+    data: {
+      discard_pile: Array<CardData>;
+      message: string;
+      player_id: string;
+    };
+  }
+  | {
+    action: "RotatePassword";
+    data: {
+      message: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "CreateInviteToken";
+    data: {
+      invite_token: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "ListRooms";
+    data: {
+      rooms: Array<RoomSummary>;
+    };
+  }
+  | {
+    action: "SubscribeLobby";
+    data: {
+      rooms: Array<RoomSummary>;
+    };
+  }
+  | {
+    action: "LobbyRoomUpdated";
+    data: {
+      room_summary: RoomSummary;
+    };
+  }
+  | {
+    action: "LobbyRoomClosed";
+    data: {
+      room_id: number;
+    };
+  }
+  | {
+    action: "EnqueueMatch";
+    data: {
+      game_type: GameType;
+      message: string;
+      player_count: number;
+    };
+  }
+  | {
+    action: "CancelMatch";
+    data: {
+      message: string;
+    };
+  }
+  | {
+    action: "MatchTimedOut";
+    data: {
+      game_type: GameType;
+      message: string;
+      player_count: number;
+    };
+  }
+  | {
+    action: "IllegalMove";
+    data: {
+      message: string;
+      player_id: string;
+    };
+  }
+  | {
+    action: "GameState";
+    data: {
+      game_state: GameState;
+    };
+  }
+  | {
+    action: "RoundOver";
+    data: {
+      scores: Array<PlayerScore>;
+      winner?: string | null;
+    };
+  }
+  | {
+    action: "ScoreboardUpdated";
+    data: {
+      room_id: number;
+      scoreboard: ScoreboardData;
+    };
+  }
+  | {
+    action: "ChipsUpdated";
+    data: {
+      chips: ChipsData;
+      room_id: number;
+    };
+  }
+  | {
+    action: "TeamsUpdated";
+    data: {
+      other_players: Array<PlayerData>;
+      room_id: number;
+      teams: Array<TeamData>;
+    };
+  }
+  | {
+    action: "TeamChat";
+    data: {
+      message: string;
+      player_name: string;
+      room_id: number;
+    };
+  }
+  | {
+    action: "BotTurn";
+    data: BotTurn;
+  }
+  /**
+   * Counts down to the server stopping, the connection is closed once it reaches zero.
+   */
+  | {
+    action: "ServerShuttingDown";
+    data: {
+      message: string;
+      seconds_left: number;
+    };
+  }
+  /**
+   * The seat is back on this connection, a `Snapshot` of the room follows.
+   */
+  | {
+    action: "ReclaimSeat";
+    data: {
+      player_id: string;
+      player_name: string;
+      room_id: number;
+    };
+  }
+  /**
+   * Sent after the missed events of a `SyncFrom` were replayed, `seq` is the last of them.
+   */
+  | {
+    action: "Synced";
+    data: {
+      replayed: number;
+      room_id: number;
+      seq: number;
+    };
+  }
+  /**
+   * Everything about the room as of event `seq`, sent instead of the missed events when
+   * they aren't kept any more.
+   */
+  | {
+    action: "Snapshot";
+    data: {
+      chips: ChipsData;
+      deck: DeckSpec;
+      discard_pile: Array<CardData>;
+      draw_deck_size: number;
+      game_type: GameType;
+      hand: Array<Card>;
+      host_id: string;
+      max_players: number;
+      players: Array<PlayerData>;
+      private: boolean;
+      room_id: number;
+      scoreboard: ScoreboardData;
+      seq: number;
+      teams: Array<TeamData>;
+    };
+  }
+  /**
+   * Everything that has happened in the room so far, without other players' hidden cards.
+   */
+  | {
+    action: "History";
+    data: {
+      entries: Array<HistoryEntry>;
+      room_id: number;
+    };
+  }
+  /**
+   * Someone wants to take back history entry `number`, everyone else votes on it.
+   */
+  | {
+    action: "UndoRequested";
+    data: {
+      event: RoomEvent;
+      number: number;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  /**
+   * The undo someone asked for won't happen, `player_id` is whoever asked.
+   */
+  | {
+    action: "UndoRejected";
+    data: {
+      message: string;
+      player_id: string;
+      room_id: number;
+    };
+  }
+  /**
+   * History entry `number` was taken back. A `Snapshot` of the room as it is now follows.
+   */
+  | {
+    action: "Undone";
+    data: {
+      event: RoomEvent;
+      number: number;
+      player_id: string;
+      room_id: number;
+    };
+  });
+
+/**
+ * How the hand ended, shown to both players once the cards are turned over.
+ */
+export type Showdown = {
+  hands: Array<ShownHand>;
+  knocker?: string | null;
+  outcome: Outcome;
+};
+
+export type ShownHand = {
+  deadwood: Array<CardData>;
+  deadwood_points: number;
+  laid_off: Array<CardData>;
+  melds: Array<MeldData>;
+  player_id: string;
+};
+
+export type SidePot = {
+  amount: number;
+  eligible: Array<string>;
+};
+
+export type SolitairePhase = "Waiting" | "Playing" | "Won";
+
+export type SolitaireView = {
+  can_undo: boolean;
+  cells: Array<CardData | null>;
+  foundations: Array<Array<CardData>>;
+  moves: number;
+  phase: SolitairePhase;
+  seed: number;
+  solvability?: Solvability | null;
+  stock_size: number;
+  tableau: Array<Array<CardData>>;
+  variant: Variant;
+  waste?: CardData | null;
+};
+
+/**
+ * What the solver found out about a position.
+ */
+export type Solvability =
+  /**
+   * A solution was found, taking this many moves.
+   */
+  | {
+    moves: number;
+    result: "Winnable";
+  }
+  /**
+   * Every position reachable from here was tried and none of them win.
+   */
+  | {
+    result: "NotWinnable";
+  }
+  /**
+   * The solver gave up before finding an answer either way.
+   */
+  | {
+    result: "Unknown";
+  };
+
+export type SpadesPhase = "Waiting" | "Bidding" | "Playing" | "RoundOver" | "GameOver";
+
+export type SpadesView = {
+  bids: Array<number | null>;
+  current_trick: Array<PlayedCard>;
+  dealer?: string | null;
+  hand: Array<CardData>;
+  last_trick: Array<PlayedCard>;
+  phase: SpadesPhase;
+  round_points: Array<PlayerScore>;
+  seats: Array<TrickSeatView>;
+  spades_broken: boolean;
+  target_score: number;
+  teams: Array<TeamView>;
+  trump?: Suite | null;
+  turn?: string | null;
+};
+
+export type Street = "Waiting" | "Preflop" | "Flop" | "Turn" | "River" | "HandOver";
+
+export type Suite = "Club" | "Heart" | "Diamond" | "Spade";
+
+export type TeamData = {
+  members: Array<string>;
+  name: string;
+  pile: Array<CardData>;
+};
+
+export type TeamView = {
+  bags: number;
+  player_ids: Array<string>;
+  score: number;
+};
+
+export type TrickSeatView = {
+  cards_in_hand: number;
+  player_id: string;
+  sitting_out: boolean;
+  tricks_won: number;
+};
+
+export type TurnView = {
+  hand_index: number;
+  player_id: string;
+};
+
+/**
+ * Who gets to take back the last change made in a room.
+ */
+export type UndoPolicy = "Anyone" | "HostOnly" | "Vote";
+
+export type Value = "Ace" | "Two" | "Three" | "Four" | "Five" | "Six" | "Seven" | "Eight" | "Nine" | "Ten" | "Jack" | "Queen" | "King";
+
+export type Variant = "Klondike" | "FreeCell" | "Spider";
+
+export type Winner = {
+  amount: number;
+  hand?: string | null;
+  player_id: string;
+};
+
+/**
+ * A place cards can be moved from or to.
+ */
+export type Zone =
+  | {
+    index: number;
+    zone: "Tableau";
+  }
+  | {
+    index: number;
+    zone: "Foundation";
+  }
+  | {
+    index: number;
+    zone: "Cell";
+  }
+  | {
+    zone: "Waste";
+  };
+
+/**
+ * How many cards are in each zone once an event has happened.
+ */
+export type ZoneSizes = {
+  discard_pile: number;
+  draw_deck: number;
+  hands: Array<HandSize>;
+};
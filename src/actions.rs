@@ -1,15 +1,410 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub enum Action {
-    CreateGame,
-    JoinRoom,
-    Chat,
-    DrawCard,
-    DrawDeckUpdated,
-    PlayerJoinedRoomInSession,
-    ToggleVisibilityOfCard,
-    DiscardCard,
-    ResetDeck,
-    Quit,
+use crate::{
+    bot::{BotKind, BotTurn},
+    card::{Card, CardData, DeckSpec},
+    chips::ChipsData,
+    codec::Encoding,
+    history::{HistoryEntry, RoomEvent},
+    lobby::RoomSummary,
+    player::PlayerData,
+    room_settings::{GameType, UndoPolicy},
+    rules::{Aces, GameMove, GameState, PlayerScore},
+    scoreboard::ScoreboardData,
+    team::TeamData,
+};
+
+/// Everything a client can ask for. On the wire the variant name is the `action` and its
+/// fields are the `data`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "action", content = "data")]
+pub enum ClientAction {
+    /// Has to be the first thing a connection sends, the server answers with `Welcome`. It's
+    /// always sent as JSON, everything after the `Welcome` uses the encoding asked for here.
+    Hello {
+        min_version: u32,
+        max_version: u32,
+        encoding: Option<Encoding>,
+    },
+    CreateGame(CreateGame),
+    JoinRoom {
+        room_id: u32,
+        player_name: String,
+        password: Option<String>,
+        invite_token: Option<String>,
+    },
+    /// Sent to everyone in the room under the name of the player's seat.
+    Chat {
+        room_id: u32,
+        player_id: String,
+        message: String,
+    },
+    DrawCard {
+        room_id: u32,
+        player_id: String,
+    },
+    ToggleVisibilityOfCard {
+        room_id: u32,
+        player_id: String,
+        card: Card,
+    },
+    DiscardCard {
+        room_id: u32,
+        player_id: String,
+        card: Card,
+    },
+    ResetDeck {
+        room_id: u32,
+        player_id: String,
+    },
+    GameMove {
+        room_id: u32,
+        player_id: String,
+        game_move: GameMove,
+    },
+    Quit {
+        room_id: u32,
+        player_id: String,
+    },
+    RotatePassword {
+        room_id: u32,
+        player_id: String,
+        password: Option<String>,
+    },
+    CreateInviteToken {
+        room_id: u32,
+        player_id: String,
+    },
+    ListRooms {
+        game_type: Option<GameType>,
+        open_seats: Option<bool>,
+        search: Option<String>,
+    },
+    SubscribeLobby,
+    UnsubscribeLobby,
+    EnqueueMatch {
+        player_name: String,
+        game_type: Option<GameType>,
+        player_count: usize,
+    },
+    CancelMatch,
+    RecordScores {
+        room_id: u32,
+        player_id: String,
+        scores: Vec<PlayerScore>,
+    },
+    Bet {
+        room_id: u32,
+        player_id: String,
+        amount: u64,
+    },
+    CollectPot {
+        room_id: u32,
+        player_id: String,
+    },
+    TransferChips {
+        room_id: u32,
+        player_id: String,
+        to_player_id: String,
+        amount: u64,
+    },
+    DefineTeams {
+        room_id: u32,
+        player_id: String,
+        team_names: Vec<String>,
+    },
+    /// Leaving out the team takes the player off their team.
+    AssignTeam {
+        room_id: u32,
+        player_id: String,
+        to_player_id: String,
+        team: Option<String>,
+    },
+    TeamChat {
+        room_id: u32,
+        player_id: String,
+        message: String,
+    },
+    PlayToTeamPile {
+        room_id: u32,
+        player_id: String,
+        card: Card,
+    },
+    TakeFromTeamPile {
+        room_id: u32,
+        player_id: String,
+        card: Card,
+    },
+    AddBot {
+        room_id: u32,
+        player_id: String,
+        bot_kind: Option<BotKind>,
+    },
+    RemoveBot {
+        room_id: u32,
+        player_id: String,
+        to_player_id: String,
+    },
+    /// Asks for every room event numbered after `seq`, the last one the client saw.
+    SyncFrom {
+        room_id: u32,
+        player_id: String,
+        seq: u64,
+    },
+    /// Takes back a seat kept from before a restart, using the token from `CreateGame` or
+    /// `JoinRoom`.
+    ReclaimSeat {
+        room_id: u32,
+        session_token: String,
+    },
+    GetHistory {
+        room_id: u32,
+        player_id: String,
+    },
+    /// Takes back the last change made in a free play room, if the room's undo setting allows
+    /// it.
+    Undo {
+        room_id: u32,
+        player_id: String,
+    },
+    /// Answers an `UndoRequested` in a room where undoing takes a vote.
+    VoteUndo {
+        room_id: u32,
+        player_id: String,
+        approve: bool,
+    },
+}
+
+/// The room a `CreateGame` asks for, anything left out gets the default for the chosen game.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
+pub struct CreateGame {
+    pub player_name: String,
+    pub password: Option<String>,
+    pub private: Option<bool>,
+    pub max_players: Option<usize>,
+    pub game_type: Option<GameType>,
+    pub deck: Option<DeckSpec>,
+    pub dealer_hits_soft_17: Option<bool>,
+    pub small_blind: Option<u64>,
+    pub big_blind: Option<u64>,
+    pub aces: Option<Aces>,
+    pub seed: Option<u64>,
+    pub target_score: Option<i64>,
+    pub starting_chips: Option<u64>,
+    pub undo: Option<UndoPolicy>,
+}
+
+/// Everything the server sends. Replies to a request are named after the action that asked
+/// for them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "action", content = "data")]
+pub enum ServerAction {
+    /// The protocol version and encoding both sides agreed on.
+    Welcome {
+        version: u32,
+        encoding: Encoding,
+    },
+    /// A request that couldn't be handled at all, as opposed to a move the rules didn't allow.
+    Error {
+        message: String,
+    },
+    /// `seq` is the number of the last room event, the next one the client sees follows it.
+    CreateGame {
+        room_id: u32,
+        seq: u64,
+        player_name: String,
+        player_id: String,
+        session_token: String,
+        draw_deck_size: usize,
+        private: bool,
+        max_players: usize,
+        game_type: GameType,
+        deck: DeckSpec,
+        chips: ChipsData,
+    },
+    JoinRoom {
+        room_id: u32,
+        seq: u64,
+        player_name: String,
+        player_id: String,
+        session_token: String,
+        draw_deck_size: usize,
+        other_players: Vec<PlayerData>,
+        discard_pile: Vec<CardData>,
+        private: bool,
+        max_players: usize,
+        game_type: GameType,
+        deck: DeckSpec,
+        scoreboard: ScoreboardData,
+        chips: ChipsData,
+        teams: Vec<TeamData>,
+    },
+    PlayerJoinedRoomInSession {
+        player_name: String,
+        player_id: String,
+    },
+    WrongPassword {
+        room_id: u32,
+        message: String,
+    },
+    RoomFull {
+        room_id: u32,
+        message: String,
+    },
+    Chat {
+        room_id: u32,
+        player_name: String,
+        message: String,
+    },
+    DrawCard {
+        card: Card,
+        draw_deck_size: usize,
+    },
+    DrawDeckUpdated {
+        player_id: String,
+        draw_deck_size: usize,
+    },
+    ToggleVisibilityOfCard {
+        player_id: String,
+        card: Card,
+    },
+    DiscardCard {
+        player_id: String,
+        card: Card,
+        hand: Vec<CardData>,
+    },
+    ResetDeck {
+        discard_pile: Vec<CardData>,
+        draw_deck_size: usize,
+        message: String,
+    },
+    Quit {
+        player_id: String,
+        discard_pile: Vec<CardData>,
+        message: String,
+    },
+    RotatePassword {
+        room_id: u32,
+        message: String,
+    },
+    CreateInviteToken {
+        room_id: u32,
+        invite_token: String,
+    },
+    ListRooms {
+        rooms: Vec<RoomSummary>,
+    },
+    SubscribeLobby {
+        rooms: Vec<RoomSummary>,
+    },
+    LobbyRoomUpdated {
+        room_summary: RoomSummary,
+    },
+    LobbyRoomClosed {
+        room_id: u32,
+    },
+    EnqueueMatch {
+        game_type: GameType,
+        player_count: usize,
+        message: String,
+    },
+    CancelMatch {
+        message: String,
+    },
+    MatchTimedOut {
+        game_type: GameType,
+        player_count: usize,
+        message: String,
+    },
+    IllegalMove {
+        player_id: String,
+        message: String,
+    },
+    GameState {
+        game_state: GameState,
+    },
+    RoundOver {
+        scores: Vec<PlayerScore>,
+        winner: Option<String>,
+    },
+    ScoreboardUpdated {
+        room_id: u32,
+        scoreboard: ScoreboardData,
+    },
+    ChipsUpdated {
+        room_id: u32,
+        chips: ChipsData,
+    },
+    TeamsUpdated {
+        room_id: u32,
+        teams: Vec<TeamData>,
+        other_players: Vec<PlayerData>,
+    },
+    TeamChat {
+        room_id: u32,
+        player_name: String,
+        message: String,
+    },
+    BotTurn(BotTurn),
+    /// Counts down to the server stopping, the connection is closed once it reaches zero.
+    ServerShuttingDown {
+        seconds_left: u64,
+        message: String,
+    },
+    /// The seat is back on this connection, a `Snapshot` of the room follows.
+    ReclaimSeat {
+        room_id: u32,
+        player_id: String,
+        player_name: String,
+    },
+    /// Sent after the missed events of a `SyncFrom` were replayed, `seq` is the last of them.
+    Synced {
+        room_id: u32,
+        seq: u64,
+        replayed: usize,
+    },
+    /// Everything about the room as of event `seq`, sent instead of the missed events when
+    /// they aren't kept any more.
+    Snapshot {
+        room_id: u32,
+        seq: u64,
+        host_id: String,
+        draw_deck_size: usize,
+        players: Vec<PlayerData>,
+        hand: Vec<Card>,
+        discard_pile: Vec<CardData>,
+        private: bool,
+        max_players: usize,
+        game_type: GameType,
+        deck: DeckSpec,
+        scoreboard: ScoreboardData,
+        chips: ChipsData,
+        teams: Vec<TeamData>,
+    },
+    /// Everything that has happened in the room so far, without other players' hidden cards.
+    History {
+        room_id: u32,
+        entries: Vec<HistoryEntry>,
+    },
+    /// Someone wants to take back history entry `number`, everyone else votes on it.
+    UndoRequested {
+        room_id: u32,
+        player_id: String,
+        number: u64,
+        event: RoomEvent,
+    },
+    /// The undo someone asked for won't happen, `player_id` is whoever asked.
+    UndoRejected {
+        room_id: u32,
+        player_id: String,
+        message: String,
+    },
+    /// History entry `number` was taken back. A `Snapshot` of the room as it is now follows.
+    Undone {
+        room_id: u32,
+        player_id: String,
+        number: u64,
+        event: RoomEvent,
+    },
 }
@@ -0,0 +1,305 @@
+use std::time::Duration;
+
+use async_std::task;
+use futures::{channel::mpsc::UnboundedSender, StreamExt};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{ClientAction, ServerAction},
+    message::{ClientMessage, ServerMessage},
+    outbox::OutboxReceiver,
+    rules::{GameMove, GameState},
+};
+
+/// How long a bot takes to make up its mind, so people can follow what it's doing.
+const THINKING_TIME: Duration = Duration::from_millis(400);
+
+/// What a bot is sent instead of the game state: the same view a player would get, plus every
+/// move the rules allow it to make and the move a sensible player would make.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BotTurn {
+    pub game_state: GameState,
+    pub legal_moves: Vec<GameMove>,
+    pub hint: Option<GameMove>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, JsonSchema)]
+pub enum BotKind {
+    /// Picks any move the rules allow.
+    #[default]
+    Random,
+    /// Plays the way the rules suggest, which is a simple strategy for each game.
+    Heuristic,
+}
+
+impl BotKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotKind::Random => "Random Bot",
+            BotKind::Heuristic => "Bot",
+        }
+    }
+
+    pub fn bot(&self) -> Box<dyn Bot> {
+        match self {
+            BotKind::Random => Box::new(RandomBot::default()),
+            BotKind::Heuristic => Box::new(HeuristicBot::default()),
+        }
+    }
+}
+
+pub trait Bot: Send {
+    /// Picks the move to make, or `None` to wait for the next turn.
+    fn choose_move(&mut self, turn: &BotTurn) -> Option<GameMove>;
+}
+
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl Default for RandomBot {
+    fn default() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn choose_move(&mut self, turn: &BotTurn) -> Option<GameMove> {
+        turn.legal_moves.choose(&mut self.rng).cloned()
+    }
+}
+
+/// Follows the hint when there is one, and falls back to a random move when the rules have
+/// no opinion.
+#[derive(Default)]
+pub struct HeuristicBot {
+    fallback: RandomBot,
+}
+
+impl Bot for HeuristicBot {
+    fn choose_move(&mut self, turn: &BotTurn) -> Option<GameMove> {
+        turn.hint
+            .clone()
+            .or_else(|| self.fallback.choose_move(turn))
+    }
+}
+
+fn bot_turn(message: ServerMessage) -> Option<BotTurn> {
+    match message.action {
+        ServerAction::BotTurn(turn) => Some(turn),
+        _ => None,
+    }
+}
+
+/// Plays for a bot sitting at a table. Everything the room sends the bot arrives here, and the
+/// moves it makes go back through `moves` as if they came in over a websocket. Stops once the
+/// bot has been removed from the room and nothing can send to it any more.
+pub async fn run_bot(
+    mut bot: Box<dyn Bot>,
+    mut receiver: OutboxReceiver,
+    moves: UnboundedSender<ClientMessage>,
+    room_id: u32,
+    player_id: String,
+) {
+    let mut last_state = None;
+    while let Some(message) = receiver.next().await {
+        let mut turn = match bot_turn(message) {
+            Some(turn) => turn,
+            None => continue,
+        };
+        task::sleep(THINKING_TIME).await;
+        // the game may have moved on while the bot was thinking
+        while let Ok(message) = receiver.try_recv() {
+            if let Some(newer_turn) = bot_turn(message) {
+                turn = newer_turn;
+            }
+        }
+        // a move that didn't change anything would only be made again
+        if last_state.as_ref() == Some(&turn.game_state) {
+            continue;
+        }
+        let game_move = match bot.choose_move(&turn) {
+            Some(game_move) => game_move,
+            None => continue,
+        };
+        last_state = Some(turn.game_state);
+
+        let message = ClientMessage::new(ClientAction::GameMove {
+            room_id,
+            player_id: player_id.clone(),
+            game_move,
+        });
+        if moves.unbounded_send(message).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::channel::mpsc;
+
+    use super::*;
+    use crate::{
+        outbox::{outbox, OutboxConfig},
+        room_settings::{GameType, RoomSettings},
+        rules::{dealt_game_state, new_rules, test_table},
+    };
+
+    fn turn(game_state: GameState, hint: Option<GameMove>) -> BotTurn {
+        BotTurn {
+            game_state,
+            legal_moves: vec![GameMove::Hit, GameMove::Stand],
+            hint,
+        }
+    }
+
+    fn random_bot(seed: u64) -> RandomBot {
+        RandomBot {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Plays a round of `game_type` with `bot` making every move, the way a room would ask it.
+    fn play_round(game_type: GameType, players: usize, bot: &mut dyn Bot, seed: u64) {
+        let settings = RoomSettings {
+            game_type,
+            ..RoomSettings::default()
+        };
+        let mut rules = new_rules(&settings);
+        let (mut table, ids, _receivers) = test_table(players, 0, seed);
+        rules.setup(&mut table).unwrap();
+        let mut moves = 0;
+        while !rules.is_round_over(&table) {
+            let (player_id, turn) = ids
+                .iter()
+                .find_map(|player_id| {
+                    let legal_moves: Vec<GameMove> = rules
+                        .bot_moves(&table, player_id)
+                        .into_iter()
+                        .filter(|game_move| rules.validate(&table, player_id, game_move).is_ok())
+                        .collect();
+                    if legal_moves.is_empty() {
+                        return None;
+                    }
+                    let turn = BotTurn {
+                        game_state: rules.view(&table, player_id)?,
+                        legal_moves,
+                        hint: rules
+                            .hint(&table, player_id)
+                            .filter(|hint| rules.validate(&table, player_id, hint).is_ok()),
+                    };
+                    Some((player_id.clone(), turn))
+                })
+                .expect("somebody can always move until the round is over");
+            let game_move = bot.choose_move(&turn).unwrap();
+            rules.validate(&table, &player_id, &game_move).unwrap();
+            rules.apply(&mut table, &player_id, &game_move).unwrap();
+            moves += 1;
+            assert!(moves < 2000, "the round never ended");
+        }
+    }
+
+    #[test]
+    fn random_bots_only_pick_moves_the_rules_allow() {
+        let mut bot = random_bot(0);
+        for _ in 0..20 {
+            let game_move = bot
+                .choose_move(&turn(dealt_game_state(GameType::Hearts, 0), None))
+                .unwrap();
+            assert!([GameMove::Hit, GameMove::Stand].contains(&game_move));
+        }
+        let no_moves = BotTurn {
+            legal_moves: vec![],
+            ..turn(dealt_game_state(GameType::Hearts, 0), None)
+        };
+        assert_eq!(bot.choose_move(&no_moves), None);
+    }
+
+    #[test]
+    fn heuristic_bots_follow_the_hint_when_there_is_one() {
+        let mut bot = HeuristicBot {
+            fallback: random_bot(0),
+        };
+        let hinted = turn(dealt_game_state(GameType::Hearts, 0), Some(GameMove::Stand));
+        for _ in 0..20 {
+            assert_eq!(bot.choose_move(&hinted), Some(GameMove::Stand));
+        }
+        let game_move = bot
+            .choose_move(&turn(dealt_game_state(GameType::Hearts, 0), None))
+            .unwrap();
+        assert!([GameMove::Hit, GameMove::Stand].contains(&game_move));
+    }
+
+    #[test]
+    fn bots_play_whole_rounds() {
+        for seed in 0..3 {
+            for (game_type, players) in [
+                (GameType::Hearts, 4),
+                (GameType::Spades, 4),
+                (GameType::Euchre, 4),
+                (GameType::CrazyEights, 3),
+                (GameType::Rummy, 3),
+                (GameType::Gin, 2),
+            ] {
+                let mut bot = HeuristicBot {
+                    fallback: random_bot(seed),
+                };
+                play_round(game_type, players, &mut bot, seed);
+            }
+            play_round(GameType::Hearts, 4, &mut random_bot(seed), seed);
+        }
+    }
+
+    #[test]
+    fn moves_are_sent_once_for_each_new_game_state() {
+        task::block_on(async {
+            let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+            let (moves, mut bot_moves) = mpsc::unbounded();
+            let bot = Box::new(HeuristicBot::default());
+            let bot_task = task::spawn(run_bot(bot, receiver, moves, 1234, "bot".to_owned()));
+
+            let first = turn(dealt_game_state(GameType::Hearts, 1), Some(GameMove::Hit));
+            sender
+                .send(ServerAction::BotTurn(first.clone()).into())
+                .unwrap();
+            let message = bot_moves.next().await.unwrap();
+            assert_eq!(
+                message.action,
+                ClientAction::GameMove {
+                    room_id: 1234,
+                    player_id: "bot".to_owned(),
+                    game_move: GameMove::Hit,
+                }
+            );
+
+            // the same state again means the move didn't change anything
+            sender.send(ServerAction::BotTurn(first).into()).unwrap();
+            task::sleep(THINKING_TIME * 2).await;
+            // only the newest of the turns sent while the bot was thinking is played
+            let stale = turn(dealt_game_state(GameType::Hearts, 2), Some(GameMove::Hit));
+            let newest = turn(dealt_game_state(GameType::Hearts, 3), Some(GameMove::Stand));
+            sender.send(ServerAction::BotTurn(stale).into()).unwrap();
+            sender.send(ServerAction::BotTurn(newest).into()).unwrap();
+            drop(sender);
+            bot_task.await;
+
+            let rest: Vec<ClientMessage> = bot_moves.collect().await;
+            let rest: Vec<ClientAction> = rest.into_iter().map(|message| message.action).collect();
+            assert_eq!(
+                rest,
+                vec![ClientAction::GameMove {
+                    room_id: 1234,
+                    player_id: "bot".to_owned(),
+                    game_move: GameMove::Stand,
+                }]
+            );
+        });
+    }
+}
@@ -1,6 +1,7 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, JsonSchema)]
 pub struct Card {
     pub suite: Suite,
     pub value: Value,
@@ -20,6 +21,23 @@ impl Card {
         self.visible = !self.visible;
     }
 
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// The card as its owner sees it, whether or not it has been turned face up.
+    pub fn revealed_card_data(&self) -> CardData {
+        CardData {
+            suite: Some(self.suite),
+            value: Some(self.value),
+            visible: self.visible,
+        }
+    }
+
     pub fn card_data(&self) -> CardData {
         if self.visible {
             CardData {
@@ -37,7 +55,7 @@ impl Card {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy, JsonSchema)]
 pub enum Suite {
     Club,
     Heart,
@@ -51,7 +69,7 @@ impl Suite {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy, JsonSchema)]
 pub enum Value {
     Ace,
     Two,
@@ -88,9 +106,81 @@ impl Value {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Describes which cards make up a room's draw deck.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub struct DeckSpec {
+    pub decks: usize,
+}
+
+impl DeckSpec {
+    pub const MAX_DECKS: usize = 8;
+
+    pub fn new(decks: usize) -> Self {
+        Self {
+            decks: decks.clamp(1, Self::MAX_DECKS),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.decks * Suite::all().len() * Value::all().len()
+    }
+
+    pub fn build(&self) -> Vec<Card> {
+        let mut cards = Vec::with_capacity(self.size());
+        for _ in 0..self.decks {
+            for suite in Suite::all().iter() {
+                for value in Value::all().iter() {
+                    cards.push(Card::new(*suite, *value));
+                }
+            }
+        }
+        cards
+    }
+}
+
+impl Default for DeckSpec {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct CardData {
     pub suite: Option<Suite>,
     pub value: Option<Value>,
     pub visible: bool,
 }
+
+/// Reads cards written like `"AS TD 2C"`, value first and then suit.
+#[cfg(test)]
+pub fn cards(text: &str) -> Vec<Card> {
+    text.split_whitespace()
+        .map(|card| {
+            let mut chars = card.chars();
+            let value = match chars.next().unwrap() {
+                'A' => Value::Ace,
+                '2' => Value::Two,
+                '3' => Value::Three,
+                '4' => Value::Four,
+                '5' => Value::Five,
+                '6' => Value::Six,
+                '7' => Value::Seven,
+                '8' => Value::Eight,
+                '9' => Value::Nine,
+                'T' => Value::Ten,
+                'J' => Value::Jack,
+                'Q' => Value::Queen,
+                'K' => Value::King,
+                other => panic!("Unknown value {}", other),
+            };
+            let suite = match chars.next().unwrap() {
+                'C' => Suite::Club,
+                'H' => Suite::Heart,
+                'D' => Suite::Diamond,
+                'S' => Suite::Spade,
+                other => panic!("Unknown suit {}", other),
+            };
+            Card::new(suite, value)
+        })
+        .collect()
+}
@@ -0,0 +1,210 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SidePot {
+    pub amount: u64,
+    pub eligible: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PlayerChips {
+    pub player_id: String,
+    pub chips: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ChipsData {
+    pub players: Vec<PlayerChips>,
+    pub pots: Vec<SidePot>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Contribution {
+    player_id: String,
+    amount: u64,
+    forfeited: bool,
+}
+
+/// The chips bet into the middle of a free play table. Only what everyone has put in is kept,
+/// the main pot and side pots are worked out from that whenever they're needed.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Pot {
+    contributions: Vec<Contribution>,
+}
+
+impl Pot {
+    pub fn add(&mut self, player_id: &str, amount: u64) {
+        match self
+            .contributions
+            .iter_mut()
+            .find(|contribution| contribution.player_id == player_id)
+        {
+            Some(contribution) => contribution.amount += amount,
+            None => self.contributions.push(Contribution {
+                player_id: player_id.to_owned(),
+                amount,
+                forfeited: false,
+            }),
+        }
+    }
+
+    /// Someone who left the table leaves their chips behind, but can't win them back.
+    pub fn forfeit(&mut self, player_id: &str) {
+        for contribution in &mut self.contributions {
+            if contribution.player_id == player_id {
+                contribution.forfeited = true;
+            }
+        }
+    }
+
+    fn active(&self) -> impl Iterator<Item = &Contribution> {
+        self.contributions
+            .iter()
+            .filter(|contribution| !contribution.forfeited)
+    }
+
+    pub fn side_pots(&self) -> Vec<SidePot> {
+        let mut levels: Vec<u64> = self
+            .active()
+            .map(|contribution| contribution.amount)
+            .collect();
+        levels.sort_unstable();
+        levels.dedup();
+
+        let mut pots: Vec<SidePot> = vec![];
+        let mut previous_level = 0;
+        for level in levels {
+            pots.push(SidePot {
+                amount: self
+                    .contributions
+                    .iter()
+                    .map(|contribution| {
+                        contribution.amount.min(level) - contribution.amount.min(previous_level)
+                    })
+                    .sum(),
+                eligible: self
+                    .active()
+                    .filter(|contribution| contribution.amount >= level)
+                    .map(|contribution| contribution.player_id.clone())
+                    .collect(),
+            });
+            previous_level = level;
+        }
+
+        // chips left behind above what anyone still playing has bet go to the last pot
+        let left_over: u64 = self
+            .contributions
+            .iter()
+            .map(|contribution| contribution.amount.saturating_sub(previous_level))
+            .sum();
+        if left_over > 0 {
+            match pots.last_mut() {
+                Some(pot) => pot.amount += left_over,
+                None => pots.push(SidePot {
+                    amount: left_over,
+                    eligible: vec![],
+                }),
+            }
+        }
+        pots
+    }
+
+    /// Takes every pot the player is eligible for, and returns how many chips that was. When
+    /// nobody still playing has anything in the pot, whoever collects it gets it all.
+    pub fn collect(&mut self, player_id: &str) -> u64 {
+        let level = if self.active().next().is_none() {
+            u64::MAX
+        } else {
+            let mine = match self
+                .active()
+                .find(|contribution| contribution.player_id == player_id)
+            {
+                Some(contribution) => contribution.amount,
+                None => return 0,
+            };
+            if self.active().any(|contribution| contribution.amount > mine) {
+                mine
+            } else {
+                u64::MAX
+            }
+        };
+
+        let mut won = 0;
+        for contribution in &mut self.contributions {
+            let taken = contribution.amount.min(level);
+            contribution.amount -= taken;
+            won += taken;
+        }
+        self.contributions
+            .retain(|contribution| contribution.amount > 0);
+        won
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pot_with(bets: &[(&str, u64)]) -> Pot {
+        let mut pot = Pot::default();
+        for (player_id, amount) in bets {
+            pot.add(player_id, *amount);
+        }
+        pot
+    }
+
+    fn eligible(pot: &SidePot) -> Vec<&str> {
+        pot.eligible.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn short_stacks_only_play_for_the_pots_they_matched() {
+        let pot = pot_with(&[("ada", 50), ("bea", 200), ("cy", 120), ("bea", 100)]);
+        let pots = pot.side_pots();
+        assert_eq!(pots.len(), 3);
+        assert_eq!(pots[0].amount, 150);
+        assert_eq!(eligible(&pots[0]), ["ada", "bea", "cy"]);
+        assert_eq!(pots[1].amount, 140);
+        assert_eq!(eligible(&pots[1]), ["bea", "cy"]);
+        assert_eq!(pots[2].amount, 180);
+        assert_eq!(eligible(&pots[2]), ["bea"]);
+    }
+
+    #[test]
+    fn collecting_every_pot_hands_out_exactly_what_was_bet() {
+        let mut pot = pot_with(&[("ada", 50), ("bea", 300), ("cy", 120)]);
+        // the short stack wins the main pot, the rest goes to whoever wins the side pots
+        assert_eq!(pot.collect("ada"), 150);
+        assert_eq!(pot.collect("ada"), 0);
+        assert_eq!(pot.collect("cy"), 140);
+        assert_eq!(pot.collect("bea"), 180);
+        assert!(pot.side_pots().is_empty());
+    }
+
+    #[test]
+    fn chips_left_behind_go_to_whoever_is_still_playing() {
+        let mut pot = pot_with(&[("ada", 100), ("bea", 40), ("cy", 100)]);
+        pot.forfeit("ada");
+        let pots = pot.side_pots();
+        assert_eq!(pots.len(), 2);
+        assert_eq!(pots[1].amount, 60 + 60);
+        assert_eq!(eligible(&pots[1]), ["cy"]);
+        // a player who left can't collect what they put in
+        assert_eq!(pot.collect("ada"), 0);
+        assert_eq!(pot.collect("cy"), 240);
+
+        let mut pot = pot_with(&[("ada", 30), ("bea", 40)]);
+        pot.forfeit("ada");
+        pot.forfeit("bea");
+        assert!(pot.side_pots()[0].eligible.is_empty());
+        assert_eq!(pot.collect("dee"), 70);
+    }
+
+    #[test]
+    fn players_with_nothing_in_the_pot_cant_collect_it() {
+        let mut pot = pot_with(&[("ada", 30), ("bea", 30)]);
+        assert_eq!(pot.collect("cy"), 0);
+        assert_eq!(pot.side_pots()[0].amount, 60);
+    }
+}
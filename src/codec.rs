@@ -0,0 +1,688 @@
+use async_tungstenite::tungstenite::Message;
+use eyre::{bail, Result};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// How messages are written on a connection, picked in the `Hello` handshake. JSON goes out in
+/// text frames, the binary encodings in binary frames.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Encoding {
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Message> {
+        let frame = match self {
+            Encoding::Json => Message::Text(serde_json::to_string(value)?),
+            Encoding::MessagePack => Message::Binary(rmp_serde::to_vec_named(value)?),
+            Encoding::Cbor => {
+                let mut bytes = vec![];
+                ciborium::ser::into_writer(value, &mut bytes)?;
+                Message::Binary(bytes)
+            }
+        };
+        Ok(frame)
+    }
+
+    /// Text frames are always JSON, which is how a client says hello before an encoding has
+    /// been agreed on.
+    pub fn decode<T: DeserializeOwned>(&self, frame: &Message) -> Result<T> {
+        let value = match (frame, self) {
+            (Message::Text(text), _) => serde_json::from_str(text)?,
+            (Message::Binary(bytes), Encoding::Json) => serde_json::from_slice(bytes)?,
+            (Message::Binary(bytes), Encoding::MessagePack) => rmp_serde::from_slice(bytes)?,
+            (Message::Binary(bytes), Encoding::Cbor) => {
+                ciborium::de::from_reader(bytes.as_slice())?
+            }
+            _ => bail!("Only text and binary frames carry messages"),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::{ClientAction, CreateGame, ServerAction},
+        bot::{BotKind, BotTurn},
+        card::{Card, DeckSpec, Suite, Value},
+        chips::{ChipsData, PlayerChips, SidePot},
+        history::{HandSize, HistoryEntry, Place, PlacedCard, RoomEvent, ZoneSizes},
+        lobby::RoomSummary,
+        message::{ClientMessage, ServerMessage},
+        outbox::{outbox, OutboxConfig},
+        player::Player,
+        room_settings::{GameType, UndoPolicy},
+        rules::{dealt_game_state, Aces, GameMove, PlayerScore},
+        scoreboard::{RoundScores, ScoreboardData},
+        team::TeamData,
+    };
+
+    const ENCODINGS: [Encoding; 3] = [Encoding::Json, Encoding::MessagePack, Encoding::Cbor];
+
+    fn ace() -> Card {
+        Card::new(Suite::Spade, Value::Ace)
+    }
+
+    fn id() -> String {
+        "3f0c1a52-9d4e-4c8b-a1f7-2b6e8d9c0a11".to_owned()
+    }
+
+    fn scores() -> Vec<PlayerScore> {
+        vec![PlayerScore {
+            player_id: id(),
+            points: -26,
+        }]
+    }
+
+    fn summary() -> RoomSummary {
+        RoomSummary {
+            room_id: 4321,
+            host_name: "Ada".to_owned(),
+            player_count: 2,
+            capacity: 4,
+            game_type: GameType::Hearts,
+            deck: DeckSpec::default(),
+            in_progress: true,
+        }
+    }
+
+    fn chips() -> ChipsData {
+        ChipsData {
+            players: vec![PlayerChips {
+                player_id: id(),
+                chips: 950,
+            }],
+            pots: vec![SidePot {
+                amount: 50,
+                eligible: vec![id()],
+            }],
+        }
+    }
+
+    fn scoreboard() -> ScoreboardData {
+        ScoreboardData {
+            target_score: Some(100),
+            totals: scores(),
+            rounds: vec![RoundScores {
+                round: 1,
+                deltas: scores(),
+            }],
+            winners: vec![id()],
+        }
+    }
+
+    fn teams() -> Vec<TeamData> {
+        vec![TeamData {
+            name: "North".to_owned(),
+            members: vec![id()],
+            pile: vec![ace().card_data()],
+        }]
+    }
+
+    fn client_actions() -> Vec<ClientAction> {
+        let room_id = 4321;
+        vec![
+            ClientAction::Hello {
+                min_version: 1,
+                max_version: 3,
+                encoding: Some(Encoding::Cbor),
+            },
+            ClientAction::CreateGame(CreateGame {
+                player_name: "Ada".to_owned(),
+                password: Some("hunter2".to_owned()),
+                private: Some(true),
+                max_players: Some(4),
+                game_type: Some(GameType::Holdem),
+                deck: Some(DeckSpec::new(2)),
+                dealer_hits_soft_17: None,
+                small_blind: Some(5),
+                big_blind: Some(10),
+                aces: Some(Aces::Either),
+                seed: Some(u64::MAX),
+                target_score: Some(-50),
+                starting_chips: None,
+                undo: Some(UndoPolicy::Vote),
+            }),
+            ClientAction::JoinRoom {
+                room_id,
+                player_name: "Bea".to_owned(),
+                password: None,
+                invite_token: Some(id()),
+            },
+            ClientAction::Chat {
+                room_id,
+                player_id: id(),
+                message: "hi ♠".to_owned(),
+            },
+            ClientAction::DrawCard {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::ToggleVisibilityOfCard {
+                room_id,
+                player_id: id(),
+                card: ace(),
+            },
+            ClientAction::DiscardCard {
+                room_id,
+                player_id: id(),
+                card: ace(),
+            },
+            ClientAction::ResetDeck {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::GameMove {
+                room_id,
+                player_id: id(),
+                game_move: GameMove::PlayWild {
+                    card: ace(),
+                    suite: Suite::Heart,
+                },
+            },
+            ClientAction::Quit {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::RotatePassword {
+                room_id,
+                player_id: id(),
+                password: None,
+            },
+            ClientAction::CreateInviteToken {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::ListRooms {
+                game_type: Some(GameType::Spades),
+                open_seats: Some(true),
+                search: Some("ada".to_owned()),
+            },
+            ClientAction::SubscribeLobby,
+            ClientAction::UnsubscribeLobby,
+            ClientAction::EnqueueMatch {
+                player_name: "Ada".to_owned(),
+                game_type: None,
+                player_count: 4,
+            },
+            ClientAction::CancelMatch,
+            ClientAction::RecordScores {
+                room_id,
+                player_id: id(),
+                scores: scores(),
+            },
+            ClientAction::Bet {
+                room_id,
+                player_id: id(),
+                amount: 25,
+            },
+            ClientAction::CollectPot {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::TransferChips {
+                room_id,
+                player_id: id(),
+                to_player_id: id(),
+                amount: 10,
+            },
+            ClientAction::DefineTeams {
+                room_id,
+                player_id: id(),
+                team_names: vec!["North".to_owned(), "South".to_owned()],
+            },
+            ClientAction::AssignTeam {
+                room_id,
+                player_id: id(),
+                to_player_id: id(),
+                team: None,
+            },
+            ClientAction::TeamChat {
+                room_id,
+                player_id: id(),
+                message: "lead hearts".to_owned(),
+            },
+            ClientAction::PlayToTeamPile {
+                room_id,
+                player_id: id(),
+                card: ace(),
+            },
+            ClientAction::TakeFromTeamPile {
+                room_id,
+                player_id: id(),
+                card: ace(),
+            },
+            ClientAction::AddBot {
+                room_id,
+                player_id: id(),
+                bot_kind: Some(BotKind::Heuristic),
+            },
+            ClientAction::RemoveBot {
+                room_id,
+                player_id: id(),
+                to_player_id: id(),
+            },
+            ClientAction::SyncFrom {
+                room_id,
+                player_id: id(),
+                seq: 17,
+            },
+            ClientAction::ReclaimSeat {
+                room_id,
+                session_token: id(),
+            },
+            ClientAction::GetHistory {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::Undo {
+                room_id,
+                player_id: id(),
+            },
+            ClientAction::VoteUndo {
+                room_id,
+                player_id: id(),
+                approve: true,
+            },
+        ]
+    }
+
+    /// Stops compiling when an action is added, so a sample gets added along with it.
+    fn client_action_index(action: &ClientAction) -> usize {
+        match action {
+            ClientAction::Hello { .. } => 0,
+            ClientAction::CreateGame(_) => 1,
+            ClientAction::JoinRoom { .. } => 2,
+            ClientAction::Chat { .. } => 3,
+            ClientAction::DrawCard { .. } => 4,
+            ClientAction::ToggleVisibilityOfCard { .. } => 5,
+            ClientAction::DiscardCard { .. } => 6,
+            ClientAction::ResetDeck { .. } => 7,
+            ClientAction::GameMove { .. } => 8,
+            ClientAction::Quit { .. } => 9,
+            ClientAction::RotatePassword { .. } => 10,
+            ClientAction::CreateInviteToken { .. } => 11,
+            ClientAction::ListRooms { .. } => 12,
+            ClientAction::SubscribeLobby => 13,
+            ClientAction::UnsubscribeLobby => 14,
+            ClientAction::EnqueueMatch { .. } => 15,
+            ClientAction::CancelMatch => 16,
+            ClientAction::RecordScores { .. } => 17,
+            ClientAction::Bet { .. } => 18,
+            ClientAction::CollectPot { .. } => 19,
+            ClientAction::TransferChips { .. } => 20,
+            ClientAction::DefineTeams { .. } => 21,
+            ClientAction::AssignTeam { .. } => 22,
+            ClientAction::TeamChat { .. } => 23,
+            ClientAction::PlayToTeamPile { .. } => 24,
+            ClientAction::TakeFromTeamPile { .. } => 25,
+            ClientAction::AddBot { .. } => 26,
+            ClientAction::RemoveBot { .. } => 27,
+            ClientAction::SyncFrom { .. } => 28,
+            ClientAction::ReclaimSeat { .. } => 29,
+            ClientAction::GetHistory { .. } => 30,
+            ClientAction::Undo { .. } => 31,
+            ClientAction::VoteUndo { .. } => 32,
+        }
+    }
+    const CLIENT_ACTIONS: usize = 33;
+
+    fn server_actions() -> Vec<ServerAction> {
+        let room_id = 4321;
+        let (sender, _) = outbox(OutboxConfig::default(), &Default::default());
+        let mut player = Player::new("Ada", sender);
+        player.hand = vec![ace()];
+        let game_state = dealt_game_state(GameType::Hearts, 0);
+        vec![
+            ServerAction::Welcome {
+                version: 1,
+                encoding: Encoding::MessagePack,
+            },
+            ServerAction::Error {
+                message: "Say Hello before anything else".to_owned(),
+            },
+            ServerAction::CreateGame {
+                room_id,
+                seq: 0,
+                player_name: "Ada".to_owned(),
+                player_id: id(),
+                session_token: id(),
+                draw_deck_size: 52,
+                private: false,
+                max_players: 8,
+                game_type: GameType::FreePlay,
+                deck: DeckSpec::default(),
+                chips: chips(),
+            },
+            ServerAction::JoinRoom {
+                room_id,
+                seq: 3,
+                player_name: "Bea".to_owned(),
+                player_id: id(),
+                session_token: id(),
+                draw_deck_size: 51,
+                other_players: vec![player.player_data()],
+                discard_pile: vec![ace().card_data()],
+                private: true,
+                max_players: 4,
+                game_type: GameType::Euchre,
+                deck: DeckSpec::default(),
+                scoreboard: scoreboard(),
+                chips: chips(),
+                teams: teams(),
+            },
+            ServerAction::PlayerJoinedRoomInSession {
+                player_name: "Bea".to_owned(),
+                player_id: id(),
+            },
+            ServerAction::WrongPassword {
+                room_id,
+                message: "Wrong password".to_owned(),
+            },
+            ServerAction::RoomFull {
+                room_id,
+                message: "Room is full".to_owned(),
+            },
+            ServerAction::Chat {
+                room_id,
+                player_name: "Ada".to_owned(),
+                message: "gg".to_owned(),
+            },
+            ServerAction::DrawCard {
+                card: ace(),
+                draw_deck_size: 0,
+            },
+            ServerAction::DrawDeckUpdated {
+                player_id: id(),
+                draw_deck_size: 0,
+            },
+            ServerAction::ToggleVisibilityOfCard {
+                player_id: id(),
+                card: ace(),
+            },
+            ServerAction::DiscardCard {
+                player_id: id(),
+                card: ace(),
+                hand: vec![ace().card_data(), ace().revealed_card_data()],
+            },
+            ServerAction::ResetDeck {
+                discard_pile: vec![],
+                draw_deck_size: 52,
+                message: "Deck reset and shuffled".to_owned(),
+            },
+            ServerAction::Quit {
+                player_id: id(),
+                discard_pile: vec![ace().card_data()],
+                message: "Ada left the room".to_owned(),
+            },
+            ServerAction::RotatePassword {
+                room_id,
+                message: "Room password changed".to_owned(),
+            },
+            ServerAction::CreateInviteToken {
+                room_id,
+                invite_token: id(),
+            },
+            ServerAction::ListRooms {
+                rooms: vec![summary()],
+            },
+            ServerAction::SubscribeLobby { rooms: vec![] },
+            ServerAction::LobbyRoomUpdated {
+                room_summary: summary(),
+            },
+            ServerAction::LobbyRoomClosed { room_id },
+            ServerAction::EnqueueMatch {
+                game_type: GameType::Gin,
+                player_count: 2,
+                message: "Looking for players".to_owned(),
+            },
+            ServerAction::CancelMatch {
+                message: "Stopped looking for players".to_owned(),
+            },
+            ServerAction::MatchTimedOut {
+                game_type: GameType::Gin,
+                player_count: 2,
+                message: "No match found, try again later".to_owned(),
+            },
+            ServerAction::IllegalMove {
+                player_id: id(),
+                message: "It isn't your turn".to_owned(),
+            },
+            ServerAction::GameState {
+                game_state: game_state.clone(),
+            },
+            ServerAction::RoundOver {
+                scores: scores(),
+                winner: Some(id()),
+            },
+            ServerAction::ScoreboardUpdated {
+                room_id,
+                scoreboard: scoreboard(),
+            },
+            ServerAction::ChipsUpdated {
+                room_id,
+                chips: chips(),
+            },
+            ServerAction::TeamsUpdated {
+                room_id,
+                teams: teams(),
+                other_players: vec![player.player_data()],
+            },
+            ServerAction::TeamChat {
+                room_id,
+                player_name: "Ada".to_owned(),
+                message: "lead hearts".to_owned(),
+            },
+            ServerAction::BotTurn(BotTurn {
+                game_state,
+                legal_moves: vec![
+                    GameMove::Pass,
+                    GameMove::Bid { tricks: 3 },
+                    GameMove::PassCards {
+                        cards: vec![ace(); 3],
+                    },
+                ],
+                hint: Some(GameMove::Raise { amount: 40 }),
+            }),
+            ServerAction::ServerShuttingDown {
+                seconds_left: 10,
+                message: "The server is shutting down in 10 seconds".to_owned(),
+            },
+            ServerAction::ReclaimSeat {
+                room_id,
+                player_id: id(),
+                player_name: "Ada".to_owned(),
+            },
+            ServerAction::Synced {
+                room_id,
+                seq: 20,
+                replayed: 3,
+            },
+            ServerAction::Snapshot {
+                room_id,
+                seq: 300,
+                host_id: id(),
+                draw_deck_size: 40,
+                players: vec![player.player_data()],
+                hand: vec![ace()],
+                discard_pile: vec![],
+                private: false,
+                max_players: 6,
+                game_type: GameType::Hearts,
+                deck: DeckSpec::default(),
+                scoreboard: scoreboard(),
+                chips: chips(),
+                teams: vec![],
+            },
+            ServerAction::History {
+                room_id,
+                entries: vec![HistoryEntry {
+                    number: 7,
+                    at: 1_700_000_000_000,
+                    player_id: id(),
+                    event: RoomEvent::Moved {
+                        game_move: GameMove::DiscardCard { card: ace() },
+                    },
+                    cards: vec![PlacedCard {
+                        card: ace(),
+                        place: Place::DiscardPile,
+                    }],
+                    zones: ZoneSizes {
+                        draw_deck: 40,
+                        discard_pile: 1,
+                        hands: vec![HandSize {
+                            player_id: id(),
+                            cards: 4,
+                        }],
+                    },
+                }],
+            },
+            ServerAction::UndoRequested {
+                room_id,
+                player_id: id(),
+                number: 12,
+                event: RoomEvent::Moved {
+                    game_move: GameMove::DiscardCard { card: ace() },
+                },
+            },
+            ServerAction::UndoRejected {
+                room_id,
+                player_id: id(),
+                message: "Bea voted against the undo".to_owned(),
+            },
+            ServerAction::Undone {
+                room_id,
+                player_id: id(),
+                number: 12,
+                event: RoomEvent::Bet { amount: 25 },
+            },
+        ]
+    }
+
+    /// Stops compiling when an action is added, so a sample gets added along with it.
+    fn server_action_index(action: &ServerAction) -> usize {
+        match action {
+            ServerAction::Welcome { .. } => 0,
+            ServerAction::Error { .. } => 1,
+            ServerAction::CreateGame { .. } => 2,
+            ServerAction::JoinRoom { .. } => 3,
+            ServerAction::PlayerJoinedRoomInSession { .. } => 4,
+            ServerAction::WrongPassword { .. } => 5,
+            ServerAction::RoomFull { .. } => 6,
+            ServerAction::Chat { .. } => 7,
+            ServerAction::DrawCard { .. } => 8,
+            ServerAction::DrawDeckUpdated { .. } => 9,
+            ServerAction::ToggleVisibilityOfCard { .. } => 10,
+            ServerAction::DiscardCard { .. } => 11,
+            ServerAction::ResetDeck { .. } => 12,
+            ServerAction::Quit { .. } => 13,
+            ServerAction::RotatePassword { .. } => 14,
+            ServerAction::CreateInviteToken { .. } => 15,
+            ServerAction::ListRooms { .. } => 16,
+            ServerAction::SubscribeLobby { .. } => 17,
+            ServerAction::LobbyRoomUpdated { .. } => 18,
+            ServerAction::LobbyRoomClosed { .. } => 19,
+            ServerAction::EnqueueMatch { .. } => 20,
+            ServerAction::CancelMatch { .. } => 21,
+            ServerAction::MatchTimedOut { .. } => 22,
+            ServerAction::IllegalMove { .. } => 23,
+            ServerAction::GameState { .. } => 24,
+            ServerAction::RoundOver { .. } => 25,
+            ServerAction::ScoreboardUpdated { .. } => 26,
+            ServerAction::ChipsUpdated { .. } => 27,
+            ServerAction::TeamsUpdated { .. } => 28,
+            ServerAction::TeamChat { .. } => 29,
+            ServerAction::BotTurn(_) => 30,
+            ServerAction::Synced { .. } => 31,
+            ServerAction::Snapshot { .. } => 32,
+            ServerAction::ServerShuttingDown { .. } => 33,
+            ServerAction::ReclaimSeat { .. } => 34,
+            ServerAction::History { .. } => 35,
+            ServerAction::UndoRequested { .. } => 36,
+            ServerAction::UndoRejected { .. } => 37,
+            ServerAction::Undone { .. } => 38,
+        }
+    }
+    const SERVER_ACTIONS: usize = 39;
+
+    fn round_trip<T>(encoding: Encoding, value: &T) -> T
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let frame = encoding.encode(value).unwrap();
+        match (encoding, &frame) {
+            (Encoding::Json, Message::Text(_)) => {}
+            (Encoding::MessagePack | Encoding::Cbor, Message::Binary(_)) => {}
+            _ => panic!("{:?} went out in the wrong kind of frame", encoding),
+        }
+        encoding.decode(&frame).unwrap()
+    }
+
+    #[test]
+    fn samples_cover_every_action() {
+        let mut client_covered = [false; CLIENT_ACTIONS];
+        for action in client_actions() {
+            client_covered[client_action_index(&action)] = true;
+        }
+        assert!(client_covered.iter().all(|covered| *covered));
+
+        let mut server_covered = [false; SERVER_ACTIONS];
+        for action in server_actions() {
+            server_covered[server_action_index(&action)] = true;
+        }
+        assert!(server_covered.iter().all(|covered| *covered));
+    }
+
+    #[test]
+    fn client_messages_round_trip_through_every_encoding() {
+        for &encoding in ENCODINGS.iter() {
+            for (index, action) in client_actions().into_iter().enumerate() {
+                let message = ClientMessage {
+                    request_id: (index % 2 == 0).then(|| format!("request-{}", index)),
+                    action,
+                };
+                assert_eq!(round_trip(encoding, &message), message, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip_through_every_encoding() {
+        for &encoding in ENCODINGS.iter() {
+            for (index, action) in server_actions().into_iter().enumerate() {
+                let message = ServerMessage {
+                    request_id: (index % 2 == 1).then(|| format!("request-{}", index)),
+                    seq: (index % 3 == 0).then_some(index as u64),
+                    action,
+                };
+                assert_eq!(round_trip(encoding, &message), message, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn text_frames_are_read_as_json_whatever_was_agreed() {
+        let hello = ClientMessage::new(ClientAction::Hello {
+            min_version: 1,
+            max_version: 1,
+            encoding: Some(Encoding::MessagePack),
+        });
+        let frame = Encoding::Json.encode(&hello).unwrap();
+        for &encoding in ENCODINGS.iter() {
+            assert_eq!(encoding.decode::<ClientMessage>(&frame).unwrap(), hello);
+        }
+    }
+
+    #[test]
+    fn control_frames_are_not_messages() {
+        let ping = Message::Ping(vec![1, 2, 3]);
+        for &encoding in ENCODINGS.iter() {
+            assert!(encoding.decode::<ClientMessage>(&ping).is_err());
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
+
+use eyre::{bail, eyre, Result};
+
+use crate::{
+    heartbeat::HeartbeatConfig,
+    outbox::{OutboxConfig, OverflowPolicy},
+};
+
+/// Pings further apart than this leave dead connections holding seats for too long.
+const MAX_PING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Settings for running the server, read from the environment.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub address: String,
+    pub outbox: OutboxConfig,
+    pub heartbeat: HeartbeatConfig,
+    /// How long rooms are warned before the server stops.
+    pub shutdown_countdown: Duration,
+    /// How long shutting down can take in total before the server exits anyway.
+    pub shutdown_deadline: Duration,
+    /// Where rooms are saved so they survive a restart, they aren't saved without one.
+    pub room_store_dir: Option<PathBuf>,
+    /// How long players of restored rooms have to reclaim their seats.
+    pub seat_reclaim_window: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:9001".to_owned(),
+            outbox: OutboxConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            shutdown_countdown: Duration::from_secs(10),
+            shutdown_deadline: Duration::from_secs(30),
+            room_store_dir: None,
+            seat_reclaim_window: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// `ADDRESS`, `OUTBOUND_QUEUE_CAPACITY`, `OUTBOUND_QUEUE_POLICY` (`disconnect` or
+    /// `coalesce`), `PING_INTERVAL_SECS`, `MISSED_PONGS`, `IDLE_TIMEOUT_SECS`,
+    /// `SHUTDOWN_COUNTDOWN_SECS`, `SHUTDOWN_DEADLINE_SECS`, `ROOM_STORE_DIR` and
+    /// `SEAT_RECLAIM_SECS` override the defaults.
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let config = Self {
+            address: env::var("ADDRESS").unwrap_or(defaults.address),
+            outbox: OutboxConfig {
+                capacity: var_or("OUTBOUND_QUEUE_CAPACITY", defaults.outbox.capacity)?,
+                policy: var_or::<OverflowPolicy>("OUTBOUND_QUEUE_POLICY", defaults.outbox.policy)?,
+            },
+            heartbeat: HeartbeatConfig {
+                ping_interval: seconds_or("PING_INTERVAL_SECS", defaults.heartbeat.ping_interval)?,
+                missed_pongs: var_or("MISSED_PONGS", defaults.heartbeat.missed_pongs)?,
+                idle_timeout: seconds_or("IDLE_TIMEOUT_SECS", defaults.heartbeat.idle_timeout)?,
+            },
+            shutdown_countdown: seconds_or("SHUTDOWN_COUNTDOWN_SECS", defaults.shutdown_countdown)?,
+            shutdown_deadline: seconds_or("SHUTDOWN_DEADLINE_SECS", defaults.shutdown_deadline)?,
+            room_store_dir: env::var_os("ROOM_STORE_DIR").map(PathBuf::from),
+            seat_reclaim_window: seconds_or("SEAT_RECLAIM_SECS", defaults.seat_reclaim_window)?,
+        };
+        check_heartbeat(&config.heartbeat)?;
+        check_shutdown(config.shutdown_countdown, config.shutdown_deadline)?;
+        Ok(config)
+    }
+}
+
+/// Fails unless the rooms still get closed once the countdown is over, with time to spare for
+/// the connections to finish before the deadline.
+fn check_shutdown(countdown: Duration, deadline: Duration) -> Result<()> {
+    if countdown >= deadline {
+        bail!("SHUTDOWN_DEADLINE_SECS has to be longer than SHUTDOWN_COUNTDOWN_SECS");
+    }
+    Ok(())
+}
+
+/// Fails on heartbeat settings that would ping in a busy loop or close healthy connections.
+fn check_heartbeat(heartbeat: &HeartbeatConfig) -> Result<()> {
+    if heartbeat.ping_interval.is_zero() || heartbeat.ping_interval > MAX_PING_INTERVAL {
+        bail!(
+            "PING_INTERVAL_SECS has to be between 1 and {}",
+            MAX_PING_INTERVAL.as_secs()
+        );
+    }
+    if heartbeat.missed_pongs == 0 {
+        bail!("MISSED_PONGS has to be at least 1");
+    }
+    // idle connections are only noticed when they're pinged
+    if heartbeat.idle_timeout < heartbeat.ping_interval {
+        bail!("IDLE_TIMEOUT_SECS can't be shorter than PING_INTERVAL_SECS");
+    }
+    Ok(())
+}
+
+fn seconds_or(name: &str, default: Duration) -> Result<Duration> {
+    var_or(name, default.as_secs()).map(Duration::from_secs)
+}
+
+fn var_or<T>(name: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|error| eyre!("{} is invalid: {}", name, error)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(ping_interval: u64, missed_pongs: u32, idle_timeout: u64) -> HeartbeatConfig {
+        HeartbeatConfig {
+            ping_interval: Duration::from_secs(ping_interval),
+            missed_pongs,
+            idle_timeout: Duration::from_secs(idle_timeout),
+        }
+    }
+
+    #[test]
+    fn heartbeats_that_would_spin_or_drop_everyone_are_rejected() {
+        assert!(check_heartbeat(&HeartbeatConfig::default()).is_ok());
+        assert!(check_heartbeat(&heartbeat(1, 1, 1)).is_ok());
+        for (config, error) in [
+            (
+                heartbeat(0, 2, 60),
+                "PING_INTERVAL_SECS has to be between 1 and 300",
+            ),
+            (
+                heartbeat(301, 2, 600),
+                "PING_INTERVAL_SECS has to be between 1 and 300",
+            ),
+            (heartbeat(15, 0, 60), "MISSED_PONGS has to be at least 1"),
+            (
+                heartbeat(15, 2, 10),
+                "IDLE_TIMEOUT_SECS can't be shorter than PING_INTERVAL_SECS",
+            ),
+        ] {
+            assert_eq!(check_heartbeat(&config).unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn the_shutdown_deadline_has_to_outlast_the_countdown() {
+        let seconds = Duration::from_secs;
+        let defaults = ServerConfig::default();
+        assert!(check_shutdown(defaults.shutdown_countdown, defaults.shutdown_deadline).is_ok());
+        assert!(check_shutdown(seconds(0), seconds(1)).is_ok());
+        for (countdown, deadline) in [(30, 10), (10, 10), (0, 0)] {
+            assert_eq!(
+                check_shutdown(seconds(countdown), seconds(deadline))
+                    .unwrap_err()
+                    .to_string(),
+                "SHUTDOWN_DEADLINE_SECS has to be longer than SHUTDOWN_COUNTDOWN_SECS"
+            );
+        }
+    }
+}
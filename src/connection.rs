@@ -0,0 +1,40 @@
+use crate::{actions::ServerAction, codec::Encoding, message::ServerMessage, outbox::Outbox};
+use eyre::Result;
+
+/// One websocket, and what has been agreed on over it so far.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub sender: Outbox,
+    /// Set by the `Hello` handshake, nothing else is handled until then.
+    pub protocol_version: Option<u32>,
+    /// How binary frames from the client are read, also set by the handshake.
+    pub encoding: Encoding,
+    /// Set for the connection bots' moves come in on, which may move for any bot.
+    pub moves_bots: bool,
+}
+
+impl Connection {
+    pub fn new(sender: Outbox) -> Self {
+        Self {
+            sender,
+            protocol_version: None,
+            encoding: Encoding::default(),
+            moves_bots: false,
+        }
+    }
+
+    /// A connection for the server's own players, which speak the current protocol without
+    /// having to say hello.
+    pub fn internal(sender: Outbox) -> Self {
+        Self {
+            sender,
+            protocol_version: Some(crate::message::PROTOCOL_VERSION),
+            encoding: Encoding::default(),
+            moves_bots: true,
+        }
+    }
+
+    pub fn reply(&self, request_id: Option<String>, action: ServerAction) -> Result<()> {
+        self.sender.send(ServerMessage::reply(request_id, action))
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+use crate::{actions::ServerAction, message::ServerMessage};
+
+/// The latest events sent to everyone in a room, numbered so that a client who missed some can
+/// catch up on them.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    last_seq: u64,
+    /// Each event with the player it wasn't sent to, if any.
+    events: VecDeque<(ServerMessage, Option<String>)>,
+}
+
+impl EventLog {
+    /// How many events are kept, a client further behind than this gets a snapshot instead.
+    pub const CAPACITY: usize = 256;
+
+    /// Numbers the event and keeps it for replaying to everyone but `except`.
+    pub fn record(&mut self, action: ServerAction, except: Option<&str>) -> ServerMessage {
+        self.last_seq += 1;
+        let message = ServerMessage {
+            seq: Some(self.last_seq),
+            ..ServerMessage::from(action)
+        };
+        if self.events.len() == Self::CAPACITY {
+            self.events.pop_front();
+        }
+        self.events
+            .push_back((message.clone(), except.map(str::to_owned)));
+        message
+    }
+
+    /// A log for a restored room, carrying on from the last event sent before it was saved.
+    pub fn starting_at(last_seq: u64) -> Self {
+        Self {
+            last_seq,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// The events after `seq` that were sent to the player, or `None` when some of them aren't
+    /// kept any more.
+    pub fn since(&self, seq: u64, player_id: &str) -> Option<Vec<ServerMessage>> {
+        let oldest_kept = self.last_seq + 1 - self.events.len() as u64;
+        if seq > self.last_seq || seq + 1 < oldest_kept {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .filter(|(message, _)| message.seq.is_some_and(|event_seq| event_seq > seq))
+                .filter(|(_, except)| except.as_deref() != Some(player_id))
+                .map(|(message, _)| message.clone())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(message: &str) -> ServerAction {
+        ServerAction::Chat {
+            room_id: 1234,
+            player_name: "Ada".to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    fn seqs(messages: Option<Vec<ServerMessage>>) -> Option<Vec<u64>> {
+        messages.map(|messages| messages.iter().filter_map(|message| message.seq).collect())
+    }
+
+    #[test]
+    fn events_are_numbered_from_one() {
+        let mut events = EventLog::default();
+        assert_eq!(events.last_seq(), 0);
+        assert_eq!(events.record(chat("hi"), None).seq, Some(1));
+        assert_eq!(events.record(chat("again"), None).seq, Some(2));
+        assert_eq!(events.last_seq(), 2);
+    }
+
+    #[test]
+    fn replays_only_what_the_player_was_sent() {
+        let mut events = EventLog::default();
+        events.record(chat("one"), None);
+        events.record(chat("two"), Some("ada"));
+        events.record(chat("three"), None);
+        assert_eq!(seqs(events.since(0, "ada")), Some(vec![1, 3]));
+        assert_eq!(seqs(events.since(1, "bea")), Some(vec![2, 3]));
+        assert_eq!(seqs(events.since(3, "bea")), Some(vec![]));
+    }
+
+    #[test]
+    fn gaps_older_than_the_buffer_need_a_snapshot() {
+        let mut events = EventLog::default();
+        for _ in 0..EventLog::CAPACITY + 10 {
+            events.record(chat("spam"), None);
+        }
+        assert_eq!(events.since(9, "ada"), None);
+        assert_eq!(
+            events.since(10, "ada").map(|messages| messages.len()),
+            Some(EventLog::CAPACITY)
+        );
+        // a seq from the future means the client is confused about which room this is
+        assert_eq!(events.since(events.last_seq() + 1, "ada"), None);
+    }
+}
@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+
+use crate::card::{Card, Suite, Value};
+
+const WHEEL: u16 = 0b1_0000_0000_1111;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum HandCategory {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+}
+
+impl HandCategory {
+    fn from_index(index: u32) -> Self {
+        match index {
+            0 => Self::HighCard,
+            1 => Self::OnePair,
+            2 => Self::TwoPair,
+            3 => Self::ThreeOfAKind,
+            4 => Self::Straight,
+            5 => Self::Flush,
+            6 => Self::FullHouse,
+            7 => Self::FourOfAKind,
+            _ => Self::StraightFlush,
+        }
+    }
+}
+
+/// The strength of the best five card poker hand in a set of cards. Better hands compare
+/// greater, and hands that compare equal split the pot.
+///
+/// The category sits above the ranks that break ties within it, so a whole rank fits in a
+/// `u32` and comparing two hands is a single integer comparison.
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Clone, Copy, Default,
+)]
+pub struct HandRank(u32);
+
+impl HandRank {
+    fn new(category: HandCategory, ranks: &[u8]) -> Self {
+        let mut packed = (category as u32) << 20;
+        for (index, rank) in ranks.iter().take(5).enumerate() {
+            packed |= (*rank as u32) << (16 - 4 * index);
+        }
+        Self(packed)
+    }
+
+    pub fn category(&self) -> HandCategory {
+        HandCategory::from_index(self.0 >> 20)
+    }
+
+    fn rank(&self, index: usize) -> u8 {
+        ((self.0 >> (16 - 4 * index)) & 0xf) as u8
+    }
+
+    pub fn describe(&self) -> String {
+        let first = self.rank(0);
+        let second = self.rank(1);
+        match self.category() {
+            HandCategory::HighCard => format!("{} high", rank_name(first)),
+            HandCategory::OnePair => format!("Pair of {}", plural_rank_name(first)),
+            HandCategory::TwoPair => format!(
+                "Two pair, {} and {}",
+                plural_rank_name(first),
+                plural_rank_name(second)
+            ),
+            HandCategory::ThreeOfAKind => format!("Three {}", plural_rank_name(first)),
+            HandCategory::Straight => format!("Straight, {} high", rank_name(first)),
+            HandCategory::Flush => format!("Flush, {} high", rank_name(first)),
+            HandCategory::FullHouse => format!(
+                "Full house, {} full of {}",
+                plural_rank_name(first),
+                plural_rank_name(second)
+            ),
+            HandCategory::FourOfAKind => format!("Four {}", plural_rank_name(first)),
+            HandCategory::StraightFlush if first == 12 => "Royal flush".to_owned(),
+            HandCategory::StraightFlush => {
+                format!("Straight flush, {} high", rank_name(first))
+            }
+        }
+    }
+}
+
+/// Ranks the best five card hand out of 5, 6 or 7 cards. Any other number of cards can't be
+/// ranked and gives `None`.
+///
+/// Nothing is allocated and no combinations are tried, the cards are folded into one bit mask
+/// of ranks per suit and the hand is read straight off those, which keeps this quick enough to
+/// run through millions of hands a second for equity calculations.
+pub fn evaluate(cards: &[Card]) -> Option<HandRank> {
+    if cards.len() < 5 || cards.len() > 7 {
+        return None;
+    }
+
+    let mut suit_masks = [0u16; 4];
+    let mut counts = [0u8; 13];
+    for card in cards {
+        let rank = rank_index(card.value);
+        suit_masks[suite_index(card.suite)] |= 1 << rank;
+        counts[rank as usize] += 1;
+    }
+
+    // seven cards can't hold a flush and a full house or four of a kind at the same time, so a
+    // flush is always the best hand unless it is also a straight
+    for suit_mask in suit_masks.iter() {
+        if suit_mask.count_ones() >= 5 {
+            if let Some(high) = straight_high(*suit_mask) {
+                return Some(HandRank::new(HandCategory::StraightFlush, &[high]));
+            }
+            return Some(HandRank::new(
+                HandCategory::Flush,
+                &highest_ranks(*suit_mask, 5),
+            ));
+        }
+    }
+
+    let mut quads = None;
+    let mut trips = [0u8; 2];
+    let mut trip_count = 0;
+    let mut pairs = [0u8; 3];
+    let mut pair_count = 0;
+    for rank in (0..13u8).rev() {
+        match counts[rank as usize] {
+            4 => quads = Some(rank),
+            3 => {
+                trips[trip_count] = rank;
+                trip_count += 1;
+            }
+            2 => {
+                pairs[pair_count] = rank;
+                pair_count += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let rank_mask = suit_masks
+        .iter()
+        .fold(0, |mask, suit_mask| mask | suit_mask);
+    let kickers = |excluded: u16, count: usize| highest_ranks(rank_mask & !excluded, count);
+
+    if let Some(quads) = quads {
+        let kicker = kickers(1 << quads, 1);
+        return Some(HandRank::new(
+            HandCategory::FourOfAKind,
+            &[quads, kicker[0]],
+        ));
+    }
+    if trip_count > 0 && (trip_count > 1 || pair_count > 0) {
+        // a second set of three is as good as a pair when filling up a full house
+        let pair = if trip_count > 1 && (pair_count == 0 || trips[1] > pairs[0]) {
+            trips[1]
+        } else {
+            pairs[0]
+        };
+        return Some(HandRank::new(HandCategory::FullHouse, &[trips[0], pair]));
+    }
+    if let Some(high) = straight_high(rank_mask) {
+        return Some(HandRank::new(HandCategory::Straight, &[high]));
+    }
+    if trip_count > 0 {
+        let kickers = kickers(1 << trips[0], 2);
+        return Some(HandRank::new(
+            HandCategory::ThreeOfAKind,
+            &[trips[0], kickers[0], kickers[1]],
+        ));
+    }
+    if pair_count > 1 {
+        let kicker = kickers((1 << pairs[0]) | (1 << pairs[1]), 1);
+        return Some(HandRank::new(
+            HandCategory::TwoPair,
+            &[pairs[0], pairs[1], kicker[0]],
+        ));
+    }
+    if pair_count > 0 {
+        let kickers = kickers(1 << pairs[0], 3);
+        return Some(HandRank::new(
+            HandCategory::OnePair,
+            &[pairs[0], kickers[0], kickers[1], kickers[2]],
+        ));
+    }
+    Some(HandRank::new(
+        HandCategory::HighCard,
+        &highest_ranks(rank_mask, 5),
+    ))
+}
+
+/// Two is the lowest rank at 0 and ace the highest at 12.
+fn rank_index(value: Value) -> u8 {
+    match value {
+        Value::Two => 0,
+        Value::Three => 1,
+        Value::Four => 2,
+        Value::Five => 3,
+        Value::Six => 4,
+        Value::Seven => 5,
+        Value::Eight => 6,
+        Value::Nine => 7,
+        Value::Ten => 8,
+        Value::Jack => 9,
+        Value::Queen => 10,
+        Value::King => 11,
+        Value::Ace => 12,
+    }
+}
+
+fn suite_index(suite: Suite) -> usize {
+    match suite {
+        Suite::Club => 0,
+        Suite::Heart => 1,
+        Suite::Diamond => 2,
+        Suite::Spade => 3,
+    }
+}
+
+/// The top rank of the best straight in a mask of ranks, counting the ace as low for the wheel.
+fn straight_high(mask: u16) -> Option<u8> {
+    let runs = mask & (mask >> 1) & (mask >> 2) & (mask >> 3) & (mask >> 4);
+    if runs != 0 {
+        Some(15 - runs.leading_zeros() as u8 + 4)
+    } else if mask & WHEEL == WHEEL {
+        Some(rank_index(Value::Five))
+    } else {
+        None
+    }
+}
+
+fn highest_ranks(mut mask: u16, count: usize) -> [u8; 5] {
+    let mut ranks = [0; 5];
+    for rank in ranks.iter_mut().take(count) {
+        if mask == 0 {
+            break;
+        }
+        let highest = 15 - mask.leading_zeros() as u8;
+        *rank = highest;
+        mask &= !(1 << highest);
+    }
+    ranks
+}
+
+fn rank_name(rank: u8) -> &'static str {
+    match rank {
+        0 => "Two",
+        1 => "Three",
+        2 => "Four",
+        3 => "Five",
+        4 => "Six",
+        5 => "Seven",
+        6 => "Eight",
+        7 => "Nine",
+        8 => "Ten",
+        9 => "Jack",
+        10 => "Queen",
+        11 => "King",
+        _ => "Ace",
+    }
+}
+
+fn plural_rank_name(rank: u8) -> String {
+    match rank {
+        4 => "Sixes".to_owned(),
+        _ => format!("{}s", rank_name(rank)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::card::{cards, DeckSpec};
+
+    fn rank(text: &str) -> HandRank {
+        evaluate(&cards(text)).unwrap()
+    }
+
+    #[test]
+    fn hands_are_named_after_their_best_five_cards() {
+        let table = [
+            ("AS KD 9C 7H 3S", "Ace high"),
+            ("4S 4D 9C 7H 3S", "Pair of Fours"),
+            ("6S 6D 9C 9H 3S", "Two pair, Nines and Sixes"),
+            ("QS QD QC 7H 3S", "Three Queens"),
+            ("AS 2D 3C 4H 5S", "Straight, Five high"),
+            ("TS JD QC KH AS", "Straight, Ace high"),
+            ("2H 9H 4H JH 7H", "Flush, Jack high"),
+            ("8S 8D 8C 3H 3S", "Full house, Eights full of Threes"),
+            ("KS KD KC KH 3S", "Four Kings"),
+            ("5C 6C 7C 8C 9C", "Straight flush, Nine high"),
+            ("AD 2D 3D 4D 5D", "Straight flush, Five high"),
+            ("TH JH QH KH AH", "Royal flush"),
+            // six and seven cards only count the best five
+            ("2S 3D 4C 5H 6S 7D", "Straight, Seven high"),
+            ("AS AD AC 2H 2S 2D KC", "Full house, Aces full of Twos"),
+            ("3H 3D 3C 3S 2H 2S 2D", "Four Threes"),
+            ("2C 5C 9C JC KC KS KD", "Flush, King high"),
+            ("9H 9D 5C 5S 2H 2D AC", "Two pair, Nines and Fives"),
+            ("2H 3H 4H 5H 6H 7H KS", "Straight flush, Seven high"),
+            ("AS 2D 3C 4H 5S 6D", "Straight, Six high"),
+        ];
+        for (hand, expected) in table.iter() {
+            assert_eq!(rank(hand).describe(), *expected, "{}", hand);
+        }
+    }
+
+    #[test]
+    fn better_hands_compare_greater() {
+        // each hand beats the one before it
+        let table = [
+            "KS QD 9C 7H 3S",
+            "AS KD 9C 7H 3S",
+            "AS KD TC 7H 3S",
+            "AS KD TC 7H 4S",
+            "2S 2D 9C 7H 3S",
+            "2S 2D TC 7H 3S",
+            "2S 2D TC 8H 3S",
+            "3S 3D 4C 5H 6S",
+            "3S 3D 2C 2H 6S",
+            "3S 3D 2C 2H 7S",
+            "4S 4D 2C 2H 3S",
+            "4S 4D 4C 2H 3S",
+            "4S 4D 4C 5H 3S",
+            "AS 2D 3C 4H 5S",
+            "2D 3C 4H 5S 6S",
+            "TS JD QC KH AS",
+            "2H 3H 4H 5H 7H",
+            "2H 3H 4H 6H 7H",
+            "AC 3C 4C 6C 7C",
+            "2S 2D 2C 3H 3S",
+            "3S 3D 3C 2H 2S",
+            "2S 2D 2C 2H 3S",
+            "2S 2D 2C 2H AS",
+            "3S 3D 3C 3H 2S",
+            "AD 2D 3D 4D 5D",
+            "2D 3D 4D 5D 6D",
+            "TD JD QD KD AD",
+        ];
+        for pair in table.windows(2) {
+            assert!(
+                rank(pair[1]) > rank(pair[0]),
+                "{} beats {}",
+                pair[1],
+                pair[0]
+            );
+        }
+    }
+
+    #[test]
+    fn hands_that_play_the_same_five_cards_tie() {
+        let table = [
+            ("AS KD 9C 7H 3S", "AH KC 9D 7S 3D"),
+            // the board plays, so neither player's sixth and seventh cards count
+            ("TS JD QC KH AS 2C 3D", "TS JD QC KH AS 4C 5D"),
+            ("9S 9D 5C 5H KS 2C 3D", "9S 9D 5C 5H KS 4C 4D"),
+            ("3H 9H 4H JH 7H 2H", "3H 9H 4H JH 7H 2C"),
+        ];
+        for (first, second) in table.iter() {
+            assert_eq!(rank(first), rank(second), "{} ties {}", first, second);
+        }
+    }
+
+    #[test]
+    fn only_five_to_seven_cards_can_be_ranked() {
+        assert_eq!(evaluate(&cards("AS KS QS JS")), None);
+        assert_eq!(evaluate(&cards("AS KS QS JS TS 9S 8S 7S")), None);
+    }
+
+    /// Only means something in an optimised build, run it with
+    /// `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn millions_of_seven_card_hands_are_ranked_a_second() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut deck = DeckSpec::default().build();
+        let hands: Vec<Vec<Card>> = (0..100_000)
+            .map(|_| {
+                deck.shuffle(&mut rng);
+                deck[..7].to_vec()
+            })
+            .collect();
+
+        let rounds = 20;
+        let started = Instant::now();
+        let mut best = None;
+        for _ in 0..rounds {
+            for hand in &hands {
+                best = best.max(evaluate(hand));
+            }
+        }
+        let per_second = (rounds * hands.len()) as f64 / started.elapsed().as_secs_f64();
+        assert!(best.is_some());
+        assert!(
+            per_second > 2_000_000.0,
+            "only {:.0} hands a second",
+            per_second
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    /// How often the server pings each connection.
+    pub ping_interval: Duration,
+    /// How many pings in a row can go unanswered before the connection is closed.
+    pub missed_pongs: u32,
+    /// How long a client can go without sending a message before it's closed, pongs don't
+    /// count.
+    pub idle_timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            missed_pongs: 2,
+            idle_timeout: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// Keeps track of whether the other end of a connection is still there.
+#[derive(Debug)]
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    unanswered: AtomicU32,
+    last_message: Mutex<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            unanswered: AtomicU32::new(0),
+            last_message: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        self.config.ping_interval
+    }
+
+    /// Any frame at all shows the connection is still open.
+    pub fn frame_received(&self) {
+        self.unanswered.store(0, Ordering::Relaxed);
+    }
+
+    pub fn message_received(&self) {
+        self.frame_received();
+        *self.last_message.lock().unwrap() = Instant::now();
+    }
+
+    /// Called before each ping, with why the connection should be closed instead.
+    pub fn check(&self) -> Option<&'static str> {
+        if self.last_message.lock().unwrap().elapsed() >= self.config.idle_timeout {
+            return Some("idle for too long");
+        }
+        if self.unanswered.fetch_add(1, Ordering::Relaxed) >= self.config.missed_pongs {
+            return Some("stopped answering pings");
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn config() -> HeartbeatConfig {
+        HeartbeatConfig {
+            ping_interval: Duration::from_millis(10),
+            missed_pongs: 2,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn closes_after_too_many_missed_pongs() {
+        let heartbeat = Heartbeat::new(config());
+        assert_eq!(heartbeat.check(), None);
+        assert_eq!(heartbeat.check(), None);
+        assert_eq!(heartbeat.check(), Some("stopped answering pings"));
+    }
+
+    #[test]
+    fn any_frame_answers_the_pings() {
+        let heartbeat = Heartbeat::new(config());
+        for _ in 0..5 {
+            assert_eq!(heartbeat.check(), None);
+            assert_eq!(heartbeat.check(), None);
+            heartbeat.frame_received();
+        }
+    }
+
+    #[test]
+    fn closes_a_client_that_only_answers_pings() {
+        let heartbeat = Heartbeat::new(HeartbeatConfig {
+            idle_timeout: Duration::from_millis(20),
+            ..config()
+        });
+        heartbeat.frame_received();
+        assert_eq!(heartbeat.check(), None);
+        thread::sleep(Duration::from_millis(30));
+        heartbeat.frame_received();
+        assert_eq!(heartbeat.check(), Some("idle for too long"));
+        heartbeat.message_received();
+        assert_eq!(heartbeat.check(), None);
+    }
+}
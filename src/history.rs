@@ -0,0 +1,606 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bot::BotKind,
+    card::Card,
+    rules::{GameMove, PlayerScore},
+    table::Table,
+    team::Team,
+};
+
+/// Something that changed the room. Together with the seed the table shuffles from, the events
+/// are enough to build the room again from nothing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "type")]
+pub enum RoomEvent {
+    Joined {
+        player_name: String,
+        bot: Option<BotKind>,
+    },
+    Left,
+    Moved {
+        game_move: GameMove,
+    },
+    ScoresRecorded {
+        scores: Vec<PlayerScore>,
+    },
+    Bet {
+        amount: u64,
+    },
+    PotCollected,
+    ChipsTransferred {
+        to_player_id: String,
+        amount: u64,
+    },
+    TeamsDefined {
+        team_names: Vec<String>,
+    },
+    TeamAssigned {
+        to_player_id: String,
+        team: Option<String>,
+    },
+    /// The card is left out of the public history when it's face down.
+    PlayedToTeamPile {
+        card: Option<Card>,
+    },
+    TookFromTeamPile {
+        card: Option<Card>,
+    },
+    /// Took back entry `number`, leaving the room as if it never happened.
+    Undone {
+        number: u64,
+    },
+    /// Stands in for a discard in the public history when the card went down face down. It's
+    /// never recorded itself.
+    DiscardedFaceDown,
+}
+
+impl RoomEvent {
+    /// The event without the cards only its player knows about, `cards` being the cards it
+    /// put somewhere new.
+    pub fn public(&self, cards: &[PlacedCard]) -> Self {
+        let face_up = |card: &Option<Card>| card.filter(Card::is_visible);
+        match self {
+            Self::Moved {
+                game_move: GameMove::PassCards { .. },
+            } => Self::Moved {
+                game_move: GameMove::PassCards { cards: vec![] },
+            },
+            Self::Moved {
+                game_move: GameMove::DiscardCard { card },
+            } if !cards.iter().any(|placed| {
+                placed.is_public()
+                    && placed.card.suite == card.suite
+                    && placed.card.value == card.value
+            }) =>
+            {
+                Self::DiscardedFaceDown
+            }
+            Self::PlayedToTeamPile { card } => Self::PlayedToTeamPile {
+                card: face_up(card),
+            },
+            Self::TookFromTeamPile { card } => Self::TookFromTeamPile {
+                card: face_up(card),
+            },
+            event => event.clone(),
+        }
+    }
+}
+
+/// Where a card on the table is.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "zone")]
+pub enum Place {
+    Hand { player_id: String },
+    DiscardPile,
+    TeamPile { team: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PlacedCard {
+    pub card: Card,
+    pub place: Place,
+}
+
+impl PlacedCard {
+    /// Cards stay private until they're turned face up, wherever they are.
+    fn is_public(&self) -> bool {
+        self.card.is_visible()
+    }
+}
+
+/// Every card that isn't in the draw deck, and where it is.
+pub fn layout(table: &Table, teams: &[Team]) -> Vec<PlacedCard> {
+    let mut cards = vec![];
+    for player in &table.players {
+        cards.extend(player.hand.iter().map(|card| PlacedCard {
+            card: *card,
+            place: Place::Hand {
+                player_id: player.id.clone(),
+            },
+        }));
+    }
+    cards.extend(table.discard_deck.iter().map(|card| PlacedCard {
+        card: *card,
+        place: Place::DiscardPile,
+    }));
+    for team in teams {
+        cards.extend(team.pile.iter().map(|card| PlacedCard {
+            card: *card,
+            place: Place::TeamPile {
+                team: team.name.clone(),
+            },
+        }));
+    }
+    cards
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HandSize {
+    pub player_id: String,
+    pub cards: usize,
+}
+
+/// How many cards are in each zone once an event has happened.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ZoneSizes {
+    pub draw_deck: usize,
+    pub discard_pile: usize,
+    pub hands: Vec<HandSize>,
+}
+
+impl ZoneSizes {
+    fn of(table: &Table) -> Self {
+        Self {
+            draw_deck: table.draw_deck.len(),
+            discard_pile: table.discard_deck.len(),
+            hands: table
+                .players
+                .iter()
+                .map(|player| HandSize {
+                    player_id: player.id.clone(),
+                    cards: player.hand.len(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HistoryEntry {
+    /// Counts up from 1 for each room.
+    pub number: u64,
+    /// Milliseconds since the Unix epoch.
+    pub at: u64,
+    pub player_id: String,
+    pub event: RoomEvent,
+    /// The cards the event put somewhere new, and where.
+    pub cards: Vec<PlacedCard>,
+    pub zones: ZoneSizes,
+}
+
+/// Everything that happened in a room, in order. Entries are only ever added.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct History {
+    /// What the table's shuffles are seeded from, so replaying the events deals the same cards.
+    pub seed: u64,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            entries: vec![],
+        }
+    }
+
+    /// Adds an event, `before` being where the cards were before it happened.
+    pub fn record(
+        &mut self,
+        player_id: &str,
+        event: RoomEvent,
+        before: &[PlacedCard],
+        table: &Table,
+        teams: &[Team],
+    ) {
+        let mut unmoved = before.to_vec();
+        let mut cards = vec![];
+        for placed_card in layout(table, teams) {
+            match unmoved.iter().position(|card| *card == placed_card) {
+                Some(index) => {
+                    unmoved.swap_remove(index);
+                }
+                None => cards.push(placed_card),
+            }
+        }
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+        self.entries.push(HistoryEntry {
+            number: self.entries.len() as u64 + 1,
+            at,
+            player_id: player_id.to_owned(),
+            event,
+            cards,
+            zones: ZoneSizes::of(table),
+        });
+    }
+
+    #[cfg(test)]
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The entries still in effect, leaving out the ones that were undone and the undos
+    /// themselves.
+    pub fn effective(&self) -> Vec<&HistoryEntry> {
+        let mut effective: Vec<&HistoryEntry> = vec![];
+        for entry in &self.entries {
+            match entry.event {
+                RoomEvent::Undone { number } => effective.retain(|kept| kept.number != number),
+                _ => effective.push(entry),
+            }
+        }
+        effective
+    }
+
+    /// The most recent change that's still in effect.
+    pub fn last_change(&self) -> Option<&HistoryEntry> {
+        self.effective().pop()
+    }
+
+    /// The history as anyone in the room may see it.
+    pub fn public(&self) -> Vec<HistoryEntry> {
+        self.entries
+            .iter()
+            .map(|entry| HistoryEntry {
+                event: entry.event.public(&entry.cards),
+                cards: entry
+                    .cards
+                    .iter()
+                    .filter(|card| card.is_public())
+                    .cloned()
+                    .collect(),
+                ..entry.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use std::sync::Arc;
+
+    use async_std::task;
+    use futures::{channel::mpsc::unbounded, StreamExt};
+
+    use super::*;
+    use crate::{
+        actions::{CreateGame, ServerAction},
+        outbox::{detached, outbox, OutboxConfig},
+        player::Player,
+        room::Room,
+        room_settings::{GameType, RoomSettings, UndoPolicy},
+        rules::Solvability,
+    };
+
+    fn settings(game_type: GameType) -> RoomSettings {
+        RoomSettings::from_create_game(&CreateGame {
+            player_name: "Ada".to_owned(),
+            game_type: Some(game_type),
+            starting_chips: Some(100),
+            ..CreateGame::default()
+        })
+    }
+
+    /// The saved room, less what a replay isn't meant to reproduce: the room's id, its
+    /// secrets and the messages that were sent.
+    fn state(room: &Room) -> Value {
+        let mut state = serde_json::to_value(room.save().unwrap()).unwrap();
+        for key in ["id", "password", "invite_tokens", "last_seq", "history"] {
+            state.as_object_mut().unwrap().remove(key);
+        }
+        for player in state["players"].as_array_mut().unwrap() {
+            player.as_object_mut().unwrap().remove("session_token");
+        }
+        state
+    }
+
+    fn hand(room: &Room, player: usize) -> Vec<Card> {
+        serde_json::from_value(state(room)["players"][player]["hand"].clone()).unwrap()
+    }
+
+    fn player_ids(room: &Room) -> Vec<String> {
+        state(room)["players"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|player| player["id"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    fn history(room: &Room) -> History {
+        serde_json::from_value(serde_json::to_value(room.save().unwrap().history).unwrap()).unwrap()
+    }
+
+    fn free_play_room() -> Room {
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, None, settings(GameType::FreePlay), None).unwrap();
+        room.join(Player::new("Bea", detached())).unwrap();
+        let ids = player_ids(&room);
+        let (ada, bea) = (&ids[0], &ids[1]);
+        for _ in 0..3 {
+            room.handle_move(ada, GameMove::DrawCard).unwrap();
+            room.handle_move(bea, GameMove::DrawCard).unwrap();
+        }
+        let card = hand(&room, 0)[0];
+        room.handle_move(ada, GameMove::DiscardCard { card })
+            .unwrap();
+        let card = hand(&room, 1)[2];
+        room.handle_move(bea, GameMove::ToggleVisibilityOfCard { card })
+            .unwrap();
+        room.bet(bea, 30).unwrap();
+        room.define_teams(ada, &["Red".to_owned()]).unwrap();
+        room.assign_team(ada, ada, Some("Red")).unwrap();
+        let card = hand(&room, 0)[0];
+        room.play_to_team_pile(ada, &card).unwrap();
+        room
+    }
+
+    #[test]
+    fn replaying_the_history_rebuilds_the_room() {
+        let mut room = free_play_room();
+        let bea = player_ids(&room)[1].clone();
+        room.remove_player_by_id(&bea).unwrap();
+        room.handle_move(&player_ids(&room)[0], GameMove::DrawCard)
+            .unwrap();
+
+        let replayed = Room::replay(&history(&room), settings(GameType::FreePlay)).unwrap();
+        assert_eq!(state(&replayed), state(&room));
+    }
+
+    #[test]
+    fn replaying_a_game_deals_the_same_cards() {
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, None, settings(GameType::Hearts), None).unwrap();
+        for name in ["Bea", "Cy", "Di"] {
+            room.join(Player::new(name, detached())).unwrap();
+        }
+        let ids = player_ids(&room);
+        room.handle_move(&ids[0], GameMove::StartRound).unwrap();
+        for (player, player_id) in ids.iter().enumerate() {
+            let cards = hand(&room, player)[..3].to_vec();
+            room.handle_move(player_id, GameMove::PassCards { cards })
+                .unwrap();
+        }
+
+        let replayed = Room::replay(&history(&room), settings(GameType::Hearts)).unwrap();
+        assert_eq!(state(&replayed), state(&room));
+    }
+
+    #[test]
+    fn the_public_history_only_shows_cards_everyone_has_seen() {
+        let room = free_play_room();
+        let history = history(&room);
+        let public = history.public();
+        assert_eq!(public.len(), history.entries().len());
+
+        let draw = &public[2];
+        assert_eq!(
+            draw.event,
+            RoomEvent::Moved {
+                game_move: GameMove::DrawCard
+            }
+        );
+        assert!(draw.cards.is_empty());
+        assert_eq!(history.entries()[2].cards.len(), 1);
+
+        let discard = &public[8];
+        assert!(matches!(
+            discard.event,
+            RoomEvent::Moved {
+                game_move: GameMove::DiscardCard { .. }
+            }
+        ));
+        assert_eq!(discard.cards.len(), 1);
+        assert_eq!(discard.cards[0].place, Place::DiscardPile);
+        assert_eq!(discard.zones.discard_pile, 1);
+        assert_eq!(discard.zones.hands[0].cards, 2);
+
+        let toggle = &public[9];
+        assert_eq!(toggle.cards.len(), 1);
+        assert!(toggle.cards[0].card.is_visible());
+
+        let team_pile = public.last().unwrap();
+        assert_eq!(team_pile.event, RoomEvent::PlayedToTeamPile { card: None });
+        assert!(team_pile.cards.is_empty());
+    }
+
+    /// Tries the move for each player until one of them is allowed to make it.
+    fn move_for_whoever_can(room: &mut Room, game_move: impl Fn(&[Card]) -> GameMove) {
+        let before = history(room).entries().len();
+        for (player, player_id) in player_ids(room).iter().enumerate() {
+            let game_move = game_move(&hand(room, player));
+            room.handle_move(player_id, game_move).unwrap();
+            if history(room).entries().len() > before {
+                return;
+            }
+        }
+        panic!("nobody could make that move");
+    }
+
+    #[test]
+    fn cards_discarded_face_down_stay_hidden() {
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, None, settings(GameType::Euchre), None).unwrap();
+        for name in ["Bea", "Cy", "Di"] {
+            room.join(Player::new(name, detached())).unwrap();
+        }
+        room.handle_move(&player_ids(&room)[0], GameMove::StartRound)
+            .unwrap();
+        move_for_whoever_can(&mut room, |_| GameMove::OrderUp { alone: false });
+        move_for_whoever_can(&mut room, |hand| GameMove::DiscardCard { card: hand[0] });
+
+        let discard = history(&room).public().pop().unwrap();
+        assert_eq!(discard.event, RoomEvent::DiscardedFaceDown);
+        assert!(discard.cards.is_empty());
+        assert_eq!(discard.zones.discard_pile, 1);
+
+        let (sender, mut receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        room.join(Player::new("Eve", sender)).unwrap();
+        let discard_pile = std::iter::from_fn(|| receiver.try_recv().ok())
+            .find_map(|message| match message.action {
+                ServerAction::JoinRoom { discard_pile, .. } => Some(discard_pile),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(discard_pile.len(), 1);
+        assert_eq!(discard_pile[0].suite, None);
+    }
+
+    #[test]
+    fn burned_cards_stay_hidden() {
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, None, settings(GameType::Holdem), None).unwrap();
+        room.join(Player::new("Bea", detached())).unwrap();
+        room.handle_move(&player_ids(&room)[0], GameMove::StartRound)
+            .unwrap();
+        move_for_whoever_can(&mut room, |_| GameMove::Call);
+        move_for_whoever_can(&mut room, |_| GameMove::Check);
+
+        let flop = history(&room).public().pop().unwrap();
+        assert_eq!(flop.zones.discard_pile, 1);
+        assert!(flop
+            .cards
+            .iter()
+            .all(|placed| placed.place != Place::DiscardPile));
+    }
+
+    #[test]
+    fn checking_a_deal_stays_out_of_the_history() {
+        let host = Player::new("Ada", detached());
+        // a deal the solver is known to answer, rather than give up on
+        let settings = RoomSettings {
+            seed: Some(1),
+            ..settings(GameType::FreeCell)
+        };
+        let mut room = Room::new(1234, host, None, settings, None).unwrap();
+        let ada = player_ids(&room)[0].clone();
+        room.handle_move(&ada, GameMove::StartRound).unwrap();
+        let before = history(&room).entries().len();
+
+        let (solver_results, mut answers) = unbounded();
+        room.check_solvable(&ada, solver_results).unwrap();
+        let solved = task::block_on(answers.next()).unwrap();
+        assert!(matches!(solved.solvability, Solvability::Winnable { .. }));
+        room.solved(&solved.position, solved.solvability).unwrap();
+        assert_eq!(history(&room).entries().len(), before);
+    }
+
+    fn undo_settings(undo: UndoPolicy) -> RoomSettings {
+        RoomSettings {
+            undo,
+            ..settings(GameType::FreePlay)
+        }
+    }
+
+    fn undo_room(undo: UndoPolicy) -> (Room, String, String) {
+        let settings = undo_settings(undo);
+        let mut room =
+            Room::new(1234, Player::new("Ada", detached()), None, settings, None).unwrap();
+        room.join(Player::new("Bea", detached())).unwrap();
+        let ids = player_ids(&room);
+        for _ in 0..3 {
+            room.handle_move(&ids[1], GameMove::DrawCard).unwrap();
+        }
+        (room, ids[0].clone(), ids[1].clone())
+    }
+
+    #[test]
+    fn undoing_puts_the_room_back_the_way_it_was() {
+        let (mut room, _, bea) = undo_room(UndoPolicy::Anyone);
+        let before_discard = state(&room);
+        let card = hand(&room, 1)[1];
+        room.handle_move(&bea, GameMove::DiscardCard { card })
+            .unwrap();
+        room.undo(&bea).unwrap();
+        assert_eq!(state(&room), before_discard);
+
+        // the undo is in the history too, so replaying it ends up in the same place
+        let entries = history(&room).entries().to_vec();
+        assert_eq!(
+            entries.last().unwrap().event,
+            RoomEvent::Undone { number: 6 }
+        );
+        assert_eq!(entries.last().unwrap().cards[0].card, card);
+        room.undo(&bea).unwrap();
+        room.handle_move(&bea, GameMove::DrawCard).unwrap();
+        let replayed = Room::replay(&history(&room), undo_settings(UndoPolicy::Anyone)).unwrap();
+        assert_eq!(state(&replayed), state(&room));
+    }
+
+    #[test]
+    fn players_joining_cant_be_undone() {
+        let (mut room, ada, _) = undo_room(UndoPolicy::Anyone);
+        for _ in 0..3 {
+            room.undo(&ada).unwrap();
+        }
+        let before = history(&room).entries().len();
+        room.undo(&ada).unwrap();
+        assert_eq!(history(&room).entries().len(), before);
+        assert_eq!(hand(&room, 1).len(), 0);
+    }
+
+    #[test]
+    fn games_with_rules_cant_be_undone() {
+        let settings = RoomSettings {
+            undo: UndoPolicy::Anyone,
+            ..settings(GameType::Hearts)
+        };
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, None, settings, None).unwrap();
+        for name in ["Bea", "Cy", "Di"] {
+            room.join(Player::new(name, detached())).unwrap();
+        }
+        let ada = player_ids(&room)[0].clone();
+        room.handle_move(&ada, GameMove::StartRound).unwrap();
+        let before = state(&room);
+        room.undo(&ada).unwrap();
+        assert_eq!(state(&room), before);
+    }
+
+    #[test]
+    fn only_the_host_can_undo_when_the_room_says_so() {
+        let (mut room, ada, bea) = undo_room(UndoPolicy::HostOnly);
+        room.undo(&bea).unwrap();
+        assert_eq!(hand(&room, 1).len(), 3);
+        room.undo(&ada).unwrap();
+        assert_eq!(hand(&room, 1).len(), 2);
+    }
+
+    #[test]
+    fn undoing_by_vote_waits_for_everyone_else_to_agree() {
+        let (mut room, ada, bea) = undo_room(UndoPolicy::Vote);
+        room.join(Player::new("Cy", detached())).unwrap();
+        let cy = player_ids(&room)[2].clone();
+        room.handle_move(&cy, GameMove::DrawCard).unwrap();
+
+        room.undo(&ada).unwrap();
+        room.vote_undo(&bea, true).unwrap();
+        assert_eq!(hand(&room, 2).len(), 1);
+        room.vote_undo(&cy, true).unwrap();
+        assert_eq!(hand(&room, 2).len(), 0);
+
+        room.undo(&ada).unwrap();
+        room.vote_undo(&bea, false).unwrap();
+        room.vote_undo(&cy, true).unwrap();
+        assert_eq!(hand(&room, 1).len(), 3);
+    }
+}
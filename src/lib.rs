@@ -2,54 +2,322 @@ use async_std::{
     net::{TcpListener, TcpStream},
     task,
 };
+use async_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Error as WebsocketError, Message,
+};
 use eyre::Result;
-use futures::{channel::mpsc::unbounded, future, pin_mut, StreamExt, TryStreamExt};
+use futures::{
+    channel::{
+        mpsc::{unbounded, UnboundedReceiver},
+        oneshot,
+    },
+    future::{self, Either, FutureExt, Shared},
+    pin_mut, stream, StreamExt, TryStreamExt,
+};
 use main_state::WrappedMainState;
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use crate::main_state::MainState;
+use crate::{
+    actions::ServerAction,
+    codec::Encoding,
+    connection::Connection,
+    heartbeat::Heartbeat,
+    main_state::MainState,
+    message::{ClientMessage, ServerMessage},
+    outbox::{outbox, OutboxConfig, QueueMetrics},
+    room::Solved,
+    room_store::JsonFileStore,
+};
 
 mod actions;
+mod bot;
 mod card;
+mod chips;
+mod codec;
+mod config;
+mod connection;
+mod event_log;
+mod hand_evaluator;
+mod heartbeat;
+mod history;
+mod lobby;
 mod main_state;
+mod matchmaking;
 mod message;
+mod outbox;
+mod password;
 mod player;
 mod room;
+mod room_settings;
+mod room_store;
+mod rules;
+mod schema;
+mod scoreboard;
+mod table;
+mod team;
+
+pub use config::ServerConfig;
+pub use schema::{write_schema, SCHEMA_DIR};
+
+/// How many ticks go by between reports on the outbound queues.
+const METRICS_INTERVAL: u64 = 60;
+
+/// Resolves once every connection should be closed because the server is stopping.
+type ShutdownSignal = Shared<oneshot::Receiver<()>>;
 
 async fn handle_connection(
     main_state: WrappedMainState,
+    config: ServerConfig,
+    metrics: Arc<QueueMetrics>,
+    shutdown: ShutdownSignal,
     raw_stream: TcpStream,
     address: SocketAddr,
 ) {
     println!("incoming connection from {}", address);
     let websocket = async_tungstenite::accept_async(raw_stream).await.unwrap();
     println!("websocket connection established: {}", address);
-    let (sender, receiver) = unbounded();
-    // main_state.lock().unwrap().add_client(address, sender);
+    let (sender, receiver) = outbox(config.outbox, &metrics);
+    let outbox = sender.clone();
+    let mut connection = Connection::new(sender);
+    let shutting_down = AtomicBool::new(false);
+    let heartbeat = Heartbeat::new(config.heartbeat);
+    let (ping_sender, pings) = unbounded();
     let (websocket_out, websocket_in) = websocket.split();
     let broadcast_incoming = websocket_in
-        .try_filter(|message| future::ready(!message.is_close()))
+        .try_filter(|message| {
+            heartbeat.frame_received();
+            future::ready(message.is_text() || message.is_binary())
+        })
         .try_for_each(|message| {
+            heartbeat.message_received();
             let mut state = main_state.lock().unwrap();
-            state
-                .handle_incoming_message(message, sender.clone())
-                .unwrap();
-
-            future::ok(())
+            // only fails once the connection can't be sent to, so there's nothing left to do
+            match state.handle_incoming_message(message, &mut connection) {
+                Ok(()) => future::ok(()),
+                Err(_) => future::err(WebsocketError::ConnectionClosed),
+            }
         });
-    let receive_from_others = receiver.map(Ok).forward(websocket_out);
-    pin_mut!(broadcast_incoming, receive_from_others);
-    future::select(broadcast_incoming, receive_from_others).await;
+    let mut encoding = Encoding::default();
+    let receive_from_others = receiver
+        .filter_map(move |message: ServerMessage| {
+            let frame = match encoding.encode(&message) {
+                Ok(frame) => Some(frame),
+                Err(error) => {
+                    println!("couldn't encode a message for {}: {}", address, error);
+                    None
+                }
+            };
+            // everything after the welcome goes out in the encoding it agreed on
+            if let ServerAction::Welcome {
+                encoding: agreed, ..
+            } = message.action
+            {
+                encoding = agreed;
+            }
+            future::ready(frame.map(Some))
+        })
+        .chain(stream::once(async {
+            Some(close_frame(shutting_down.load(Ordering::Relaxed)))
+        }))
+        // the pings never run out, so the queue running out is what ends the connection
+        .chain(stream::once(future::ready(None)));
+    let receive_from_others = stream::select(receive_from_others, pings.map(Some))
+        .take_while(|frame| future::ready(frame.is_some()))
+        .filter_map(future::ready)
+        .map(Ok)
+        .forward(websocket_out);
+    let keep_alive = async {
+        loop {
+            task::sleep(heartbeat.ping_interval()).await;
+            if let Some(reason) = heartbeat.check() {
+                println!("closing connection {}: {}", address, reason);
+                return;
+            }
+            ping_sender.unbounded_send(Message::Ping(vec![])).ok();
+        }
+    };
+    let close_on_shutdown = async {
+        if shutdown.await.is_ok() {
+            shutting_down.store(true, Ordering::Relaxed);
+            // what's already queued still goes out, then the close frame
+            outbox.close();
+        }
+        future::pending::<()>().await
+    };
+    {
+        pin_mut!(
+            broadcast_incoming,
+            receive_from_others,
+            keep_alive,
+            close_on_shutdown
+        );
+        future::select(
+            broadcast_incoming,
+            future::select(
+                receive_from_others,
+                future::select(keep_alive, close_on_shutdown),
+            ),
+        )
+        .await;
+    }
+    println!("connection closed: {}", address);
+    if let Err(error) = main_state.lock().unwrap().disconnect(&connection) {
+        println!("couldn't clean up after {}: {}", address, error);
+    }
+}
+
+/// The last frame sent on a connection, once its queue has been closed.
+fn close_frame(shutting_down: bool) -> Message {
+    let (code, reason) = if shutting_down {
+        (CloseCode::Away, "The server is shutting down")
+    } else {
+        (
+            CloseCode::Policy,
+            "Too many messages were waiting to be sent",
+        )
+    };
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// Feeds the moves bots make into the main state, the same way a connection does for a player.
+async fn run_bots(main_state: WrappedMainState, moves: UnboundedReceiver<ClientMessage>) {
+    // replies outside of the room only ever report a move that failed
+    let (sender, mut replies) = outbox(OutboxConfig::default(), &Arc::default());
+    let mut connection = Connection::internal(sender);
+    moves
+        .for_each(|message| {
+            let mut state = main_state.lock().unwrap();
+            if let Err(error) = state.handle_message(message, &mut connection) {
+                println!("couldn't handle a bot move: {}", error);
+            }
+            while let Ok(reply) = replies.try_recv() {
+                println!("bot move failed: {:?}", reply.action);
+            }
+            future::ready(())
+        })
+        .await;
 }
 
-pub async fn run() -> Result<()> {
-    let address = "127.0.0.1:9001".to_string();
+/// Hands what the FreeCell solver found back to the rooms that asked.
+async fn run_solver(main_state: WrappedMainState, results: UnboundedReceiver<Solved>) {
+    results
+        .for_each(|solved| {
+            if let Err(error) = main_state.lock().unwrap().solved(solved) {
+                println!("couldn't hand back what the solver found: {}", error);
+            }
+            future::ready(())
+        })
+        .await;
+}
+
+async fn run_ticks(main_state: WrappedMainState, metrics: Arc<QueueMetrics>) {
+    for tick in 1.. {
+        task::sleep(Duration::from_secs(1)).await;
+        if let Err(error) = main_state.lock().unwrap().tick() {
+            println!("tick failed: {}", error);
+        }
+        if tick % METRICS_INTERVAL == 0 {
+            println!("{}", metrics.report());
+        }
+    }
+}
+
+pub async fn run(config: ServerConfig) -> Result<()> {
     let main_state = MainState::new_wrapped();
-    let listener = TcpListener::bind(&address).await?;
-    println!("websocket server listening on {}", address);
+    let metrics = Arc::new(QueueMetrics::default());
+    let listener = TcpListener::bind(&config.address).await?;
+    println!("websocket server listening on {}", config.address);
+    task::spawn(run_ticks(main_state.clone(), metrics.clone()));
+    let (bot_moves, bot_moves_receiver) = unbounded();
+    main_state.lock().unwrap().connect_bots(bot_moves);
+    task::spawn(run_bots(main_state.clone(), bot_moves_receiver));
+    let (solver_results, solver_results_receiver) = unbounded();
+    main_state.lock().unwrap().connect_solver(solver_results);
+    task::spawn(run_solver(main_state.clone(), solver_results_receiver));
+    if let Some(dir) = &config.room_store_dir {
+        let store = Box::new(JsonFileStore::new(dir)?);
+        let restored = main_state
+            .lock()
+            .unwrap()
+            .restore_rooms(store, config.seat_reclaim_window)?;
+        println!("restored {} rooms from {}", restored, dir.display());
+    }
+
+    let (signal_sender, mut signals) = unbounded();
+    let signalled = AtomicBool::new(false);
+    ctrlc::set_handler(move || {
+        // a second signal means not waiting for the shutdown to finish
+        if signalled.swap(true, Ordering::Relaxed) {
+            process::exit(1);
+        }
+        signal_sender.unbounded_send(()).ok();
+    })?;
+    let (close_connections, shutdown) = oneshot::channel();
+    let shutdown = shutdown.shared();
 
-    while let Ok((stream, address)) = listener.accept().await {
-        task::spawn(handle_connection(main_state.clone(), stream, address));
+    loop {
+        let accept = listener.accept();
+        pin_mut!(accept);
+        match future::select(accept, signals.next()).await {
+            Either::Left((Ok((stream, address)), _)) => {
+                task::spawn(handle_connection(
+                    main_state.clone(),
+                    config.clone(),
+                    metrics.clone(),
+                    shutdown.clone(),
+                    stream,
+                    address,
+                ));
+            }
+            _ => break,
+        }
+    }
+    drop(listener);
+
+    println!("shutting down");
+    let shut_down = shut_down(
+        main_state,
+        &metrics,
+        config.shutdown_countdown,
+        close_connections,
+    );
+    match async_std::future::timeout(config.shutdown_deadline, shut_down).await {
+        Ok(result) => result,
+        Err(_) => {
+            println!("connections were still open at the shutdown deadline");
+            Ok(())
+        }
+    }
+}
+
+/// Counts every room down to the server stopping, then closes every connection and waits for
+/// them to finish sending.
+async fn shut_down(
+    main_state: WrappedMainState,
+    metrics: &QueueMetrics,
+    countdown: Duration,
+    close_connections: oneshot::Sender<()>,
+) -> Result<()> {
+    for seconds_left in (1..=countdown.as_secs()).rev() {
+        main_state.lock().unwrap().announce_shutdown(seconds_left)?;
+        task::sleep(Duration::from_secs(1)).await;
+    }
+    main_state.lock().unwrap().close()?;
+    close_connections.send(()).ok();
+    while metrics.open() > 0 {
+        task::sleep(Duration::from_millis(100)).await;
     }
     Ok(())
 }
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::ServerAction::{self, LobbyRoomClosed, LobbyRoomUpdated},
+    card::DeckSpec,
+    message::ServerMessage,
+    outbox::Outbox,
+    room_settings::GameType,
+};
+
+/// What the lobby shows about a public room.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RoomSummary {
+    pub room_id: u32,
+    pub host_name: String,
+    pub player_count: usize,
+    pub capacity: usize,
+    pub game_type: GameType,
+    pub deck: DeckSpec,
+    pub in_progress: bool,
+}
+
+impl RoomSummary {
+    pub fn has_open_seat(&self) -> bool {
+        self.player_count < self.capacity
+    }
+
+    /// `open_seats` keeps only rooms with a free seat when true, and only full rooms when false.
+    pub fn matches(
+        &self,
+        game_type: Option<GameType>,
+        open_seats: Option<bool>,
+        search: Option<&str>,
+    ) -> bool {
+        if let Some(game_type) = game_type {
+            if self.game_type != game_type {
+                return false;
+            }
+        }
+        if let Some(open_seats) = open_seats {
+            if self.has_open_seat() != open_seats {
+                return false;
+            }
+        }
+
+        match search {
+            Some(search) => {
+                let search = search.trim().to_lowercase();
+                self.host_name.to_lowercase().contains(&search)
+                    || self.room_id.to_string().starts_with(&search)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Connections that asked to be kept up to date with the public room list, along with the
+/// summaries they were last sent so that only the rooms that changed get pushed.
+#[derive(Debug, Default)]
+pub struct Lobby {
+    subscribers: Vec<Outbox>,
+    last_published: HashMap<u32, RoomSummary>,
+}
+
+impl Lobby {
+    pub fn subscribe(&mut self, sender: Outbox) {
+        if !self.is_subscribed(&sender) {
+            self.subscribers.push(sender);
+        }
+    }
+
+    pub fn unsubscribe(&mut self, sender: &Outbox) {
+        self.subscribers
+            .retain(|subscriber| !subscriber.same_queue(sender));
+    }
+
+    pub fn is_subscribed(&self, sender: &Outbox) -> bool {
+        self.subscribers
+            .iter()
+            .any(|subscriber| subscriber.same_queue(sender))
+    }
+
+    pub fn publish(&mut self, summaries: Vec<RoomSummary>) {
+        let mut messages = vec![];
+        let mut current = HashMap::new();
+        for summary in summaries {
+            if self.last_published.get(&summary.room_id) != Some(&summary) {
+                messages.push(LobbyRoomUpdated {
+                    room_summary: summary.clone(),
+                });
+            }
+            current.insert(summary.room_id, summary);
+        }
+        for room_id in self.last_published.keys() {
+            if !current.contains_key(room_id) {
+                messages.push(LobbyRoomClosed { room_id: *room_id });
+            }
+        }
+        self.last_published = current;
+
+        for message in messages {
+            self.send_to_subscribers(message);
+        }
+    }
+
+    fn send_to_subscribers(&mut self, action: ServerAction) {
+        let message = ServerMessage::from(action);
+        // a failed send means the connection is gone, so it can stop receiving updates
+        self.subscribers
+            .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::outbox::{outbox, OutboxConfig, OutboxReceiver};
+
+    fn summary(room_id: u32, host_name: &str) -> RoomSummary {
+        RoomSummary {
+            room_id,
+            host_name: host_name.to_owned(),
+            player_count: 1,
+            capacity: 4,
+            game_type: GameType::FreePlay,
+            deck: DeckSpec::new(1),
+            in_progress: false,
+        }
+    }
+
+    fn received(receiver: &mut OutboxReceiver) -> Vec<Value> {
+        let mut messages = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(serde_json::to_value(message).unwrap());
+        }
+        messages
+    }
+
+    #[test]
+    fn rooms_can_be_filtered_by_game_and_searched_by_host_or_id() {
+        let room = summary(4321, "Ada Lovelace");
+        assert!(room.matches(None, None, None));
+        assert!(room.matches(Some(GameType::FreePlay), None, None));
+        assert!(!room.matches(Some(GameType::Spades), None, None));
+        assert!(room.matches(None, None, Some(" lovelace ")));
+        assert!(room.matches(None, None, Some("43")));
+        assert!(!room.matches(None, None, Some("21")));
+        assert!(!room.matches(Some(GameType::FreePlay), None, Some("bea")));
+    }
+
+    #[test]
+    fn rooms_can_be_filtered_by_whether_they_have_a_free_seat() {
+        let mut room = summary(4321, "Ada");
+        assert!(room.matches(None, Some(true), None));
+        assert!(!room.matches(None, Some(false), None));
+
+        room.player_count = room.capacity;
+        assert!(!room.matches(None, Some(true), None));
+        assert!(room.matches(None, Some(false), None));
+        assert!(room.matches(None, None, None));
+    }
+
+    #[test]
+    fn subscribers_only_hear_about_rooms_that_changed() {
+        let mut lobby = Lobby::default();
+        let (sender, mut receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        lobby.subscribe(sender);
+        let mut hearts = summary(1111, "Ada");
+        let spades = summary(2222, "Bea");
+
+        lobby.publish(vec![hearts.clone()]);
+        let messages = received(&mut receiver);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["action"], "LobbyRoomUpdated");
+        assert_eq!(messages[0]["data"]["room_summary"]["room_id"], 1111);
+
+        lobby.publish(vec![hearts.clone(), spades.clone()]);
+        let messages = received(&mut receiver);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["data"]["room_summary"]["room_id"], 2222);
+
+        hearts.player_count = 4;
+        lobby.publish(vec![hearts.clone(), spades.clone()]);
+        let messages = received(&mut receiver);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["data"]["room_summary"]["player_count"], 4);
+
+        lobby.publish(vec![spades.clone()]);
+        let messages = received(&mut receiver);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["action"], "LobbyRoomClosed");
+        assert_eq!(messages[0]["data"]["room_id"], 1111);
+
+        lobby.publish(vec![spades]);
+        assert!(received(&mut receiver).is_empty());
+    }
+
+    #[test]
+    fn closed_connections_stop_being_sent_updates() {
+        let mut lobby = Lobby::default();
+        let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        lobby.subscribe(sender.clone());
+        lobby.subscribe(sender.clone());
+        drop(receiver);
+        lobby.publish(vec![summary(1111, "Ada")]);
+        assert!(!lobby.is_subscribed(&sender));
+    }
+}
@@ -1,7 +1,15 @@
+use std::path::Path;
+
 use async_std::task;
-use deck_of_cards_simulator_backend::run;
+use deck_of_cards_simulator_backend::{run, write_schema, ServerConfig, SCHEMA_DIR};
 use eyre::Result;
 
 fn main() -> Result<()> {
-    task::block_on(run())
+    // `--write-schema [dir]` regenerates the protocol schema instead of starting the server
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--write-schema") {
+        let dir = args.next().unwrap_or_else(|| SCHEMA_DIR.to_owned());
+        return write_schema(Path::new(&dir));
+    }
+    task::block_on(run(ServerConfig::from_env()?))
 }
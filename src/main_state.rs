@@ -1,22 +1,51 @@
 use crate::{
-    actions::Action::{
-        Chat, CreateGame, DiscardCard, DrawCard, DrawDeckUpdated, JoinRoom, Quit, ResetDeck,
-        ToggleVisibilityOfCard,
+    actions::{
+        ClientAction::{self, *},
+        CreateGame,
+        ServerAction::{
+            self, Error, MatchTimedOut, RoomFull, ServerShuttingDown, Welcome, WrongPassword,
+        },
+    },
+    bot::BotKind,
+    codec::Encoding,
+    connection::Connection,
+    lobby::{Lobby, RoomSummary},
+    matchmaking::{MatchRequest, Matchmaker},
+    message::{
+        negotiate_version, ClientMessage, ServerMessage, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
     },
-    message::{CustomMessage, CustomMessageBuilder},
     player::Player,
-    room::Room,
+    room::{Room, Solved},
+    room_settings::{GameType, RoomSettings},
+    room_store::RoomStore,
+    rules::GameMove,
 };
 use async_tungstenite::tungstenite::Message;
-use eyre::Result;
+use eyre::{bail, Result};
 use futures::channel::mpsc::UnboundedSender;
-use std::sync::{Arc, Mutex};
+use rand::{seq::SliceRandom, thread_rng};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 pub type WrappedMainState = Arc<Mutex<MainState>>;
 
 #[derive(Debug, Default)]
 pub struct MainState {
     rooms: Vec<Room>,
+    lobby: Lobby,
+    matchmaker: Matchmaker,
+    bot_moves: Option<UnboundedSender<ClientMessage>>,
+    solver_results: Option<UnboundedSender<Solved>>,
+    /// Set once the server starts shutting down, no new games are started after that.
+    shutting_down: bool,
+    store: Option<Box<dyn RoomStore>>,
+    /// The rooms that have been saved, so the ones that close can be deleted.
+    saved: HashSet<u32>,
+    /// How long restored players have to reclaim their seats.
+    reclaim_window: Duration,
 }
 
 impl MainState {
@@ -25,138 +54,1272 @@ impl MainState {
         Arc::new(Mutex::new(main_state))
     }
 
+    /// Where bots send their moves, without it bots can't be added to rooms.
+    pub fn connect_bots(&mut self, bot_moves: UnboundedSender<ClientMessage>) {
+        self.bot_moves = Some(bot_moves);
+    }
+
+    /// Where the FreeCell solver sends what it found, without it games can't be checked.
+    pub fn connect_solver(&mut self, solver_results: UnboundedSender<Solved>) {
+        self.solver_results = Some(solver_results);
+    }
+
+    /// Hands what the solver found to the room that asked, if it's still open.
+    pub fn solved(&mut self, solved: Solved) -> Result<()> {
+        match self.room(solved.room_id) {
+            Ok(room) => room.solved(&solved.position, solved.solvability),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Brings back the rooms kept in `store`, and keeps saving rooms to it from now on. Bots
+    /// should be connected first so the restored ones can carry on playing.
+    pub fn restore_rooms(
+        &mut self,
+        mut store: Box<dyn RoomStore>,
+        reclaim_window: Duration,
+    ) -> Result<usize> {
+        for saved_room in store.load_all()? {
+            self.saved.insert(saved_room.id);
+            self.rooms
+                .push(Room::restore(saved_room, self.bot_moves.clone())?);
+        }
+        self.store = Some(store);
+        self.reclaim_window = reclaim_window;
+        Ok(self.rooms.len())
+    }
+
+    /// Saves every room that changed since it was last saved, and deletes the ones that closed.
+    pub fn persist(&mut self) -> Result<()> {
+        let store = match &mut self.store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let mut saved = HashSet::new();
+        for room in &mut self.rooms {
+            if room.take_unsaved() {
+                if let Err(error) = room.save().and_then(|saved_room| store.save(&saved_room)) {
+                    room.mark_unsaved();
+                    return Err(error);
+                }
+            }
+            saved.insert(room.id);
+        }
+        for room_id in &self.saved {
+            if !saved.contains(room_id) {
+                store.delete(*room_id)?;
+            }
+        }
+        self.saved = saved;
+        Ok(())
+    }
+
     pub fn handle_incoming_message(
         &mut self,
         raw_message: Message,
-        sender: UnboundedSender<Message>,
+        connection: &mut Connection,
     ) -> Result<()> {
-        let message: CustomMessage = serde_json::from_str(raw_message.to_text()?)?;
-        match message.action {
-            CreateGame => self.handle_create_game(message, sender)?,
-            JoinRoom => self.handle_join_room(message, sender)?,
-            Chat => self.handle_chat(message)?,
-            DrawCard => self.handle_draw_card(message)?,
-            ToggleVisibilityOfCard => self.handle_toggle_visibility_of_card(message)?,
-            DiscardCard => self.handle_discard_card(message)?,
-            ResetDeck => self.handle_reset_deck(message)?,
-            Quit => self.handle_quit(message)?,
-            _ => {}
-        }
-        Ok(())
+        let message = match connection.encoding.decode(&raw_message) {
+            Ok(message) => message,
+            Err(error) => {
+                let message = format!("That message couldn't be read: {}", error);
+                return connection.reply(None, Error { message });
+            }
+        };
+        self.handle_message(message, connection)
     }
 
-    fn handle_create_game(
+    /// Handles one message from a connection. Anything that goes wrong is sent back as an
+    /// `Error` carrying the request id, only failing to reach the connection is returned.
+    pub fn handle_message(
         &mut self,
-        message: CustomMessage,
-        sender: UnboundedSender<Message>,
+        message: ClientMessage,
+        connection: &mut Connection,
     ) -> Result<()> {
-        let player_name = message.data.get_player_name()?;
-        let player = Player::new(player_name, sender);
-        self.create_room(player)?;
+        let request_id = message.request_id.clone();
+        if let Err(error) = self.handle_client_action(message, connection) {
+            connection.reply(
+                request_id,
+                Error {
+                    message: error.to_string(),
+                },
+            )?;
+        }
+        self.lobby.publish(self.public_room_summaries());
         Ok(())
     }
 
-    fn handle_join_room(
+    fn handle_client_action(
         &mut self,
-        message: CustomMessage,
-        sender: UnboundedSender<Message>,
+        message: ClientMessage,
+        connection: &mut Connection,
     ) -> Result<()> {
-        let player = Player::new(message.data.get_player_name()?, sender);
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
+        let request_id = message.request_id;
+        if let Hello {
+            min_version,
+            max_version,
+            encoding,
+        } = message.action
         {
-            room.join(player)?;
+            let encoding = encoding.unwrap_or_default();
+            return self.handle_hello(request_id, min_version, max_version, encoding, connection);
+        }
+        if connection.protocol_version.is_none() {
+            bail!("Say Hello before anything else");
+        }
+        let opens_a_seat = matches!(
+            message.action,
+            ClientAction::CreateGame(_) | JoinRoom { .. } | EnqueueMatch { .. }
+        );
+        if self.shutting_down && opens_a_seat {
+            bail!("The server is shutting down");
+        }
+
+        match message.action {
+            Hello { .. } => {}
+            ClientAction::CreateGame(request) => {
+                self.handle_create_game(request_id, request, connection)?
+            }
+            JoinRoom {
+                room_id,
+                player_name,
+                password,
+                invite_token,
+            } => self.handle_join_room(
+                request_id,
+                room_id,
+                Player::new(&player_name, connection.sender.clone()),
+                password.as_deref(),
+                invite_token.as_deref(),
+            )?,
+            Chat {
+                room_id,
+                player_id,
+                message,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .chat(&player_id, &message)?;
+            }
+            DrawCard { room_id, player_id } => self.handle_game_move(
+                request_id,
+                room_id,
+                &player_id,
+                GameMove::DrawCard,
+                connection,
+            )?,
+            ToggleVisibilityOfCard {
+                room_id,
+                player_id,
+                card,
+            } => self.handle_game_move(
+                request_id,
+                room_id,
+                &player_id,
+                GameMove::ToggleVisibilityOfCard { card },
+                connection,
+            )?,
+            DiscardCard {
+                room_id,
+                player_id,
+                card,
+            } => self.handle_game_move(
+                request_id,
+                room_id,
+                &player_id,
+                GameMove::DiscardCard { card },
+                connection,
+            )?,
+            ResetDeck { room_id, player_id } => self.handle_game_move(
+                request_id,
+                room_id,
+                &player_id,
+                GameMove::ResetDeck,
+                connection,
+            )?,
+            ClientAction::GameMove {
+                room_id,
+                player_id,
+                game_move,
+            } => self.handle_game_move(request_id, room_id, &player_id, game_move, connection)?,
+            Quit { room_id, player_id } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .remove_player_by_id(&player_id)?;
+                self.rooms.retain(|room| !room.is_empty());
+            }
+            RotatePassword {
+                room_id,
+                player_id,
+                password,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .rotate_password(&player_id, password.as_deref())?;
+            }
+            CreateInviteToken { room_id, player_id } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .create_invite_token(&player_id)?;
+            }
+            ListRooms {
+                game_type,
+                open_seats,
+                search,
+            } => self.handle_list_rooms(
+                request_id,
+                game_type,
+                open_seats,
+                search.as_deref(),
+                connection,
+            )?,
+            SubscribeLobby => self.handle_subscribe_lobby(request_id, connection)?,
+            UnsubscribeLobby => self.lobby.unsubscribe(&connection.sender),
+            EnqueueMatch {
+                player_name,
+                game_type,
+                player_count,
+            } => self.handle_enqueue_match(
+                request_id,
+                &player_name,
+                game_type.unwrap_or_default(),
+                player_count,
+                connection,
+            )?,
+            CancelMatch => self.handle_cancel_match(request_id, connection)?,
+            RecordScores {
+                room_id,
+                player_id,
+                scores,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .record_scores(&player_id, scores)?;
+            }
+            Bet {
+                room_id,
+                player_id,
+                amount,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .bet(&player_id, amount)?;
+            }
+            CollectPot { room_id, player_id } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .collect_pot(&player_id)?;
+            }
+            TransferChips {
+                room_id,
+                player_id,
+                to_player_id,
+                amount,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .transfer_chips(&player_id, &to_player_id, amount)?;
+            }
+            DefineTeams {
+                room_id,
+                player_id,
+                team_names,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .define_teams(&player_id, &team_names)?;
+            }
+            AssignTeam {
+                room_id,
+                player_id,
+                to_player_id,
+                team,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .assign_team(&player_id, &to_player_id, team.as_deref())?;
+            }
+            TeamChat {
+                room_id,
+                player_id,
+                message,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .team_chat(&player_id, &message)?;
+            }
+            PlayToTeamPile {
+                room_id,
+                player_id,
+                card,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .play_to_team_pile(&player_id, &card)?;
+            }
+            TakeFromTeamPile {
+                room_id,
+                player_id,
+                card,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .take_from_team_pile(&player_id, &card)?;
+            }
+            AddBot {
+                room_id,
+                player_id,
+                bot_kind,
+            } => self.handle_add_bot(
+                request_id,
+                room_id,
+                &player_id,
+                bot_kind.unwrap_or_default(),
+                connection,
+            )?,
+            RemoveBot {
+                room_id,
+                player_id,
+                to_player_id,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .remove_bot(&player_id, &to_player_id)?;
+            }
+            SyncFrom {
+                room_id,
+                player_id,
+                seq,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .sync_from(&player_id, seq)?;
+            }
+            GetHistory { room_id, player_id } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .send_history(&player_id)?;
+            }
+            Undo { room_id, player_id } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .undo(&player_id)?;
+            }
+            VoteUndo {
+                room_id,
+                player_id,
+                approve,
+            } => {
+                self.room_for(room_id, &player_id, request_id, connection)?
+                    .vote_undo(&player_id, approve)?;
+            }
+            ReclaimSeat {
+                room_id,
+                session_token,
+            } => self.room(room_id)?.reclaim_seat(
+                &session_token,
+                connection.sender.clone(),
+                request_id,
+            )?,
         }
         Ok(())
     }
 
-    fn handle_chat(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            let outgoing_message = CustomMessageBuilder::new()
-                .set_action(message.action)
-                .set_player_name(message.data.get_player_name()?)
-                .set_room_id(message.data.get_room_id().unwrap())
-                .set_message(message.data.get_message()?)
-                .build()?;
-            room.broadcast_to_room(outgoing_message).unwrap();
+    /// Warns every room that the server is about to stop, and stops new games from starting.
+    pub fn announce_shutdown(&mut self, seconds_left: u64) -> Result<()> {
+        self.shutting_down = true;
+        for room in &mut self.rooms {
+            room.broadcast_to_room(ServerShuttingDown {
+                seconds_left,
+                message: format!("The server is shutting down in {} seconds", seconds_left),
+            })?;
         }
         Ok(())
     }
 
-    fn handle_draw_card(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            room.draw_card(message.data.get_player_id()?)?;
-            let message = CustomMessageBuilder::new()
-                .set_action(DrawDeckUpdated)
-                .set_draw_deck_size(room.draw_deck.len())
-                .set_player_id(message.data.get_player_id()?.to_owned())
-                .build()?;
-            room.broadcast_to_room(message).unwrap();
+    /// Saves the rooms one last time before the connections close. Seats are kept from then
+    /// on, so players can reclaim them after the restart.
+    pub fn close(&mut self) -> Result<()> {
+        self.shutting_down = true;
+        self.persist()
+    }
+
+    /// Cleans up after a connection that closed: its players leave their rooms, and it stops
+    /// waiting for a match or following the lobby.
+    pub fn disconnect(&mut self, connection: &Connection) -> Result<()> {
+        self.lobby.unsubscribe(&connection.sender);
+        self.matchmaker.cancel(&connection.sender);
+        // seats stay taken while shutting down, so they're saved for after the restart
+        if !self.shutting_down {
+            for room in &mut self.rooms {
+                if let Err(error) = room.remove_connection(&connection.sender) {
+                    report_room_error(room, error);
+                }
+            }
         }
+        self.rooms.retain(|room| !room.is_empty());
+        self.lobby.publish(self.public_room_summaries());
         Ok(())
     }
 
-    fn handle_toggle_visibility_of_card(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
+    /// Runs the work that isn't triggered by an incoming message, called about once a second.
+    pub fn tick(&mut self) -> Result<()> {
+        for request in self.matchmaker.take_expired() {
+            let message_to_player = ServerMessage::from(MatchTimedOut {
+                game_type: request.game_type,
+                player_count: request.player_count,
+                message: "No match found, try again later".to_owned(),
+            });
+            // the player may have closed the page while waiting
+            request.sender.send(message_to_player).ok();
+        }
+        let reclaim_window = self.reclaim_window;
+        for room in &mut self.rooms {
+            let result = room
+                .resync_lagging_players()
+                .and_then(|()| room.remove_unclaimed_seats(reclaim_window));
+            if let Err(error) = result {
+                report_room_error(room, error);
+            }
+        }
+        self.rooms.retain(|room| !room.is_empty());
+        // a full disk shouldn't stop the games, they're saved again on the next tick
+        if let Err(error) = self.persist() {
+            println!("couldn't save the rooms: {}", error);
+        }
+        self.lobby.publish(self.public_room_summaries());
+        Ok(())
+    }
+
+    /// Finds a room to change, which means it has to be saved again.
+    fn room(&mut self, room_id: u32) -> Result<&mut Room> {
+        match self.rooms.iter_mut().find(|room| room.id == room_id) {
+            Some(room) => {
+                room.mark_unsaved();
+                Ok(room)
+            }
+            None => bail!("Room {} doesn't exist", room_id),
+        }
+    }
+
+    /// Finds the room a request is for, and remembers who sent it so replies carry its id.
+    /// Players can only act from the connection holding their seat, since anyone in the room
+    /// can see their id.
+    fn room_for(
+        &mut self,
+        room_id: u32,
+        player_id: &str,
+        request_id: Option<String>,
+        connection: &Connection,
+    ) -> Result<&mut Room> {
+        let room = self.room(room_id)?;
+        if !room.is_seat_of(player_id, connection) {
+            bail!("You don't hold that seat in room {}", room_id);
+        }
+        room.set_requester(player_id, request_id);
+        Ok(room)
+    }
+
+    fn handle_hello(
+        &mut self,
+        request_id: Option<String>,
+        min_version: u32,
+        max_version: u32,
+        encoding: Encoding,
+        connection: &mut Connection,
+    ) -> Result<()> {
+        let version = match negotiate_version(min_version, max_version) {
+            Some(version) => version,
+            None => bail!(
+                "This server speaks protocol versions {} to {}",
+                MIN_PROTOCOL_VERSION,
+                PROTOCOL_VERSION
+            ),
+        };
+        connection.protocol_version = Some(version);
+        connection.encoding = encoding;
+        connection.reply(request_id, Welcome { version, encoding })
+    }
+
+    /// Picks an id for a new room that no open or saved room has, so the new room can't end up
+    /// saved over another one.
+    fn unused_room_id(&self) -> Result<u32> {
+        let taken: HashSet<u32> = self
             .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            room.toggle_visibility_of_card(
-                message.data.get_player_id()?,
-                message.data.get_card()?,
-            )?;
+            .iter()
+            .map(|room| room.id)
+            .chain(self.saved.iter().copied())
+            .collect();
+        let unused: Vec<u32> = (1000..=9999)
+            .filter(|room_id| !taken.contains(room_id))
+            .collect();
+        match unused.choose(&mut thread_rng()) {
+            Some(room_id) => Ok(*room_id),
+            None => bail!("There's no room for another game, try again later"),
         }
+    }
 
+    fn handle_create_game(
+        &mut self,
+        request_id: Option<String>,
+        request: CreateGame,
+        connection: &Connection,
+    ) -> Result<()> {
+        let player = Player::new(&request.player_name, connection.sender.clone());
+        let settings = RoomSettings::from_create_game(&request);
+        let room_id = self.unused_room_id()?;
+        let room = Room::new(
+            room_id,
+            player,
+            request.password.as_deref(),
+            settings,
+            request_id,
+        )?;
+        self.rooms.push(room);
         Ok(())
     }
 
-    fn create_room(&mut self, player: Player) -> Result<()> {
-        let room = Room::new(player)?;
-        self.rooms.push(room);
+    fn handle_join_room(
+        &mut self,
+        request_id: Option<String>,
+        room_id: u32,
+        mut player: Player,
+        password: Option<&str>,
+        invite_token: Option<&str>,
+    ) -> Result<()> {
+        let player_id = player.id.clone();
+        let room = self.room(room_id)?;
+        room.set_requester(&player_id, request_id.clone());
+        if !room.authorize(password, invite_token) {
+            let message_to_player = WrongPassword {
+                room_id,
+                message: "Wrong password".to_owned(),
+            };
+            player.send(ServerMessage::reply(request_id, message_to_player))?;
+            return Ok(());
+        }
+        if room.is_full() {
+            let message_to_player = RoomFull {
+                room_id,
+                message: "Room is full".to_owned(),
+            };
+            player.send(ServerMessage::reply(request_id, message_to_player))?;
+            return Ok(());
+        }
+        room.join(player)?;
+        room.consume_invite(invite_token);
         Ok(())
     }
 
-    fn handle_discard_card(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            room.discard_card(message.data.get_player_id()?, message.data.get_card()?)?;
+    fn handle_game_move(
+        &mut self,
+        request_id: Option<String>,
+        room_id: u32,
+        player_id: &str,
+        game_move: GameMove,
+        connection: &Connection,
+    ) -> Result<()> {
+        let solver_results = self.solver_results.clone();
+        let room = self.room_for(room_id, player_id, request_id, connection)?;
+        match (game_move, solver_results) {
+            (GameMove::CheckSolvable, Some(solver_results)) => {
+                room.check_solvable(player_id, solver_results)
+            }
+            (game_move, _) => room.handle_move(player_id, game_move),
         }
+    }
+
+    fn handle_add_bot(
+        &mut self,
+        request_id: Option<String>,
+        room_id: u32,
+        player_id: &str,
+        bot_kind: BotKind,
+        connection: &Connection,
+    ) -> Result<()> {
+        let bot_moves = match &self.bot_moves {
+            Some(bot_moves) => bot_moves.clone(),
+            None => bail!("Bots aren't available on this server"),
+        };
+        self.room_for(room_id, player_id, request_id, connection)?
+            .add_bot(player_id, bot_kind, bot_moves)?;
         Ok(())
     }
 
-    fn handle_reset_deck(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            room.reset_deck()?;
+    fn handle_list_rooms(
+        &mut self,
+        request_id: Option<String>,
+        game_type: Option<GameType>,
+        open_seats: Option<bool>,
+        search: Option<&str>,
+        connection: &Connection,
+    ) -> Result<()> {
+        let rooms = self
+            .public_room_summaries()
+            .into_iter()
+            .filter(|summary| summary.matches(game_type, open_seats, search))
+            .collect();
+        connection.reply(request_id, ServerAction::ListRooms { rooms })
+    }
+
+    fn handle_subscribe_lobby(
+        &mut self,
+        request_id: Option<String>,
+        connection: &Connection,
+    ) -> Result<()> {
+        // the full list goes out first, after that subscribers only hear about changes
+        let rooms = self.public_room_summaries();
+        connection.reply(request_id, ServerAction::SubscribeLobby { rooms })?;
+        self.lobby.subscribe(connection.sender.clone());
+        Ok(())
+    }
+
+    fn public_room_summaries(&self) -> Vec<RoomSummary> {
+        self.rooms
+            .iter()
+            .filter(|room| !room.is_private())
+            .map(|room| room.summary())
+            .collect()
+    }
+
+    fn handle_enqueue_match(
+        &mut self,
+        request_id: Option<String>,
+        player_name: &str,
+        game_type: GameType,
+        player_count: usize,
+        connection: &Connection,
+    ) -> Result<()> {
+        let player_count = Matchmaker::player_count(game_type, player_count);
+        let request = MatchRequest::new(
+            player_name,
+            game_type,
+            player_count,
+            connection.sender.clone(),
+        );
+        self.matchmaker.enqueue(request);
+        connection.reply(
+            request_id,
+            ServerAction::EnqueueMatch {
+                game_type,
+                player_count,
+                message: "Looking for players".to_owned(),
+            },
+        )?;
+        self.create_matched_rooms()?;
+        Ok(())
+    }
+
+    fn handle_cancel_match(
+        &mut self,
+        request_id: Option<String>,
+        connection: &Connection,
+    ) -> Result<()> {
+        if self.matchmaker.cancel(&connection.sender) {
+            connection.reply(
+                request_id,
+                ServerAction::CancelMatch {
+                    message: "Stopped looking for players".to_owned(),
+                },
+            )?;
         }
         Ok(())
     }
 
-    fn handle_quit(&mut self, message: CustomMessage) -> Result<()> {
-        if let Some(room) = self
-            .rooms
-            .iter_mut()
-            .find(|room| room.id == message.data.get_room_id().unwrap())
-        {
-            room.remove_player_by_id(message.data.get_player_id()?)?;
+    fn create_matched_rooms(&mut self) -> Result<()> {
+        while let Some(requests) = self.matchmaker.take_match() {
+            let game_type = requests[0].game_type;
+            let settings = RoomSettings {
+                max_players: requests.len(),
+                game_type,
+                deck: game_type.deck(None),
+                ..RoomSettings::default()
+            };
+            let players = requests
+                .into_iter()
+                .map(|request| Player::new(&request.player_name, request.sender))
+                .collect();
+            let room = Room::new_matched(self.unused_room_id()?, players, settings)?;
+            self.rooms.push(room);
         }
         Ok(())
     }
 }
+
+/// Lets a room know about something that went wrong with it outside of anyone's request, so
+/// one broken room doesn't stop the work for every other room.
+fn report_room_error(room: &mut Room, error: eyre::Report) {
+    println!("room {}: {}", room.id, error);
+    let message = error.to_string();
+    room.broadcast_to_room(Error { message }).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        outbox::{outbox, OutboxConfig, OutboxReceiver},
+        room_settings::UndoPolicy,
+        room_store::SavedRoom,
+    };
+
+    /// Keeps nothing, only which rooms were saved.
+    #[derive(Debug, Default)]
+    struct CountingStore {
+        saves: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl RoomStore for CountingStore {
+        fn load_all(&mut self) -> Result<Vec<SavedRoom>> {
+            Ok(vec![])
+        }
+
+        fn save(&mut self, room: &SavedRoom) -> Result<()> {
+            self.saves.lock().unwrap().push(room.id);
+            Ok(())
+        }
+
+        fn delete(&mut self, _room_id: u32) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A connection that has said hello, and where its messages end up.
+    fn connect(state: &mut MainState) -> (Connection, OutboxReceiver) {
+        let (sender, mut receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let mut connection = Connection::new(sender);
+        let hello = Hello {
+            min_version: PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            encoding: None,
+        };
+        send(state, &mut connection, hello);
+        receiver.try_recv().unwrap();
+        (connection, receiver)
+    }
+
+    fn send(state: &mut MainState, connection: &mut Connection, action: ClientAction) {
+        state
+            .handle_message(ClientMessage::new(action), connection)
+            .unwrap();
+    }
+
+    fn received(receiver: &mut OutboxReceiver) -> Vec<ServerAction> {
+        let mut actions = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            actions.push(message.action);
+        }
+        actions
+    }
+
+    /// Opens a room with `password`, returning the host's connection, the room id and the
+    /// host's player id.
+    fn create_room(
+        state: &mut MainState,
+        password: &str,
+    ) -> (Connection, OutboxReceiver, u32, String) {
+        let request = CreateGame {
+            password: Some(password.to_owned()),
+            ..CreateGame::default()
+        };
+        create_room_with(state, request)
+    }
+
+    /// Opens a room hosted by Ada with the given settings.
+    fn create_room_with(
+        state: &mut MainState,
+        request: CreateGame,
+    ) -> (Connection, OutboxReceiver, u32, String) {
+        let (mut host, mut host_messages) = connect(state);
+        let request = CreateGame {
+            player_name: "Ada".to_owned(),
+            ..request
+        };
+        send(state, &mut host, ClientAction::CreateGame(request));
+        match received(&mut host_messages).as_slice() {
+            [ServerAction::CreateGame {
+                room_id, player_id, ..
+            }] => {
+                let (room_id, player_id) = (*room_id, player_id.clone());
+                (host, host_messages, room_id, player_id)
+            }
+            other => panic!("expected the new room, got {:?}", other),
+        }
+    }
+
+    /// Opens a room, returning its id and the host's player id.
+    fn create_game(
+        state: &mut MainState,
+        connection: &mut Connection,
+        receiver: &mut OutboxReceiver,
+    ) -> (u32, String) {
+        let request = CreateGame {
+            player_name: "Ada".to_owned(),
+            ..CreateGame::default()
+        };
+        send(state, connection, ClientAction::CreateGame(request));
+        match received(receiver).as_slice() {
+            [ServerAction::CreateGame {
+                room_id, player_id, ..
+            }] => (*room_id, player_id.clone()),
+            other => panic!("expected the new room, got {:?}", other),
+        }
+    }
+
+    fn invite_token(
+        state: &mut MainState,
+        host: &mut Connection,
+        host_messages: &mut OutboxReceiver,
+        room_id: u32,
+        host_id: &str,
+    ) -> String {
+        let create_invite_token = CreateInviteToken {
+            room_id,
+            player_id: host_id.to_owned(),
+        };
+        send(state, host, create_invite_token);
+        match received(host_messages).remove(0) {
+            ServerAction::CreateInviteToken { invite_token, .. } => invite_token,
+            other => panic!("expected an invite token, got {:?}", other),
+        }
+    }
+
+    /// Whether a guest joining with `password` or `invite_token` got in.
+    fn join(
+        state: &mut MainState,
+        room_id: u32,
+        password: Option<&str>,
+        invite_token: Option<&str>,
+    ) -> bool {
+        let (mut guest, mut guest_messages) = connect(state);
+        let join_room = JoinRoom {
+            room_id,
+            player_name: "Bea".to_owned(),
+            password: password.map(str::to_owned),
+            invite_token: invite_token.map(str::to_owned),
+        };
+        send(state, &mut guest, join_room);
+        match received(&mut guest_messages).remove(0) {
+            ServerAction::JoinRoom { .. } => true,
+            ServerAction::WrongPassword { .. } | ServerAction::RoomFull { .. } => false,
+            other => panic!("expected to join or be turned away, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nothing_is_handled_before_hello() {
+        let mut state = MainState::default();
+        let (sender, mut receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let mut connection = Connection::new(sender);
+        send(&mut state, &mut connection, SubscribeLobby);
+        assert_eq!(
+            received(&mut receiver),
+            vec![Error {
+                message: "Say Hello before anything else".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn joining_takes_the_room_password() {
+        let mut state = MainState::default();
+        let (_host, _host_messages, room_id, _) = create_room(&mut state, "secret");
+        assert!(!join(&mut state, room_id, Some("guess"), None));
+        assert!(!join(&mut state, room_id, None, None));
+        assert!(join(&mut state, room_id, Some("secret"), None));
+    }
+
+    #[test]
+    fn invite_tokens_only_work_once() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages, room_id, host_id) = create_room(&mut state, "secret");
+        let token = invite_token(&mut state, &mut host, &mut host_messages, room_id, &host_id);
+        assert!(join(&mut state, room_id, None, Some(&token)));
+        assert!(!join(&mut state, room_id, None, Some(&token)));
+    }
+
+    #[test]
+    fn invite_tokens_arent_used_up_when_the_room_is_full() {
+        let mut state = MainState::default();
+        let request = CreateGame {
+            password: Some("secret".to_owned()),
+            max_players: Some(2),
+            ..CreateGame::default()
+        };
+        let (mut host, mut host_messages, room_id, host_id) = create_room_with(&mut state, request);
+        let token = invite_token(&mut state, &mut host, &mut host_messages, room_id, &host_id);
+        assert!(join(&mut state, room_id, Some("secret"), None));
+        assert!(!join(&mut state, room_id, None, Some(&token)));
+        assert!(state.room(room_id).unwrap().authorize(None, Some(&token)));
+    }
+
+    #[test]
+    fn private_rooms_without_a_password_need_an_invite() {
+        let mut state = MainState::default();
+        let request = CreateGame {
+            private: Some(true),
+            ..CreateGame::default()
+        };
+        let (mut host, mut host_messages, room_id, host_id) = create_room_with(&mut state, request);
+        assert!(!join(&mut state, room_id, None, None));
+        let token = invite_token(&mut state, &mut host, &mut host_messages, room_id, &host_id);
+        assert!(join(&mut state, room_id, None, Some(&token)));
+    }
+
+    #[test]
+    fn only_the_host_can_change_the_password_or_hand_out_invites() {
+        let mut state = MainState::default();
+        let (_host, _host_messages, room_id, _) =
+            create_room_with(&mut state, CreateGame::default());
+        let (mut guest, mut guest_messages) = connect(&mut state);
+        let guest_id = join_room(&mut state, room_id, &mut guest, &mut guest_messages);
+
+        let rotate_password = RotatePassword {
+            room_id,
+            player_id: guest_id.clone(),
+            password: Some("changed".to_owned()),
+        };
+        send(&mut state, &mut guest, rotate_password);
+        let create_invite_token = CreateInviteToken {
+            room_id,
+            player_id: guest_id,
+        };
+        send(&mut state, &mut guest, create_invite_token);
+        let rejections: Vec<_> = received(&mut guest_messages)
+            .into_iter()
+            .filter_map(|action| match action {
+                ServerAction::IllegalMove { message, .. } => Some(message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            rejections,
+            vec![
+                "Only the host can change the room password",
+                "Only the host can create invite tokens",
+            ]
+        );
+        assert!(join(&mut state, room_id, None, None));
+    }
+
+    #[test]
+    fn rotating_the_password_stops_old_passwords_and_invites_working() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages, room_id, host_id) = create_room(&mut state, "secret");
+        let token = invite_token(&mut state, &mut host, &mut host_messages, room_id, &host_id);
+        let rotate_password = RotatePassword {
+            room_id,
+            player_id: host_id,
+            password: Some("changed".to_owned()),
+        };
+        send(&mut state, &mut host, rotate_password);
+        assert!(matches!(
+            received(&mut host_messages).as_slice(),
+            [ServerAction::RotatePassword { .. }]
+        ));
+
+        assert!(!join(&mut state, room_id, None, Some(&token)));
+        assert!(!join(&mut state, room_id, Some("secret"), None));
+        assert!(join(&mut state, room_id, Some("changed"), None));
+    }
+
+    #[test]
+    fn only_rooms_that_changed_are_saved_again() {
+        let mut state = MainState::default();
+        let store = CountingStore::default();
+        let saves = store.saves.clone();
+        state
+            .restore_rooms(Box::new(store), Duration::from_secs(60))
+            .unwrap();
+        let (mut host, mut host_messages, room_id, host_id) =
+            create_room_with(&mut state, CreateGame::default());
+        let (_other_host, _other_host_messages, other_room_id, _) =
+            create_room_with(&mut state, CreateGame::default());
+        state.persist().unwrap();
+        let mut saved = saves.lock().unwrap().split_off(0);
+        saved.sort();
+        let mut expected = vec![room_id, other_room_id];
+        expected.sort();
+        assert_eq!(saved, expected);
+
+        state.persist().unwrap();
+        assert!(saves.lock().unwrap().is_empty());
+
+        let chat = Chat {
+            room_id,
+            player_id: host_id,
+            message: "hello".to_owned(),
+        };
+        send(&mut state, &mut host, chat);
+        received(&mut host_messages);
+        state.persist().unwrap();
+        assert_eq!(*saves.lock().unwrap(), [room_id]);
+    }
+
+    #[test]
+    fn bots_cant_be_added_when_the_server_has_none() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages, room_id, host_id) =
+            create_room_with(&mut state, CreateGame::default());
+        let add_bot = AddBot {
+            room_id,
+            player_id: host_id,
+            bot_kind: None,
+        };
+        send(&mut state, &mut host, add_bot);
+        assert_eq!(
+            received(&mut host_messages),
+            vec![Error {
+                message: "Bots aren't available on this server".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn private_rooms_are_left_out_of_the_room_list() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        for (name, private) in [("Ada", true), ("Bea", false)] {
+            let request = CreateGame {
+                player_name: name.to_owned(),
+                private: Some(private),
+                ..CreateGame::default()
+            };
+            send(&mut state, &mut host, ClientAction::CreateGame(request));
+        }
+        received(&mut host_messages);
+
+        let list_rooms = ListRooms {
+            game_type: None,
+            open_seats: None,
+            search: None,
+        };
+        send(&mut state, &mut host, list_rooms);
+        match received(&mut host_messages).as_slice() {
+            [ServerAction::ListRooms { rooms }] => {
+                assert_eq!(rooms.len(), 1);
+                assert_eq!(rooms[0].host_name, "Bea");
+            }
+            other => panic!("expected the room list, got {:?}", other),
+        }
+    }
+
+    fn join_room(
+        state: &mut MainState,
+        room_id: u32,
+        connection: &mut Connection,
+        receiver: &mut OutboxReceiver,
+    ) -> String {
+        let join_room = JoinRoom {
+            room_id,
+            player_name: "Bea".to_owned(),
+            password: None,
+            invite_token: None,
+        };
+        send(state, connection, join_room);
+        received(receiver)
+            .into_iter()
+            .find_map(|action| match action {
+                ServerAction::JoinRoom { player_id, .. } => Some(player_id),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn players_can_only_act_from_their_own_connection() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        let request = CreateGame {
+            player_name: "Ada".to_owned(),
+            starting_chips: Some(100),
+            ..CreateGame::default()
+        };
+        send(&mut state, &mut host, ClientAction::CreateGame(request));
+        let (room_id, ada) = match received(&mut host_messages).as_slice() {
+            [ServerAction::CreateGame {
+                room_id, player_id, ..
+            }] => (*room_id, player_id.clone()),
+            other => panic!("expected the new room, got {:?}", other),
+        };
+        let (mut guest, mut guest_messages) = connect(&mut state);
+        let bea = join_room(&mut state, room_id, &mut guest, &mut guest_messages);
+        received(&mut host_messages);
+
+        let transfer = |player_id: &str, to_player_id: &str| TransferChips {
+            room_id,
+            player_id: player_id.to_owned(),
+            to_player_id: to_player_id.to_owned(),
+            amount: 50,
+        };
+        send(&mut state, &mut guest, transfer(&ada, &bea));
+        assert!(matches!(
+            received(&mut guest_messages).as_slice(),
+            [Error { .. }]
+        ));
+        assert!(received(&mut host_messages).is_empty());
+
+        send(&mut state, &mut guest, transfer(&bea, &ada));
+        assert!(matches!(
+            received(&mut host_messages).as_slice(),
+            [ServerAction::ChipsUpdated { .. }]
+        ));
+    }
+
+    #[test]
+    fn chat_goes_out_under_the_name_of_the_senders_seat() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        let (room_id, ada) = create_game(&mut state, &mut host, &mut host_messages);
+        let (mut guest, mut guest_messages) = connect(&mut state);
+        let bea = join_room(&mut state, room_id, &mut guest, &mut guest_messages);
+        received(&mut host_messages);
+
+        let chat = |player_id: &str| Chat {
+            room_id,
+            player_id: player_id.to_owned(),
+            message: "hi".to_owned(),
+        };
+        send(&mut state, &mut guest, chat(&ada));
+        assert!(matches!(
+            received(&mut guest_messages).as_slice(),
+            [Error { .. }]
+        ));
+        assert!(received(&mut host_messages).is_empty());
+
+        send(&mut state, &mut guest, chat(&bea));
+        assert_eq!(
+            received(&mut host_messages),
+            vec![ServerAction::Chat {
+                room_id,
+                player_name: "Bea".to_owned(),
+                message: "hi".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_the_hosts_connection_can_hand_out_invites() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        let (room_id, ada) = create_game(&mut state, &mut host, &mut host_messages);
+        let (mut guest, mut guest_messages) = connect(&mut state);
+        join_room(&mut state, room_id, &mut guest, &mut guest_messages);
+
+        let create_invite_token = CreateInviteToken {
+            room_id,
+            player_id: ada,
+        };
+        send(&mut state, &mut guest, create_invite_token.clone());
+        assert!(matches!(
+            received(&mut guest_messages).as_slice(),
+            [Error { .. }]
+        ));
+        send(&mut state, &mut host, create_invite_token);
+        assert!(received(&mut host_messages)
+            .iter()
+            .any(|action| matches!(action, ServerAction::CreateInviteToken { .. })));
+    }
+
+    #[test]
+    fn players_can_only_vote_on_an_undo_for_themselves() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        let request = CreateGame {
+            player_name: "Ada".to_owned(),
+            undo: Some(UndoPolicy::Vote),
+            ..CreateGame::default()
+        };
+        send(&mut state, &mut host, ClientAction::CreateGame(request));
+        let (room_id, ada) = match received(&mut host_messages).as_slice() {
+            [ServerAction::CreateGame {
+                room_id, player_id, ..
+            }] => (*room_id, player_id.clone()),
+            other => panic!("expected the new room, got {:?}", other),
+        };
+        let (mut bea, mut bea_messages) = connect(&mut state);
+        let bea_id = join_room(&mut state, room_id, &mut bea, &mut bea_messages);
+        let (mut cy, mut cy_messages) = connect(&mut state);
+        let cy_id = join_room(&mut state, room_id, &mut cy, &mut cy_messages);
+        let draw_card = DrawCard {
+            room_id,
+            player_id: bea_id.clone(),
+        };
+        send(&mut state, &mut bea, draw_card);
+        let undo = Undo {
+            room_id,
+            player_id: ada,
+        };
+        send(&mut state, &mut host, undo);
+        received(&mut bea_messages);
+
+        let vote_undo = |player_id: &str| VoteUndo {
+            room_id,
+            player_id: player_id.to_owned(),
+            approve: true,
+        };
+        send(&mut state, &mut bea, vote_undo(&cy_id));
+        assert!(matches!(
+            received(&mut bea_messages).as_slice(),
+            [Error { .. }]
+        ));
+        send(&mut state, &mut bea, vote_undo(&bea_id));
+        assert!(!received(&mut bea_messages)
+            .iter()
+            .any(|action| matches!(action, ServerAction::Undone { .. })));
+        received(&mut cy_messages);
+        send(&mut state, &mut cy, vote_undo(&cy_id));
+        assert!(received(&mut cy_messages)
+            .iter()
+            .any(|action| matches!(action, ServerAction::Undone { .. })));
+    }
+
+    #[test]
+    fn quitting_as_someone_who_isnt_in_the_room_is_an_error() {
+        let mut state = MainState::default();
+        let (mut host, mut host_messages) = connect(&mut state);
+        let (room_id, _) = create_game(&mut state, &mut host, &mut host_messages);
+        let (mut stranger, mut stranger_messages) = connect(&mut state);
+        let quit = Quit {
+            room_id,
+            player_id: "made up".to_owned(),
+        };
+        send(&mut state, &mut stranger, quit);
+        assert!(matches!(
+            received(&mut stranger_messages).as_slice(),
+            [Error { .. }]
+        ));
+        assert_eq!(state.rooms.len(), 1);
+    }
+
+    #[test]
+    fn requests_for_a_room_that_doesnt_exist_get_an_error() {
+        let mut state = MainState::default();
+        let (mut connection, mut messages) = connect(&mut state);
+        let join_room = JoinRoom {
+            room_id: 1,
+            player_name: "Bea".to_owned(),
+            password: None,
+            invite_token: None,
+        };
+        send(&mut state, &mut connection, join_room);
+        assert_eq!(
+            received(&mut messages),
+            vec![Error {
+                message: "Room 1 doesn't exist".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn new_rooms_never_reuse_the_id_of_an_open_or_saved_room() {
+        let mut state = MainState {
+            saved: (1000..=9999).filter(|room_id| *room_id != 5000).collect(),
+            ..MainState::default()
+        };
+        let (mut connection, mut messages) = connect(&mut state);
+        let (room_id, _) = create_game(&mut state, &mut connection, &mut messages);
+        assert_eq!(room_id, 5000);
+
+        let (mut connection, mut messages) = connect(&mut state);
+        let request = CreateGame {
+            player_name: "Bea".to_owned(),
+            ..CreateGame::default()
+        };
+        send(
+            &mut state,
+            &mut connection,
+            ClientAction::CreateGame(request),
+        );
+        assert_eq!(
+            received(&mut messages),
+            vec![Error {
+                message: "There's no room for another game, try again later".to_owned()
+            }]
+        );
+        assert_eq!(state.rooms.len(), 1);
+    }
+}
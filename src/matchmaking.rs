@@ -0,0 +1,228 @@
+use std::time::{Duration, Instant};
+
+use crate::{outbox::Outbox, room_settings::GameType};
+
+#[derive(Debug)]
+pub struct MatchRequest {
+    pub player_name: String,
+    pub game_type: GameType,
+    pub player_count: usize,
+    pub sender: Outbox,
+    enqueued_at: Instant,
+}
+
+impl MatchRequest {
+    pub fn new(
+        player_name: &str,
+        game_type: GameType,
+        player_count: usize,
+        sender: Outbox,
+    ) -> Self {
+        Self {
+            player_name: player_name.to_owned(),
+            game_type,
+            player_count,
+            sender,
+            enqueued_at: Instant::now(),
+        }
+    }
+
+    fn is_compatible(&self, other: &MatchRequest) -> bool {
+        self.game_type == other.game_type && self.player_count == other.player_count
+    }
+}
+
+/// Players waiting for quick-match, oldest first.
+#[derive(Debug, Default)]
+pub struct Matchmaker {
+    queue: Vec<MatchRequest>,
+}
+
+impl Matchmaker {
+    pub const TIMEOUT: Duration = Duration::from_secs(60);
+    pub const MIN_PLAYERS: usize = 2;
+
+    /// The number of players to find for a match of `game_type`, as close to `requested` as
+    /// the game allows. Only games played alone are matched for a single player.
+    pub fn player_count(game_type: GameType, requested: usize) -> usize {
+        let player_counts = game_type.player_counts();
+        requested
+            .max(Self::MIN_PLAYERS)
+            .clamp(*player_counts.start(), *player_counts.end())
+    }
+
+    /// A connection can only wait for one match at a time, so enqueueing again replaces the
+    /// previous request.
+    pub fn enqueue(&mut self, request: MatchRequest) {
+        self.cancel(&request.sender);
+        self.queue.push(request);
+    }
+
+    pub fn cancel(&mut self, sender: &Outbox) -> bool {
+        let queue_length = self.queue.len();
+        self.queue
+            .retain(|request| !request.sender.same_queue(sender));
+        self.queue.len() != queue_length
+    }
+
+    /// Takes the longest waiting group of players that want the same game with the same number
+    /// of players, once enough of them are queued.
+    pub fn take_match(&mut self) -> Option<Vec<MatchRequest>> {
+        self.queue.retain(|request| !request.sender.is_closed());
+        let indexes = self.queue.iter().find_map(|request| {
+            let indexes: Vec<usize> = self
+                .queue
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| request.is_compatible(other))
+                .map(|(index, _)| index)
+                .take(request.player_count)
+                .collect();
+            if indexes.len() == request.player_count {
+                Some(indexes)
+            } else {
+                None
+            }
+        })?;
+
+        let mut matched = vec![];
+        for index in indexes.into_iter().rev() {
+            matched.push(self.queue.remove(index));
+        }
+        matched.reverse();
+        Some(matched)
+    }
+
+    pub fn take_expired(&mut self) -> Vec<MatchRequest> {
+        let (expired, waiting) = self
+            .queue
+            .drain(..)
+            .partition(|request| request.enqueued_at.elapsed() >= Self::TIMEOUT);
+        self.queue = waiting;
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::outbox::{outbox, OutboxConfig, OutboxReceiver};
+
+    fn request(name: &str, player_count: usize) -> (MatchRequest, OutboxReceiver) {
+        let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        (
+            MatchRequest::new(name, GameType::FreePlay, player_count, sender),
+            receiver,
+        )
+    }
+
+    fn names(matched: &[MatchRequest]) -> Vec<&str> {
+        matched
+            .iter()
+            .map(|request| request.player_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn matches_are_for_as_many_players_as_the_game_takes() {
+        for (game_type, requested, player_count) in [
+            (GameType::Hearts, 2, 4),
+            (GameType::Spades, 16, 4),
+            (GameType::Euchre, 4, 4),
+            (GameType::Klondike, 4, 1),
+            (GameType::FreeCell, 0, 1),
+            (GameType::Gin, 3, 2),
+            (GameType::Rummy, 10, 6),
+            (GameType::CrazyEights, 5, 5),
+            (GameType::FreePlay, 1, 2),
+            (GameType::Blackjack, 100, 16),
+        ] {
+            assert_eq!(
+                Matchmaker::player_count(game_type, requested),
+                player_count,
+                "{:?} for {} players",
+                game_type,
+                requested
+            );
+        }
+    }
+
+    #[test]
+    fn a_match_is_made_once_enough_players_want_the_same_game() {
+        let mut matchmaker = Matchmaker::default();
+        let mut receivers = vec![];
+        for (name, player_count) in [("Ada", 3), ("Bea", 2), ("Cy", 3)] {
+            let (request, receiver) = request(name, player_count);
+            matchmaker.enqueue(request);
+            receivers.push(receiver);
+        }
+        assert!(matchmaker.take_match().is_none());
+
+        let (request, _receiver) = request("Di", 3);
+        matchmaker.enqueue(request);
+        assert_eq!(
+            names(&matchmaker.take_match().unwrap()),
+            ["Ada", "Cy", "Di"]
+        );
+        assert!(matchmaker.take_match().is_none());
+    }
+
+    #[test]
+    fn cancelled_and_disconnected_players_are_not_matched() {
+        let mut matchmaker = Matchmaker::default();
+        let (ada, _ada_receiver) = request("Ada", 2);
+        let ada_sender = ada.sender.clone();
+        matchmaker.enqueue(ada);
+        let (bea, bea_receiver) = request("Bea", 2);
+        matchmaker.enqueue(bea);
+        drop(bea_receiver);
+        assert!(matchmaker.cancel(&ada_sender));
+        assert!(!matchmaker.cancel(&ada_sender));
+
+        let (cy, _cy_receiver) = request("Cy", 2);
+        matchmaker.enqueue(cy);
+        assert!(matchmaker.take_match().is_none());
+        let (di, _di_receiver) = request("Di", 2);
+        matchmaker.enqueue(di);
+        assert_eq!(names(&matchmaker.take_match().unwrap()), ["Cy", "Di"]);
+    }
+
+    #[test]
+    fn enqueueing_again_replaces_the_earlier_request() {
+        let mut matchmaker = Matchmaker::default();
+        let (first, _receiver) = request("Ada", 2);
+        let sender = first.sender.clone();
+        matchmaker.enqueue(first);
+        matchmaker.enqueue(MatchRequest::new("Ada", GameType::FreePlay, 2, sender));
+        assert!(matchmaker.take_match().is_none());
+    }
+
+    #[test]
+    fn requests_expire_after_the_timeout() {
+        let mut matchmaker = Matchmaker::default();
+        let (mut waited, _waited_receiver) = request("Ada", 2);
+        waited.enqueued_at = Instant::now() - Matchmaker::TIMEOUT;
+        matchmaker.enqueue(waited);
+        let (fresh, _fresh_receiver) = request("Bea", 3);
+        matchmaker.enqueue(fresh);
+
+        assert_eq!(names(&matchmaker.take_expired()), ["Ada"]);
+        assert!(matchmaker.take_expired().is_empty());
+    }
+
+    #[test]
+    fn solitaire_players_are_matched_alone() {
+        let mut matchmaker = Matchmaker::default();
+        let (sender, _receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let player_count = Matchmaker::player_count(GameType::Klondike, 4);
+        matchmaker.enqueue(MatchRequest::new(
+            "Ada",
+            GameType::Klondike,
+            player_count,
+            sender,
+        ));
+        assert_eq!(matchmaker.take_match().unwrap().len(), 1);
+    }
+}
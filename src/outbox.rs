@@ -0,0 +1,298 @@
+use std::{
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use eyre::{bail, Result};
+use futures::{
+    channel::mpsc::{unbounded, TryRecvError, UnboundedReceiver, UnboundedSender},
+    Stream, StreamExt,
+};
+
+use crate::message::ServerMessage;
+
+/// What happens to a connection that isn't reading its messages as fast as they're sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Close the connection, the client can join again when it's able to keep up.
+    #[default]
+    Disconnect,
+    /// Drop messages until the client catches up, then send it a snapshot of each room it's in.
+    Coalesce,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = eyre::Report;
+
+    fn from_str(policy: &str) -> Result<Self> {
+        match policy {
+            "disconnect" => Ok(Self::Disconnect),
+            "coalesce" => Ok(Self::Coalesce),
+            _ => bail!(
+                "Unknown overflow policy {}, use disconnect or coalesce",
+                policy
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutboxConfig {
+    /// How many messages can wait to be written to one connection.
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            policy: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// Totals across every outgoing queue, for keeping an eye on slow clients.
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    open: AtomicUsize,
+    waiting: AtomicUsize,
+    deepest: AtomicUsize,
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl QueueMetrics {
+    /// How many connections still have a queue.
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self) -> String {
+        format!(
+            "outbound queues: {} open, {} messages waiting, deepest {}, {} dropped, {} slow clients disconnected",
+            self.open.load(Ordering::Relaxed),
+            self.waiting.load(Ordering::Relaxed),
+            self.deepest.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+            self.disconnected.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Queue {
+    config: OutboxConfig,
+    depth: AtomicUsize,
+    lagging: AtomicBool,
+    metrics: Arc<QueueMetrics>,
+}
+
+/// Creates the outgoing queue for one connection.
+pub fn outbox(config: OutboxConfig, metrics: &Arc<QueueMetrics>) -> (Outbox, OutboxReceiver) {
+    let (sender, receiver) = unbounded();
+    let queue = Arc::new(Queue {
+        config,
+        depth: AtomicUsize::new(0),
+        lagging: AtomicBool::new(false),
+        metrics: metrics.clone(),
+    });
+    metrics.open.fetch_add(1, Ordering::Relaxed);
+    (
+        Outbox {
+            sender,
+            queue: queue.clone(),
+        },
+        OutboxReceiver { receiver, queue },
+    )
+}
+
+/// An outbox nobody reads, for players who aren't connected. Sending to it fails.
+pub fn detached() -> Outbox {
+    let (outbox, _) = outbox(OutboxConfig::default(), &Arc::default());
+    outbox
+}
+
+/// The sending end of a connection's queue, clones all share the same queue.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    sender: UnboundedSender<ServerMessage>,
+    queue: Arc<Queue>,
+}
+
+impl Outbox {
+    /// Queues a message, unless the queue is full. Then the overflow policy decides what
+    /// happens, and the message is dropped either way.
+    pub fn send(&self, message: ServerMessage) -> Result<()> {
+        let queue = &self.queue;
+        if self.sender.is_closed() {
+            bail!("The connection is closed");
+        }
+        if queue.depth.load(Ordering::Relaxed) >= queue.config.capacity {
+            queue.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            match queue.config.policy {
+                OverflowPolicy::Disconnect => {
+                    queue.metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+                    self.sender.close_channel();
+                }
+                OverflowPolicy::Coalesce => queue.lagging.store(true, Ordering::Relaxed),
+            }
+            return Ok(());
+        }
+
+        // counted before sending, so the writer can never take more than was counted
+        let depth = queue.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        queue.metrics.waiting.fetch_add(1, Ordering::Relaxed);
+        if let Err(error) = self.sender.unbounded_send(message) {
+            queue.depth.fetch_sub(1, Ordering::Relaxed);
+            queue.metrics.waiting.fetch_sub(1, Ordering::Relaxed);
+            return Err(error.into());
+        }
+        queue.metrics.deepest.fetch_max(depth, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether messages were dropped and the queue has since drained to half its capacity, in
+    /// which case the client needs catching up. Only answers yes once per overflow.
+    pub fn recovered(&self) -> bool {
+        let queue = &self.queue;
+        queue.depth.load(Ordering::Relaxed) <= queue.config.capacity / 2
+            && queue.lagging.swap(false, Ordering::Relaxed)
+    }
+
+    /// Stops taking messages, what's already queued still goes out.
+    pub fn close(&self) {
+        self.sender.close_channel();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    pub fn same_queue(&self, other: &Outbox) -> bool {
+        self.sender.same_receiver(&other.sender)
+    }
+}
+
+/// The receiving end of a connection's queue, which the task writing to the websocket reads.
+#[derive(Debug)]
+pub struct OutboxReceiver {
+    receiver: UnboundedReceiver<ServerMessage>,
+    queue: Arc<Queue>,
+}
+
+impl OutboxReceiver {
+    pub fn try_recv(&mut self) -> Result<ServerMessage, TryRecvError> {
+        let message = self.receiver.try_recv()?;
+        self.taken();
+        Ok(message)
+    }
+
+    fn taken(&self) {
+        self.queue.depth.fetch_sub(1, Ordering::Relaxed);
+        self.queue.metrics.waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Stream for OutboxReceiver {
+    type Item = ServerMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let message = self.receiver.poll_next_unpin(context);
+        if let Poll::Ready(Some(_)) = message {
+            self.taken();
+        }
+        message
+    }
+}
+
+impl Drop for OutboxReceiver {
+    fn drop(&mut self) {
+        let metrics = &self.queue.metrics;
+        metrics.open.fetch_sub(1, Ordering::Relaxed);
+        metrics.waiting.fetch_sub(
+            self.queue.depth.swap(0, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::ServerAction;
+
+    fn message() -> ServerMessage {
+        ServerMessage::from(ServerAction::Error {
+            message: "hello".to_owned(),
+        })
+    }
+
+    fn config(policy: OverflowPolicy) -> OutboxConfig {
+        OutboxConfig {
+            capacity: 4,
+            policy,
+        }
+    }
+
+    #[test]
+    fn disconnects_a_client_that_falls_too_far_behind() {
+        let metrics = Arc::default();
+        let (outbox, mut receiver) = outbox(config(OverflowPolicy::Disconnect), &metrics);
+        for _ in 0..4 {
+            outbox.send(message()).unwrap();
+        }
+        assert!(!outbox.is_closed());
+        outbox.send(message()).unwrap();
+        assert!(outbox.is_closed());
+        assert!(outbox.send(message()).is_err());
+        // what was already queued still goes out before the connection ends
+        for _ in 0..4 {
+            assert!(receiver.try_recv().is_ok());
+        }
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Closed));
+        assert_eq!(metrics.disconnected.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn coalescing_drops_messages_until_the_client_catches_up() {
+        let metrics = Arc::default();
+        let (outbox, mut receiver) = outbox(config(OverflowPolicy::Coalesce), &metrics);
+        for _ in 0..6 {
+            outbox.send(message()).unwrap();
+        }
+        assert!(!outbox.is_closed());
+        assert_eq!(metrics.dropped.load(Ordering::Relaxed), 2);
+        assert!(!outbox.recovered());
+
+        receiver.try_recv().unwrap();
+        assert!(!outbox.recovered());
+        receiver.try_recv().unwrap();
+        assert!(outbox.recovered());
+        assert!(!outbox.recovered());
+    }
+
+    #[test]
+    fn metrics_follow_the_queues() {
+        let metrics = Arc::default();
+        let (first, mut first_receiver) = outbox(OutboxConfig::default(), &metrics);
+        let (second, second_receiver) = outbox(OutboxConfig::default(), &metrics);
+        for _ in 0..3 {
+            first.send(message()).unwrap();
+        }
+        second.send(message()).unwrap();
+        first_receiver.try_recv().unwrap();
+        assert_eq!(metrics.open.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.waiting.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.deepest.load(Ordering::Relaxed), 3);
+
+        drop(second_receiver);
+        assert_eq!(metrics.open.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.waiting.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,58 @@
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A room password that is only ever kept as a salted hash.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HashedPassword {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+
+impl HashedPassword {
+    pub fn new(password: &str) -> Self {
+        let mut salt = [0; 16];
+        thread_rng().fill_bytes(&mut salt);
+        let hash = Self::hash(&salt, password);
+        Self { salt, hash }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        let hash = Self::hash(&self.salt, password);
+        // compare every byte so the time taken doesn't leak how much of the hash matched
+        hash.iter()
+            .zip(self.hash.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+    }
+
+    fn hash(salt: &[u8], password: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(password.as_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_same_password_verifies() {
+        let password = HashedPassword::new("secret");
+        assert!(password.verify("secret"));
+        assert!(!password.verify("Secret"));
+        assert!(!password.verify("secret "));
+        assert!(!password.verify(""));
+    }
+
+    #[test]
+    fn the_same_password_is_salted_differently_each_time() {
+        let first = HashedPassword::new("secret");
+        let second = HashedPassword::new("secret");
+        assert_ne!(first.salt, second.salt);
+        assert_ne!(first.hash, second.hash);
+        assert!(second.verify("secret"));
+    }
+}
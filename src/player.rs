@@ -1,34 +1,105 @@
+use std::time::Instant;
+
 use crate::{
+    bot::BotKind,
     card::{Card, CardData},
-    message::CustomMessage,
+    message::ServerMessage,
+    outbox::{detached, Outbox},
 };
-use async_tungstenite::tungstenite::Message;
 use eyre::Result;
-use futures::channel::mpsc::UnboundedSender;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub struct Player {
     pub name: String,
-    sender: UnboundedSender<Message>,
+    sender: Outbox,
     pub id: String,
     pub hand: Vec<Card>,
+    pub chips: u64,
+    pub team: Option<String>,
+    pub bot: Option<BotKind>,
+    /// Lets whoever holds it take the seat back on a new connection.
+    pub session_token: String,
+    /// Set for players restored from a saved room until they reclaim their seat.
+    pub disconnected_at: Option<Instant>,
 }
 
 impl Player {
-    pub fn new(name: &str, sender: UnboundedSender<Message>) -> Self {
+    pub fn new(name: &str, sender: Outbox) -> Self {
         Self {
             name: name.to_owned(),
             sender,
             id: Uuid::new_v4().to_string(),
             hand: vec![],
+            chips: 0,
+            team: None,
+            bot: None,
+            session_token: Uuid::new_v4().to_string(),
+            disconnected_at: None,
+        }
+    }
+
+    /// A player run by the server, whose messages go to an agent instead of a websocket. The
+    /// agent connects once it's started.
+    pub fn new_bot(name: &str, bot_kind: BotKind) -> Self {
+        Self {
+            bot: Some(bot_kind),
+            ..Self::new(name, detached())
+        }
+    }
+
+    /// Puts a player back from a saved room, without a connection until they reclaim it.
+    pub fn restore(saved: SavedPlayer) -> Self {
+        Self {
+            name: saved.name,
+            sender: detached(),
+            id: saved.id,
+            hand: saved.hand,
+            chips: saved.chips,
+            team: saved.team,
+            bot: saved.bot,
+            session_token: saved.session_token,
+            disconnected_at: Some(Instant::now()),
+        }
+    }
+
+    pub fn save(&self) -> SavedPlayer {
+        SavedPlayer {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            hand: self.hand.clone(),
+            chips: self.chips,
+            team: self.team.clone(),
+            bot: self.bot,
+            session_token: self.session_token.clone(),
         }
     }
 
-    pub fn send(&mut self, message: CustomMessage) -> Result<()> {
-        self.sender.unbounded_send(message.into())?;
-        Ok(())
+    pub fn reconnect(&mut self, sender: Outbox) {
+        self.sender = sender;
+        self.disconnected_at = None;
+    }
+
+    /// Hands a rebuilt player the connection and session of the player they stand in for.
+    pub fn reconnect_as(&mut self, old: &Player) {
+        self.sender = old.sender.clone();
+        self.session_token = old.session_token.clone();
+        self.disconnected_at = old.disconnected_at;
+    }
+
+    pub fn send(&mut self, message: ServerMessage) -> Result<()> {
+        self.sender.send(message)
+    }
+
+    pub fn is_sent_to(&self, outbox: &Outbox) -> bool {
+        self.sender.same_queue(outbox)
+    }
+
+    /// Whether messages to this player were dropped and they've since caught up.
+    pub fn recovered(&self) -> bool {
+        self.sender.recovered()
     }
 
     pub fn add_card(&mut self, card: Card) {
@@ -55,6 +126,9 @@ impl Player {
             name: self.name.clone(),
             id: self.id.clone(),
             hand: self.hand.iter().map(|card| card.card_data()).collect(),
+            chips: self.chips,
+            team: self.team.clone(),
+            bot: self.bot.is_some(),
         }
     }
 
@@ -71,9 +145,24 @@ impl Player {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct PlayerData {
     name: String,
     id: String,
     hand: Vec<CardData>,
+    chips: u64,
+    team: Option<String>,
+    bot: bool,
+}
+
+/// A player as kept in a saved room.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedPlayer {
+    name: String,
+    id: String,
+    hand: Vec<Card>,
+    chips: u64,
+    team: Option<String>,
+    bot: Option<BotKind>,
+    session_token: String,
 }
@@ -1,229 +1,1434 @@
-use eyre::Result;
-use rand::seq::SliceRandom;
+use std::{panic, sync::Arc, time::Duration};
+
+use async_std::task;
+use eyre::{bail, eyre, Result};
+use futures::channel::mpsc::UnboundedSender;
 use rand::{thread_rng, Rng};
+use uuid::Uuid;
 
 use crate::player::PlayerData;
 use crate::{
-    actions::Action::JoinRoom,
-    card::{Card, Suite, Value},
-    message::{CustomMessage, CustomMessageBuilder},
+    actions::ServerAction,
+    bot::{run_bot, BotKind, BotTurn},
+    card::{Card, CardData},
+    chips::{ChipsData, PlayerChips, Pot},
+    connection::Connection,
+    event_log::EventLog,
+    history::{self, History, HistoryEntry, PlacedCard, RoomEvent},
+    lobby::RoomSummary,
+    message::{ClientMessage, ServerMessage},
+    outbox::{detached, outbox, Outbox, OutboxConfig},
+    password::HashedPassword,
     player::Player,
+    room_settings::{GameType, RoomSettings, UndoPolicy},
+    room_store::SavedRoom,
+    rules::{self, new_rules, GameMove, GameRules, GameState, PlayerScore, Position, Solvability},
+    scoreboard::Scoreboard,
+    table::Table,
+    team::{seat_by_team, Team, TeamData},
 };
 
 #[derive(Debug)]
 pub struct Room {
     pub id: u32,
-    players: Vec<Player>,
-    pub draw_deck: Vec<Card>,
-    pub discard_deck: Vec<Card>,
+    table: Table,
+    rules: Box<dyn GameRules>,
+    host_id: String,
+    password: Option<HashedPassword>,
+    settings: RoomSettings,
+    invite_tokens: Vec<String>,
+    scoreboard: Scoreboard,
+    pot: Pot,
+    teams: Vec<Team>,
+    history: History,
+    undo_vote: Option<UndoVote>,
+    /// Whether anything may have changed since the room was last saved.
+    unsaved: bool,
+}
+
+/// What the solver found for a room, on its way back to it.
+#[derive(Debug)]
+pub struct Solved {
+    pub room_id: u32,
+    pub position: Position,
+    pub solvability: Solvability,
+}
+
+/// An undo waiting on the other players to agree to it.
+#[derive(Debug, Clone)]
+struct UndoVote {
+    requester: String,
+    number: u64,
+    approvals: Vec<String>,
 }
 
 impl Room {
-    pub fn new(player: Player) -> Result<Self> {
+    pub fn new(
+        id: u32,
+        mut player: Player,
+        password: Option<&str>,
+        settings: RoomSettings,
+        request_id: Option<String>,
+    ) -> Result<Self> {
+        player.chips = settings.starting_chips;
         let player_name = player.name.clone();
-        let mut rng = thread_rng();
-        let id = rng.gen_range(1000..=9999);
         let player_id = player.id.clone();
-        let players = vec![player];
-        let draw_deck = vec![];
-        let discard_deck = vec![];
-        let mut room = Self {
+        let session_token = player.session_token.clone();
+        let joined = RoomEvent::Joined {
+            player_name: player_name.clone(),
+            bot: player.bot,
+        };
+        let mut room = Self::empty(id, &player_id, password, settings, thread_rng().gen());
+        let before = room.layout();
+        room.table.players.push(player);
+        room.record(&player_id, joined, &before);
+        room.set_requester(&player_id, request_id);
+        let message_to_host = ServerAction::CreateGame {
+            room_id: room.id,
+            seq: room.table.events.last_seq(),
+            player_name,
+            player_id: player_id.clone(),
+            session_token,
+            draw_deck_size: room.table.draw_deck.len(),
+            private: room.settings.private,
+            max_players: room.settings.max_players,
+            game_type: room.settings.game_type,
+            deck: room.settings.deck,
+            chips: room.chips_data(),
+        };
+        room.table.send_to_player(&player_id, message_to_host)?;
+        Ok(room)
+    }
+
+    /// Opens a room for players grouped together by matchmaking. The first player becomes the
+    /// host, and everyone gets the same payload as if they had joined by room id.
+    pub fn new_matched(id: u32, players: Vec<Player>, settings: RoomSettings) -> Result<Self> {
+        let host_id = players
+            .first()
+            .map(|player| player.id.clone())
+            .unwrap_or_default();
+        let mut room = Self::empty(id, &host_id, None, settings, thread_rng().gen());
+        for player in players {
+            room.join(player)?;
+        }
+        Ok(room)
+    }
+
+    fn empty(
+        id: u32,
+        host_id: &str,
+        password: Option<&str>,
+        settings: RoomSettings,
+        seed: u64,
+    ) -> Self {
+        let password = password
+            .filter(|password| !password.is_empty())
+            .map(HashedPassword::new);
+        let rules = new_rules(&settings);
+        let target_score = rules.target_score().or(settings.target_score);
+        Self {
             id,
-            players,
-            draw_deck,
-            discard_deck,
-        };
-        room.reset_draw_deck();
-        let message = CustomMessageBuilder::new()
-            .set_action(crate::actions::Action::CreateGame)
-            .set_room_id(id)
-            .set_player_name(&player_name)
-            .set_draw_deck_size(room.draw_deck.len())
-            .set_player_id(player_id)
-            .build()?;
-        room.broadcast_to_room(message)?;
+            table: Table::new(settings.deck, seed),
+            rules,
+            scoreboard: Scoreboard::new(target_score, settings.game_type.low_score_wins()),
+            host_id: host_id.to_owned(),
+            password,
+            settings,
+            invite_tokens: vec![],
+            pot: Pot::default(),
+            teams: vec![],
+            history: History::new(seed),
+            undo_vote: None,
+            unsaved: true,
+        }
+    }
+
+    /// Builds a room again from nothing but its history, which leaves it the same as the room
+    /// the history came from.
+    #[cfg(test)]
+    pub fn replay(history: &History, settings: RoomSettings) -> Result<Self> {
+        Self::rebuild(0, history.seed, &history.effective(), settings)
+    }
+
+    /// Plays `entries` out in a new room whose table shuffles from `seed`.
+    fn rebuild(
+        id: u32,
+        seed: u64,
+        entries: &[&HistoryEntry],
+        settings: RoomSettings,
+    ) -> Result<Self> {
+        let host_id = entries
+            .first()
+            .map(|entry| entry.player_id.clone())
+            .unwrap_or_default();
+        let mut room = Self::empty(id, &host_id, None, settings, seed);
+        for entry in entries {
+            room.apply_event(&entry.player_id, entry.event.clone())?;
+        }
         Ok(room)
     }
 
-    pub fn broadcast_to_room(&mut self, message: CustomMessage) -> Result<()> {
-        for player in &mut self.players {
-            player.send(message.clone())?;
+    fn apply_event(&mut self, player_id: &str, event: RoomEvent) -> Result<()> {
+        let missing_card = || eyre!("The history is missing a card");
+        match event {
+            RoomEvent::Joined { player_name, bot } => {
+                let mut player = match bot {
+                    Some(bot_kind) => Player::new_bot(&player_name, bot_kind),
+                    None => Player::new(&player_name, detached()),
+                };
+                player.id = player_id.to_owned();
+                self.join(player)
+            }
+            RoomEvent::Left => self.remove_player_by_id(player_id),
+            RoomEvent::Moved { game_move } => self.handle_move(player_id, game_move),
+            RoomEvent::ScoresRecorded { scores } => self.record_scores(player_id, scores),
+            RoomEvent::Bet { amount } => self.bet(player_id, amount),
+            RoomEvent::PotCollected => self.collect_pot(player_id),
+            RoomEvent::ChipsTransferred {
+                to_player_id,
+                amount,
+            } => self.transfer_chips(player_id, &to_player_id, amount),
+            RoomEvent::TeamsDefined { team_names } => self.define_teams(player_id, &team_names),
+            RoomEvent::TeamAssigned { to_player_id, team } => {
+                self.assign_team(player_id, &to_player_id, team.as_deref())
+            }
+            RoomEvent::PlayedToTeamPile { card } => {
+                self.play_to_team_pile(player_id, &card.ok_or_else(missing_card)?)
+            }
+            RoomEvent::TookFromTeamPile { card } => {
+                self.take_from_team_pile(player_id, &card.ok_or_else(missing_card)?)
+            }
+            // undone entries are left out before the history gets this far
+            RoomEvent::Undone { .. } => Ok(()),
+            RoomEvent::DiscardedFaceDown => Err(missing_card()),
         }
-        Ok(())
     }
 
-    pub fn broadcast_to_everyone_else(
-        &mut self,
-        message: CustomMessage,
-        player_id: &str,
-    ) -> Result<()> {
-        for player in &mut self.players {
-            if player.id != player_id {
-                player.send(message.clone())?;
+    /// Where the cards are now, to compare against once an event has moved them.
+    fn layout(&self) -> Vec<PlacedCard> {
+        history::layout(&self.table, &self.teams)
+    }
+
+    fn record(&mut self, player_id: &str, event: RoomEvent, before: &[PlacedCard]) {
+        self.history
+            .record(player_id, event, before, &self.table, &self.teams);
+    }
+
+    /// Sends the player everything that has happened in the room, leaving out other players'
+    /// hidden cards.
+    pub fn send_history(&mut self, player_id: &str) -> Result<()> {
+        if self.table.player(player_id).is_none() {
+            bail!("You aren't in room {}", self.id);
+        }
+        let message_to_player = ServerAction::History {
+            room_id: self.id,
+            entries: self.history.public(),
+        };
+        self.table.send_to_player(player_id, message_to_player)
+    }
+
+    /// Brings back a room saved before the server restarted. Its players get their seats back
+    /// by reclaiming them, and its bots carry on playing straight away.
+    pub fn restore(
+        saved: SavedRoom,
+        bot_moves: Option<UnboundedSender<ClientMessage>>,
+    ) -> Result<Self> {
+        let mut rules = new_rules(&saved.settings);
+        rules.restore(saved.rules)?;
+        let mut table = Table::new(saved.settings.deck, saved.history.seed);
+        table.shuffles = saved.shuffles;
+        table.players = saved.players.into_iter().map(Player::restore).collect();
+        table.draw_deck = saved.draw_deck;
+        table.discard_deck = saved.discard_deck;
+        table.events = EventLog::starting_at(saved.last_seq);
+        let mut room = Self {
+            id: saved.id,
+            table,
+            rules,
+            host_id: saved.host_id,
+            password: saved.password,
+            settings: saved.settings,
+            invite_tokens: saved.invite_tokens,
+            scoreboard: saved.scoreboard,
+            pot: saved.pot,
+            teams: saved.teams,
+            history: saved.history,
+            undo_vote: None,
+            unsaved: false,
+        };
+        if let Some(bot_moves) = bot_moves {
+            for bot in room.table.players.iter_mut() {
+                if let Some(bot_kind) = bot.bot {
+                    start_bot(saved.id, bot, bot_kind, bot_moves.clone());
+                }
+            }
+            room.send_game_views()?;
+        }
+        Ok(room)
+    }
+
+    pub fn save(&self) -> Result<SavedRoom> {
+        Ok(SavedRoom {
+            id: self.id,
+            host_id: self.host_id.clone(),
+            password: self.password.clone(),
+            settings: self.settings.clone(),
+            invite_tokens: self.invite_tokens.clone(),
+            scoreboard: self.scoreboard.clone(),
+            pot: self.pot.clone(),
+            teams: self.teams.clone(),
+            players: self.table.players.iter().map(Player::save).collect(),
+            draw_deck: self.table.draw_deck.clone(),
+            discard_deck: self.table.discard_deck.clone(),
+            rules: self.rules.save()?,
+            last_seq: self.table.events.last_seq(),
+            history: self.history.clone(),
+            shuffles: self.table.shuffles,
+        })
+    }
+
+    /// Marks the room as needing to be saved again.
+    pub fn mark_unsaved(&mut self) {
+        self.unsaved = true;
+    }
+
+    /// Whether the room needs saving, which it won't again until it's marked unsaved.
+    pub fn take_unsaved(&mut self) -> bool {
+        std::mem::take(&mut self.unsaved)
+    }
+
+    /// Remembers who sent the request being handled, so direct replies to them carry its id.
+    pub fn set_requester(&mut self, player_id: &str, request_id: Option<String>) {
+        self.table.set_requester(player_id, request_id);
+    }
+
+    /// Whether `player_id` is a seat `connection` holds. A seat belongs to the connection its
+    /// messages go to, and bots to the connection their moves come in on.
+    pub fn is_seat_of(&self, player_id: &str, connection: &Connection) -> bool {
+        self.table.player(player_id).is_some_and(|player| {
+            player.is_sent_to(&connection.sender) || (connection.moves_bots && player.bot.is_some())
+        })
+    }
+
+    pub fn is_host(&self, player_id: &str) -> bool {
+        self.host_id == player_id
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.settings.private
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.table.players.len() >= self.settings.max_players
+    }
+
+    /// Rooms with nobody but bots left in them are empty too.
+    pub fn is_empty(&self) -> bool {
+        self.table.players.iter().all(|player| player.bot.is_some())
+    }
+
+    pub fn summary(&self) -> RoomSummary {
+        let host_name = self
+            .table
+            .players
+            .iter()
+            .find(|player| self.is_host(&player.id))
+            .map(|player| player.name.clone())
+            .unwrap_or_default();
+        RoomSummary {
+            room_id: self.id,
+            host_name,
+            player_count: self.table.players.len(),
+            capacity: self.settings.max_players,
+            game_type: self.settings.game_type,
+            deck: self.settings.deck,
+            in_progress: self.rules.in_progress(&self.table),
+        }
+    }
+
+    /// Checks whether someone may join, either with the room password or with one of the
+    /// invite tokens handed out by the host. Private rooms without a password can only be
+    /// joined with an invite token.
+    pub fn authorize(&self, password: Option<&str>, invite_token: Option<&str>) -> bool {
+        if invite_token.is_some_and(|invite_token| {
+            self.invite_tokens.iter().any(|token| token == invite_token)
+        }) {
+            return true;
+        }
+
+        match &self.password {
+            Some(hashed_password) => {
+                password.is_some_and(|password| hashed_password.verify(password))
             }
+            None => !self.is_private(),
         }
+    }
+
+    /// Invite tokens can only be used once, so they're used up once someone has joined with
+    /// one.
+    pub fn consume_invite(&mut self, invite_token: Option<&str>) {
+        self.invite_tokens
+            .retain(|token| Some(token.as_str()) != invite_token);
+    }
+
+    /// Replaces the room password, or removes it when no password is given. Any invite tokens
+    /// that haven't been used yet stop working.
+    pub fn rotate_password(&mut self, player_id: &str, password: Option<&str>) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can change the room password");
+        }
+
+        self.password = password
+            .filter(|password| !password.is_empty())
+            .map(HashedPassword::new);
+        self.invite_tokens.clear();
+        let text_message = if self.password.is_some() {
+            "Room password changed"
+        } else {
+            "Room password removed"
+        };
+        let message_to_host = ServerAction::RotatePassword {
+            room_id: self.id,
+            message: text_message.to_owned(),
+        };
+        self.table.send_to_player(player_id, message_to_host)?;
+        Ok(())
+    }
+
+    pub fn create_invite_token(&mut self, player_id: &str) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can create invite tokens");
+        }
+
+        let invite_token = Uuid::new_v4().to_string();
+        self.invite_tokens.push(invite_token.clone());
+        let message_to_host = ServerAction::CreateInviteToken {
+            room_id: self.id,
+            invite_token,
+        };
+        self.table.send_to_player(player_id, message_to_host)?;
         Ok(())
     }
 
+    pub fn broadcast_to_room(&mut self, message: ServerAction) -> Result<()> {
+        self.table.broadcast_to_room(message)
+    }
+
     pub fn join(&mut self, mut player: Player) -> Result<()> {
+        player.chips = self.settings.starting_chips;
+        let before = self.layout();
+        let joined = RoomEvent::Joined {
+            player_name: player.name.clone(),
+            bot: player.bot,
+        };
         let other_players: Vec<PlayerData> = self
+            .table
             .players
             .iter()
             .map(|player| player.player_data())
             .collect();
-        let message_to_player = CustomMessageBuilder::new()
-            .set_action(JoinRoom)
-            .set_room_id(self.id)
-            .set_player_name(&player.name)
-            .set_draw_deck_size(self.draw_deck.len())
-            .set_player_id(player.id.clone())
-            .set_other_players(other_players)
-            .set_discard_pile(self.discard_deck.clone())
-            .build()?;
-        player.send(message_to_player)?;
-        self.players.push(player.clone());
-        let message_to_everyone_else = CustomMessageBuilder::new()
-            .set_action(crate::actions::Action::PlayerJoinedRoomInSession)
-            .set_player_name(&player.name)
-            .set_player_id(player.id.clone())
-            .build()?;
-
-        self.broadcast_to_everyone_else(message_to_everyone_else, &player.id)?;
+        let player_name = player.name.clone();
+        let player_id = player.id.clone();
+        let session_token = player.session_token.clone();
+        self.table.players.push(player);
+        self.record(&player_id, joined, &before);
+        let message_to_everyone_else = ServerAction::PlayerJoinedRoomInSession {
+            player_name: player_name.clone(),
+            player_id: player_id.clone(),
+        };
+        self.table
+            .broadcast_to_everyone_else(message_to_everyone_else, &player_id)?;
+
+        // sent after the announcement so the new player's seq already counts it
+        let message_to_player = ServerAction::JoinRoom {
+            room_id: self.id,
+            seq: self.table.events.last_seq(),
+            player_name,
+            player_id: player_id.clone(),
+            session_token,
+            draw_deck_size: self.table.draw_deck.len(),
+            other_players,
+            discard_pile: self.discard_pile_data(),
+            private: self.settings.private,
+            max_players: self.settings.max_players,
+            game_type: self.settings.game_type,
+            deck: self.settings.deck,
+            scoreboard: self.scoreboard.scoreboard_data(),
+            chips: self.chips_data(),
+            teams: self.teams_data(),
+        };
+        self.table.send_to_player(&player_id, message_to_player)?;
+        self.send_game_views()?;
         Ok(())
     }
 
-    pub fn draw_card(&mut self, player_id: &str) -> Result<()> {
-        let card = if let Some(card) = self.draw_deck.pop() {
-            card
+    pub fn handle_move(&mut self, player_id: &str, game_move: GameMove) -> Result<()> {
+        if game_move == GameMove::StartRound && !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can start a new round");
+        }
+        if let Err(error) = self.rules.validate(&self.table, player_id, &game_move) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+
+        let before = self.layout();
+        let chips_before = self.chips_data().players;
+        let was_round_over =
+            game_move != GameMove::StartRound && self.rules.is_round_over(&self.table);
+        if game_move == GameMove::StartRound {
+            seat_by_team(&mut self.table.players, &self.teams);
+            self.rules.setup(&mut self.table)?;
         } else {
-            return Ok(());
+            self.rules.apply(&mut self.table, player_id, &game_move)?;
+        }
+        self.record(player_id, RoomEvent::Moved { game_move }, &before);
+        self.send_game_views()?;
+        if !was_round_over && self.rules.is_round_over(&self.table) {
+            self.end_round()?;
+        }
+        if self.chips_data().players != chips_before {
+            self.send_chips()?;
+        }
+        Ok(())
+    }
+
+    /// Asks the solver whether the player's game can still be won. The search runs on a
+    /// thread of its own, so the rest of the server carries on meanwhile, and the answer comes
+    /// back through `solver_results`. Nothing changes in the game, so it's not in the history.
+    pub fn check_solvable(
+        &mut self,
+        player_id: &str,
+        solver_results: UnboundedSender<Solved>,
+    ) -> Result<()> {
+        let position = match self.rules.solver_position(&self.table, player_id) {
+            Ok(Some(position)) => position,
+            Ok(None) => return self.send_game_view(player_id),
+            Err(error) => return self.reject_move(player_id, &error.to_string()),
         };
+        let room_id = self.id;
+        task::spawn(async move {
+            let searched = position.clone();
+            // a solver that falls over still has to answer, or the game would stay stuck
+            // waiting on it
+            let solvability = task::spawn_blocking(move || {
+                panic::catch_unwind(|| rules::solve(&searched)).unwrap_or(Solvability::Unknown)
+            })
+            .await;
+            let solved = Solved {
+                room_id,
+                position,
+                solvability,
+            };
+            // the server may be stopping, in which case nobody is waiting for the answer
+            solver_results.unbounded_send(solved).ok();
+        });
+        Ok(())
+    }
+
+    pub fn solved(&mut self, position: &Position, solvability: Solvability) -> Result<()> {
+        self.table.clear_requester();
+        self.rules.solved(position, solvability);
+        self.send_game_views()
+    }
+
+    fn reject_move(&mut self, player_id: &str, reason: &str) -> Result<()> {
+        let message_to_player = ServerAction::IllegalMove {
+            player_id: player_id.to_owned(),
+            message: reason.to_owned(),
+        };
+        self.table.send_to_player(player_id, message_to_player)
+    }
+
+    fn end_round(&mut self) -> Result<()> {
+        let scores = self.rules.score(&self.table);
+        let message_to_all_players = ServerAction::RoundOver {
+            scores: scores.clone(),
+            winner: self.rules.winner(&self.table),
+        };
+        self.table.broadcast_to_room(message_to_all_players)?;
+        self.record_round(scores)
+    }
+
+    /// Lets the host of a free play room keep score by hand, since there are no rules to do it.
+    pub fn record_scores(&mut self, player_id: &str, scores: Vec<PlayerScore>) -> Result<()> {
+        if self.settings.game_type != GameType::FreePlay {
+            return self.reject_move(player_id, "Scores are kept by the game in this room");
+        }
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can record scores");
+        }
+
+        let before = self.layout();
+        let scores_recorded = RoomEvent::ScoresRecorded {
+            scores: scores.clone(),
+        };
+        self.record(player_id, scores_recorded, &before);
+        self.record_round(scores)
+    }
+
+    fn record_round(&mut self, scores: Vec<PlayerScore>) -> Result<()> {
+        self.scoreboard.record_round(scores);
+        let message_to_all_players = ServerAction::ScoreboardUpdated {
+            room_id: self.id,
+            scoreboard: self.scoreboard.scoreboard_data(),
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    pub fn bet(&mut self, player_id: &str, amount: u64) -> Result<()> {
+        let before = self.layout();
+        if let Err(error) = self.check_bank(amount) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+        if let Err(error) = self.table.take_chips(player_id, amount) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+        self.pot.add(player_id, amount);
+        self.record(player_id, RoomEvent::Bet { amount }, &before);
+        self.send_chips()
+    }
 
-        let player = if let Some(player) = self
+    pub fn collect_pot(&mut self, player_id: &str) -> Result<()> {
+        let before = self.layout();
+        if let Err(error) = self.check_bank(1) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+        let won = self.pot.collect(player_id);
+        if won == 0 {
+            return self.reject_move(player_id, "There's nothing in the pot for you to collect");
+        }
+        self.table.add_chips(player_id, won);
+        self.record(player_id, RoomEvent::PotCollected, &before);
+        self.send_chips()
+    }
+
+    pub fn transfer_chips(
+        &mut self,
+        player_id: &str,
+        to_player_id: &str,
+        amount: u64,
+    ) -> Result<()> {
+        let before = self.layout();
+        if let Err(error) = self.check_bank(amount) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+        if self.table.player(to_player_id).is_none() || to_player_id == player_id {
+            return self.reject_move(player_id, "That player isn't at the table");
+        }
+        if let Err(error) = self.table.take_chips(player_id, amount) {
+            return self.reject_move(player_id, &error.to_string());
+        }
+        self.table.add_chips(to_player_id, amount);
+        let chips_transferred = RoomEvent::ChipsTransferred {
+            to_player_id: to_player_id.to_owned(),
+            amount,
+        };
+        self.record(player_id, chips_transferred, &before);
+        self.send_chips()
+    }
+
+    /// Games with betting rules move the chips themselves, the bank is only for free play.
+    fn check_bank(&self, amount: u64) -> Result<()> {
+        if self.settings.game_type != GameType::FreePlay {
+            bail!("Chips are handled by the game in this room");
+        }
+        if amount == 0 {
+            bail!("That has to be at least 1 chip");
+        }
+        Ok(())
+    }
+
+    /// The discard pile as anyone may see it, cards discarded face down stay hidden.
+    fn discard_pile_data(&self) -> Vec<CardData> {
+        self.table
+            .discard_deck
+            .iter()
+            .map(Card::card_data)
+            .collect()
+    }
+
+    fn chips_data(&self) -> ChipsData {
+        ChipsData {
+            players: self
+                .table
+                .players
+                .iter()
+                .map(|player| PlayerChips {
+                    player_id: player.id.clone(),
+                    chips: player.chips,
+                })
+                .collect(),
+            pots: self.pot.side_pots(),
+        }
+    }
+
+    fn send_chips(&mut self) -> Result<()> {
+        let message_to_all_players = ServerAction::ChipsUpdated {
+            room_id: self.id,
+            chips: self.chips_data(),
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    /// Replaces the teams with new empty ones. Players on a team that's gone lose their team,
+    /// and any cards left in its pile go to the discard pile.
+    pub fn define_teams(&mut self, player_id: &str, team_names: &[String]) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can set up teams");
+        }
+
+        let before = self.layout();
+        let mut teams: Vec<Team> = vec![];
+        for name in team_names {
+            if !name.is_empty() && !teams.iter().any(|team| &team.name == name) {
+                teams.push(Team::new(name));
+            }
+        }
+        for old_team in std::mem::replace(&mut self.teams, teams) {
+            match self
+                .teams
+                .iter_mut()
+                .find(|team| team.name == old_team.name)
+            {
+                Some(team) => team.pile = old_team.pile,
+                None => self.table.discard_deck.extend(old_team.pile),
+            }
+        }
+        let teams = &self.teams;
+        for player in &mut self.table.players {
+            let on_a_team = player
+                .team
+                .as_ref()
+                .is_some_and(|name| teams.iter().any(|team| &team.name == name));
+            if !on_a_team {
+                player.team = None;
+            }
+        }
+        let teams_defined = RoomEvent::TeamsDefined {
+            team_names: team_names.to_vec(),
+        };
+        self.record(player_id, teams_defined, &before);
+        self.send_teams()
+    }
+
+    pub fn assign_team(
+        &mut self,
+        player_id: &str,
+        to_player_id: &str,
+        team: Option<&str>,
+    ) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can put players on teams");
+        }
+        if team.is_some_and(|name| !self.teams.iter().any(|team| team.name == name)) {
+            return self.reject_move(player_id, "There's no team with that name");
+        }
+
+        let before = self.layout();
+        match self.table.player_mut(to_player_id) {
+            Some(player) => player.team = team.map(str::to_owned),
+            None => return self.reject_move(player_id, "That player isn't at the table"),
+        }
+        let team_assigned = RoomEvent::TeamAssigned {
+            to_player_id: to_player_id.to_owned(),
+            team: team.map(str::to_owned),
+        };
+        self.record(player_id, team_assigned, &before);
+        self.send_teams()
+    }
+
+    pub fn chat(&mut self, player_id: &str, text: &str) -> Result<()> {
+        let player_name = match self.table.player(player_id) {
+            Some(player) => player.name.clone(),
+            None => bail!("You aren't in room {}", self.id),
+        };
+        let message_to_all_players = ServerAction::Chat {
+            room_id: self.id,
+            player_name,
+            message: text.to_owned(),
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    pub fn team_chat(&mut self, player_id: &str, text: &str) -> Result<()> {
+        let (player_name, team) = match self.table.player(player_id) {
+            Some(Player {
+                name,
+                team: Some(team),
+                ..
+            }) => (name.clone(), team.clone()),
+            _ => return self.reject_move(player_id, "You aren't on a team"),
+        };
+        let message_to_team = ServerMessage::from(ServerAction::TeamChat {
+            room_id: self.id,
+            player_name,
+            message: text.to_owned(),
+        });
+        for player in &mut self.table.players {
+            if player.team.as_ref() == Some(&team) {
+                player.send(message_to_team.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn play_to_team_pile(&mut self, player_id: &str, card: &Card) -> Result<()> {
+        let before = self.layout();
+        let team_index = match self.check_team_pile(player_id) {
+            Ok(team_index) => team_index,
+            Err(error) => return self.reject_move(player_id, &error.to_string()),
+        };
+        let card = match self
+            .table
+            .player_mut(player_id)
+            .and_then(|player| player.discard_card(card))
+        {
+            Some(card) => card,
+            None => return self.reject_move(player_id, "You don't have that card"),
+        };
+        self.teams[team_index].pile.push(card);
+        let card = Some(card);
+        self.record(player_id, RoomEvent::PlayedToTeamPile { card }, &before);
+        self.send_teams()
+    }
+
+    pub fn take_from_team_pile(&mut self, player_id: &str, card: &Card) -> Result<()> {
+        let before = self.layout();
+        let team_index = match self.check_team_pile(player_id) {
+            Ok(team_index) => team_index,
+            Err(error) => return self.reject_move(player_id, &error.to_string()),
+        };
+        let pile = &mut self.teams[team_index].pile;
+        let card = match pile
+            .iter()
+            .position(|pile_card| pile_card.suite == card.suite && pile_card.value == card.value)
+        {
+            Some(index) => pile.remove(index),
+            None => return self.reject_move(player_id, "That card isn't in your team's pile"),
+        };
+        if let Some(player) = self.table.player_mut(player_id) {
+            player.add_card(card);
+        }
+        let card = Some(card);
+        self.record(player_id, RoomEvent::TookFromTeamPile { card }, &before);
+        self.send_teams()
+    }
+
+    /// Team piles are moved around by hand, so like the chip bank they're only for free play.
+    fn check_team_pile(&self, player_id: &str) -> Result<usize> {
+        if self.settings.game_type != GameType::FreePlay {
+            bail!("Team piles are only used in free play");
+        }
+        let team = self
+            .table
+            .player(player_id)
+            .and_then(|player| player.team.as_ref());
+        match self
+            .teams
+            .iter()
+            .position(|candidate| Some(&candidate.name) == team)
+        {
+            Some(index) => Ok(index),
+            None => bail!("You aren't on a team"),
+        }
+    }
+
+    fn teams_data(&self) -> Vec<TeamData> {
+        self.teams
+            .iter()
+            .map(|team| team.team_data(&self.table.players))
+            .collect()
+    }
+
+    fn send_teams(&mut self) -> Result<()> {
+        let message_to_all_players = ServerAction::TeamsUpdated {
+            room_id: self.id,
+            teams: self.teams_data(),
+            other_players: self
+                .table
+                .players
+                .iter()
+                .map(|player| player.player_data())
+                .collect(),
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    /// Sits a bot down in an empty seat. The bot plays from its own task, which ends once the
+    /// bot leaves the room.
+    pub fn add_bot(
+        &mut self,
+        player_id: &str,
+        bot_kind: BotKind,
+        bot_moves: UnboundedSender<ClientMessage>,
+    ) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can add bots");
+        }
+        // there are no rules in free play for a bot to follow
+        if self.settings.game_type == GameType::FreePlay {
+            return self.reject_move(player_id, "Bots can't play free play");
+        }
+        if self.is_full() {
+            return self.reject_move(player_id, "There are no empty seats for a bot");
+        }
+
+        let bot_number = self
+            .table
             .players
-            .iter_mut()
-            .find(|player| player.id == player_id)
+            .iter()
+            .filter(|player| player.bot.is_some())
+            .count()
+            + 1;
+        let mut bot = Player::new_bot(&format!("{} {}", bot_kind.name(), bot_number), bot_kind);
+        start_bot(self.id, &mut bot, bot_kind, bot_moves);
+        self.join(bot)
+    }
+
+    pub fn remove_bot(&mut self, player_id: &str, bot_id: &str) -> Result<()> {
+        if !self.is_host(player_id) {
+            return self.reject_move(player_id, "Only the host can remove bots");
+        }
+        if self
+            .table
+            .player(bot_id)
+            .is_none_or(|player| player.bot.is_none())
         {
-            player
-        } else {
+            return self.reject_move(player_id, "That player isn't a bot");
+        }
+
+        self.remove_player_by_id(bot_id)
+    }
+
+    /// Everything a bot needs to pick its next move, or `None` when there's nothing it can do.
+    fn bot_turn(&self, player_id: &str, game_state: GameState) -> Option<BotTurn> {
+        let is_legal = |game_move: &GameMove| {
+            self.rules
+                .validate(&self.table, player_id, game_move)
+                .is_ok()
+        };
+        let legal_moves: Vec<GameMove> = self
+            .rules
+            .bot_moves(&self.table, player_id)
+            .into_iter()
+            .filter(is_legal)
+            .collect();
+        if legal_moves.is_empty() {
+            return None;
+        }
+        Some(BotTurn {
+            game_state,
+            legal_moves,
+            hint: self.rules.hint(&self.table, player_id).filter(is_legal),
+        })
+    }
+
+    /// Takes back the last change made in the room, straight away or once the other players
+    /// have voted for it, depending on the room's undo setting.
+    pub fn undo(&mut self, player_id: &str) -> Result<()> {
+        let (number, event) = match self.last_undoable_change() {
+            Ok(change) => change,
+            Err(error) => return self.reject_move(player_id, &error.to_string()),
+        };
+        match self.settings.undo {
+            UndoPolicy::HostOnly if !self.is_host(player_id) => {
+                self.reject_move(player_id, "Only the host can undo in this room")
+            }
+            UndoPolicy::Vote if !self.undo_voters(player_id).is_empty() => {
+                self.start_undo_vote(player_id, number, event)
+            }
+            _ => self.undo_change(player_id, number, event),
+        }
+    }
+
+    pub fn vote_undo(&mut self, player_id: &str, approve: bool) -> Result<()> {
+        let vote = match &mut self.undo_vote {
+            Some(vote) if vote.requester != player_id => vote,
+            _ => return self.reject_move(player_id, "There's no undo for you to vote on"),
+        };
+        if approve && !vote.approvals.iter().any(|voter| voter == player_id) {
+            vote.approvals.push(player_id.to_owned());
+        }
+        let vote = vote.clone();
+        if !approve {
+            self.undo_vote = None;
+            let player_name = self
+                .table
+                .player(player_id)
+                .map(|player| player.name.clone())
+                .unwrap_or_default();
+            let message = format!("{} voted against the undo", player_name);
+            return self.reject_undo(&vote.requester, message);
+        }
+        let everyone_agrees = self
+            .undo_voters(&vote.requester)
+            .iter()
+            .all(|voter| vote.approvals.contains(voter));
+        if !everyone_agrees {
             return Ok(());
+        }
+
+        self.undo_vote = None;
+        match self.last_undoable_change() {
+            Ok((number, event)) if number == vote.number => {
+                self.undo_change(&vote.requester, number, event)
+            }
+            _ => {
+                let message = "The room changed before everyone voted".to_owned();
+                self.reject_undo(&vote.requester, message)
+            }
+        }
+    }
+
+    /// The number of the history entry an undo would take back, and what it did as everyone
+    /// may see it. Players joining or leaving can't be undone, and neither can anything in a
+    /// game with rules, where replaying it deals the same cards the player already saw.
+    fn last_undoable_change(&self) -> Result<(u64, RoomEvent)> {
+        if self.settings.game_type != GameType::FreePlay {
+            bail!("Only free play rooms can undo");
+        }
+        match self.history.last_change() {
+            Some(entry) if !matches!(entry.event, RoomEvent::Joined { .. } | RoomEvent::Left) => {
+                Ok((entry.number, entry.event.public(&entry.cards)))
+            }
+            _ => bail!("There's nothing to undo"),
+        }
+    }
+
+    /// Everyone who gets a say in an undo `requester` asked for.
+    fn undo_voters(&self, requester: &str) -> Vec<String> {
+        self.table
+            .players
+            .iter()
+            .filter(|player| {
+                player.id != requester && player.bot.is_none() && player.disconnected_at.is_none()
+            })
+            .map(|player| player.id.clone())
+            .collect()
+    }
+
+    fn start_undo_vote(&mut self, player_id: &str, number: u64, event: RoomEvent) -> Result<()> {
+        if self.undo_vote.is_some() {
+            return self.reject_move(player_id, "There's already an undo waiting for votes");
+        }
+        self.undo_vote = Some(UndoVote {
+            requester: player_id.to_owned(),
+            number,
+            approvals: vec![],
+        });
+        let message_to_all_players = ServerAction::UndoRequested {
+            room_id: self.id,
+            player_id: player_id.to_owned(),
+            number,
+            event,
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    fn reject_undo(&mut self, requester: &str, message: String) -> Result<()> {
+        let message_to_all_players = ServerAction::UndoRejected {
+            room_id: self.id,
+            player_id: requester.to_owned(),
+            message,
+        };
+        self.table.broadcast_to_room(message_to_all_players)
+    }
+
+    /// Rebuilds the room from its history without entry `number`, keeping everyone's
+    /// connections, and catches everyone up on the result.
+    fn undo_change(&mut self, player_id: &str, number: u64, event: RoomEvent) -> Result<()> {
+        let before = self.layout();
+        let entries: Vec<&HistoryEntry> = self
+            .history
+            .effective()
+            .into_iter()
+            .filter(|entry| entry.number != number)
+            .collect();
+        let Self {
+            table,
+            rules,
+            host_id,
+            scoreboard,
+            pot,
+            teams,
+            ..
+        } = Self::rebuild(self.id, self.history.seed, &entries, self.settings.clone())?;
+        let mut players = table.players;
+        for player in &mut players {
+            if let Some(old) = self.table.player(&player.id) {
+                player.reconnect_as(old);
+            }
+        }
+        self.table.players = players;
+        self.table.draw_deck = table.draw_deck;
+        self.table.discard_deck = table.discard_deck;
+        self.table.shuffles = table.shuffles;
+        self.rules = rules;
+        self.host_id = host_id;
+        self.scoreboard = scoreboard;
+        self.pot = pot;
+        self.teams = teams;
+        self.record(player_id, RoomEvent::Undone { number }, &before);
+
+        let message_to_all_players = ServerAction::Undone {
+            room_id: self.id,
+            player_id: player_id.to_owned(),
+            number,
+            event,
         };
+        self.table.broadcast_to_room(message_to_all_players)?;
+        let player_ids: Vec<String> = self
+            .table
+            .players
+            .iter()
+            .map(|player| player.id.clone())
+            .collect();
+        for player_id in player_ids {
+            let message_to_player = self.snapshot(&player_id);
+            self.table.send_to_player(&player_id, message_to_player)?;
+        }
+        self.send_game_views()
+    }
 
-        player.add_card(card);
-        let message_to_player = CustomMessageBuilder::new()
-            .set_action(crate::actions::Action::DrawCard)
-            .set_card(card)
-            .set_draw_deck_size(self.draw_deck.len())
-            .build()?;
-        player.send(message_to_player)?;
+    /// Catches a player up on the room events after `seq`, or sends them the whole room when
+    /// those events aren't kept any more. Either way their view of the game follows.
+    pub fn sync_from(&mut self, player_id: &str, seq: u64) -> Result<()> {
+        if self.table.player(player_id).is_none() {
+            bail!("You aren't in room {}", self.id);
+        }
+        let message_to_player = match self.table.events.since(seq, player_id) {
+            Some(events) => {
+                let replayed = events.len();
+                if let Some(player) = self.table.player_mut(player_id) {
+                    for event in events {
+                        player.send(event)?;
+                    }
+                }
+                ServerAction::Synced {
+                    room_id: self.id,
+                    seq: self.table.events.last_seq(),
+                    replayed,
+                }
+            }
+            None => self.snapshot(player_id),
+        };
+        self.table.send_to_player(player_id, message_to_player)?;
+        self.send_game_view(player_id)
+    }
+
+    /// Sends a snapshot to every player who had messages dropped because they fell behind,
+    /// once they've caught up with the rest.
+    pub fn resync_lagging_players(&mut self) -> Result<()> {
+        self.table.clear_requester();
+        let player_ids: Vec<String> = self
+            .table
+            .players
+            .iter()
+            .filter(|player| player.recovered())
+            .map(|player| player.id.clone())
+            .collect();
+        for player_id in player_ids {
+            let message_to_player = self.snapshot(&player_id);
+            self.table.send_to_player(&player_id, message_to_player)?;
+            self.send_game_view(&player_id)?;
+        }
         Ok(())
     }
 
-    pub fn toggle_visibility_of_card(&mut self, player_id: &str, card: &Card) -> Result<()> {
-        let player = if let Some(player) = self
+    fn snapshot(&self, player_id: &str) -> ServerAction {
+        ServerAction::Snapshot {
+            room_id: self.id,
+            seq: self.table.events.last_seq(),
+            host_id: self.host_id.clone(),
+            draw_deck_size: self.table.draw_deck.len(),
+            players: self
+                .table
+                .players
+                .iter()
+                .map(|player| player.player_data())
+                .collect(),
+            hand: self
+                .table
+                .player(player_id)
+                .map(|player| player.hand.clone())
+                .unwrap_or_default(),
+            discard_pile: self.discard_pile_data(),
+            private: self.settings.private,
+            max_players: self.settings.max_players,
+            game_type: self.settings.game_type,
+            deck: self.settings.deck,
+            scoreboard: self.scoreboard.scoreboard_data(),
+            chips: self.chips_data(),
+            teams: self.teams_data(),
+        }
+    }
+
+    fn send_game_views(&mut self) -> Result<()> {
+        let player_ids: Vec<String> = self
+            .table
             .players
-            .iter_mut()
-            .find(|player| player.id == player_id)
+            .iter()
+            .map(|player| player.id.clone())
+            .collect();
+        for player_id in player_ids {
+            self.send_game_view(&player_id)?;
+        }
+        Ok(())
+    }
+
+    fn send_game_view(&mut self, player_id: &str) -> Result<()> {
+        let game_state = match self.rules.view(&self.table, player_id) {
+            Some(game_state) => game_state,
+            None => return Ok(()),
+        };
+        if self
+            .table
+            .player(player_id)
+            .is_some_and(|player| player.bot.is_some())
         {
-            player
+            if let Some(bot_turn) = self.bot_turn(player_id, game_state) {
+                self.table
+                    .send_to_player(player_id, ServerAction::BotTurn(bot_turn))?;
+            }
         } else {
-            return Ok(());
+            let message_to_player = ServerAction::GameState { game_state };
+            self.table.send_to_player(player_id, message_to_player)?;
+        }
+        Ok(())
+    }
+
+    /// Gives the seat holding `session_token` to a new connection, and catches its player up
+    /// on the room.
+    pub fn reclaim_seat(
+        &mut self,
+        session_token: &str,
+        sender: Outbox,
+        request_id: Option<String>,
+    ) -> Result<()> {
+        let player = match self
+            .table
+            .players
+            .iter_mut()
+            .find(|player| player.bot.is_none() && player.session_token == session_token)
+        {
+            Some(player) => player,
+            None => bail!("There's no seat to reclaim in room {}", self.id),
+        };
+        player.reconnect(sender);
+        let player_id = player.id.clone();
+        let message_to_player = ServerAction::ReclaimSeat {
+            room_id: self.id,
+            player_id: player_id.clone(),
+            player_name: player.name.clone(),
         };
+        self.set_requester(&player_id, request_id);
+        self.table.send_to_player(&player_id, message_to_player)?;
+        let message_to_player = self.snapshot(&player_id);
+        self.table.send_to_player(&player_id, message_to_player)?;
+        self.send_game_view(&player_id)
+    }
 
-        if let Some(card) = player.toggle_visibility_of_card(card) {
-            let message_to_all_players = CustomMessageBuilder::new()
-                .set_action(crate::actions::Action::ToggleVisibilityOfCard)
-                .set_card(card)
-                .set_player_id(player.id.clone())
-                .build()?;
-            self.broadcast_to_room(message_to_all_players)?;
+    /// Gives up the seats of restored players who haven't reclaimed them within `window`.
+    pub fn remove_unclaimed_seats(&mut self, window: Duration) -> Result<()> {
+        let player_ids: Vec<String> = self
+            .table
+            .players
+            .iter()
+            .filter(|player| {
+                player
+                    .disconnected_at
+                    .is_some_and(|disconnected_at| disconnected_at.elapsed() >= window)
+            })
+            .map(|player| player.id.clone())
+            .collect();
+        self.table.clear_requester();
+        for player_id in player_ids {
+            self.remove_player_by_id(&player_id)?;
         }
+        Ok(())
+    }
 
+    /// Takes every player whose messages go to a closed connection out of the room.
+    pub fn remove_connection(&mut self, outbox: &Outbox) -> Result<()> {
+        let player_ids: Vec<String> = self
+            .table
+            .players
+            .iter()
+            .filter(|player| player.is_sent_to(outbox))
+            .map(|player| player.id.clone())
+            .collect();
+        self.table.clear_requester();
+        for player_id in player_ids {
+            self.remove_player_by_id(&player_id)?;
+        }
         Ok(())
     }
 
-    pub fn discard_card(&mut self, player_id: &str, card: &Card) -> Result<()> {
-        let player = if let Some(player) = self
+    pub fn remove_player_by_id(&mut self, player_id: &str) -> Result<()> {
+        let player_index = match self
+            .table
             .players
-            .iter_mut()
-            .find(|player| player.id == player_id)
+            .iter()
+            .position(|player| player.id == player_id)
         {
-            player
-        } else {
-            return Ok(());
+            Some(player_index) => player_index,
+            None => bail!("That player isn't in this room"),
         };
-        if let Some(discarded_card) = player.discard_card(card) {
-            self.discard_deck.push(discarded_card);
-            let message_to_all_players = CustomMessageBuilder::new()
-                .set_action(crate::actions::Action::DiscardCard)
-                .set_card(discarded_card)
-                .set_player_id(player.id.clone())
-                .set_hand(player.hand.clone())
-                .build()?;
-            self.broadcast_to_room(message_to_all_players)?;
+        self.unsaved = true;
+        let before = self.layout();
+        let mut player = self.table.players.remove(player_index);
+        self.table.discard_deck.append(&mut player.hand);
+        self.pot.forfeit(player_id);
+        self.rules.player_left(&mut self.table, player_id)?;
+        if self.is_host(player_id) {
+            if let Some(new_host) = self
+                .table
+                .players
+                .iter()
+                .find(|player| player.bot.is_none())
+            {
+                self.host_id = new_host.id.clone();
+            }
         }
+        self.record(player_id, RoomEvent::Left, &before);
+        let text_message = format!("{} left the room", player.name);
+        let message_to_all_players = ServerAction::Quit {
+            player_id: player_id.to_owned(),
+            discard_pile: self.discard_pile_data(),
+            message: text_message,
+        };
+        self.table.broadcast_to_room(message_to_all_players)?;
+        self.send_game_views()?;
         Ok(())
     }
+}
 
-    pub fn reset_deck(&mut self) -> Result<()> {
-        self.reset_draw_deck();
-        self.shuffle_draw_deck();
-        self.discard_deck.clear();
-        for player in &mut self.players {
-            player.empty_hand();
-        }
-        let message_to_all_players = CustomMessageBuilder::new()
-            .set_action(crate::actions::Action::ResetDeck)
-            .set_discard_pile(vec![])
-            .set_draw_deck_size(self.draw_deck.len())
-            .set_message("Deck reset and shuffled")
-            .build()?;
-        self.broadcast_to_room(message_to_all_players)?;
-        Ok(())
+/// Connects a bot to the agent that plays for it, which runs until the bot leaves the room.
+fn start_bot(
+    room_id: u32,
+    bot: &mut Player,
+    bot_kind: BotKind,
+    bot_moves: UnboundedSender<ClientMessage>,
+) {
+    // bots keep up with anything, so they're left out of the queue metrics
+    let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+    bot.reconnect(sender);
+    task::spawn(run_bot(
+        bot_kind.bot(),
+        receiver,
+        bot_moves,
+        room_id,
+        bot.id.clone(),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+
+    use super::*;
+    use crate::{actions::CreateGame, outbox::OutboxReceiver};
+
+    fn room_with_host(game_type: GameType) -> (Room, String, OutboxReceiver) {
+        let settings = RoomSettings::from_create_game(&CreateGame {
+            player_name: "Ada".to_owned(),
+            game_type: Some(game_type),
+            ..CreateGame::default()
+        });
+        let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let host = Player::new("Ada", sender);
+        let host_id = host.id.clone();
+        (
+            Room::new(1234, host, None, settings, None).unwrap(),
+            host_id,
+            receiver,
+        )
     }
 
-    fn reset_draw_deck(&mut self) {
-        self.draw_deck.clear();
-        // let ten_of_hearts = Card::new(crate::card::Suite::Heart, crate::card::Value::Ten);
-        // self.draw_deck.push(ten_of_hearts);
-        for suite in Suite::all().iter() {
-            for value in Value::all().iter() {
-                let card = Card::new(*suite, *value);
-                self.draw_deck.push(card);
+    fn illegal_moves(receiver: &mut OutboxReceiver) -> Vec<String> {
+        let mut messages = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            if let ServerAction::IllegalMove { message, .. } = message.action {
+                messages.push(message);
             }
         }
-        self.shuffle_draw_deck();
+        messages
     }
 
-    fn shuffle_draw_deck(&mut self) {
-        let mut rng = thread_rng();
-        self.draw_deck.shuffle(&mut rng);
+    #[test]
+    fn bots_only_sit_down_in_rooms_with_rules() {
+        let (bot_moves, _) = mpsc::unbounded();
+        let (mut room, host_id, mut receiver) = room_with_host(GameType::FreePlay);
+        room.add_bot(&host_id, BotKind::Heuristic, bot_moves.clone())
+            .unwrap();
+        assert_eq!(room.table.players.len(), 1);
+        assert_eq!(illegal_moves(&mut receiver), ["Bots can't play free play"]);
+
+        let (mut room, host_id, mut receiver) = room_with_host(GameType::Hearts);
+        room.add_bot(&host_id, BotKind::Heuristic, bot_moves)
+            .unwrap();
+        assert_eq!(room.table.players.len(), 2);
+        assert!(illegal_moves(&mut receiver).is_empty());
     }
 
-    pub fn remove_player_by_id(&mut self, player_id: &str) -> Result<()> {
-        let mut player_index = None;
-        let mut player_name = None;
-        for (index, player) in self.players.iter_mut().enumerate() {
-            if player.id != player_id {
-                continue;
-            }
-
-            player_index = Some(index);
-            self.discard_deck.append(&mut player.hand);
-            break;
-        }
-        if let Some(player_index) = player_index {
-            player_name = Some(self.players[player_index].name.clone());
-            self.players.remove(player_index);
-        }
-        let text_message = format!("{} left the room", player_name.unwrap());
-        let message_to_all_players = CustomMessageBuilder::new()
-            .set_action(crate::actions::Action::Quit)
-            .set_player_id(player_id.to_owned())
-            .set_discard_pile(self.discard_deck.clone())
-            .set_message(&text_message)
-            .build()?;
-        self.broadcast_to_room(message_to_all_players)?;
-        Ok(())
+    #[test]
+    fn only_the_host_adds_and_removes_bots() {
+        let (bot_moves, _) = mpsc::unbounded();
+        let (mut room, host_id, _host_messages) = room_with_host(GameType::Hearts);
+        room.add_bot(&host_id, BotKind::Random, bot_moves.clone())
+            .unwrap();
+        let bot_id = room.table.players[1].id.clone();
+        let (sender, mut guest_messages) = outbox(OutboxConfig::default(), &Arc::default());
+        let guest = Player::new("Bea", sender);
+        let guest_id = guest.id.clone();
+        room.join(guest).unwrap();
+
+        room.add_bot(&guest_id, BotKind::Random, bot_moves).unwrap();
+        room.remove_bot(&guest_id, &bot_id).unwrap();
+        assert_eq!(
+            illegal_moves(&mut guest_messages),
+            [
+                "Only the host can add bots",
+                "Only the host can remove bots"
+            ]
+        );
+        assert_eq!(room.table.players.len(), 3);
+    }
+
+    #[test]
+    fn the_scoreboard_plays_to_the_games_own_target() {
+        let (room, _, _receiver) = room_with_host(GameType::Hearts);
+        let scoreboard = room.scoreboard.scoreboard_data();
+        assert_eq!(scoreboard.target_score, Some(rules::Hearts::TARGET_SCORE));
+    }
+
+    #[test]
+    fn only_the_host_records_scores_and_only_in_free_play() {
+        let scores = |player_id: &str| {
+            vec![PlayerScore {
+                player_id: player_id.to_owned(),
+                points: 10,
+            }]
+        };
+        let (mut room, host_id, mut host_messages) = room_with_host(GameType::FreePlay);
+        let (sender, mut guest_messages) = outbox(OutboxConfig::default(), &Arc::default());
+        let guest = Player::new("Bea", sender);
+        let guest_id = guest.id.clone();
+        room.join(guest).unwrap();
+        room.record_scores(&guest_id, scores(&guest_id)).unwrap();
+        assert_eq!(
+            illegal_moves(&mut guest_messages),
+            ["Only the host can record scores"]
+        );
+        assert!(room.scoreboard.scoreboard_data().rounds.is_empty());
+        room.record_scores(&host_id, scores(&guest_id)).unwrap();
+        assert!(illegal_moves(&mut host_messages).is_empty());
+        assert_eq!(room.scoreboard.scoreboard_data().rounds.len(), 1);
+
+        let (mut room, host_id, mut receiver) = room_with_host(GameType::Hearts);
+        room.record_scores(&host_id, scores(&host_id)).unwrap();
+        assert_eq!(
+            illegal_moves(&mut receiver),
+            ["Scores are kept by the game in this room"]
+        );
     }
 }
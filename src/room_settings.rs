@@ -0,0 +1,194 @@
+use std::ops::RangeInclusive;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{actions::CreateGame, card::DeckSpec, rules::Aces};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default, JsonSchema)]
+pub enum GameType {
+    #[default]
+    FreePlay,
+    Blackjack,
+    Holdem,
+    Hearts,
+    Spades,
+    Euchre,
+    Rummy,
+    Gin,
+    CrazyEights,
+    Klondike,
+    FreeCell,
+    Spider,
+}
+
+impl GameType {
+    /// The deck a room plays with, games that only work with one particular deck ignore what
+    /// was asked for.
+    pub fn deck(&self, requested: Option<DeckSpec>) -> DeckSpec {
+        match self {
+            GameType::FreePlay => requested.unwrap_or_default(),
+            GameType::Blackjack => requested.unwrap_or_else(|| DeckSpec::new(6)),
+            GameType::Holdem
+            | GameType::Hearts
+            | GameType::Spades
+            | GameType::Euchre
+            | GameType::Rummy
+            | GameType::Gin
+            | GameType::CrazyEights
+            | GameType::Klondike
+            | GameType::FreeCell => DeckSpec::default(),
+            GameType::Spider => DeckSpec::new(2),
+        }
+    }
+
+    /// Whether the player with the fewest points wins a match.
+    pub fn low_score_wins(&self) -> bool {
+        *self == GameType::Hearts
+    }
+
+    /// How many players the game can be played with. Games dealt for a fixed table need
+    /// exactly that many.
+    pub fn player_counts(&self) -> RangeInclusive<usize> {
+        match self {
+            GameType::FreePlay | GameType::Blackjack => 1..=RoomSettings::MAX_PLAYERS,
+            GameType::Holdem => 2..=RoomSettings::MAX_PLAYERS,
+            GameType::Hearts | GameType::Spades | GameType::Euchre => 4..=4,
+            GameType::Rummy => 2..=6,
+            GameType::Gin => 2..=2,
+            GameType::CrazyEights => 2..=8,
+            GameType::Klondike | GameType::FreeCell | GameType::Spider => 1..=1,
+        }
+    }
+}
+
+/// Who gets to take back the last change made in a room.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default, JsonSchema)]
+pub enum UndoPolicy {
+    Anyone,
+    #[default]
+    HostOnly,
+    /// Anyone can ask, and it happens once every other player in the room agrees.
+    Vote,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoomSettings {
+    pub private: bool,
+    pub max_players: usize,
+    pub game_type: GameType,
+    pub deck: DeckSpec,
+    pub dealer_hits_soft_17: bool,
+    pub small_blind: u64,
+    pub big_blind: u64,
+    pub aces: Aces,
+    /// Deals the first round from this seed so it can be replayed.
+    pub seed: Option<u64>,
+    /// Ends the match once anyone's total reaches it.
+    pub target_score: Option<i64>,
+    /// Chips every player sits down with.
+    pub starting_chips: u64,
+    pub undo: UndoPolicy,
+}
+
+impl RoomSettings {
+    pub const DEFAULT_MAX_PLAYERS: usize = 8;
+    pub const MAX_PLAYERS: usize = 16;
+
+    /// Reads the settings a `CreateGame` message asked for, anything left out gets the default
+    /// for the chosen game.
+    pub fn from_create_game(request: &CreateGame) -> Self {
+        let default_settings = Self::default();
+        let game_type = request.game_type.unwrap_or(default_settings.game_type);
+        let small_blind = request
+            .small_blind
+            .unwrap_or(default_settings.small_blind)
+            .max(1);
+        let big_blind = request
+            .big_blind
+            .unwrap_or(small_blind * 2)
+            .max(small_blind);
+        Self {
+            private: request.private.unwrap_or(default_settings.private),
+            max_players: {
+                let player_counts = game_type.player_counts();
+                request
+                    .max_players
+                    .unwrap_or(default_settings.max_players)
+                    .clamp(*player_counts.start(), *player_counts.end())
+            },
+            game_type,
+            deck: game_type.deck(request.deck.map(|deck| DeckSpec::new(deck.decks))),
+            dealer_hits_soft_17: request
+                .dealer_hits_soft_17
+                .unwrap_or(default_settings.dealer_hits_soft_17),
+            small_blind,
+            big_blind,
+            aces: request.aces.unwrap_or(default_settings.aces),
+            seed: request.seed,
+            target_score: request.target_score,
+            starting_chips: request
+                .starting_chips
+                .unwrap_or(default_settings.starting_chips),
+            undo: request.undo.unwrap_or(default_settings.undo),
+        }
+    }
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            private: false,
+            max_players: Self::DEFAULT_MAX_PLAYERS,
+            game_type: GameType::default(),
+            deck: DeckSpec::default(),
+            dealer_hits_soft_17: false,
+            small_blind: 5,
+            big_blind: 10,
+            aces: Aces::default(),
+            seed: None,
+            target_score: None,
+            starting_chips: 1000,
+            undo: UndoPolicy::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_players(game_type: GameType, max_players: Option<usize>) -> usize {
+        RoomSettings::from_create_game(&CreateGame {
+            player_name: "Ada".to_owned(),
+            game_type: Some(game_type),
+            max_players,
+            ..CreateGame::default()
+        })
+        .max_players
+    }
+
+    #[test]
+    fn rooms_seat_as_many_players_as_the_game_takes() {
+        for (game_type, requested, seats) in [
+            (GameType::Hearts, Some(2), 4),
+            (GameType::Spades, None, 4),
+            (GameType::Euchre, Some(16), 4),
+            (GameType::Spider, Some(4), 1),
+            (GameType::Gin, None, 2),
+            (GameType::Rummy, None, 6),
+            (GameType::Holdem, Some(1), 2),
+            (GameType::CrazyEights, Some(3), 3),
+            (GameType::FreePlay, None, RoomSettings::DEFAULT_MAX_PLAYERS),
+            (GameType::Blackjack, Some(100), RoomSettings::MAX_PLAYERS),
+        ] {
+            assert_eq!(
+                max_players(game_type, requested),
+                seats,
+                "{:?} asked for {:?} players",
+                game_type,
+                requested
+            );
+        }
+    }
+}
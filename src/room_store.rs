@@ -0,0 +1,166 @@
+use std::{
+    fmt::Debug,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::Card, chips::Pot, history::History, password::HashedPassword, player::SavedPlayer,
+    room_settings::RoomSettings, scoreboard::Scoreboard, team::Team,
+};
+
+/// Everything needed to bring a room back after the server restarts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub id: u32,
+    pub host_id: String,
+    pub password: Option<HashedPassword>,
+    pub settings: RoomSettings,
+    pub invite_tokens: Vec<String>,
+    pub scoreboard: Scoreboard,
+    pub pot: Pot,
+    pub teams: Vec<Team>,
+    pub players: Vec<SavedPlayer>,
+    pub draw_deck: Vec<Card>,
+    pub discard_deck: Vec<Card>,
+    /// Whatever the game's rules keep track of beyond the table.
+    pub rules: serde_json::Value,
+    /// The number of the last room event, so the numbering carries on after a restart.
+    pub last_seq: u64,
+    pub history: History,
+    /// How many times the table has shuffled, so the next shuffle is the one a replay would do.
+    pub shuffles: u64,
+}
+
+/// Where rooms are kept while the server isn't running.
+pub trait RoomStore: Debug + Send {
+    fn load_all(&mut self) -> Result<Vec<SavedRoom>>;
+
+    /// Saves the room, replacing whatever was saved for it before.
+    fn save(&mut self, room: &SavedRoom) -> Result<()>;
+
+    fn delete(&mut self, room_id: u32) -> Result<()>;
+}
+
+/// Keeps every room as its own JSON file in one directory.
+#[derive(Debug)]
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_owned(),
+        })
+    }
+
+    fn path(&self, room_id: u32) -> PathBuf {
+        self.dir.join(format!("room-{}.json", room_id))
+    }
+}
+
+impl RoomStore for JsonFileStore {
+    fn load_all(&mut self) -> Result<Vec<SavedRoom>> {
+        let mut rooms = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            if !name.is_some_and(|name| name.starts_with("room-") && name.ends_with(".json")) {
+                continue;
+            }
+            // one unreadable room shouldn't keep every other room from coming back
+            match serde_json::from_str(&fs::read_to_string(&path)?) {
+                Ok(room) => rooms.push(room),
+                Err(error) => println!("skipping {}: {}", path.display(), error),
+            }
+        }
+        Ok(rooms)
+    }
+
+    fn save(&mut self, room: &SavedRoom) -> Result<()> {
+        // written next to the old file first, so a crash halfway through can't lose the room
+        let path = self.path(room.id);
+        let temporary_path = path.with_extension("json.tmp");
+        fs::write(&temporary_path, serde_json::to_string(room)?)?;
+        fs::rename(temporary_path, path)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, room_id: u32) -> Result<()> {
+        match fs::remove_file(self.path(room_id)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        actions::CreateGame, outbox::detached, player::Player, room::Room, room_settings::GameType,
+    };
+
+    /// Removes the store's directory once the test is done with it, even if the test failed.
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn store() -> (JsonFileStore, TempDir) {
+        let dir = std::env::temp_dir().join(format!("rooms-{}", Uuid::new_v4()));
+        (JsonFileStore::new(&dir).unwrap(), TempDir(dir))
+    }
+
+    fn hearts_room() -> Room {
+        let settings = RoomSettings::from_create_game(&CreateGame {
+            player_name: "Ada".to_owned(),
+            password: Some("secret".to_owned()),
+            game_type: Some(GameType::Hearts),
+            ..CreateGame::default()
+        });
+        let host = Player::new("Ada", detached());
+        let mut room = Room::new(1234, host, Some("secret"), settings, None).unwrap();
+        for name in ["Bea", "Cy", "Di"] {
+            room.join(Player::new(name, detached())).unwrap();
+        }
+        room
+    }
+
+    #[test]
+    fn rooms_come_back_the_way_they_were_saved() {
+        let (mut store, _dir) = store();
+        let room = hearts_room();
+        let saved = room.save().unwrap();
+        let before = serde_json::to_value(&saved).unwrap();
+        store.save(&saved).unwrap();
+
+        let mut loaded = store.load_all().unwrap();
+        assert_eq!(loaded.len(), 1);
+        let restored = Room::restore(loaded.remove(0), None).unwrap();
+        let after = serde_json::to_value(restored.save().unwrap()).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn deleted_rooms_stay_gone() {
+        let (mut store, _dir) = store();
+        let saved = hearts_room().save().unwrap();
+        store.save(&saved).unwrap();
+        store.delete(saved.id).unwrap();
+        assert!(store.load_all().unwrap().is_empty());
+        // deleting a room that was never saved isn't an error
+        store.delete(saved.id).unwrap();
+    }
+}
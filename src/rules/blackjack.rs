@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    candidate_moves, restore_state, save_state, trick::hand_of, GameMove, GameRules, GameState,
+    PlayerScore,
+};
+use crate::{
+    card::{Card, CardData, Value},
+    table::Table,
+};
+
+const MAX_HANDS_PER_PLAYER: usize = 4;
+const BOT_BET: u64 = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "BlackjackPhase")]
+enum Phase {
+    Betting,
+    Playing,
+    RoundOver,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+enum HandResult {
+    Blackjack,
+    Win,
+    Push,
+    Lose,
+    Bust,
+}
+
+/// The best total for a set of cards, and whether an ace is still being counted as 11.
+pub fn hand_value(cards: &[Card]) -> (u8, bool) {
+    let total: u8 = cards.iter().map(|card| card_points(card.value)).sum();
+    let has_ace = cards.iter().any(|card| card.value == Value::Ace);
+    if has_ace && total + 10 <= 21 {
+        (total + 10, true)
+    } else {
+        (total, false)
+    }
+}
+
+fn card_points(value: Value) -> u8 {
+    match value {
+        Value::Ace => 1,
+        Value::Two => 2,
+        Value::Three => 3,
+        Value::Four => 4,
+        Value::Five => 5,
+        Value::Six => 6,
+        Value::Seven => 7,
+        Value::Eight => 8,
+        Value::Nine => 9,
+        Value::Ten | Value::Jack | Value::Queen | Value::King => 10,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct Hand {
+    cards: Vec<Card>,
+    bet: u64,
+    doubled: bool,
+    from_split: bool,
+    finished: bool,
+    result: Option<HandResult>,
+}
+
+impl Hand {
+    fn value(&self) -> u8 {
+        hand_value(&self.cards).0
+    }
+
+    fn is_blackjack(&self) -> bool {
+        !self.from_split && self.cards.len() == 2 && self.value() == 21
+    }
+
+    fn is_bust(&self) -> bool {
+        self.value() > 21
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Seat {
+    player_id: String,
+    hands: Vec<Hand>,
+    active_hand: usize,
+}
+
+/// Everyone plays against a dealer that the server plays by the book: it draws until it
+/// reaches 17, and on a soft 17 only when the room was set up that way.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Blackjack {
+    dealer_hits_soft_17: bool,
+    phase: Phase,
+    bets: HashMap<String, u64>,
+    seats: Vec<Seat>,
+    turn: usize,
+    dealer: Vec<Card>,
+    results: HashMap<String, i64>,
+}
+
+impl Blackjack {
+    pub fn new(dealer_hits_soft_17: bool) -> Self {
+        Self {
+            dealer_hits_soft_17,
+            phase: Phase::Betting,
+            bets: HashMap::new(),
+            seats: vec![],
+            turn: 0,
+            dealer: vec![],
+            results: HashMap::new(),
+        }
+    }
+
+    fn active_hand(&self, player_id: &str) -> Result<&Hand> {
+        if self.phase != Phase::Playing {
+            bail!("There is no hand being played");
+        }
+        let seat = self
+            .seats
+            .get(self.turn)
+            .filter(|seat| seat.player_id == player_id)
+            .ok_or_else(|| eyre!("It isn't your turn"))?;
+        Ok(&seat.hands[seat.active_hand])
+    }
+
+    fn active_hand_mut(&mut self) -> &mut Hand {
+        let seat = &mut self.seats[self.turn];
+        &mut seat.hands[seat.active_hand]
+    }
+
+    /// Deals from the shoe, shuffling the discard pile back in if the shoe runs out mid-round.
+    fn deal_card(table: &mut Table, face_up: bool) -> Result<Card> {
+        if table.draw_deck.is_empty() {
+            table.draw_deck.append(&mut table.discard_deck);
+            for card in &mut table.draw_deck {
+                card.set_visible(false);
+            }
+            table.shuffle_draw_deck();
+        }
+        let mut card = table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("The shoe is empty"))?;
+        card.set_visible(face_up);
+        Ok(card)
+    }
+
+    /// Moves the turn on to the next hand that still needs playing, and lets the dealer play
+    /// once every hand is finished.
+    fn advance(&mut self, table: &mut Table) -> Result<()> {
+        while self.turn < self.seats.len() {
+            let seat = &mut self.seats[self.turn];
+            while seat.active_hand < seat.hands.len() {
+                if !seat.hands[seat.active_hand].finished {
+                    return Ok(());
+                }
+                seat.active_hand += 1;
+            }
+            self.turn += 1;
+        }
+        self.play_dealer(table)?;
+        self.settle(table);
+        Ok(())
+    }
+
+    fn play_dealer(&mut self, table: &mut Table) -> Result<()> {
+        for card in &mut self.dealer {
+            card.set_visible(true);
+        }
+        let everyone_bust = self
+            .seats
+            .iter()
+            .flat_map(|seat| seat.hands.iter())
+            .all(|hand| hand.is_bust());
+        if everyone_bust {
+            return Ok(());
+        }
+
+        loop {
+            let (value, soft) = hand_value(&self.dealer);
+            let hits = value < 17 || (value == 17 && soft && self.dealer_hits_soft_17);
+            if !hits {
+                return Ok(());
+            }
+            let card = Self::deal_card(table, true)?;
+            self.dealer.push(card);
+        }
+    }
+
+    fn settle(&mut self, table: &mut Table) {
+        let dealer_value = hand_value(&self.dealer).0;
+        let dealer_blackjack = self.dealer.len() == 2 && dealer_value == 21;
+        let dealer_bust = dealer_value > 21;
+        self.results.clear();
+        for seat in &mut self.seats {
+            let mut net = 0;
+            let mut payout = 0;
+            for hand in &mut seat.hands {
+                let value = hand.value();
+                let result = if hand.is_bust() {
+                    HandResult::Bust
+                } else if hand.is_blackjack() && !dealer_blackjack {
+                    HandResult::Blackjack
+                } else if dealer_blackjack && !hand.is_blackjack() {
+                    HandResult::Lose
+                } else if dealer_bust || value > dealer_value {
+                    HandResult::Win
+                } else if value == dealer_value {
+                    HandResult::Push
+                } else {
+                    HandResult::Lose
+                };
+                let returned = match result {
+                    HandResult::Blackjack => hand.bet + hand.bet * 3 / 2,
+                    HandResult::Win => hand.bet * 2,
+                    HandResult::Push => hand.bet,
+                    HandResult::Lose | HandResult::Bust => 0,
+                };
+                hand.result = Some(result);
+                hand.finished = true;
+                payout += returned;
+                net += returned as i64 - hand.bet as i64;
+            }
+            self.results.insert(seat.player_id.clone(), net);
+            table.add_chips(&seat.player_id, payout);
+        }
+        self.phase = Phase::RoundOver;
+    }
+
+    fn discard_round(&mut self, table: &mut Table) {
+        for seat in self.seats.drain(..) {
+            for mut hand in seat.hands {
+                table.discard_deck.append(&mut hand.cards);
+            }
+        }
+        table.discard_deck.append(&mut self.dealer);
+    }
+}
+
+impl GameRules for Blackjack {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        self.discard_round(table);
+        // reshuffle the whole shoe once a quarter of it is left, like the cut card would
+        if table.draw_deck.len() < table.deck.size() / 4 {
+            table.reset_draw_deck();
+            table.discard_deck.clear();
+        }
+
+        self.results.clear();
+        for player in &mut table.players {
+            if let Some(bet) = self.bets.remove(&player.id) {
+                player.chips = player.chips.saturating_sub(bet);
+                self.seats.push(Seat {
+                    player_id: player.id.clone(),
+                    hands: vec![Hand {
+                        bet,
+                        ..Hand::default()
+                    }],
+                    active_hand: 0,
+                });
+            }
+        }
+        self.bets.clear();
+
+        for face_up in [true, false].iter() {
+            for index in 0..self.seats.len() {
+                let card = Self::deal_card(table, true)?;
+                self.seats[index].hands[0].cards.push(card);
+            }
+            let card = Self::deal_card(table, *face_up)?;
+            self.dealer.push(card);
+        }
+
+        self.phase = Phase::Playing;
+        self.turn = 0;
+        if hand_value(&self.dealer).0 == 21 {
+            // the dealer peeks at their hole card, a blackjack ends the round straight away
+            for card in &mut self.dealer {
+                card.set_visible(true);
+            }
+            self.settle(table);
+            return Ok(());
+        }
+        for seat in &mut self.seats {
+            let hand = &mut seat.hands[0];
+            hand.finished = hand.is_blackjack();
+        }
+        self.advance(table)
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if self.phase == Phase::Playing {
+                    bail!("The round isn't over yet");
+                }
+                let anyone_bet = table
+                    .players
+                    .iter()
+                    .any(|player| self.bets.contains_key(&player.id));
+                if !anyone_bet {
+                    bail!("Nobody has placed a bet");
+                }
+            }
+            GameMove::PlaceBet { amount } => {
+                if self.phase == Phase::Playing {
+                    bail!("Bets can only be placed between rounds");
+                }
+                if *amount == 0 {
+                    bail!("A bet has to be at least 1 chip");
+                }
+                if *amount > table.chips(player_id) {
+                    bail!("You don't have enough chips for that bet");
+                }
+            }
+            GameMove::Hit | GameMove::Stand => {
+                self.active_hand(player_id)?;
+            }
+            GameMove::DoubleDown => {
+                let hand = self.active_hand(player_id)?;
+                if hand.cards.len() != 2 {
+                    bail!("You can only double down on your first two cards");
+                }
+                if hand.bet > table.chips(player_id) {
+                    bail!("You don't have enough chips to double down");
+                }
+            }
+            GameMove::Split => {
+                let hand = self.active_hand(player_id)?;
+                if hand.cards.len() != 2 || hand.cards[0].value != hand.cards[1].value {
+                    bail!("You can only split a pair");
+                }
+                if self.seats[self.turn].hands.len() >= MAX_HANDS_PER_PLAYER {
+                    bail!("You can't split any more hands");
+                }
+                if hand.bet > table.chips(player_id) {
+                    bail!("You don't have enough chips to split");
+                }
+            }
+            _ => bail!("That move isn't part of blackjack"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::PlaceBet { amount } => {
+                self.bets.insert(player_id.to_owned(), *amount);
+                return Ok(());
+            }
+            GameMove::Hit => {
+                let card = Self::deal_card(table, true)?;
+                let hand = self.active_hand_mut();
+                hand.cards.push(card);
+                hand.finished = hand.value() >= 21;
+            }
+            GameMove::Stand => {
+                self.active_hand_mut().finished = true;
+            }
+            GameMove::DoubleDown => {
+                let bet = self.active_hand_mut().bet;
+                table.take_chips(player_id, bet)?;
+                let card = Self::deal_card(table, true)?;
+                let hand = self.active_hand_mut();
+                hand.bet += bet;
+                hand.doubled = true;
+                hand.cards.push(card);
+                hand.finished = true;
+            }
+            GameMove::Split => {
+                let bet = self.active_hand_mut().bet;
+                table.take_chips(player_id, bet)?;
+                let first_card = Self::deal_card(table, true)?;
+                let second_card = Self::deal_card(table, true)?;
+                let hand = self.active_hand_mut();
+                let split_card = hand.cards.pop().expect("a pair has two cards");
+                let split_aces = split_card.value == Value::Ace;
+                hand.from_split = true;
+                hand.cards.push(first_card);
+                // split aces only get one more card each
+                hand.finished = split_aces || hand.value() == 21;
+                let new_hand = Hand {
+                    cards: vec![split_card, second_card],
+                    bet,
+                    from_split: true,
+                    finished: split_aces || hand_value(&[split_card, second_card]).0 == 21,
+                    ..Hand::default()
+                };
+                let seat = &mut self.seats[self.turn];
+                seat.hands.insert(seat.active_hand + 1, new_hand);
+            }
+            _ => return Ok(()),
+        }
+        self.advance(table)
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        self.phase == Phase::RoundOver
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.results
+            .iter()
+            .map(|(player_id, net)| PlayerScore {
+                player_id: player_id.clone(),
+                points: *net,
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.phase == Phase::Playing
+    }
+
+    fn view(&self, table: &Table, _player_id: &str) -> Option<GameState> {
+        let turn = self
+            .seats
+            .get(self.turn)
+            .filter(|_| self.phase == Phase::Playing)
+            .map(|seat| TurnView {
+                player_id: seat.player_id.clone(),
+                hand_index: seat.active_hand,
+            });
+        let dealer_revealed = self.dealer.iter().all(|card| card.is_visible());
+        let view = BlackjackView {
+            phase: self.phase,
+            dealer: DealerView {
+                cards: self.dealer.iter().map(|card| card.card_data()).collect(),
+                value: if dealer_revealed && !self.dealer.is_empty() {
+                    Some(hand_value(&self.dealer).0)
+                } else {
+                    None
+                },
+            },
+            seats: self
+                .seats
+                .iter()
+                .map(|seat| SeatView {
+                    player_id: seat.player_id.clone(),
+                    hands: seat
+                        .hands
+                        .iter()
+                        .map(|hand| {
+                            let (value, soft) = hand_value(&hand.cards);
+                            HandView {
+                                cards: hand.cards.iter().map(|card| card.card_data()).collect(),
+                                value,
+                                soft,
+                                bet: hand.bet,
+                                doubled: hand.doubled,
+                                finished: hand.finished,
+                                result: hand.result,
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+            turn,
+            chips: table
+                .players
+                .iter()
+                .map(|player| ChipsView {
+                    player_id: player.id.clone(),
+                    bankroll: player.chips,
+                    bet: self.bets.get(&player.id).copied(),
+                    won: self.results.get(&player.id).copied(),
+                })
+                .collect(),
+            shoe_size: table.draw_deck.len(),
+            dealer_hits_soft_17: self.dealer_hits_soft_17,
+        };
+        Some(GameState::Blackjack(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        self.bets.remove(player_id);
+        let index = match self
+            .seats
+            .iter()
+            .position(|seat| seat.player_id == player_id)
+        {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let seat = self.seats.remove(index);
+        for mut hand in seat.hands {
+            table.discard_deck.append(&mut hand.cards);
+        }
+        if self.phase != Phase::Playing {
+            return Ok(());
+        }
+        if self.seats.is_empty() {
+            table.discard_deck.append(&mut self.dealer);
+            self.phase = Phase::Betting;
+            return Ok(());
+        }
+        if index < self.turn {
+            self.turn -= 1;
+        } else if index == self.turn {
+            if let Some(seat) = self.seats.get_mut(self.turn) {
+                seat.active_hand = 0;
+            }
+            self.advance(table)?;
+        }
+        Ok(())
+    }
+
+    fn bot_moves(&self, table: &Table, player_id: &str) -> Vec<GameMove> {
+        // a bet stays put until the round starts, so there's no point placing it again
+        candidate_moves(hand_of(table, player_id))
+            .into_iter()
+            .filter(|game_move| {
+                !matches!(game_move, GameMove::PlaceBet { .. })
+                    || !self.bets.contains_key(player_id)
+            })
+            .collect()
+    }
+
+    fn hint(&self, _table: &Table, player_id: &str) -> Option<GameMove> {
+        if self.phase != Phase::Playing {
+            return (!self.bets.contains_key(player_id))
+                .then_some(GameMove::PlaceBet { amount: BOT_BET });
+        }
+        let hand = self.active_hand(player_id).ok()?;
+        Some(if hand.value() < 17 {
+            GameMove::Hit
+        } else {
+            GameMove::Stand
+        })
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BlackjackView {
+    phase: Phase,
+    dealer: DealerView,
+    seats: Vec<SeatView>,
+    turn: Option<TurnView>,
+    chips: Vec<ChipsView>,
+    shoe_size: usize,
+    dealer_hits_soft_17: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct DealerView {
+    cards: Vec<CardData>,
+    value: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[schemars(rename = "BlackjackSeatView")]
+struct SeatView {
+    player_id: String,
+    hands: Vec<HandView>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct HandView {
+    cards: Vec<CardData>,
+    value: u8,
+    soft: bool,
+    bet: u64,
+    doubled: bool,
+    finished: bool,
+    result: Option<HandResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct TurnView {
+    player_id: String,
+    hand_index: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct ChipsView {
+    player_id: String,
+    bankroll: u64,
+    bet: Option<u64>,
+    won: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card::Suite, rules::test_table};
+
+    fn play(rules: &mut Blackjack, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    #[test]
+    fn a_round_pays_out_what_each_player_won() {
+        let (mut table, ids, _receivers) = test_table(2, 1000, 0);
+        let mut rules = Blackjack::new(false);
+        for _ in 0..20 {
+            for player_id in &ids {
+                play(
+                    &mut rules,
+                    &mut table,
+                    player_id,
+                    GameMove::PlaceBet { amount: 10 },
+                );
+            }
+            rules
+                .validate(&table, &ids[0], &GameMove::StartRound)
+                .unwrap();
+            let before: Vec<u64> = ids.iter().map(|id| table.chips(id)).collect();
+            rules.setup(&mut table).unwrap();
+            while !rules.is_round_over(&table) {
+                let player_id = rules.seats[rules.turn].player_id.clone();
+                let hand = rules.active_hand(&player_id).unwrap();
+                let game_move = if hand.value() < 17 {
+                    GameMove::Hit
+                } else {
+                    GameMove::Stand
+                };
+                play(&mut rules, &mut table, &player_id, game_move);
+            }
+
+            let dealer = hand_value(&rules.dealer).0;
+            assert!(dealer >= 17 || rules.seats.iter().all(|seat| seat.hands[0].is_bust()));
+            for score in rules.score(&table) {
+                assert!([-10, 0, 10, 15].contains(&score.points), "{:?}", score);
+                let index = ids.iter().position(|id| *id == score.player_id).unwrap();
+                assert_eq!(
+                    table.chips(&score.player_id) as i64,
+                    before[index] as i64 + score.points
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn moves_out_of_turn_or_beyond_the_bankroll_are_rejected() {
+        let (mut table, ids, _receivers) = test_table(2, 1000, 0);
+        let mut rules = Blackjack::new(false);
+        assert!(rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .is_err());
+        assert!(rules.validate(&table, &ids[0], &GameMove::Hit).is_err());
+        let too_much = GameMove::PlaceBet { amount: 1001 };
+        assert!(rules.validate(&table, &ids[0], &too_much).is_err());
+        let nothing = GameMove::PlaceBet { amount: 0 };
+        assert!(rules.validate(&table, &ids[0], &nothing).is_err());
+
+        for player_id in &ids {
+            play(
+                &mut rules,
+                &mut table,
+                player_id,
+                GameMove::PlaceBet { amount: 10 },
+            );
+        }
+        rules.setup(&mut table).unwrap();
+        if rules.is_round_over(&table) {
+            // the dealer was dealt a blackjack
+            return;
+        }
+        let waiting = ids
+            .iter()
+            .find(|player_id| **player_id != rules.seats[rules.turn].player_id)
+            .unwrap();
+        assert!(rules.validate(&table, waiting, &GameMove::Stand).is_err());
+        let acting = rules.seats[rules.turn].player_id.clone();
+        let more = GameMove::PlaceBet { amount: 5 };
+        assert!(rules.validate(&table, &acting, &more).is_err());
+        assert!(rules
+            .validate(&table, &acting, &GameMove::DrawCard)
+            .is_err());
+    }
+
+    #[test]
+    fn aces_count_as_eleven_until_that_would_bust() {
+        let ace = Card::new(Suite::Spade, Value::Ace);
+        let six = Card::new(Suite::Heart, Value::Six);
+        let king = Card::new(Suite::Club, Value::King);
+        assert_eq!(hand_value(&[ace, six]), (17, true));
+        assert_eq!(hand_value(&[ace, six, king]), (17, false));
+        assert_eq!(hand_value(&[ace, ace, king]), (12, false));
+        assert_eq!(hand_value(&[ace, king]), (21, true));
+    }
+}
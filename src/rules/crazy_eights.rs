@@ -0,0 +1,588 @@
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    restore_state, save_state,
+    trick::{deal, hand_of, holds},
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData, Suite, Value},
+    table::Table,
+};
+
+const MIN_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 8;
+const EIGHT_POINTS: u32 = 50;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "CrazyEightsPhase")]
+enum Phase {
+    Waiting,
+    Playing,
+    RoundOver,
+}
+
+/// What a card does besides being played, on top of matching the discard pile.
+fn effect(value: Value) -> Option<Effect> {
+    match value {
+        Value::Two => Some(Effect::DrawTwo),
+        Value::Queen => Some(Effect::Skip),
+        Value::Ace => Some(Effect::Reverse),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum Effect {
+    DrawTwo,
+    Skip,
+    Reverse,
+}
+
+/// What a card left in a hand is worth to the winner.
+fn card_points(card: &Card) -> u32 {
+    match card.value {
+        Value::Eight => EIGHT_POINTS,
+        Value::Jack | Value::Queen | Value::King | Value::Ten => 10,
+        Value::Ace => 1,
+        Value::Two => 2,
+        Value::Three => 3,
+        Value::Four => 4,
+        Value::Five => 5,
+        Value::Six => 6,
+        Value::Seven => 7,
+        Value::Nine => 9,
+    }
+}
+
+/// Crazy Eights, with the special cards of Uno mapped onto a regular deck: twos make the next
+/// player draw two, queens skip the next player and aces reverse the direction of play. Eights
+/// are wild and the player names the suit that has to follow.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CrazyEights {
+    phase: Phase,
+    seats: Vec<String>,
+    turn: usize,
+    clockwise: bool,
+    /// The suit named with the eight on top of the discard pile.
+    declared_suit: Option<Suite>,
+    winner: Option<usize>,
+    round_points: Vec<i64>,
+}
+
+impl CrazyEights {
+    pub fn new() -> Self {
+        Self {
+            phase: Phase::Waiting,
+            seats: vec![],
+            turn: 0,
+            clockwise: true,
+            declared_suit: None,
+            winner: None,
+            round_points: vec![],
+        }
+    }
+
+    fn hand_size(players: usize) -> usize {
+        if players == 2 {
+            7
+        } else {
+            5
+        }
+    }
+
+    fn next_seat(&self, seat: usize) -> usize {
+        let seats = self.seats.len();
+        if self.clockwise {
+            (seat + 1) % seats
+        } else {
+            (seat + seats - 1) % seats
+        }
+    }
+
+    fn suit_to_follow(&self, table: &Table) -> Option<Suite> {
+        self.declared_suit
+            .or_else(|| table.discard_deck.last().map(|top| top.suite))
+    }
+
+    fn playable(&self, table: &Table, card: &Card) -> bool {
+        if card.value == Value::Eight {
+            return true;
+        }
+        match table.discard_deck.last() {
+            Some(top) => Some(card.suite) == self.suit_to_follow(table) || card.value == top.value,
+            None => true,
+        }
+    }
+
+    fn check_play(&self, table: &Table, player_id: &str, card: &Card) -> Result<()> {
+        if self.phase != Phase::Playing {
+            bail!("There is no hand being played");
+        }
+        if self.seats.get(self.turn).map(String::as_str) != Some(player_id) {
+            bail!("It isn't your turn");
+        }
+        if !holds(hand_of(table, player_id), card) {
+            bail!("That card isn't in your hand");
+        }
+        if !self.playable(table, card) {
+            bail!("That card doesn't match the suit or value on the discard pile");
+        }
+        Ok(())
+    }
+
+    /// Draws from the stock, turning the discard pile under the top card over when it runs out.
+    fn draw(table: &mut Table, player_id: &str) -> bool {
+        if table.draw_deck.is_empty() && table.discard_deck.len() > 1 {
+            let top = table.discard_deck.pop();
+            table.draw_deck = std::mem::take(&mut table.discard_deck);
+            table.shuffle_draw_deck();
+            table.discard_deck.extend(top);
+        }
+        match table.draw_deck.pop() {
+            Some(mut card) => {
+                card.set_visible(false);
+                if let Some(player) = table.player_mut(player_id) {
+                    player.add_card(card);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Passes the turn on to the next player who can play, drawing cards for anyone who can't.
+    /// If the stock runs dry and nobody can play, the round is blocked and ends.
+    fn advance(&mut self, table: &mut Table, skip: bool) {
+        self.turn = self.next_seat(self.turn);
+        if skip {
+            self.turn = self.next_seat(self.turn);
+        }
+        let mut passes = 0;
+        loop {
+            let player_id = self.seats[self.turn].clone();
+            let can_play = hand_of(table, &player_id)
+                .iter()
+                .any(|card| self.playable(table, card));
+            if can_play {
+                return;
+            }
+            if !Self::draw(table, &player_id) {
+                passes += 1;
+                if passes == self.seats.len() {
+                    self.end_round(table, self.fewest_points(table));
+                    return;
+                }
+                self.turn = self.next_seat(self.turn);
+            }
+        }
+    }
+
+    fn fewest_points(&self, table: &Table) -> usize {
+        (0..self.seats.len())
+            .min_by_key(|seat| hand_points(table, &self.seats[*seat]))
+            .unwrap_or_default()
+    }
+
+    fn play(&mut self, table: &mut Table, player_id: &str, card: &Card) -> Result<Option<Effect>> {
+        let mut played = table
+            .player_mut(player_id)
+            .and_then(|player| player.discard_card(card))
+            .ok_or_else(|| eyre!("That card isn't in your hand"))?;
+        played.set_visible(true);
+        table.discard_deck.push(played);
+        self.declared_suit = None;
+        Ok(effect(played.value))
+    }
+
+    fn after_play(&mut self, table: &mut Table, effect: Option<Effect>) {
+        let seat = self.turn;
+        if hand_of(table, &self.seats[seat]).is_empty() {
+            self.end_round(table, seat);
+            return;
+        }
+        match effect {
+            Some(Effect::DrawTwo) => {
+                let victim = self.next_seat(seat);
+                let victim_id = self.seats[victim].clone();
+                for _ in 0..2 {
+                    Self::draw(table, &victim_id);
+                }
+                self.advance(table, true);
+            }
+            Some(Effect::Skip) => self.advance(table, true),
+            // with two players a reverse just gives the same player another turn
+            Some(Effect::Reverse) if self.seats.len() == 2 => self.advance(table, true),
+            Some(Effect::Reverse) => {
+                self.clockwise = !self.clockwise;
+                self.advance(table, false);
+            }
+            None => self.advance(table, false),
+        }
+    }
+
+    fn end_round(&mut self, table: &Table, winner: usize) {
+        let left_over: u32 = self
+            .seats
+            .iter()
+            .enumerate()
+            .filter(|(seat, _)| *seat != winner)
+            .map(|(_, player_id)| hand_points(table, player_id))
+            .sum();
+        self.round_points = vec![0; self.seats.len()];
+        self.round_points[winner] = left_over as i64;
+        self.winner = Some(winner);
+        self.phase = Phase::RoundOver;
+    }
+}
+
+impl Default for CrazyEights {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hand_points(table: &Table, player_id: &str) -> u32 {
+    hand_of(table, player_id).iter().map(card_points).sum()
+}
+
+impl GameRules for CrazyEights {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        table.collect_cards();
+        self.seats = table
+            .players
+            .iter()
+            .take(MAX_PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &self.seats, Self::hand_size(self.seats.len()))?;
+        // an eight can't start the pile, it goes back to the bottom of the stock
+        let mut upcard = loop {
+            let card = table
+                .draw_deck
+                .pop()
+                .ok_or_else(|| eyre!("The deck ran out of cards"))?;
+            if card.value != Value::Eight {
+                break card;
+            }
+            table.draw_deck.insert(0, card);
+        };
+        upcard.set_visible(true);
+        table.discard_deck.push(upcard);
+        self.clockwise = true;
+        self.declared_suit = None;
+        self.winner = None;
+        self.round_points.clear();
+        self.phase = Phase::Playing;
+        // the first player might not be able to match the up card either
+        self.turn = self.seats.len() - 1;
+        self.advance(table, false);
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if self.phase == Phase::Playing {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < MIN_PLAYERS {
+                    bail!("Crazy eights needs at least two players");
+                }
+            }
+            GameMove::PlayCard { card } => {
+                self.check_play(table, player_id, card)?;
+                if card.value == Value::Eight {
+                    bail!("Eights are wild, name the suit to follow when playing one");
+                }
+            }
+            GameMove::PlayWild { card, .. } => {
+                self.check_play(table, player_id, card)?;
+                if card.value != Value::Eight {
+                    bail!("Only eights are wild");
+                }
+            }
+            _ => bail!("That move isn't part of crazy eights"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::PlayCard { card } => {
+                let effect = self.play(table, player_id, card)?;
+                self.after_play(table, effect);
+            }
+            GameMove::PlayWild { card, suite } => {
+                let effect = self.play(table, player_id, card)?;
+                self.declared_suit = Some(*suite);
+                self.after_play(table, effect);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        self.phase == Phase::RoundOver
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.seats
+            .iter()
+            .zip(self.round_points.iter())
+            .map(|(player_id, points)| PlayerScore {
+                player_id: player_id.clone(),
+                points: *points,
+            })
+            .collect()
+    }
+
+    fn winner(&self, _table: &Table) -> Option<String> {
+        self.winner.and_then(|seat| self.seats.get(seat).cloned())
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.phase == Phase::Playing
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let view = CrazyEightsView {
+            phase: self.phase,
+            turn: self.seats.get(self.turn).cloned(),
+            clockwise: self.clockwise,
+            top_card: table.discard_deck.last().map(Card::card_data),
+            suit_to_follow: self.suit_to_follow(table),
+            hand: hand_of(table, player_id)
+                .iter()
+                .map(Card::revealed_card_data)
+                .collect(),
+            seats: self
+                .seats
+                .iter()
+                .map(|seat| SeatView {
+                    player_id: seat.clone(),
+                    cards_in_hand: hand_of(table, seat).len(),
+                })
+                .collect(),
+            stock_size: table.draw_deck.len(),
+            winner: self.winner(table),
+            round_points: self.score(table),
+        };
+        Some(GameState::CrazyEights(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        let seat = match self.seats.iter().position(|seat| seat == player_id) {
+            Some(seat) => seat,
+            None => return Ok(()),
+        };
+        // the room drops the leaving player's face down hand on the discard pile, so the last
+        // card played has to be put back on top
+        if let Some(index) = table
+            .discard_deck
+            .iter()
+            .rposition(|card| card.is_visible())
+        {
+            let card = table.discard_deck.remove(index);
+            table.discard_deck.push(card);
+        }
+        self.seats.remove(seat);
+        if self.phase != Phase::Playing {
+            return Ok(());
+        }
+        if self.seats.len() < MIN_PLAYERS {
+            self.phase = Phase::Waiting;
+            return Ok(());
+        }
+        if seat < self.turn {
+            self.turn -= 1;
+        } else if seat == self.turn {
+            // step back so advancing lands on whoever sat after the player who left
+            let seats = self.seats.len();
+            self.turn = if self.clockwise {
+                (seat + seats - 1) % seats
+            } else {
+                seat % seats
+            };
+            self.advance(table, false);
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        let hand = hand_of(table, player_id);
+        // get rid of the expensive cards first, and save the eights for when nothing else fits
+        let best = hand
+            .iter()
+            .filter(|card| {
+                card.value != Value::Eight && self.check_play(table, player_id, card).is_ok()
+            })
+            .max_by_key(|card| card_points(card));
+        if let Some(card) = best {
+            return Some(GameMove::PlayCard { card: *card });
+        }
+        let eight = hand.iter().find(|card| card.value == Value::Eight)?;
+        let suite = Suite::all().iter().copied().max_by_key(|suite| {
+            hand.iter()
+                .filter(|card| card.suite == *suite && card.value != Value::Eight)
+                .count()
+        })?;
+        Some(GameMove::PlayWild {
+            card: *eight,
+            suite,
+        })
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CrazyEightsView {
+    phase: Phase,
+    turn: Option<String>,
+    clockwise: bool,
+    top_card: Option<CardData>,
+    suit_to_follow: Option<Suite>,
+    hand: Vec<CardData>,
+    seats: Vec<SeatView>,
+    stock_size: usize,
+    winner: Option<String>,
+    round_points: Vec<PlayerScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[schemars(rename = "CrazyEightsSeatView")]
+struct SeatView {
+    player_id: String,
+    cards_in_hand: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card::cards,
+        outbox::OutboxReceiver,
+        rules::{play_hints, test_table},
+    };
+
+    fn deal_round(
+        players: usize,
+        seed: u64,
+    ) -> (CrazyEights, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(players, 0, seed);
+        let mut rules = CrazyEights::new();
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    fn play(rules: &mut CrazyEights, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    #[test]
+    fn the_winner_scores_what_everyone_else_is_left_holding() {
+        for players in [2, 3, 5, 8] {
+            let (mut rules, mut table, ids, _receivers) = deal_round(players, 0);
+            play_hints(&mut rules, &mut table);
+            assert!(rules.is_round_over(&table));
+            let winner = rules.winner(&table).unwrap();
+            let left_over: u32 = ids
+                .iter()
+                .filter(|player_id| **player_id != winner)
+                .map(|player_id| hand_points(&table, player_id))
+                .sum();
+            let scores = rules.score(&table);
+            let winner_score = scores.iter().find(|score| score.player_id == winner);
+            assert_eq!(winner_score.unwrap().points, left_over as i64);
+            let cards: usize = ids
+                .iter()
+                .map(|id| hand_of(&table, id).len())
+                .sum::<usize>()
+                + table.draw_deck.len()
+                + table.discard_deck.len();
+            assert_eq!(cards, 52);
+        }
+    }
+
+    #[test]
+    fn queens_skip_eights_name_the_suit_and_twos_make_the_next_player_draw() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(3, 0);
+        for (player_id, hand) in ids.iter().zip(["QH 2S 3C", "4H 3D", "6H 8C 7C"]) {
+            table.player_mut(player_id).unwrap().hand = cards(hand);
+        }
+        table.discard_deck = cards("5H");
+        rules.turn = 0;
+
+        let queen = GameMove::PlayCard {
+            card: cards("QH")[0],
+        };
+        play(&mut rules, &mut table, &ids[0], queen);
+        assert_eq!(rules.turn, 2);
+
+        let eight = cards("8C")[0];
+        assert!(rules
+            .validate(&table, &ids[2], &GameMove::PlayCard { card: eight })
+            .is_err());
+        let wild = GameMove::PlayWild {
+            card: eight,
+            suite: Suite::Spade,
+        };
+        play(&mut rules, &mut table, &ids[2], wild);
+        assert_eq!(rules.suit_to_follow(&table), Some(Suite::Spade));
+        assert_eq!(rules.turn, 0);
+
+        let two = GameMove::PlayCard {
+            card: cards("2S")[0],
+        };
+        play(&mut rules, &mut table, &ids[0], two);
+        assert_eq!(hand_of(&table, &ids[1]).len(), 2 + 2);
+        assert_ne!(rules.turn, 1);
+    }
+
+    #[test]
+    fn cards_that_dont_match_or_arent_yours_to_play_are_rejected() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(2, 0);
+        table.player_mut(&ids[0]).unwrap().hand = cards("9S 5C 3H");
+        table.player_mut(&ids[1]).unwrap().hand = cards("5D 9D");
+        table.discard_deck = cards("5H");
+        rules.turn = 0;
+
+        let mismatch = GameMove::PlayCard {
+            card: cards("9S")[0],
+        };
+        assert!(rules.validate(&table, &ids[0], &mismatch).is_err());
+        let not_held = GameMove::PlayCard {
+            card: cards("4H")[0],
+        };
+        assert!(rules.validate(&table, &ids[0], &not_held).is_err());
+        let out_of_turn = GameMove::PlayCard {
+            card: cards("5D")[0],
+        };
+        assert!(rules.validate(&table, &ids[1], &out_of_turn).is_err());
+        let not_wild = GameMove::PlayWild {
+            card: cards("3H")[0],
+            suite: Suite::Club,
+        };
+        assert!(rules.validate(&table, &ids[0], &not_wild).is_err());
+        // matching the value is as good as matching the suit
+        let same_value = GameMove::PlayCard {
+            card: cards("5C")[0],
+        };
+        play(&mut rules, &mut table, &ids[0], same_value);
+        assert_eq!(rules.turn, 1);
+    }
+}
@@ -0,0 +1,473 @@
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    restore_state, save_state,
+    trick::{
+        ace_high_rank, deal, hand_of, holds, playable_cards, same_colour, TrickEngine, TrickView,
+    },
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData, Suite, Value},
+    table::Table,
+};
+
+const PLAYERS: usize = 4;
+const CARDS_PER_HAND: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "EuchrePhase")]
+enum Phase {
+    Waiting,
+    /// Going around once to see whether anyone orders up the turned up card as trump.
+    OrderingUp,
+    /// The dealer picked up the turned up card and has to discard one.
+    DealerDiscard,
+    /// Going around a second time to name any other suit as trump.
+    NamingTrump,
+    Playing,
+    RoundOver,
+    GameOver,
+}
+
+/// Seats across from each other play as partners.
+fn team_of(seat: usize) -> usize {
+    seat % 2
+}
+
+/// Euchre for two partnerships, played with only the nines up to the aces. Whoever makes trump
+/// can go alone, leaving their partner out of the hand for a chance at four points.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Euchre {
+    target_score: i64,
+    phase: Phase,
+    engine: TrickEngine,
+    dealer: usize,
+    upcard: Option<Card>,
+    maker: Option<usize>,
+    alone: bool,
+    round_points: [i64; 2],
+    team_scores: [i64; 2],
+}
+
+impl Euchre {
+    pub const TARGET_SCORE: i64 = 10;
+
+    pub fn new(target_score: i64) -> Self {
+        Self {
+            target_score,
+            phase: Phase::Waiting,
+            engine: TrickEngine::new(vec![]),
+            dealer: 0,
+            upcard: None,
+            maker: None,
+            alone: false,
+            round_points: [0; 2],
+            team_scores: [0; 2],
+        }
+    }
+
+    fn bidding(&self) -> bool {
+        matches!(self.phase, Phase::OrderingUp | Phase::NamingTrump)
+    }
+
+    fn check_bidder(&self, player_id: &str) -> Result<usize> {
+        if !self.bidding() {
+            bail!("Trump has already been made");
+        }
+        let seat = self
+            .engine
+            .seat_of(player_id)
+            .ok_or_else(|| eyre!("You aren't playing this hand"))?;
+        if seat != self.engine.turn {
+            bail!("It isn't your turn");
+        }
+        Ok(seat)
+    }
+
+    fn make_trump(&mut self, seat: usize, trump: Suite, alone: bool) {
+        self.engine.trump = Some(trump);
+        self.maker = Some(seat);
+        self.alone = alone;
+        if alone {
+            self.engine.sitting_out = Some((seat + 2) % PLAYERS);
+        }
+    }
+
+    fn start_play(&mut self) {
+        self.phase = Phase::Playing;
+        let leader = self.engine.next_seat(self.dealer);
+        self.engine.start(leader);
+    }
+
+    fn end_round(&mut self) {
+        let maker = self.maker.unwrap_or_default();
+        let makers = team_of(maker);
+        let tricks: usize = (0..PLAYERS)
+            .filter(|seat| team_of(*seat) == makers)
+            .map(|seat| self.engine.tricks_won(seat))
+            .sum();
+        let mut round_points = [0; 2];
+        match tricks {
+            5 if self.alone => round_points[makers] = 4,
+            5 => round_points[makers] = 2,
+            3 | 4 => round_points[makers] = 1,
+            _ => round_points[1 - makers] = 2,
+        }
+        for (score, points) in self.team_scores.iter_mut().zip(round_points) {
+            *score += points;
+        }
+        self.round_points = round_points;
+        self.dealer = (self.dealer + 1) % PLAYERS;
+        let game_over = self
+            .team_scores
+            .iter()
+            .any(|score| *score >= self.target_score);
+        self.phase = if game_over {
+            Phase::GameOver
+        } else {
+            Phase::RoundOver
+        };
+    }
+}
+
+impl GameRules for Euchre {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        if self.phase == Phase::GameOver {
+            self.team_scores = [0; 2];
+            self.dealer = 0;
+        }
+        table.collect_cards();
+        table
+            .draw_deck
+            .retain(|card| ace_high_rank(card.value) >= ace_high_rank(Value::Nine));
+        let seats: Vec<String> = table
+            .players
+            .iter()
+            .take(PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &seats, CARDS_PER_HAND)?;
+        let mut upcard = table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("The deck ran out of cards"))?;
+        upcard.set_visible(true);
+        self.upcard = Some(upcard);
+        self.engine = TrickEngine::new(seats);
+        self.engine.bowers = true;
+        self.engine.turn = self.engine.next_seat(self.dealer);
+        self.maker = None;
+        self.alone = false;
+        self.round_points = [0; 2];
+        self.phase = Phase::OrderingUp;
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if self.in_progress(table) {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < PLAYERS {
+                    bail!("Euchre needs four players");
+                }
+            }
+            GameMove::OrderUp { .. } => {
+                self.check_bidder(player_id)?;
+                if self.phase != Phase::OrderingUp {
+                    bail!("The turned up card has already been turned down");
+                }
+            }
+            GameMove::NameTrump { suite, .. } => {
+                self.check_bidder(player_id)?;
+                if self.phase != Phase::NamingTrump {
+                    bail!("You can only order up the turned up card");
+                }
+                if self.upcard.is_some_and(|upcard| upcard.suite == *suite) {
+                    bail!("That suit was turned down");
+                }
+            }
+            GameMove::Pass => {
+                let seat = self.check_bidder(player_id)?;
+                if self.phase == Phase::NamingTrump && seat == self.dealer {
+                    bail!("The dealer has to name trump");
+                }
+            }
+            GameMove::DiscardCard { card } => {
+                if self.phase != Phase::DealerDiscard
+                    || self.engine.seat_of(player_id) != Some(self.dealer)
+                {
+                    bail!("Only the dealer discards, after picking up");
+                }
+                if !holds(hand_of(table, player_id), card) {
+                    bail!("That card isn't in your hand");
+                }
+            }
+            GameMove::PlayCard { card } => {
+                if self.phase != Phase::Playing {
+                    bail!("There is no hand being played");
+                }
+                self.engine.validate_play(table, player_id, card)?;
+            }
+            _ => bail!("That move isn't part of euchre"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        let seat = self.engine.seat_of(player_id).unwrap_or_default();
+        match game_move {
+            GameMove::OrderUp { alone } => {
+                let upcard = self
+                    .upcard
+                    .take()
+                    .ok_or_else(|| eyre!("No card is turned up"))?;
+                self.make_trump(seat, upcard.suite, *alone);
+                // the dealer doesn't pick up when their partner is going alone without them
+                if self.engine.sitting_out == Some(self.dealer) {
+                    table.discard_deck.push(upcard);
+                    self.start_play();
+                } else {
+                    let dealer_id = self.engine.seats[self.dealer].clone();
+                    if let Some(dealer) = table.player_mut(&dealer_id) {
+                        let mut upcard = upcard;
+                        upcard.set_visible(false);
+                        dealer.add_card(upcard);
+                    }
+                    self.phase = Phase::DealerDiscard;
+                }
+            }
+            GameMove::NameTrump { suite, alone } => {
+                if let Some(upcard) = self.upcard.take() {
+                    table.discard_deck.push(upcard);
+                }
+                self.make_trump(seat, *suite, *alone);
+                self.start_play();
+            }
+            GameMove::Pass => {
+                if seat == self.dealer {
+                    self.phase = Phase::NamingTrump;
+                }
+                self.engine.turn = self.engine.next_seat(seat);
+            }
+            GameMove::DiscardCard { card } => {
+                if let Some(mut discarded) = table
+                    .player_mut(player_id)
+                    .and_then(|player| player.discard_card(card))
+                {
+                    discarded.set_visible(false);
+                    table.discard_deck.push(discarded);
+                }
+                self.start_play();
+            }
+            GameMove::PlayCard { card } => {
+                let trick_finished = self.engine.play(table, card)?.is_some();
+                if trick_finished && self.engine.hands_empty(table) {
+                    self.end_round();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::RoundOver | Phase::GameOver)
+    }
+
+    fn target_score(&self) -> Option<i64> {
+        Some(self.target_score)
+    }
+
+    /// Partners share their points, so both of them are given the partnership's score.
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.engine
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(seat, player_id)| PlayerScore {
+                player_id: player_id.clone(),
+                points: self.round_points[team_of(seat)],
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        !matches!(
+            self.phase,
+            Phase::Waiting | Phase::RoundOver | Phase::GameOver
+        )
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let view = EuchreView {
+            phase: self.phase,
+            trick: self.engine.view(table, player_id),
+            dealer: self.engine.seats.get(self.dealer).cloned(),
+            upcard: self.upcard.map(|card| card.card_data()),
+            maker: self
+                .maker
+                .and_then(|seat| self.engine.seats.get(seat).cloned()),
+            alone: self.alone,
+            team_scores: self.team_scores,
+            round_points: self.score(table),
+            target_score: self.target_score,
+        };
+        Some(GameState::Euchre(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        if self.engine.seat_of(player_id).is_some() && self.in_progress(table) {
+            self.phase = Phase::Waiting;
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        let seat = self.engine.seat_of(player_id)?;
+        let hand = hand_of(table, player_id);
+        // the bowers count towards the suit they'd be trump for
+        let trumps_held = |trump: Suite| {
+            hand.iter()
+                .filter(|card| {
+                    card.suite == trump
+                        || (card.value == Value::Jack && same_colour(card.suite, trump))
+                })
+                .count()
+        };
+        match self.phase {
+            Phase::OrderingUp => {
+                let upcard = self.upcard?;
+                Some(if trumps_held(upcard.suite) >= 3 {
+                    GameMove::OrderUp { alone: false }
+                } else {
+                    GameMove::Pass
+                })
+            }
+            Phase::DealerDiscard => {
+                let trump = self.engine.trump?;
+                hand.iter()
+                    .min_by_key(|card| (card.suite == trump, ace_high_rank(card.value)))
+                    .map(|card| GameMove::DiscardCard { card: *card })
+            }
+            Phase::NamingTrump => {
+                let turned_down = self.upcard.map(|card| card.suite);
+                let (suite, held) = Suite::all()
+                    .iter()
+                    .copied()
+                    .filter(|suite| Some(*suite) != turned_down)
+                    .map(|suite| (suite, trumps_held(suite)))
+                    .max_by_key(|(_, held)| *held)?;
+                Some(if held >= 3 || seat == self.dealer {
+                    GameMove::NameTrump {
+                        suite,
+                        alone: false,
+                    }
+                } else {
+                    GameMove::Pass
+                })
+            }
+            Phase::Playing => {
+                let partner_winning = self
+                    .engine
+                    .winning_seat()
+                    .is_some_and(|winner| team_of(winner) == team_of(seat));
+                self.engine
+                    .pick_card(&playable_cards(self, table, player_id), !partner_winning)
+                    .map(|card| GameMove::PlayCard { card })
+            }
+            _ => None,
+        }
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct EuchreView {
+    phase: Phase,
+    #[serde(flatten)]
+    trick: TrickView,
+    dealer: Option<String>,
+    upcard: Option<CardData>,
+    maker: Option<String>,
+    alone: bool,
+    team_scores: [i64; 2],
+    round_points: Vec<PlayerScore>,
+    target_score: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        outbox::OutboxReceiver,
+        rules::{play_hints, test_table},
+    };
+
+    fn deal_round(seed: u64) -> (Euchre, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(PLAYERS, 0, seed);
+        let mut rules = Euchre::new(Euchre::TARGET_SCORE);
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    #[test]
+    fn a_round_gives_points_to_one_partnership() {
+        for seed in 0..10 {
+            let (mut rules, mut table, _, _receivers) = deal_round(seed);
+            play_hints(&mut rules, &mut table);
+            assert!(rules.is_round_over(&table));
+            let tricks: usize = (0..PLAYERS).map(|seat| rules.engine.tricks_won(seat)).sum();
+            assert_eq!(tricks, CARDS_PER_HAND);
+            let [first, second] = rules.round_points;
+            assert!((first == 0) != (second == 0), "{:?}", rules.round_points);
+            assert_eq!(rules.dealer, 1);
+        }
+    }
+
+    #[test]
+    fn the_dealer_is_stuck_naming_trump_once_everyone_has_passed() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(0);
+        let upcard = rules.upcard.unwrap();
+        let name = GameMove::NameTrump {
+            suite: upcard.suite,
+            alone: false,
+        };
+        assert!(rules.validate(&table, &ids[1], &name).is_err());
+        assert!(rules.validate(&table, &ids[2], &GameMove::Pass).is_err());
+        for seat in [1, 2, 3, 0, 1, 2, 3] {
+            rules.validate(&table, &ids[seat], &GameMove::Pass).unwrap();
+            rules
+                .apply(&mut table, &ids[seat], &GameMove::Pass)
+                .unwrap();
+        }
+        assert!(rules.validate(&table, &ids[0], &GameMove::Pass).is_err());
+        let order_up = GameMove::OrderUp { alone: false };
+        assert!(rules.validate(&table, &ids[0], &order_up).is_err());
+        assert!(rules.validate(&table, &ids[0], &name).is_err());
+        let other_suit = Suite::all()
+            .iter()
+            .copied()
+            .find(|suite| *suite != upcard.suite)
+            .unwrap();
+        let name = GameMove::NameTrump {
+            suite: other_suit,
+            alone: false,
+        };
+        rules.validate(&table, &ids[0], &name).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+use eyre::{bail, Result};
+
+use super::{GameMove, GameRules, PlayerScore};
+use crate::table::Table;
+
+/// No rules at all, the table behaves like a real one and everyone is trusted to play fair.
+#[derive(Debug, Default)]
+pub struct FreePlay;
+
+impl GameRules for FreePlay {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        table.reset_deck()
+    }
+
+    fn validate(&self, _table: &Table, _player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound
+            | GameMove::DrawCard
+            | GameMove::DiscardCard { .. }
+            | GameMove::ToggleVisibilityOfCard { .. }
+            | GameMove::ResetDeck => Ok(()),
+            _ => bail!("That move needs a game with rules"),
+        }
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound | GameMove::ResetDeck => table.reset_deck(),
+            GameMove::DrawCard => table.draw_card(player_id),
+            GameMove::DiscardCard { card } => table.discard_card(player_id, card),
+            GameMove::ToggleVisibilityOfCard { card } => {
+                table.toggle_visibility_of_card(player_id, card)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        false
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        card::DeckSpec,
+        outbox::{outbox, OutboxConfig},
+        player::Player,
+    };
+
+    #[test]
+    fn anyone_can_draw_and_discard_and_the_round_never_ends() {
+        let mut table = Table::new(DeckSpec::new(1), 0);
+        let (sender, _receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let player = Player::new("Ada", sender);
+        let player_id = player.id.clone();
+        table.players.push(player);
+        let mut rules = FreePlay;
+        rules.setup(&mut table).unwrap();
+
+        for _ in 0..2 {
+            assert!(rules
+                .validate(&table, &player_id, &GameMove::DrawCard)
+                .is_ok());
+            rules
+                .apply(&mut table, &player_id, &GameMove::DrawCard)
+                .unwrap();
+        }
+        let card = table.players[0].hand[0];
+        let discard = GameMove::DiscardCard { card };
+        assert!(rules.validate(&table, &player_id, &discard).is_ok());
+        rules.apply(&mut table, &player_id, &discard).unwrap();
+
+        assert_eq!(table.draw_deck.len(), 50);
+        assert_eq!(table.players[0].hand.len(), 1);
+        // the discard pile is face up
+        let mut discarded = card;
+        discarded.set_visible(true);
+        assert_eq!(table.discard_deck, vec![discarded]);
+        assert!(rules.in_progress(&table));
+        assert!(!rules.is_round_over(&table));
+
+        rules
+            .apply(&mut table, &player_id, &GameMove::ResetDeck)
+            .unwrap();
+        assert_eq!(table.draw_deck.len(), 52);
+        assert!(!rules.in_progress(&table));
+    }
+}
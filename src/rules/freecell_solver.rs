@@ -0,0 +1,321 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::card::{Card, Suite, Value};
+
+/// How many positions the solver looks at before giving up on an answer.
+const SOLVER_LIMIT: usize = 200_000;
+
+/// What the solver found out about a position.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(tag = "result")]
+pub enum Solvability {
+    /// A solution was found, taking this many moves.
+    Winnable { moves: usize },
+    /// Every position reachable from here was tried and none of them win.
+    NotWinnable,
+    /// The solver gave up before finding an answer either way.
+    Unknown,
+}
+
+/// Cards are packed into a byte, suit in the high bits and rank from 1 to 13 in the low bits,
+/// leaving zero free for an empty cell.
+type Packed = u8;
+
+fn pack(card: &Card) -> Packed {
+    let suit = match card.suite {
+        Suite::Club => 0,
+        Suite::Diamond => 1,
+        Suite::Heart => 2,
+        Suite::Spade => 3,
+    };
+    let rank = Value::all()
+        .iter()
+        .position(|value| *value == card.value)
+        .unwrap_or_default() as u8
+        + 1;
+    suit << 4 | rank
+}
+
+fn suit(card: Packed) -> usize {
+    (card >> 4) as usize
+}
+
+fn rank(card: Packed) -> u8 {
+    card & 0xf
+}
+
+fn is_red(card: Packed) -> bool {
+    matches!(suit(card), 1 | 2)
+}
+
+fn builds_on(card: Packed, onto: Packed) -> bool {
+    rank(card) + 1 == rank(onto) && is_red(card) != is_red(onto)
+}
+
+/// A FreeCell position as the solver sees it, taken from a game so the search can run
+/// somewhere else.
+#[derive(Debug, Clone)]
+pub struct Position {
+    columns: Vec<Vec<Packed>>,
+    cells: Vec<Packed>,
+    /// The highest rank on the foundation of each suit.
+    foundations: [u8; 4],
+}
+
+impl Position {
+    pub fn new(tableau: &[Vec<Card>], cells: &[Option<Card>], foundations: &[Vec<Card>]) -> Self {
+        let mut founded = [0u8; 4];
+        for card in foundations.iter().flatten() {
+            let card = pack(card);
+            founded[suit(card)] = founded[suit(card)].max(rank(card));
+        }
+        Self {
+            columns: tableau
+                .iter()
+                .map(|column| column.iter().map(pack).collect())
+                .collect(),
+            cells: cells
+                .iter()
+                .map(|cell| cell.as_ref().map(pack).unwrap_or(0))
+                .collect(),
+            foundations: founded,
+        }
+    }
+
+    fn founded(&self) -> usize {
+        self.foundations.iter().map(|rank| *rank as usize).sum()
+    }
+
+    fn can_found(&self, card: Packed) -> bool {
+        self.foundations[suit(card)] + 1 == rank(card)
+    }
+
+    /// Cards that nothing else could ever need to be built on are put on the foundations
+    /// straight away, which keeps the search from trying pointless orders of moves.
+    fn auto_found(&mut self) {
+        loop {
+            let safe = |position: &Position, card: Packed| {
+                let opposite = if is_red(card) { [0, 3] } else { [1, 2] };
+                position.can_found(card)
+                    && (rank(card) <= 2
+                        || opposite
+                            .iter()
+                            .all(|other| position.foundations[*other] + 1 >= rank(card)))
+            };
+            let mut moved = false;
+            for index in 0..self.cells.len() {
+                let card = self.cells[index];
+                if card != 0 && safe(self, card) {
+                    self.foundations[suit(card)] += 1;
+                    self.cells[index] = 0;
+                    moved = true;
+                }
+            }
+            for index in 0..self.columns.len() {
+                if let Some(card) = self.columns[index].last().copied() {
+                    if safe(self, card) {
+                        self.foundations[suit(card)] += 1;
+                        self.columns[index].pop();
+                        moved = true;
+                    }
+                }
+            }
+            if !moved {
+                return;
+            }
+        }
+    }
+
+    /// The same position with the cells and columns in a fixed order, since shuffling them
+    /// around doesn't change anything.
+    pub fn key(&self) -> Vec<u8> {
+        let mut cells = self.cells.clone();
+        cells.sort_unstable();
+        let mut columns: Vec<&Vec<Packed>> = self.columns.iter().collect();
+        columns.sort_unstable();
+        let mut key = cells;
+        key.extend_from_slice(&self.foundations);
+        for column in columns {
+            key.push(0xff);
+            key.extend_from_slice(column);
+        }
+        key
+    }
+
+    /// How far this position looks from being solved. Cards still to be founded count, and so
+    /// does every card sitting on top of the next card each foundation needs.
+    fn distance(&self) -> usize {
+        let mut distance = (52 - self.founded()) * 2;
+        for column in &self.columns {
+            for (depth, card) in column.iter().enumerate() {
+                if self.can_found(*card) {
+                    distance += column.len() - depth - 1;
+                }
+            }
+        }
+        distance
+    }
+
+    fn successors(&self) -> Vec<Position> {
+        let mut next = vec![];
+        let free_cell = self.cells.iter().position(|cell| *cell == 0);
+        let empty_column = self.columns.iter().position(Vec::is_empty);
+
+        for from in 0..self.columns.len() {
+            let card = match self.columns[from].last() {
+                Some(card) => *card,
+                None => continue,
+            };
+            if self.can_found(card) {
+                let mut position = self.clone();
+                position.columns[from].pop();
+                position.foundations[suit(card)] += 1;
+                next.push(position);
+            }
+            for to in 0..self.columns.len() {
+                if to == from {
+                    continue;
+                }
+                let fits = match self.columns[to].last() {
+                    Some(top) => builds_on(card, *top),
+                    // moving a whole column into an empty one gets nowhere
+                    None => Some(to) == empty_column && self.columns[from].len() > 1,
+                };
+                if fits {
+                    let mut position = self.clone();
+                    position.columns[from].pop();
+                    position.columns[to].push(card);
+                    next.push(position);
+                }
+            }
+            if let Some(cell) = free_cell {
+                let mut position = self.clone();
+                position.columns[from].pop();
+                position.cells[cell] = card;
+                next.push(position);
+            }
+        }
+
+        for cell in 0..self.cells.len() {
+            let card = self.cells[cell];
+            if card == 0 {
+                continue;
+            }
+            if self.can_found(card) {
+                let mut position = self.clone();
+                position.cells[cell] = 0;
+                position.foundations[suit(card)] += 1;
+                next.push(position);
+            }
+            for to in 0..self.columns.len() {
+                let fits = match self.columns[to].last() {
+                    Some(top) => builds_on(card, *top),
+                    None => Some(to) == empty_column,
+                };
+                if fits {
+                    let mut position = self.clone();
+                    position.cells[cell] = 0;
+                    position.columns[to].push(card);
+                    next.push(position);
+                }
+            }
+        }
+
+        for position in &mut next {
+            position.auto_found();
+        }
+        next
+    }
+}
+
+/// Searches for a way to get every card onto the foundations, one card at a time, trying the
+/// most promising positions first. Longer runs are only ever moved through the free cells and
+/// empty columns, so one card at a time finds every solution there is.
+pub fn solve(start: &Position) -> Solvability {
+    search(start, SOLVER_LIMIT)
+}
+
+/// Gives up once more than `limit` positions have been looked at.
+fn search(start: &Position, limit: usize) -> Solvability {
+    let mut start = start.clone();
+    start.auto_found();
+
+    let mut positions = vec![(start, 0)];
+    let mut seen = HashSet::new();
+    let mut queue = BinaryHeap::new();
+    seen.insert(positions[0].0.key());
+    queue.push(Reverse((positions[0].0.distance(), 0)));
+
+    while let Some(Reverse((_, index))) = queue.pop() {
+        let (position, moves) = positions[index].clone();
+        if position.founded() == 52 {
+            return Solvability::Winnable { moves };
+        }
+        for next in position.successors() {
+            if !seen.insert(next.key()) {
+                continue;
+            }
+            if seen.len() > limit {
+                return Solvability::Unknown;
+            }
+            queue.push(Reverse((next.distance(), positions.len())));
+            positions.push((next, moves + 1));
+        }
+    }
+    Solvability::NotWinnable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::{cards, DeckSpec};
+
+    fn founded_except(suite: Suite) -> Vec<Vec<Card>> {
+        Suite::all()
+            .iter()
+            .map(|other| {
+                Value::all()
+                    .iter()
+                    .filter(|_| *other != suite)
+                    .map(|value| Card::new(*other, *value))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_position_with_no_moves_left_is_not_winnable() {
+        let clubs = cards("KC QC JC TC 9C 8C 7C 6C 5C 4C 3C AC 2C");
+        let position = Position::new(
+            std::slice::from_ref(&clubs),
+            &[],
+            &founded_except(Suite::Club),
+        );
+        assert_eq!(solve(&position), Solvability::NotWinnable);
+
+        // with a free cell the two can wait there while the ace goes up
+        let position = Position::new(&[clubs], &[None], &founded_except(Suite::Club));
+        assert!(matches!(solve(&position), Solvability::Winnable { .. }));
+
+        // and once the ace is on top everything goes up without a single move
+        let clubs = cards("KC QC JC TC 9C 8C 7C 6C 5C 4C 3C 2C AC");
+        let position = Position::new(&[clubs], &[], &founded_except(Suite::Club));
+        assert_eq!(solve(&position), Solvability::Winnable { moves: 0 });
+    }
+
+    #[test]
+    fn the_solver_gives_up_past_its_limit() {
+        let mut tableau = vec![vec![]; 8];
+        for (index, card) in DeckSpec::default().build().into_iter().enumerate() {
+            tableau[index % 8].push(card);
+        }
+        let position = Position::new(&tableau, &[None; 4], &[vec![], vec![], vec![], vec![]]);
+        assert_eq!(search(&position, 10), Solvability::Unknown);
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    meld::{arrange, best_discard, draw_hint, same_card, Aces, Arrangement, Meld, MeldData},
+    restore_state, save_state,
+    trick::{deal, hand_of, holds},
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData},
+    table::Table,
+};
+
+const PLAYERS: usize = 2;
+const HAND_SIZE: usize = 10;
+const MAX_KNOCK_DEADWOOD: u32 = 10;
+const GIN_BONUS: i64 = 25;
+const UNDERCUT_BONUS: i64 = 25;
+/// The hand is a draw once the stock is down to this many cards without anyone knocking.
+const DEAD_STOCK: usize = 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "GinPhase")]
+enum Phase {
+    Waiting,
+    Draw,
+    Discard,
+    RoundOver,
+    GameOver,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+enum Outcome {
+    Gin,
+    Knock,
+    Undercut,
+    /// The stock ran down before anyone knocked.
+    Draw,
+}
+
+/// How the hand ended, shown to both players once the cards are turned over.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct Showdown {
+    outcome: Outcome,
+    knocker: Option<String>,
+    hands: Vec<ShownHand>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct ShownHand {
+    player_id: String,
+    melds: Vec<MeldData>,
+    laid_off: Vec<CardData>,
+    deadwood: Vec<CardData>,
+    deadwood_points: u32,
+}
+
+impl ShownHand {
+    fn new(player_id: &str, arrangement: &Arrangement, laid_off: &[Card]) -> Self {
+        Self {
+            player_id: player_id.to_owned(),
+            melds: arrangement.melds.iter().map(Meld::meld_data).collect(),
+            laid_off: laid_off.iter().map(Card::revealed_card_data).collect(),
+            deadwood: arrangement
+                .deadwood
+                .iter()
+                .map(Card::revealed_card_data)
+                .collect(),
+            deadwood_points: arrangement.deadwood_points(),
+        }
+    }
+}
+
+/// Gin rummy for two. Nothing is melded during play, a player with ten points of deadwood or
+/// less can knock, and the hands are arranged into their best melds when they are turned over.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Gin {
+    aces: Aces,
+    target_score: i64,
+    phase: Phase,
+    seats: Vec<String>,
+    turn: usize,
+    dealer: usize,
+    taken_discard: Option<Card>,
+    showdown: Option<Showdown>,
+    round_points: Vec<i64>,
+    totals: HashMap<String, i64>,
+}
+
+impl Gin {
+    pub const TARGET_SCORE: i64 = 100;
+
+    pub fn new(aces: Aces, target_score: i64) -> Self {
+        Self {
+            aces,
+            target_score,
+            phase: Phase::Waiting,
+            seats: vec![],
+            turn: 0,
+            dealer: 0,
+            taken_discard: None,
+            showdown: None,
+            round_points: vec![],
+            totals: HashMap::new(),
+        }
+    }
+
+    fn in_round(&self) -> bool {
+        matches!(self.phase, Phase::Draw | Phase::Discard)
+    }
+
+    fn check_turn(&self, player_id: &str, phase: Phase) -> Result<()> {
+        if !self.in_round() {
+            bail!("There is no hand being played");
+        }
+        if self.seats.get(self.turn).map(String::as_str) != Some(player_id) {
+            bail!("It isn't your turn");
+        }
+        if self.phase != phase {
+            match phase {
+                Phase::Draw => bail!("You have already drawn this turn"),
+                _ => bail!("You have to draw a card first"),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_discard(&self, hand: &[Card], card: &Card) -> Result<()> {
+        if !holds(hand, card) {
+            bail!("That card isn't in your hand");
+        }
+        let only_copy = hand.iter().filter(|held| same_card(held, card)).count() == 1;
+        if only_copy
+            && self
+                .taken_discard
+                .is_some_and(|taken| same_card(&taken, card))
+        {
+            bail!("You can't discard the card you just picked up");
+        }
+        Ok(())
+    }
+
+    fn discard(&mut self, table: &mut Table, player_id: &str, card: &Card) -> Result<()> {
+        let mut discarded = table
+            .player_mut(player_id)
+            .and_then(|player| player.discard_card(card))
+            .ok_or_else(|| eyre!("That card isn't in your hand"))?;
+        discarded.set_visible(true);
+        table.discard_deck.push(discarded);
+        self.taken_discard = None;
+        Ok(())
+    }
+
+    fn knock(&mut self, table: &mut Table) {
+        reveal_hands(table);
+        let knocker = self.turn;
+        let defender = 1 - knocker;
+        let knocker_hand = arrange(hand_of(table, &self.seats[knocker]), self.aces);
+        let mut defender_hand = arrange(hand_of(table, &self.seats[defender]), self.aces);
+        let knocker_points = knocker_hand.deadwood_points() as i64;
+
+        // the defender can lay deadwood off on the knocker's melds, unless the knocker went gin
+        let mut laid_off = vec![];
+        if knocker_points > 0 {
+            let mut melds = knocker_hand.melds.clone();
+            let mut index = 0;
+            // laying one card off can make room for another, so start over after each one
+            while index < defender_hand.deadwood.len() {
+                let card = defender_hand.deadwood[index];
+                let extension = melds.iter().enumerate().find_map(|(meld, cards)| {
+                    cards
+                        .extended(std::slice::from_ref(&card), self.aces)
+                        .ok()
+                        .map(|extended| (meld, extended))
+                });
+                if let Some((meld, extended)) = extension {
+                    melds[meld] = extended;
+                    defender_hand.deadwood.remove(index);
+                    laid_off.push(card);
+                    index = 0;
+                } else {
+                    index += 1;
+                }
+            }
+        }
+        let defender_points = defender_hand.deadwood_points() as i64;
+
+        let mut round_points = vec![0; PLAYERS];
+        let outcome = if knocker_points == 0 {
+            round_points[knocker] = GIN_BONUS + defender_points;
+            Outcome::Gin
+        } else if defender_points <= knocker_points {
+            round_points[defender] = UNDERCUT_BONUS + knocker_points - defender_points;
+            Outcome::Undercut
+        } else {
+            round_points[knocker] = defender_points - knocker_points;
+            Outcome::Knock
+        };
+        let mut hands = vec![
+            ShownHand::new(&self.seats[knocker], &knocker_hand, &[]),
+            ShownHand::new(&self.seats[defender], &defender_hand, &laid_off),
+        ];
+        hands.sort_by_key(|hand| self.seats.iter().position(|seat| *seat == hand.player_id));
+        self.showdown = Some(Showdown {
+            outcome,
+            knocker: Some(self.seats[knocker].clone()),
+            hands,
+        });
+        self.end_round(round_points);
+    }
+
+    fn end_round(&mut self, round_points: Vec<i64>) {
+        for (player_id, points) in self.seats.iter().zip(round_points.iter()) {
+            *self.totals.entry(player_id.clone()).or_insert(0) += points;
+        }
+        self.round_points = round_points;
+        self.dealer = 1 - self.dealer;
+        let game_over = self
+            .totals
+            .values()
+            .any(|total| *total >= self.target_score);
+        self.phase = if game_over {
+            Phase::GameOver
+        } else {
+            Phase::RoundOver
+        };
+    }
+}
+
+fn reveal_hands(table: &mut Table) {
+    for player in &mut table.players {
+        for card in &mut player.hand {
+            card.set_visible(true);
+        }
+    }
+}
+
+impl GameRules for Gin {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        if self.phase == Phase::GameOver {
+            self.totals.clear();
+            self.dealer = 0;
+        }
+        table.collect_cards();
+        self.seats = table
+            .players
+            .iter()
+            .take(PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &self.seats, HAND_SIZE)?;
+        let mut upcard = table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("The deck ran out of cards"))?;
+        upcard.set_visible(true);
+        table.discard_deck.push(upcard);
+        self.turn = 1 - self.dealer;
+        self.taken_discard = None;
+        self.showdown = None;
+        self.round_points.clear();
+        self.phase = Phase::Draw;
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        let hand = hand_of(table, player_id);
+        match game_move {
+            GameMove::StartRound => {
+                if self.in_round() {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < PLAYERS {
+                    bail!("Gin needs two players");
+                }
+            }
+            GameMove::DrawCard => {
+                self.check_turn(player_id, Phase::Draw)?;
+            }
+            GameMove::DrawDiscard => {
+                self.check_turn(player_id, Phase::Draw)?;
+                if table.discard_deck.is_empty() {
+                    bail!("The discard pile is empty");
+                }
+            }
+            GameMove::DiscardCard { card } => {
+                self.check_turn(player_id, Phase::Discard)?;
+                self.check_discard(hand, card)?;
+            }
+            GameMove::Knock { card } => {
+                self.check_turn(player_id, Phase::Discard)?;
+                self.check_discard(hand, card)?;
+                let mut rest = hand.to_vec();
+                if let Some(index) = rest.iter().position(|held| same_card(held, card)) {
+                    rest.remove(index);
+                }
+                let deadwood = arrange(&rest, self.aces).deadwood_points();
+                if deadwood > MAX_KNOCK_DEADWOOD {
+                    bail!(
+                        "You can only knock with {} or less deadwood, you have {}",
+                        MAX_KNOCK_DEADWOOD,
+                        deadwood
+                    );
+                }
+            }
+            _ => bail!("That move isn't part of gin"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::DrawCard => {
+                let mut card = table
+                    .draw_deck
+                    .pop()
+                    .ok_or_else(|| eyre!("There is nothing left to draw"))?;
+                card.set_visible(false);
+                if let Some(player) = table.player_mut(player_id) {
+                    player.add_card(card);
+                }
+                self.phase = Phase::Discard;
+            }
+            GameMove::DrawDiscard => {
+                let mut card = table
+                    .discard_deck
+                    .pop()
+                    .ok_or_else(|| eyre!("The discard pile is empty"))?;
+                self.taken_discard = Some(card);
+                card.set_visible(false);
+                if let Some(player) = table.player_mut(player_id) {
+                    player.add_card(card);
+                }
+                self.phase = Phase::Discard;
+            }
+            GameMove::DiscardCard { card } => {
+                self.discard(table, player_id, card)?;
+                if table.draw_deck.len() <= DEAD_STOCK {
+                    self.showdown = Some(Showdown {
+                        outcome: Outcome::Draw,
+                        knocker: None,
+                        hands: vec![],
+                    });
+                    reveal_hands(table);
+                    self.end_round(vec![0; PLAYERS]);
+                } else {
+                    self.turn = 1 - self.turn;
+                    self.phase = Phase::Draw;
+                }
+            }
+            GameMove::Knock { card } => {
+                self.discard(table, player_id, card)?;
+                self.knock(table);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::RoundOver | Phase::GameOver)
+    }
+
+    fn target_score(&self) -> Option<i64> {
+        Some(self.target_score)
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.seats
+            .iter()
+            .zip(self.round_points.iter())
+            .map(|(player_id, points)| PlayerScore {
+                player_id: player_id.clone(),
+                points: *points,
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.in_round()
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let hand = hand_of(table, player_id);
+        let view = GinView {
+            phase: self.phase,
+            turn: self.seats.get(self.turn).cloned(),
+            hand: hand.iter().map(Card::revealed_card_data).collect(),
+            deadwood_points: arrange(hand, self.aces).deadwood_points(),
+            opponent_cards: self
+                .seats
+                .iter()
+                .filter(|seat| *seat != player_id)
+                .map(|seat| hand_of(table, seat).len())
+                .sum(),
+            discard_top: table.discard_deck.last().map(Card::card_data),
+            stock_size: table.draw_deck.len(),
+            aces: self.aces,
+            showdown: self.showdown.clone(),
+            round_points: self.score(table),
+            totals: self.totals.clone(),
+            target_score: self.target_score,
+        };
+        Some(GameState::Gin(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        if self.seats.iter().any(|seat| seat == player_id) && self.in_progress(table) {
+            self.phase = Phase::Waiting;
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        if self.seats.get(self.turn)? != player_id {
+            return None;
+        }
+        let hand = hand_of(table, player_id);
+        match self.phase {
+            Phase::Draw => Some(draw_hint(hand, table.discard_deck.last(), self.aces)),
+            Phase::Discard => {
+                let (card, deadwood) = best_discard(hand, self.aces, self.taken_discard.as_ref())?;
+                Some(if deadwood <= MAX_KNOCK_DEADWOOD {
+                    GameMove::Knock { card }
+                } else {
+                    GameMove::DiscardCard { card }
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct GinView {
+    phase: Phase,
+    turn: Option<String>,
+    hand: Vec<CardData>,
+    deadwood_points: u32,
+    opponent_cards: usize,
+    discard_top: Option<CardData>,
+    stock_size: usize,
+    aces: Aces,
+    showdown: Option<Showdown>,
+    round_points: Vec<PlayerScore>,
+    totals: HashMap<String, i64>,
+    target_score: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card::cards,
+        outbox::OutboxReceiver,
+        rules::{play_hints, test_table},
+    };
+
+    fn deal_round(seed: u64) -> (Gin, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(PLAYERS, 0, seed);
+        let mut rules = Gin::new(Aces::Low, Gin::TARGET_SCORE);
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    fn play(rules: &mut Gin, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    #[test]
+    fn a_round_ends_with_a_knock_or_a_dead_stock() {
+        for seed in 0..10 {
+            let (mut rules, mut table, ids, _receivers) = deal_round(seed);
+            play_hints(&mut rules, &mut table);
+            assert!(rules.is_round_over(&table));
+            let showdown = rules.showdown.as_ref().unwrap();
+            let scorers = rules
+                .round_points
+                .iter()
+                .filter(|points| **points > 0)
+                .count();
+            match showdown.outcome {
+                Outcome::Draw => assert_eq!(scorers, 0),
+                _ => assert_eq!(scorers, 1),
+            }
+            let cards: usize = ids
+                .iter()
+                .map(|id| hand_of(&table, id).len())
+                .sum::<usize>()
+                + table.draw_deck.len()
+                + table.discard_deck.len();
+            assert_eq!(cards, 52);
+        }
+    }
+
+    #[test]
+    fn going_gin_scores_the_bonus_and_the_other_hands_deadwood() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(0);
+        let (player, other) = (ids[rules.turn].clone(), ids[1 - rules.turn].clone());
+        play(&mut rules, &mut table, &player, GameMove::DrawCard);
+        table.player_mut(&player).unwrap().hand = cards("2C 3C 4C 5H 5D 5S 9S 9D 9C 9H KH");
+        table.player_mut(&other).unwrap().hand = cards("AS 2S 3S 4D 6D 8D 8C JH QH TC");
+        let knock = GameMove::Knock {
+            card: cards("KH")[0],
+        };
+        play(&mut rules, &mut table, &player, knock);
+
+        assert!(rules.is_round_over(&table));
+        assert_eq!(rules.showdown.as_ref().unwrap().outcome, Outcome::Gin);
+        let scores = rules.score(&table);
+        let score_of = |player_id: &str| {
+            scores
+                .iter()
+                .find(|score| score.player_id == player_id)
+                .unwrap()
+                .points
+        };
+        assert_eq!(score_of(&player), GIN_BONUS + 4 + 6 + 8 + 8 + 10 + 10 + 10);
+        assert_eq!(score_of(&other), 0);
+    }
+
+    #[test]
+    fn moves_out_of_order_are_rejected() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(0);
+        let (player, other) = (ids[rules.turn].clone(), ids[1 - rules.turn].clone());
+        let card = hand_of(&table, &player)[0];
+        assert!(rules
+            .validate(&table, &player, &GameMove::DiscardCard { card })
+            .is_err());
+        assert!(rules.validate(&table, &other, &GameMove::DrawCard).is_err());
+        assert!(rules
+            .validate(&table, &player, &GameMove::StartRound)
+            .is_err());
+
+        table.player_mut(&player).unwrap().hand = cards("AS 3D 5C 7H 9S JD KC QH TS 8D");
+        table.discard_deck.extend(cards("2H"));
+        play(&mut rules, &mut table, &player, GameMove::DrawDiscard);
+        let taken = rules.taken_discard.unwrap();
+        let put_back = GameMove::DiscardCard { card: taken };
+        assert!(rules.validate(&table, &player, &put_back).is_err());
+        let knock = GameMove::Knock {
+            card: cards("KC")[0],
+        };
+        assert!(rules.validate(&table, &player, &knock).is_err());
+        assert!(rules
+            .validate(&table, &player, &GameMove::DrawCard)
+            .is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+
+use eyre::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    restore_state, save_state,
+    trick::{ace_high_rank, deal, hand_of, holds, playable_cards, TrickEngine, TrickView},
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, Suite, Value},
+    table::Table,
+};
+
+const PLAYERS: usize = 4;
+const CARDS_TO_PASS: usize = 3;
+const MOON_POINTS: i64 = 26;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "HeartsPhase")]
+enum Phase {
+    Waiting,
+    Passing,
+    Playing,
+    RoundOver,
+    GameOver,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+enum PassDirection {
+    Left,
+    Right,
+    Across,
+    Hold,
+}
+
+impl PassDirection {
+    fn for_round(round: usize) -> Self {
+        match round % 4 {
+            0 => Self::Left,
+            1 => Self::Right,
+            2 => Self::Across,
+            _ => Self::Hold,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        match self {
+            Self::Left => 1,
+            Self::Right => 3,
+            Self::Across => 2,
+            Self::Hold => 0,
+        }
+    }
+}
+
+fn is_point_card(card: &Card) -> bool {
+    card.suite == Suite::Heart || is_queen_of_spades(card)
+}
+
+fn is_queen_of_spades(card: &Card) -> bool {
+    card.suite == Suite::Spade && card.value == Value::Queen
+}
+
+fn is_two_of_clubs(card: &Card) -> bool {
+    card.suite == Suite::Club && card.value == Value::Two
+}
+
+fn points(cards: &[Card]) -> i64 {
+    cards
+        .iter()
+        .map(|card| {
+            if card.suite == Suite::Heart {
+                1
+            } else if is_queen_of_spades(card) {
+                13
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Hearts for four players. Everyone is trying to avoid hearts and the queen of spades, unless
+/// they manage to take all of them and shoot the moon. The game ends once someone reaches the
+/// target score, and the lowest score wins.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hearts {
+    target_score: i64,
+    phase: Phase,
+    engine: TrickEngine,
+    round: usize,
+    passes: HashMap<usize, Vec<Card>>,
+    hearts_broken: bool,
+    first_trick: bool,
+    round_points: Vec<i64>,
+    totals: HashMap<String, i64>,
+}
+
+impl Hearts {
+    pub const TARGET_SCORE: i64 = 100;
+
+    pub fn new(target_score: i64) -> Self {
+        Self {
+            target_score,
+            phase: Phase::Waiting,
+            engine: TrickEngine::new(vec![]),
+            round: 0,
+            passes: HashMap::new(),
+            hearts_broken: false,
+            first_trick: true,
+            round_points: vec![],
+            totals: HashMap::new(),
+        }
+    }
+
+    fn pass_direction(&self) -> PassDirection {
+        PassDirection::for_round(self.round)
+    }
+
+    fn start_play(&mut self, table: &Table) {
+        let leader = (0..self.engine.seats.len())
+            .find(|seat| {
+                hand_of(table, &self.engine.seats[*seat])
+                    .iter()
+                    .any(is_two_of_clubs)
+            })
+            .unwrap_or(0);
+        self.engine.start(leader);
+        self.phase = Phase::Playing;
+    }
+
+    fn exchange_passes(&mut self, table: &mut Table) {
+        let offset = self.pass_direction().offset();
+        let seats = self.engine.seats.len();
+        let mut passes: Vec<(usize, Vec<Card>)> = self.passes.drain().collect();
+        passes.sort_by_key(|(seat, _)| *seat);
+        // take every pass out of the hands first so nobody gets their own cards passed back
+        let passes: Vec<(usize, Vec<Card>)> = passes
+            .into_iter()
+            .map(|(seat, cards)| {
+                let taken = match table.player_mut(&self.engine.seats[seat]) {
+                    Some(player) => cards
+                        .iter()
+                        .filter_map(|card| player.discard_card(card))
+                        .collect(),
+                    None => vec![],
+                };
+                (seat, taken)
+            })
+            .collect();
+        for (seat, cards) in passes {
+            let receiver = (seat + offset) % seats;
+            if let Some(player) = table.player_mut(&self.engine.seats[receiver]) {
+                for card in cards {
+                    player.add_card(card);
+                }
+            }
+        }
+    }
+
+    fn end_round(&mut self) {
+        let mut round_points: Vec<i64> =
+            self.engine.piles.iter().map(|pile| points(pile)).collect();
+        if let Some(shooter) = round_points
+            .iter()
+            .position(|points| *points == MOON_POINTS)
+        {
+            for (seat, points) in round_points.iter_mut().enumerate() {
+                *points = if seat == shooter { 0 } else { MOON_POINTS };
+            }
+        }
+        for (seat, points) in round_points.iter().enumerate() {
+            *self
+                .totals
+                .entry(self.engine.seats[seat].clone())
+                .or_insert(0) += points;
+        }
+        self.round_points = round_points;
+        self.round += 1;
+        let game_over = self
+            .totals
+            .values()
+            .any(|total| *total >= self.target_score);
+        self.phase = if game_over {
+            Phase::GameOver
+        } else {
+            Phase::RoundOver
+        };
+    }
+}
+
+impl GameRules for Hearts {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        if self.phase == Phase::GameOver {
+            self.totals.clear();
+            self.round = 0;
+        }
+        table.collect_cards();
+        let seats: Vec<String> = table
+            .players
+            .iter()
+            .take(PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &seats, 52 / PLAYERS)?;
+        self.engine = TrickEngine::new(seats);
+        self.passes.clear();
+        self.hearts_broken = false;
+        self.first_trick = true;
+        self.round_points.clear();
+        if self.pass_direction() == PassDirection::Hold {
+            self.start_play(table);
+        } else {
+            self.phase = Phase::Passing;
+        }
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if matches!(self.phase, Phase::Passing | Phase::Playing) {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < PLAYERS {
+                    bail!("Hearts needs four players");
+                }
+            }
+            GameMove::PassCards { cards } => {
+                if self.phase != Phase::Passing {
+                    bail!("It isn't time to pass cards");
+                }
+                let seat = match self.engine.seat_of(player_id) {
+                    Some(seat) => seat,
+                    None => bail!("You aren't playing this hand"),
+                };
+                if self.passes.contains_key(&seat) {
+                    bail!("You have already passed your cards");
+                }
+                if cards.len() != CARDS_TO_PASS {
+                    bail!("You have to pass exactly three cards");
+                }
+                let hand = hand_of(table, player_id);
+                for (index, card) in cards.iter().enumerate() {
+                    if !holds(hand, card) {
+                        bail!("You can only pass cards from your hand");
+                    }
+                    if cards[..index]
+                        .iter()
+                        .any(|other| other.suite == card.suite && other.value == card.value)
+                    {
+                        bail!("You can't pass the same card twice");
+                    }
+                }
+            }
+            GameMove::PlayCard { card } => {
+                if self.phase != Phase::Playing {
+                    bail!("There is no hand being played");
+                }
+                self.engine.validate_play(table, player_id, card)?;
+                let hand = hand_of(table, player_id);
+                if self.first_trick && self.engine.is_leading() && !is_two_of_clubs(card) {
+                    bail!("The two of clubs has to lead the first trick");
+                }
+                if self.engine.is_leading()
+                    && card.suite == Suite::Heart
+                    && !self.hearts_broken
+                    && hand.iter().any(|held| held.suite != Suite::Heart)
+                {
+                    bail!("Hearts haven't been broken yet");
+                }
+                // nobody can dump points on the first trick unless their hand is nothing but
+                // points
+                let following_with_choice = self.engine.led_suit() != Some(card.suite);
+                if self.first_trick
+                    && !self.engine.is_leading()
+                    && following_with_choice
+                    && is_point_card(card)
+                    && hand.iter().any(|held| !is_point_card(held))
+                {
+                    bail!("Points can't be played on the first trick");
+                }
+            }
+            _ => bail!("That move isn't part of hearts"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::PassCards { cards } => {
+                if let Some(seat) = self.engine.seat_of(player_id) {
+                    self.passes.insert(seat, cards.clone());
+                }
+                if self.passes.len() == self.engine.seats.len() {
+                    self.exchange_passes(table);
+                    self.start_play(table);
+                }
+            }
+            GameMove::PlayCard { card } => {
+                if card.suite == Suite::Heart {
+                    self.hearts_broken = true;
+                }
+                if self.engine.play(table, card)?.is_some() {
+                    self.first_trick = false;
+                    if self.engine.hands_empty(table) {
+                        self.end_round();
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::RoundOver | Phase::GameOver)
+    }
+
+    fn target_score(&self) -> Option<i64> {
+        Some(self.target_score)
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.engine
+            .seats
+            .iter()
+            .zip(self.round_points.iter())
+            .map(|(player_id, points)| PlayerScore {
+                player_id: player_id.clone(),
+                points: *points,
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::Passing | Phase::Playing)
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let passed = self
+            .engine
+            .seat_of(player_id)
+            .is_some_and(|seat| self.passes.contains_key(&seat));
+        let view = HeartsView {
+            phase: self.phase,
+            trick: self.engine.view(table, player_id),
+            pass_direction: self.pass_direction(),
+            passed,
+            hearts_broken: self.hearts_broken,
+            round_points: self.score(table),
+            totals: self.totals.clone(),
+            target_score: self.target_score,
+        };
+        Some(GameState::Hearts(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        // hearts can't be finished three handed, so the round is abandoned
+        if self.engine.seat_of(player_id).is_some() && self.in_progress(table) {
+            self.phase = Phase::Waiting;
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        match self.phase {
+            Phase::Passing => {
+                let mut hand = hand_of(table, player_id).to_vec();
+                hand.sort_by_key(|card| ace_high_rank(card.value));
+                Some(GameMove::PassCards {
+                    cards: hand.into_iter().rev().take(CARDS_TO_PASS).collect(),
+                })
+            }
+            Phase::Playing => self
+                .engine
+                .pick_card(&playable_cards(self, table, player_id), false)
+                .map(|card| GameMove::PlayCard { card }),
+            _ => None,
+        }
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HeartsView {
+    phase: Phase,
+    #[serde(flatten)]
+    trick: TrickView,
+    pass_direction: PassDirection,
+    passed: bool,
+    hearts_broken: bool,
+    round_points: Vec<PlayerScore>,
+    totals: HashMap<String, i64>,
+    target_score: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        outbox::OutboxReceiver,
+        rules::{play_hints, test_table},
+    };
+
+    fn deal_round(seed: u64) -> (Hearts, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(PLAYERS, 0, seed);
+        let mut rules = Hearts::new(Hearts::TARGET_SCORE);
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    #[test]
+    fn every_point_in_the_deck_is_taken_by_the_end_of_a_round() {
+        for seed in 0..5 {
+            let (mut rules, mut table, ids, _receivers) = deal_round(seed);
+            assert_eq!(play_hints(&mut rules, &mut table), 4 + 52);
+            assert!(rules.is_round_over(&table));
+            assert!(ids
+                .iter()
+                .all(|player_id| hand_of(&table, player_id).is_empty()));
+            let points: i64 = rules.score(&table).iter().map(|score| score.points).sum();
+            // shooting the moon gives everyone else the 26 points instead
+            assert!(points == MOON_POINTS || points == 3 * MOON_POINTS);
+            assert_eq!(rules.pass_direction(), PassDirection::Right);
+        }
+    }
+
+    #[test]
+    fn passes_and_leads_that_break_the_rules_are_rejected() {
+        let (table, ids, _receivers) = test_table(3, 0, 0);
+        let rules = Hearts::new(Hearts::TARGET_SCORE);
+        assert!(rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .is_err());
+
+        let (mut rules, mut table, ids, _receivers) = deal_round(0);
+        let hand = hand_of(&table, &ids[0]).to_vec();
+        let two_cards = GameMove::PassCards {
+            cards: hand[..2].to_vec(),
+        };
+        assert!(rules.validate(&table, &ids[0], &two_cards).is_err());
+        let same_card_twice = GameMove::PassCards {
+            cards: vec![hand[0], hand[0], hand[1]],
+        };
+        assert!(rules.validate(&table, &ids[0], &same_card_twice).is_err());
+        let early = GameMove::PlayCard { card: hand[0] };
+        assert!(rules.validate(&table, &ids[0], &early).is_err());
+
+        for player_id in &ids {
+            let pass = GameMove::PassCards {
+                cards: hand_of(&table, player_id)[..CARDS_TO_PASS].to_vec(),
+            };
+            rules.validate(&table, player_id, &pass).unwrap();
+            rules.apply(&mut table, player_id, &pass).unwrap();
+        }
+        let leader = rules.engine.seats[rules.engine.turn].clone();
+        let not_two_of_clubs = hand_of(&table, &leader)
+            .iter()
+            .find(|card| !is_two_of_clubs(card))
+            .copied()
+            .unwrap();
+        let lead = GameMove::PlayCard {
+            card: not_two_of_clubs,
+        };
+        assert!(rules.validate(&table, &leader, &lead).is_err());
+        let waiting = ids.iter().find(|player_id| **player_id != leader).unwrap();
+        let out_of_turn = GameMove::PlayCard {
+            card: hand_of(&table, waiting)[0],
+        };
+        assert!(rules.validate(&table, waiting, &out_of_turn).is_err());
+    }
+}
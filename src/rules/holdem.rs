@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    restore_state, save_state, trick::ace_high_rank, GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData, Value},
+    hand_evaluator::{evaluate, HandRank},
+    table::Table,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+enum Street {
+    Waiting,
+    Preflop,
+    Flop,
+    Turn,
+    River,
+    HandOver,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Seat {
+    player_id: String,
+    stack: u64,
+    starting_stack: u64,
+    street_bet: u64,
+    committed: u64,
+    folded: bool,
+    all_in: bool,
+    acted: bool,
+    shown: bool,
+}
+
+impl Seat {
+    fn can_act(&self) -> bool {
+        !self.folded && !self.all_in
+    }
+
+    fn put_in(&mut self, amount: u64) {
+        let amount = amount.min(self.stack);
+        self.stack -= amount;
+        self.street_bet += amount;
+        self.committed += amount;
+        self.all_in = self.stack == 0;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct Winner {
+    player_id: String,
+    amount: u64,
+    hand: Option<String>,
+}
+
+/// No-limit Texas Hold'em. The hole cards live in each player's hand, so they stay hidden from
+/// everyone else until they have to be shown at showdown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Holdem {
+    small_blind: u64,
+    big_blind: u64,
+    seats: Vec<Seat>,
+    hands_played: usize,
+    button: usize,
+    turn: usize,
+    street: Street,
+    board: Vec<Card>,
+    current_bet: u64,
+    min_raise: u64,
+    last_aggressor: Option<usize>,
+    winners: Vec<Winner>,
+}
+
+impl Holdem {
+    pub fn new(small_blind: u64, big_blind: u64) -> Self {
+        Self {
+            small_blind,
+            big_blind,
+            seats: vec![],
+            hands_played: 0,
+            button: 0,
+            turn: 0,
+            street: Street::Waiting,
+            board: vec![],
+            current_bet: 0,
+            min_raise: big_blind,
+            last_aggressor: None,
+            winners: vec![],
+        }
+    }
+
+    fn is_betting(&self) -> bool {
+        matches!(
+            self.street,
+            Street::Preflop | Street::Flop | Street::Turn | Street::River
+        )
+    }
+
+    fn seat_to_act(&self, player_id: &str) -> Result<&Seat> {
+        if !self.is_betting() {
+            bail!("There is no hand being played");
+        }
+        self.seats
+            .get(self.turn)
+            .filter(|seat| seat.player_id == player_id)
+            .ok_or_else(|| eyre!("It isn't your turn"))
+    }
+
+    fn next_seat(&self, from: usize, offset: usize) -> usize {
+        (from + offset) % self.seats.len()
+    }
+
+    /// The next seat after `from` that still owes an action on this street.
+    fn next_to_act(&self, from: usize) -> Option<usize> {
+        (1..=self.seats.len())
+            .map(|offset| self.next_seat(from, offset))
+            .find(|index| {
+                let seat = &self.seats[*index];
+                seat.can_act() && (!seat.acted || seat.street_bet < self.current_bet)
+            })
+    }
+
+    fn contenders(&self) -> Vec<usize> {
+        (0..self.seats.len())
+            .filter(|index| !self.seats[*index].folded)
+            .collect()
+    }
+
+    fn deal(table: &mut Table) -> Result<Card> {
+        table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("The deck ran out of cards"))
+    }
+
+    fn deal_board(&mut self, table: &mut Table, count: usize) -> Result<()> {
+        let burned = Self::deal(table)?;
+        table.discard_deck.push(burned);
+        for _ in 0..count {
+            let mut card = Self::deal(table)?;
+            card.set_visible(true);
+            self.board.push(card);
+        }
+        Ok(())
+    }
+
+    fn reopen_betting(&mut self, raiser: usize) {
+        for (index, seat) in self.seats.iter_mut().enumerate() {
+            if index != raiser {
+                seat.acted = false;
+            }
+        }
+        self.last_aggressor = Some(raiser);
+    }
+
+    /// Works out what happens after the seat at `from` acted or left: the hand either goes on
+    /// to the next player, the next street, or ends.
+    fn continue_hand(&mut self, table: &mut Table, from: usize) -> Result<()> {
+        let contenders = self.contenders();
+        if contenders.len() == 1 {
+            self.award_uncontested(table, contenders[0]);
+            return Ok(());
+        }
+        if let Some(next) = self.next_to_act(from) {
+            self.turn = next;
+            return Ok(());
+        }
+        self.end_street(table)
+    }
+
+    fn end_street(&mut self, table: &mut Table) -> Result<()> {
+        loop {
+            for seat in &mut self.seats {
+                seat.street_bet = 0;
+                seat.acted = false;
+            }
+            self.current_bet = 0;
+            self.min_raise = self.big_blind;
+            self.street = match self.street {
+                Street::Preflop => {
+                    self.deal_board(table, 3)?;
+                    Street::Flop
+                }
+                Street::Flop => {
+                    self.deal_board(table, 1)?;
+                    Street::Turn
+                }
+                Street::Turn => {
+                    self.deal_board(table, 1)?;
+                    Street::River
+                }
+                _ => return self.showdown(table),
+            };
+            self.last_aggressor = None;
+
+            // once everyone but one player is all in there is nobody left to bet against, so
+            // the rest of the board is dealt straight away
+            let can_act = self.seats.iter().filter(|seat| seat.can_act()).count();
+            if can_act > 1 {
+                if let Some(next) = self.next_to_act(self.button) {
+                    self.turn = next;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn award_uncontested(&mut self, table: &mut Table, winner: usize) {
+        let pot: u64 = self.seats.iter().map(|seat| seat.committed).sum();
+        self.seats[winner].stack += pot;
+        self.winners = vec![Winner {
+            player_id: self.seats[winner].player_id.clone(),
+            amount: pot,
+            hand: None,
+        }];
+        self.finish_hand(table);
+    }
+
+    fn showdown(&mut self, table: &mut Table) -> Result<()> {
+        let mut ranks = HashMap::new();
+        for index in self.contenders() {
+            let player_id = &self.seats[index].player_id;
+            let mut cards = table
+                .player(player_id)
+                .map(|player| player.hand.clone())
+                .unwrap_or_default();
+            cards.extend(self.board.iter().copied());
+            ranks.insert(index, evaluate(&cards).unwrap_or_default());
+        }
+
+        // the last player to bet or raise shows first, everyone after them only has to show a
+        // hand that is at least as good as the best one shown so far
+        let first = match self
+            .last_aggressor
+            .filter(|index| !self.seats[*index].folded)
+        {
+            Some(index) => index,
+            None => self.next_seat(self.button, 1),
+        };
+        let mut best_shown: Option<&HandRank> = None;
+        for offset in 0..self.seats.len() {
+            let index = self.next_seat(first, offset);
+            if let Some(hand_rank) = ranks.get(&index) {
+                if best_shown.is_none_or(|best| hand_rank >= best) {
+                    self.seats[index].shown = true;
+                    best_shown = Some(hand_rank);
+                }
+            }
+        }
+
+        self.winners.clear();
+        let mut levels: Vec<u64> = self.seats.iter().map(|seat| seat.committed).collect();
+        levels.sort_unstable();
+        levels.dedup();
+        let mut pots: Vec<(u64, Vec<usize>)> = vec![];
+        let mut previous_level = 0;
+        for level in levels.into_iter().filter(|level| *level > 0) {
+            let pot: u64 = self
+                .seats
+                .iter()
+                .map(|seat| seat.committed.min(level) - seat.committed.min(previous_level))
+                .sum();
+            let eligible: Vec<usize> = ranks
+                .keys()
+                .copied()
+                .filter(|index| self.seats[*index].committed >= level)
+                .collect();
+            previous_level = level;
+            // chips only folded players put in that deep are dead money for the pot below
+            match pots.last_mut() {
+                Some((last_pot, _)) if eligible.is_empty() => *last_pot += pot,
+                _ => pots.push((pot, eligible)),
+            }
+        }
+        for (pot, eligible) in pots {
+            let best = match eligible.iter().map(|index| &ranks[index]).max() {
+                Some(best) => *best,
+                None => continue,
+            };
+            // split pots go around the table from the button, so the odd chips land left of it
+            let pot_winners: Vec<usize> = (1..=self.seats.len())
+                .map(|offset| self.next_seat(self.button, offset))
+                .filter(|index| eligible.contains(index) && ranks[index] == best)
+                .collect();
+            let share = pot / pot_winners.len() as u64;
+            let mut odd_chips = pot % pot_winners.len() as u64;
+            for index in pot_winners {
+                let mut amount = share;
+                if odd_chips > 0 {
+                    amount += 1;
+                    odd_chips -= 1;
+                }
+                let seat = &mut self.seats[index];
+                seat.stack += amount;
+                seat.shown = true;
+                match self
+                    .winners
+                    .iter_mut()
+                    .find(|winner| winner.player_id == seat.player_id)
+                {
+                    Some(winner) => winner.amount += amount,
+                    None => self.winners.push(Winner {
+                        player_id: seat.player_id.clone(),
+                        amount,
+                        hand: Some(ranks[&index].describe()),
+                    }),
+                }
+            }
+        }
+
+        for seat in self.seats.iter().filter(|seat| seat.shown) {
+            if let Some(player) = table.player_mut(&seat.player_id) {
+                for card in &mut player.hand {
+                    card.set_visible(true);
+                }
+            }
+        }
+        self.finish_hand(table);
+        Ok(())
+    }
+
+    /// Chips only move between the players' stacks once the hand is over, until then the seats
+    /// keep track of them.
+    fn finish_hand(&mut self, table: &mut Table) {
+        for seat in &self.seats {
+            if let Some(player) = table.player_mut(&seat.player_id) {
+                player.chips = seat.stack;
+            }
+        }
+        self.street = Street::HandOver;
+    }
+}
+
+impl GameRules for Holdem {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        table.collect_cards();
+        self.board.clear();
+        self.winners.clear();
+        self.seats = table
+            .players
+            .iter()
+            .filter(|player| player.chips > 0)
+            .map(|player| Seat {
+                player_id: player.id.clone(),
+                stack: player.chips,
+                starting_stack: player.chips,
+                street_bet: 0,
+                committed: 0,
+                folded: false,
+                all_in: false,
+                acted: false,
+                shown: false,
+            })
+            .collect();
+
+        self.button = self.hands_played % self.seats.len();
+        self.hands_played += 1;
+        let (small_blind, big_blind) = if self.seats.len() == 2 {
+            (self.button, self.next_seat(self.button, 1))
+        } else {
+            (
+                self.next_seat(self.button, 1),
+                self.next_seat(self.button, 2),
+            )
+        };
+        self.seats[small_blind].put_in(self.small_blind);
+        self.seats[big_blind].put_in(self.big_blind);
+        self.current_bet = self.big_blind;
+        self.min_raise = self.big_blind;
+        self.last_aggressor = None;
+
+        for _ in 0..2 {
+            for offset in 1..=self.seats.len() {
+                let index = self.next_seat(self.button, offset);
+                let card = Self::deal(table)?;
+                if let Some(player) = table.player_mut(&self.seats[index].player_id) {
+                    player.add_card(card);
+                }
+            }
+        }
+
+        self.street = Street::Preflop;
+        self.continue_hand(table, big_blind)
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        let seat = match game_move {
+            GameMove::StartRound => {
+                if self.is_betting() {
+                    bail!("The hand isn't over yet");
+                }
+                let players_with_chips = table
+                    .players
+                    .iter()
+                    .filter(|player| player.chips > 0)
+                    .count();
+                if players_with_chips < 2 {
+                    bail!("At least two players with chips are needed");
+                }
+                return Ok(());
+            }
+            GameMove::Fold
+            | GameMove::Check
+            | GameMove::Call
+            | GameMove::AllIn
+            | GameMove::Bet { .. }
+            | GameMove::Raise { .. } => self.seat_to_act(player_id)?,
+            _ => bail!("That move isn't part of Texas Hold'em"),
+        };
+
+        let to_call = self.current_bet - seat.street_bet;
+        // a player facing an all in that was too small to count as a raise can't raise again
+        let can_raise = !seat.acted;
+        match game_move {
+            GameMove::Check if to_call > 0 => bail!("You can't check, there is a bet to call"),
+            GameMove::Call if to_call == 0 => bail!("There is nothing to call"),
+            GameMove::Bet { amount } => {
+                if self.current_bet > 0 {
+                    bail!("There is already a bet, raise instead");
+                }
+                if *amount > seat.stack {
+                    bail!("You don't have that many chips");
+                }
+                if *amount < self.big_blind && *amount < seat.stack {
+                    bail!("A bet has to be at least the big blind");
+                }
+            }
+            GameMove::Raise { amount } => {
+                if self.current_bet == 0 {
+                    bail!("There is no bet to raise, bet instead");
+                }
+                if !can_raise {
+                    bail!("You can only call or fold");
+                }
+                if *amount <= self.current_bet {
+                    bail!("A raise has to be more than the current bet");
+                }
+                let raise_by = amount - seat.street_bet;
+                if raise_by > seat.stack {
+                    bail!("You don't have that many chips");
+                }
+                if *amount < self.current_bet + self.min_raise && raise_by < seat.stack {
+                    bail!(
+                        "You have to raise to at least {}",
+                        self.current_bet + self.min_raise
+                    );
+                }
+            }
+            GameMove::AllIn if !can_raise && seat.stack > to_call => {
+                bail!("You can only call or fold")
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        let index = match self
+            .seats
+            .iter()
+            .position(|seat| seat.player_id == player_id)
+        {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let current_bet = self.current_bet;
+        let seat = &mut self.seats[index];
+        match game_move {
+            GameMove::Fold => seat.folded = true,
+            GameMove::Check => {}
+            GameMove::Call => seat.put_in(current_bet - seat.street_bet),
+            GameMove::Bet { amount } => seat.put_in(*amount),
+            GameMove::Raise { amount } => seat.put_in(amount - seat.street_bet),
+            GameMove::AllIn => seat.put_in(seat.stack),
+            _ => return Ok(()),
+        }
+        seat.acted = true;
+
+        let street_bet = seat.street_bet;
+        if street_bet > current_bet {
+            let raised_by = street_bet - current_bet;
+            self.current_bet = street_bet;
+            // an all in for less than a full raise doesn't give anyone another turn to raise
+            if raised_by >= self.min_raise {
+                self.min_raise = raised_by;
+                self.reopen_betting(index);
+            } else {
+                self.last_aggressor = Some(index);
+            }
+        }
+        self.continue_hand(table, index)
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        self.street == Street::HandOver
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.seats
+            .iter()
+            .map(|seat| PlayerScore {
+                player_id: seat.player_id.clone(),
+                points: seat.stack as i64 - seat.starting_stack as i64,
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.is_betting()
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let seats = self
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(index, seat)| {
+                let hole_cards = table
+                    .player(&seat.player_id)
+                    .map(|player| {
+                        player
+                            .hand
+                            .iter()
+                            .map(|card| {
+                                if seat.player_id == player_id {
+                                    card.revealed_card_data()
+                                } else {
+                                    card.card_data()
+                                }
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                SeatView {
+                    player_id: seat.player_id.clone(),
+                    stack: seat.stack,
+                    street_bet: seat.street_bet,
+                    folded: seat.folded,
+                    all_in: seat.all_in,
+                    button: index == self.button,
+                    hole_cards,
+                }
+            })
+            .collect();
+        let to_call = self
+            .seats
+            .iter()
+            .find(|seat| seat.player_id == player_id)
+            .map(|seat| (self.current_bet - seat.street_bet).min(seat.stack));
+        let view = HoldemView {
+            street: self.street,
+            board: self.board.iter().map(|card| card.card_data()).collect(),
+            pot: self.seats.iter().map(|seat| seat.committed).sum(),
+            seats,
+            turn: self
+                .seats
+                .get(self.turn)
+                .filter(|_| self.is_betting())
+                .map(|seat| seat.player_id.clone()),
+            current_bet: self.current_bet,
+            min_raise_to: self.current_bet + self.min_raise,
+            to_call,
+            small_blind: self.small_blind,
+            big_blind: self.big_blind,
+            winners: self.winners.clone(),
+        };
+        Some(GameState::Holdem(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        let index = match self
+            .seats
+            .iter()
+            .position(|seat| seat.player_id == player_id)
+        {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        if !self.is_betting() {
+            self.seats.remove(index);
+            return Ok(());
+        }
+        // their chips stay in the pot, they just can't win it any more
+        self.seats[index].folded = true;
+        if index == self.turn {
+            self.continue_hand(table, index)?;
+        } else if self.contenders().len() == 1 {
+            self.award_uncontested(table, self.contenders()[0]);
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        let seat = self.seat_to_act(player_id).ok()?;
+        let hole_cards = &table.player(player_id)?.hand;
+        // stay in with a pair, or before the flop with two high cards
+        let paired = hole_cards.iter().enumerate().any(|(index, card)| {
+            hole_cards[index + 1..]
+                .iter()
+                .chain(self.board.iter())
+                .any(|other| other.value == card.value)
+        });
+        let high_cards = self.board.is_empty()
+            && hole_cards
+                .iter()
+                .all(|card| ace_high_rank(card.value) >= ace_high_rank(Value::Ten));
+        Some(if seat.street_bet == self.current_bet {
+            GameMove::Check
+        } else if paired || high_cards {
+            GameMove::Call
+        } else {
+            GameMove::Fold
+        })
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct HoldemView {
+    street: Street,
+    board: Vec<CardData>,
+    pot: u64,
+    seats: Vec<SeatView>,
+    turn: Option<String>,
+    current_bet: u64,
+    min_raise_to: u64,
+    to_call: Option<u64>,
+    small_blind: u64,
+    big_blind: u64,
+    winners: Vec<Winner>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[schemars(rename = "HoldemSeatView")]
+struct SeatView {
+    player_id: String,
+    stack: u64,
+    street_bet: u64,
+    folded: bool,
+    all_in: bool,
+    button: bool,
+    hole_cards: Vec<CardData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{outbox::OutboxReceiver, rules::test_table};
+
+    fn seat_players(stacks: &[u64]) -> (Holdem, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(stacks.len(), 0, 0);
+        for (player, stack) in table.players.iter_mut().zip(stacks) {
+            player.chips = *stack;
+        }
+        let mut rules = Holdem::new(5, 10);
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    fn play(rules: &mut Holdem, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    fn total_chips(table: &Table) -> u64 {
+        table.players.iter().map(|player| player.chips).sum()
+    }
+
+    #[test]
+    fn a_hand_checked_down_to_showdown_keeps_every_chip() {
+        let (mut rules, mut table, ids, _receivers) = seat_players(&[500, 500]);
+        // heads up the button posts the small blind and acts first before the flop
+        play(&mut rules, &mut table, &ids[0], GameMove::Call);
+        play(&mut rules, &mut table, &ids[1], GameMove::Check);
+        for _ in 0..3 {
+            play(&mut rules, &mut table, &ids[1], GameMove::Check);
+            play(&mut rules, &mut table, &ids[0], GameMove::Check);
+        }
+        assert!(rules.is_round_over(&table));
+        assert_eq!(rules.board.len(), 5);
+        assert_eq!(total_chips(&table), 1000);
+        let won: u64 = rules.winners.iter().map(|winner| winner.amount).sum();
+        assert_eq!(won, 20);
+        let net: i64 = rules.score(&table).iter().map(|score| score.points).sum();
+        assert_eq!(net, 0);
+    }
+
+    #[test]
+    fn chips_nobody_left_can_win_go_to_the_pot_below() {
+        let (mut rules, mut table, ids, _receivers) = seat_players(&[1000, 50, 100, 1000]);
+        play(&mut rules, &mut table, &ids[3], GameMove::Call);
+        let raise = GameMove::Raise { amount: 300 };
+        play(&mut rules, &mut table, &ids[0], raise);
+        play(&mut rules, &mut table, &ids[1], GameMove::AllIn);
+        play(&mut rules, &mut table, &ids[2], GameMove::AllIn);
+        // the raiser walks away, so nobody still in the hand matched the top of their bet
+        rules.player_left(&mut table, &ids[0]).unwrap();
+        play(&mut rules, &mut table, &ids[3], GameMove::Fold);
+
+        assert!(rules.is_round_over(&table));
+        assert_eq!(total_chips(&table), 2150);
+        let won: u64 = rules.winners.iter().map(|winner| winner.amount).sum();
+        assert_eq!(won, 460);
+    }
+
+    #[test]
+    fn moves_out_of_turn_or_against_the_bet_are_rejected() {
+        let (rules, table, ids, _receivers) = seat_players(&[500, 500]);
+        assert!(rules.validate(&table, &ids[1], &GameMove::Call).is_err());
+        assert!(rules.validate(&table, &ids[0], &GameMove::Check).is_err());
+        let too_small = GameMove::Raise { amount: 15 };
+        assert!(rules.validate(&table, &ids[0], &too_small).is_err());
+        let too_big = GameMove::Raise { amount: 600 };
+        assert!(rules.validate(&table, &ids[0], &too_big).is_err());
+        assert!(rules.validate(&table, &ids[0], &GameMove::Hit).is_err());
+    }
+}
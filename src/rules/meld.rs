@@ -0,0 +1,375 @@
+use eyre::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::GameMove;
+use crate::card::{Card, CardData, Value};
+
+/// Where an ace can sit in a run.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default, JsonSchema)]
+pub enum Aces {
+    /// Only below the two, A-2-3.
+    #[default]
+    Low,
+    /// Only above the king, Q-K-A.
+    High,
+    /// Either end, but a run can't go around the corner from king to two.
+    Either,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, JsonSchema)]
+pub enum MeldKind {
+    /// Three or more cards of the same value.
+    Set,
+    /// Three or more cards of one suit with consecutive values.
+    Run,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Meld {
+    pub kind: MeldKind,
+    pub cards: Vec<Card>,
+}
+
+impl Meld {
+    pub fn new(cards: Vec<Card>, aces: Aces) -> Result<Self> {
+        let kind = meld_kind(&cards, aces)?;
+        let mut meld = Self { kind, cards };
+        meld.sort(aces);
+        Ok(meld)
+    }
+
+    /// The meld with `cards` added to it, if it is still a meld.
+    pub fn extended(&self, cards: &[Card], aces: Aces) -> Result<Self> {
+        let mut extended = self.cards.clone();
+        extended.extend_from_slice(cards);
+        Self::new(extended, aces)
+    }
+
+    fn sort(&mut self, aces: Aces) {
+        let high = self.kind == MeldKind::Run
+            && run_ranks(&self.cards, Aces::Low).is_none()
+            && aces != Aces::Low;
+        self.cards.sort_by_key(|card| rank(card.value, high));
+    }
+
+    pub fn meld_data(&self) -> MeldData {
+        MeldData {
+            kind: self.kind,
+            cards: self
+                .cards
+                .iter()
+                .map(|card| card.revealed_card_data())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MeldData {
+    kind: MeldKind,
+    cards: Vec<CardData>,
+}
+
+/// Checks that the cards form a set or a run.
+pub fn meld_kind(cards: &[Card], aces: Aces) -> Result<MeldKind> {
+    if cards.len() < 3 {
+        bail!("A meld needs at least three cards");
+    }
+    if cards.iter().all(|card| card.value == cards[0].value) {
+        return Ok(MeldKind::Set);
+    }
+    if cards.iter().any(|card| card.suite != cards[0].suite) {
+        bail!("A meld has to be all the same value or a run in one suit");
+    }
+    let fits = match aces {
+        Aces::Low => run_ranks(cards, Aces::Low).is_some(),
+        Aces::High => run_ranks(cards, Aces::High).is_some(),
+        Aces::Either => {
+            run_ranks(cards, Aces::Low).is_some() || run_ranks(cards, Aces::High).is_some()
+        }
+    };
+    if !fits {
+        bail!("The cards in a run have to follow on from each other");
+    }
+    Ok(MeldKind::Run)
+}
+
+fn run_ranks(cards: &[Card], aces: Aces) -> Option<Vec<u8>> {
+    let mut ranks: Vec<u8> = cards
+        .iter()
+        .map(|card| rank(card.value, aces == Aces::High))
+        .collect();
+    ranks.sort_unstable();
+    ranks
+        .windows(2)
+        .all(|pair| pair[1] == pair[0] + 1)
+        .then_some(ranks)
+}
+
+fn rank(value: Value, ace_high: bool) -> u8 {
+    match value {
+        Value::Ace if ace_high => 14,
+        Value::Ace => 1,
+        Value::Two => 2,
+        Value::Three => 3,
+        Value::Four => 4,
+        Value::Five => 5,
+        Value::Six => 6,
+        Value::Seven => 7,
+        Value::Eight => 8,
+        Value::Nine => 9,
+        Value::Ten => 10,
+        Value::Jack => 11,
+        Value::Queen => 12,
+        Value::King => 13,
+    }
+}
+
+/// What a card left in a hand counts against its owner. Aces are one, picture cards ten.
+pub fn card_points(card: &Card) -> u32 {
+    match card.value {
+        Value::Jack | Value::Queen | Value::King => 10,
+        value => rank(value, false) as u32,
+    }
+}
+
+pub fn same_card(first: &Card, second: &Card) -> bool {
+    first.suite == second.suite && first.value == second.value
+}
+
+/// Takes each of `cards` out of `pool`, failing if any of them isn't there.
+pub fn take_cards(pool: &mut Vec<Card>, cards: &[Card]) -> Result<Vec<Card>> {
+    let mut taken = vec![];
+    for card in cards {
+        match pool.iter().position(|held| same_card(held, card)) {
+            Some(index) => taken.push(pool.remove(index)),
+            None => bail!("That card isn't yours to meld"),
+        }
+    }
+    Ok(taken)
+}
+
+/// The best way to meld a hand: the melds that leave the fewest points of deadwood, and those
+/// leftover cards.
+#[derive(Debug, Clone, Default)]
+pub struct Arrangement {
+    pub melds: Vec<Meld>,
+    pub deadwood: Vec<Card>,
+}
+
+impl Arrangement {
+    pub fn deadwood_points(&self) -> u32 {
+        self.deadwood.iter().map(card_points).sum()
+    }
+}
+
+/// The card to throw away that leaves the least deadwood behind, and how much that is. The card
+/// in `keep` isn't considered, for the discard that was just picked up.
+pub fn best_discard(hand: &[Card], aces: Aces, keep: Option<&Card>) -> Option<(Card, u32)> {
+    (0..hand.len())
+        .filter(|index| keep.is_none_or(|kept| !same_card(&hand[*index], kept)))
+        .map(|index| {
+            let mut rest = hand.to_vec();
+            let card = rest.remove(index);
+            (card, arrange(&rest, aces).deadwood_points())
+        })
+        .min_by_key(|(card, deadwood)| (*deadwood, u32::MAX - card_points(card)))
+}
+
+/// A bot's choice of where to draw from: the discard pile when the top card would leave less
+/// deadwood in the hand, otherwise the stock.
+pub fn draw_hint(hand: &[Card], discard_top: Option<&Card>, aces: Aces) -> GameMove {
+    let top = match discard_top {
+        Some(top) => top,
+        None => return GameMove::DrawCard,
+    };
+    let mut with_top = hand.to_vec();
+    with_top.push(*top);
+    match best_discard(&with_top, aces, Some(top)) {
+        Some((_, deadwood)) if deadwood < arrange(hand, aces).deadwood_points() => {
+            GameMove::DrawDiscard
+        }
+        _ => GameMove::DrawCard,
+    }
+}
+
+/// Finds the arrangement of a hand with the least deadwood by trying every combination of sets
+/// and runs that don't share a card. Hands are small enough that this is instant.
+pub fn arrange(hand: &[Card], aces: Aces) -> Arrangement {
+    let candidates = candidate_melds(hand, aces);
+    let mut best = (0, vec![]);
+    let mut chosen = vec![];
+    search(hand, &candidates, 0, 0, 0, &mut chosen, &mut best);
+
+    let (_, best_melds) = best;
+    let mut used = 0u32;
+    let melds = best_melds
+        .iter()
+        .map(|index: &usize| {
+            let mask = candidates[*index];
+            used |= mask;
+            let cards = cards_in(hand, mask);
+            Meld::new(cards, aces).expect("candidates are always melds")
+        })
+        .collect();
+    let deadwood = cards_in(hand, !used);
+    Arrangement { melds, deadwood }
+}
+
+fn search(
+    hand: &[Card],
+    candidates: &[u32],
+    start: usize,
+    used: u32,
+    melded_points: u32,
+    chosen: &mut Vec<usize>,
+    best: &mut (u32, Vec<usize>),
+) {
+    if melded_points > best.0 {
+        *best = (melded_points, chosen.clone());
+    }
+    for (index, mask) in candidates.iter().enumerate().skip(start) {
+        if used & mask != 0 {
+            continue;
+        }
+        let points = cards_in(hand, *mask).iter().map(card_points).sum::<u32>();
+        chosen.push(index);
+        search(
+            hand,
+            candidates,
+            index + 1,
+            used | mask,
+            melded_points + points,
+            chosen,
+            best,
+        );
+        chosen.pop();
+    }
+}
+
+fn cards_in(hand: &[Card], mask: u32) -> Vec<Card> {
+    hand.iter()
+        .enumerate()
+        .filter(|(index, _)| mask & (1 << index) != 0)
+        .map(|(_, card)| *card)
+        .collect()
+}
+
+/// Every set and run in the hand, as bit masks of positions in the hand.
+fn candidate_melds(hand: &[Card], aces: Aces) -> Vec<u32> {
+    let hand = &hand[..hand.len().min(32)];
+    let mut candidates = vec![];
+
+    for value in Value::all() {
+        let matching: Vec<usize> = (0..hand.len())
+            .filter(|index| hand[*index].value == value)
+            .collect();
+        for size in 3..=matching.len() {
+            for_each_combination(&matching, size, &mut |combination| {
+                candidates.push(combination.iter().fold(0, |mask, index| mask | 1 << index));
+            });
+        }
+    }
+
+    let ace_modes: &[bool] = match aces {
+        Aces::Low => &[false],
+        Aces::High => &[true],
+        Aces::Either => &[false, true],
+    };
+    for ace_high in ace_modes {
+        for index in 0..hand.len() {
+            let mut mask = 1u32 << index;
+            let mut length = 1;
+            let mut next_rank = rank(hand[index].value, *ace_high) + 1;
+            while let Some(next) = (0..hand.len()).find(|other| {
+                hand[*other].suite == hand[index].suite
+                    && rank(hand[*other].value, *ace_high) == next_rank
+            }) {
+                mask |= 1 << next;
+                length += 1;
+                next_rank += 1;
+                if length >= 3 && !candidates.contains(&mask) {
+                    candidates.push(mask);
+                }
+            }
+        }
+    }
+    candidates
+}
+
+fn for_each_combination(items: &[usize], size: usize, visit: &mut impl FnMut(&[usize])) {
+    fn recurse(
+        items: &[usize],
+        size: usize,
+        start: usize,
+        current: &mut Vec<usize>,
+        visit: &mut impl FnMut(&[usize]),
+    ) {
+        if current.len() == size {
+            visit(current);
+            return;
+        }
+        for index in start..items.len() {
+            current.push(items[index]);
+            recurse(items, size, index + 1, current, visit);
+            current.pop();
+        }
+    }
+    recurse(items, size, 0, &mut vec![], visit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::cards;
+
+    #[test]
+    fn sets_and_runs_are_melds_and_anything_else_is_not() {
+        let table = [
+            ("7S 7D 7C", Aces::Low, Some(MeldKind::Set)),
+            ("7S 7D 7C 7H", Aces::Low, Some(MeldKind::Set)),
+            ("4H 6H 5H", Aces::Low, Some(MeldKind::Run)),
+            ("AH 2H 3H", Aces::Low, Some(MeldKind::Run)),
+            ("AH 2H 3H", Aces::High, None),
+            ("QH KH AH", Aces::High, Some(MeldKind::Run)),
+            ("QH KH AH", Aces::Low, None),
+            ("QH KH AH", Aces::Either, Some(MeldKind::Run)),
+            ("KH AH 2H", Aces::Either, None),
+            ("4H 5H 6S", Aces::Low, None),
+            ("4H 5H 7H", Aces::Low, None),
+            ("7S 7D", Aces::Low, None),
+        ];
+        for (hand, aces, expected) in table.iter() {
+            assert_eq!(meld_kind(&cards(hand), *aces).ok(), *expected, "{}", hand);
+        }
+    }
+
+    #[test]
+    fn a_hand_is_arranged_to_leave_the_least_deadwood() {
+        // the seven of hearts can go in the set or the run, but not both
+        let hand = cards("7S 7D 7H 8H 9H KC 2D");
+        let arrangement = arrange(&hand, Aces::Low);
+        assert_eq!(arrangement.melds.len(), 1);
+        assert_eq!(arrangement.deadwood_points(), 10 + 2 + 7 + 7);
+
+        let hand = cards("7S 7D 7H 7C 8H 9H");
+        let arrangement = arrange(&hand, Aces::Low);
+        assert_eq!(arrangement.melds.len(), 2);
+        assert_eq!(arrangement.deadwood_points(), 0);
+
+        let (card, deadwood) = best_discard(&cards("5S 5D 5H KC 2D"), Aces::Low, None).unwrap();
+        assert_eq!(card.value, Value::King);
+        assert_eq!(deadwood, 2);
+    }
+
+    #[test]
+    fn a_meld_can_only_be_extended_while_it_stays_a_meld() {
+        let run = Meld::new(cards("5C 3C 4C"), Aces::Low).unwrap();
+        assert_eq!(run.cards, cards("3C 4C 5C"));
+        let extended = run.extended(&cards("6C"), Aces::Low).unwrap();
+        assert_eq!(extended.cards.len(), 4);
+        assert!(run.extended(&cards("7C"), Aces::Low).is_err());
+        assert!(run.extended(&cards("6D"), Aces::Low).is_err());
+    }
+}
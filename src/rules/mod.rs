@@ -0,0 +1,314 @@
+use std::fmt::Debug;
+
+use eyre::{bail, Result};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    card::{Card, Suite},
+    room_settings::{GameType, RoomSettings},
+    table::Table,
+};
+
+mod blackjack;
+mod crazy_eights;
+mod euchre;
+mod free_play;
+mod freecell_solver;
+mod gin;
+mod hearts;
+mod holdem;
+mod meld;
+mod rummy;
+mod solitaire;
+mod spades;
+mod trick;
+
+pub use blackjack::Blackjack;
+pub use crazy_eights::CrazyEights;
+pub use euchre::Euchre;
+pub use free_play::FreePlay;
+pub use freecell_solver::{solve, Position, Solvability};
+pub use gin::Gin;
+pub use hearts::Hearts;
+pub use holdem::Holdem;
+pub use meld::Aces;
+pub use rummy::Rummy;
+pub use solitaire::{Solitaire, Variant, Zone};
+pub use spades::Spades;
+
+/// Something a player wants to do at the table. The first few are the sandbox moves every room
+/// has always had, the rest only mean something to the game they belong to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "type")]
+pub enum GameMove {
+    StartRound,
+    DrawCard,
+    DiscardCard { card: Card },
+    ToggleVisibilityOfCard { card: Card },
+    ResetDeck,
+    PlaceBet { amount: u64 },
+    Hit,
+    Stand,
+    DoubleDown,
+    Split,
+    Check,
+    Call,
+    Fold,
+    AllIn,
+    Bet { amount: u64 },
+    Raise { amount: u64 },
+    PlayCard { card: Card },
+    PassCards { cards: Vec<Card> },
+    Bid { tricks: u8 },
+    OrderUp { alone: bool },
+    NameTrump { suite: Suite, alone: bool },
+    Pass,
+    DrawDiscard,
+    LayMeld { cards: Vec<Card> },
+    ExtendMeld { meld: usize, cards: Vec<Card> },
+    RearrangeMelds { melds: Vec<Vec<Card>> },
+    Knock { card: Card },
+    PlayWild { card: Card, suite: Suite },
+    MoveCards { from: Zone, to: Zone, count: usize },
+    DrawStock,
+    UndoMove,
+    CheckSolvable,
+}
+
+/// What one player is allowed to see of the game being played, tagged with the game it's from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "game")]
+pub enum GameState {
+    Blackjack(blackjack::BlackjackView),
+    Holdem(holdem::HoldemView),
+    Hearts(hearts::HeartsView),
+    Spades(spades::SpadesView),
+    Euchre(euchre::EuchreView),
+    Rummy(rummy::RummyView),
+    Gin(gin::GinView),
+    CrazyEights(crazy_eights::CrazyEightsView),
+    Solitaire(solitaire::SolitaireView),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PlayerScore {
+    pub player_id: String,
+    pub points: i64,
+}
+
+/// The rules a room is played by. The room asks the rules whether a move is allowed before
+/// applying it, so the rules never have to deal with moves they rejected.
+pub trait GameRules: Debug + Send {
+    /// Deals a new round. Only the host can start a round.
+    fn setup(&mut self, table: &mut Table) -> Result<()>;
+
+    /// Fails with a reason that can be shown to the player when the move isn't allowed.
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()>;
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()>;
+
+    fn is_round_over(&self, table: &Table) -> bool;
+
+    /// Points each player earned in the round that just ended.
+    fn score(&self, table: &Table) -> Vec<PlayerScore>;
+
+    /// Who won the round that just ended, for games where somebody wins outright.
+    fn winner(&self, _table: &Table) -> Option<String> {
+        None
+    }
+
+    /// The score that ends a match, for games that are played to one.
+    fn target_score(&self) -> Option<i64> {
+        None
+    }
+
+    /// Whether a round is being played, as shown in the lobby.
+    fn in_progress(&self, table: &Table) -> bool {
+        !table.discard_deck.is_empty() || table.players.iter().any(|player| !player.hand.is_empty())
+    }
+
+    /// The game state one player is allowed to see, sent to them after every move. Games that
+    /// have nothing to show beyond the table itself return `None`.
+    fn view(&self, _table: &Table, _player_id: &str) -> Option<GameState> {
+        None
+    }
+
+    /// Called after a player has left the table, in case it was their turn.
+    fn player_left(&mut self, _table: &mut Table, _player_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Moves a bot could try right now. Anything the rules don't allow is filtered out by the
+    /// room, so this only needs to cover every move worth making.
+    fn bot_moves(&self, table: &Table, player_id: &str) -> Vec<GameMove> {
+        candidate_moves(trick::hand_of(table, player_id))
+    }
+
+    /// The move a sensible player would make, for the bots that play more than randomly.
+    fn hint(&self, _table: &Table, _player_id: &str) -> Option<GameMove> {
+        None
+    }
+
+    /// The FreeCell position a player wants the solver to look at, or `None` when the answer
+    /// is already known and in their view. Fails when there's nothing the solver can check.
+    fn solver_position(&mut self, _table: &Table, _player_id: &str) -> Result<Option<Position>> {
+        bail!("Only FreeCell deals can be checked")
+    }
+
+    /// What the solver found for `position`, which the game may have moved on from since.
+    fn solved(&mut self, _position: &Position, _solvability: Solvability) {}
+
+    /// Everything the rules keep track of beyond the table, so a saved room can pick up where
+    /// it left off. Rules without any state of their own have nothing to save.
+    fn save(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::Value::Null)
+    }
+
+    /// Puts back what `save` returned, into rules made from the same room settings.
+    fn restore(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn save_state<T: Serialize>(rules: &T) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(rules)?)
+}
+
+fn restore_state<T: DeserializeOwned>(rules: &mut T, state: serde_json::Value) -> Result<()> {
+    *rules = serde_json::from_value(state)?;
+    Ok(())
+}
+
+/// Every move that might be allowed in one game or another with this hand. Moves that need
+/// more than the hand to pick from, like choosing cards to solitaire zones, are left out.
+pub fn candidate_moves(hand: &[Card]) -> Vec<GameMove> {
+    let mut moves = vec![
+        GameMove::DrawCard,
+        GameMove::DrawDiscard,
+        GameMove::DrawStock,
+        GameMove::PlaceBet { amount: 10 },
+        GameMove::Hit,
+        GameMove::Stand,
+        GameMove::DoubleDown,
+        GameMove::Split,
+        GameMove::Check,
+        GameMove::Call,
+        GameMove::Fold,
+        GameMove::AllIn,
+        GameMove::OrderUp { alone: false },
+        GameMove::Pass,
+    ];
+    moves.extend((0..=13).map(|tricks| GameMove::Bid { tricks }));
+    for suite in Suite::all() {
+        moves.push(GameMove::NameTrump {
+            suite,
+            alone: false,
+        });
+    }
+    for card in hand {
+        moves.push(GameMove::PlayCard { card: *card });
+        moves.push(GameMove::DiscardCard { card: *card });
+        moves.push(GameMove::Knock { card: *card });
+        for suite in Suite::all() {
+            moves.push(GameMove::PlayWild { card: *card, suite });
+        }
+    }
+    if hand.len() >= 3 {
+        moves.push(GameMove::PassCards {
+            cards: hand[..3].to_vec(),
+        });
+    }
+    for found in meld::arrange(hand, Aces::Either).melds {
+        moves.push(GameMove::LayMeld { cards: found.cards });
+    }
+    moves
+}
+
+pub fn new_rules(settings: &RoomSettings) -> Box<dyn GameRules> {
+    match settings.game_type {
+        GameType::FreePlay => Box::new(FreePlay),
+        GameType::Blackjack => Box::new(Blackjack::new(settings.dealer_hits_soft_17)),
+        GameType::Holdem => Box::new(Holdem::new(settings.small_blind, settings.big_blind)),
+        GameType::Hearts => Box::new(Hearts::new(
+            settings.target_score.unwrap_or(Hearts::TARGET_SCORE),
+        )),
+        GameType::Spades => Box::new(Spades::new(
+            settings.target_score.unwrap_or(Spades::TARGET_SCORE),
+        )),
+        GameType::Euchre => Box::new(Euchre::new(
+            settings.target_score.unwrap_or(Euchre::TARGET_SCORE),
+        )),
+        GameType::Rummy => Box::new(Rummy::new(
+            settings.aces,
+            settings.target_score.unwrap_or(Rummy::TARGET_SCORE),
+        )),
+        GameType::Gin => Box::new(Gin::new(
+            settings.aces,
+            settings.target_score.unwrap_or(Gin::TARGET_SCORE),
+        )),
+        GameType::CrazyEights => Box::new(CrazyEights::new()),
+        GameType::Klondike => Box::new(Solitaire::new(Variant::Klondike, settings.seed)),
+        GameType::FreeCell => Box::new(Solitaire::new(Variant::FreeCell, settings.seed)),
+        GameType::Spider => Box::new(Solitaire::new(Variant::Spider, settings.seed)),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_table(
+    count: usize,
+    chips: u64,
+    seed: u64,
+) -> (Table, Vec<String>, Vec<crate::outbox::OutboxReceiver>) {
+    use crate::{
+        card::DeckSpec,
+        outbox::{outbox, OutboxConfig},
+        player::Player,
+    };
+    use std::sync::Arc;
+
+    let mut table = Table::new(DeckSpec::default(), seed);
+    let mut ids = vec![];
+    let mut receivers = vec![];
+    for index in 0..count {
+        let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+        let mut player = Player::new(&format!("Player {}", index), sender);
+        player.chips = chips;
+        ids.push(player.id.clone());
+        receivers.push(receiver);
+        table.players.push(player);
+    }
+    (table, ids, receivers)
+}
+
+/// What the first player sees of a freshly dealt round of `game_type`.
+#[cfg(test)]
+pub(crate) fn dealt_game_state(game_type: GameType, seed: u64) -> GameState {
+    let settings = RoomSettings {
+        game_type,
+        ..RoomSettings::default()
+    };
+    let mut rules = new_rules(&settings);
+    let (mut table, ids, _receivers) = test_table(4, 100, seed);
+    rules.setup(&mut table).unwrap();
+    rules.view(&table, &ids[0]).unwrap()
+}
+
+/// Plays out the round in progress with every player following their hint, returning how many
+/// moves that took.
+#[cfg(test)]
+fn play_hints(rules: &mut dyn GameRules, table: &mut Table) -> usize {
+    let mut moves = 0;
+    while !rules.is_round_over(table) {
+        let (player_id, game_move) = table
+            .players
+            .iter()
+            .filter_map(|player| Some((player.id.clone(), rules.hint(table, &player.id)?)))
+            .find(|(player_id, game_move)| rules.validate(table, player_id, game_move).is_ok())
+            .expect("somebody can always move until the round is over");
+        rules.apply(table, &player_id, &game_move).unwrap();
+        moves += 1;
+        assert!(moves < 1000, "the round never ended");
+    }
+    moves
+}
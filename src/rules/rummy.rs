@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    candidate_moves,
+    meld::{
+        arrange, best_discard, card_points, draw_hint, same_card, take_cards, Aces, Meld, MeldData,
+    },
+    restore_state, save_state,
+    trick::{deal, hand_of, holds},
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData},
+    table::Table,
+};
+
+const MIN_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 6;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "RummyPhase")]
+enum Phase {
+    Waiting,
+    Draw,
+    Meld,
+    RoundOver,
+    GameOver,
+}
+
+/// Rummy for two to six players. On their turn a player draws from the stock or the discard
+/// pile, lays down, extends or rearranges melds on the table, then discards. Whoever gets rid of
+/// every card wins the round and scores what everyone else is left holding.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rummy {
+    aces: Aces,
+    target_score: i64,
+    phase: Phase,
+    seats: Vec<String>,
+    turn: usize,
+    melds: Vec<Meld>,
+    /// The card taken from the discard pile this turn, which can't go straight back.
+    taken_discard: Option<Card>,
+    round_points: Vec<i64>,
+    totals: HashMap<String, i64>,
+}
+
+impl Rummy {
+    pub const TARGET_SCORE: i64 = 500;
+
+    pub fn new(aces: Aces, target_score: i64) -> Self {
+        Self {
+            aces,
+            target_score,
+            phase: Phase::Waiting,
+            seats: vec![],
+            turn: 0,
+            melds: vec![],
+            taken_discard: None,
+            round_points: vec![],
+            totals: HashMap::new(),
+        }
+    }
+
+    fn hand_size(players: usize) -> usize {
+        match players {
+            2 => 10,
+            3 | 4 => 7,
+            _ => 6,
+        }
+    }
+
+    fn check_turn(&self, player_id: &str, phase: Phase) -> Result<()> {
+        if !self.in_round() {
+            bail!("There is no hand being played");
+        }
+        if self.seats.get(self.turn).map(String::as_str) != Some(player_id) {
+            bail!("It isn't your turn");
+        }
+        if self.phase != phase {
+            match phase {
+                Phase::Draw => bail!("You have already drawn this turn"),
+                _ => bail!("You have to draw a card first"),
+            }
+        }
+        Ok(())
+    }
+
+    fn in_round(&self) -> bool {
+        matches!(self.phase, Phase::Draw | Phase::Meld)
+    }
+
+    fn take_from_hand(table: &mut Table, player_id: &str, cards: &[Card]) -> Result<Vec<Card>> {
+        let player = table
+            .player_mut(player_id)
+            .ok_or_else(|| eyre!("You aren't at this table"))?;
+        let mut taken = take_cards(&mut player.hand, cards)?;
+        for card in &mut taken {
+            card.set_visible(true);
+        }
+        Ok(taken)
+    }
+
+    fn check_rearrangement(&self, hand: &[Card], melds: &[Vec<Card>]) -> Result<()> {
+        let mut on_table: Vec<Card> = self
+            .melds
+            .iter()
+            .flat_map(|meld| meld.cards.iter().copied())
+            .collect();
+        let mut from_hand = vec![];
+        for meld in melds {
+            for card in meld {
+                if let Some(index) = on_table.iter().position(|laid| same_card(laid, card)) {
+                    on_table.remove(index);
+                } else {
+                    from_hand.push(*card);
+                }
+            }
+            Meld::new(meld.clone(), self.aces)?;
+        }
+        if !on_table.is_empty() {
+            bail!("Every card already on the table has to stay in a meld");
+        }
+        let mut hand = hand.to_vec();
+        take_cards(&mut hand, &from_hand)?;
+        Ok(())
+    }
+
+    /// Ends the turn, or the round if the player has nothing left in their hand.
+    fn end_turn(&mut self, table: &Table, player_id: &str) {
+        self.taken_discard = None;
+        if hand_of(table, player_id).is_empty() {
+            self.end_round(table);
+        } else {
+            self.turn = (self.turn + 1) % self.seats.len();
+            self.phase = Phase::Draw;
+        }
+    }
+
+    fn end_round(&mut self, table: &Table) {
+        let left_over: i64 = self
+            .seats
+            .iter()
+            .map(|player_id| {
+                hand_of(table, player_id)
+                    .iter()
+                    .map(card_points)
+                    .sum::<u32>() as i64
+            })
+            .sum();
+        self.round_points = vec![0; self.seats.len()];
+        self.round_points[self.turn] = left_over;
+        *self
+            .totals
+            .entry(self.seats[self.turn].clone())
+            .or_insert(0) += left_over;
+        let game_over = self
+            .totals
+            .values()
+            .any(|total| *total >= self.target_score);
+        self.phase = if game_over {
+            Phase::GameOver
+        } else {
+            Phase::RoundOver
+        };
+    }
+
+    fn draw_from_stock(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        if table.draw_deck.is_empty() {
+            // everything but the top of the discard pile is turned over to make a new stock
+            let top = table.discard_deck.pop();
+            table.draw_deck = std::mem::take(&mut table.discard_deck);
+            table.draw_deck.reverse();
+            table.discard_deck.extend(top);
+        }
+        let mut card = table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("There is nothing left to draw"))?;
+        card.set_visible(false);
+        if let Some(player) = table.player_mut(player_id) {
+            player.add_card(card);
+        }
+        Ok(())
+    }
+}
+
+impl Rummy {
+    /// Every card in the hand that fits on one of the melds on the table.
+    fn layoffs(&self, hand: &[Card]) -> Vec<GameMove> {
+        let mut layoffs = vec![];
+        for card in hand {
+            for (index, meld) in self.melds.iter().enumerate() {
+                if meld.extended(&[*card], self.aces).is_ok() {
+                    layoffs.push(GameMove::ExtendMeld {
+                        meld: index,
+                        cards: vec![*card],
+                    });
+                }
+            }
+        }
+        layoffs
+    }
+}
+
+impl GameRules for Rummy {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        if self.phase == Phase::GameOver {
+            self.totals.clear();
+        }
+        table.collect_cards();
+        self.seats = table
+            .players
+            .iter()
+            .take(MAX_PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &self.seats, Self::hand_size(self.seats.len()))?;
+        let mut upcard = table
+            .draw_deck
+            .pop()
+            .ok_or_else(|| eyre!("The deck ran out of cards"))?;
+        upcard.set_visible(true);
+        table.discard_deck.push(upcard);
+        self.melds.clear();
+        self.taken_discard = None;
+        self.round_points.clear();
+        self.turn = 0;
+        self.phase = Phase::Draw;
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        let hand = hand_of(table, player_id);
+        match game_move {
+            GameMove::StartRound => {
+                if self.in_round() {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < MIN_PLAYERS {
+                    bail!("Rummy needs at least two players");
+                }
+            }
+            GameMove::DrawCard => {
+                self.check_turn(player_id, Phase::Draw)?;
+                if table.draw_deck.is_empty() && table.discard_deck.len() < 2 {
+                    bail!("There is nothing left to draw");
+                }
+            }
+            GameMove::DrawDiscard => {
+                self.check_turn(player_id, Phase::Draw)?;
+                if table.discard_deck.is_empty() {
+                    bail!("The discard pile is empty");
+                }
+            }
+            GameMove::LayMeld { cards } => {
+                self.check_turn(player_id, Phase::Meld)?;
+                take_cards(&mut hand.to_vec(), cards)?;
+                Meld::new(cards.clone(), self.aces)?;
+            }
+            GameMove::ExtendMeld { meld, cards } => {
+                self.check_turn(player_id, Phase::Meld)?;
+                take_cards(&mut hand.to_vec(), cards)?;
+                self.melds
+                    .get(*meld)
+                    .ok_or_else(|| eyre!("That meld isn't on the table"))?
+                    .extended(cards, self.aces)?;
+            }
+            GameMove::RearrangeMelds { melds } => {
+                self.check_turn(player_id, Phase::Meld)?;
+                self.check_rearrangement(hand, melds)?;
+            }
+            GameMove::DiscardCard { card } => {
+                self.check_turn(player_id, Phase::Meld)?;
+                if !holds(hand, card) {
+                    bail!("That card isn't in your hand");
+                }
+                let only_copy = hand.iter().filter(|held| same_card(held, card)).count() == 1;
+                if only_copy
+                    && self
+                        .taken_discard
+                        .is_some_and(|taken| same_card(&taken, card))
+                {
+                    bail!("You can't discard the card you just picked up");
+                }
+            }
+            _ => bail!("That move isn't part of rummy"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::DrawCard => {
+                self.draw_from_stock(table, player_id)?;
+                self.phase = Phase::Meld;
+            }
+            GameMove::DrawDiscard => {
+                let mut card = table
+                    .discard_deck
+                    .pop()
+                    .ok_or_else(|| eyre!("The discard pile is empty"))?;
+                self.taken_discard = Some(card);
+                card.set_visible(false);
+                if let Some(player) = table.player_mut(player_id) {
+                    player.add_card(card);
+                }
+                self.phase = Phase::Meld;
+            }
+            GameMove::LayMeld { cards } => {
+                let cards = Self::take_from_hand(table, player_id, cards)?;
+                self.melds.push(Meld::new(cards, self.aces)?);
+            }
+            GameMove::ExtendMeld { meld, cards } => {
+                let cards = Self::take_from_hand(table, player_id, cards)?;
+                self.melds[*meld] = self.melds[*meld].extended(&cards, self.aces)?;
+            }
+            GameMove::RearrangeMelds { melds } => {
+                let mut on_table: Vec<Card> =
+                    self.melds.drain(..).flat_map(|meld| meld.cards).collect();
+                for cards in melds {
+                    let mut meld_cards = vec![];
+                    for card in cards {
+                        match take_cards(&mut on_table, std::slice::from_ref(card)) {
+                            Ok(mut laid) => meld_cards.append(&mut laid),
+                            Err(_) => meld_cards.append(&mut Self::take_from_hand(
+                                table,
+                                player_id,
+                                std::slice::from_ref(card),
+                            )?),
+                        }
+                    }
+                    self.melds.push(Meld::new(meld_cards, self.aces)?);
+                }
+            }
+            GameMove::DiscardCard { card } => {
+                let mut discarded = table
+                    .player_mut(player_id)
+                    .and_then(|player| player.discard_card(card))
+                    .ok_or_else(|| eyre!("That card isn't in your hand"))?;
+                discarded.set_visible(true);
+                table.discard_deck.push(discarded);
+                self.end_turn(table, player_id);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+        // melding every last card goes out without needing a discard
+        if hand_of(table, player_id).is_empty() {
+            self.end_turn(table, player_id);
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::RoundOver | Phase::GameOver)
+    }
+
+    fn target_score(&self) -> Option<i64> {
+        Some(self.target_score)
+    }
+
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.seats
+            .iter()
+            .zip(self.round_points.iter())
+            .map(|(player_id, points)| PlayerScore {
+                player_id: player_id.clone(),
+                points: *points,
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.in_round()
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let view = RummyView {
+            phase: self.phase,
+            turn: self.seats.get(self.turn).cloned(),
+            hand: hand_of(table, player_id)
+                .iter()
+                .map(|card| card.revealed_card_data())
+                .collect(),
+            cards_in_hand: self
+                .seats
+                .iter()
+                .map(|seat| (seat.clone(), hand_of(table, seat).len()))
+                .collect(),
+            melds: self.melds.iter().map(Meld::meld_data).collect(),
+            discard_top: table.discard_deck.last().map(Card::card_data),
+            stock_size: table.draw_deck.len(),
+            aces: self.aces,
+            round_points: self.score(table),
+            totals: self.totals.clone(),
+            target_score: self.target_score,
+        };
+        Some(GameState::Rummy(view))
+    }
+
+    fn player_left(&mut self, _table: &mut Table, player_id: &str) -> Result<()> {
+        let seat = match self.seats.iter().position(|seat| seat == player_id) {
+            Some(seat) => seat,
+            None => return Ok(()),
+        };
+        self.seats.remove(seat);
+        if self.in_round() {
+            if self.seats.len() < MIN_PLAYERS {
+                self.phase = Phase::Waiting;
+                return Ok(());
+            }
+            if seat < self.turn {
+                self.turn -= 1;
+            } else if seat == self.turn {
+                self.turn %= self.seats.len();
+                self.taken_discard = None;
+                self.phase = Phase::Draw;
+            }
+        }
+        Ok(())
+    }
+
+    fn bot_moves(&self, table: &Table, player_id: &str) -> Vec<GameMove> {
+        let hand = hand_of(table, player_id);
+        let mut moves = candidate_moves(hand);
+        moves.extend(self.layoffs(hand));
+        moves
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        if self.seats.get(self.turn)? != player_id {
+            return None;
+        }
+        let hand = hand_of(table, player_id);
+        match self.phase {
+            Phase::Draw => Some(draw_hint(hand, table.discard_deck.last(), self.aces)),
+            Phase::Meld => {
+                if let Some(meld) = arrange(hand, self.aces).melds.into_iter().next() {
+                    return Some(GameMove::LayMeld { cards: meld.cards });
+                }
+                // laying off the last card would leave nothing to discard
+                if hand.len() > 1 {
+                    if let Some(layoff) = self.layoffs(hand).into_iter().next() {
+                        return Some(layoff);
+                    }
+                }
+                best_discard(hand, self.aces, self.taken_discard.as_ref())
+                    .map(|(card, _)| GameMove::DiscardCard { card })
+            }
+            _ => None,
+        }
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RummyView {
+    phase: Phase,
+    turn: Option<String>,
+    hand: Vec<CardData>,
+    cards_in_hand: HashMap<String, usize>,
+    melds: Vec<MeldData>,
+    discard_top: Option<CardData>,
+    stock_size: usize,
+    aces: Aces,
+    round_points: Vec<PlayerScore>,
+    totals: HashMap<String, i64>,
+    target_score: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card::cards, outbox::OutboxReceiver, rules::test_table};
+
+    fn deal_round(players: usize, seed: u64) -> (Rummy, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(players, 0, seed);
+        let mut rules = Rummy::new(Aces::Low, Rummy::TARGET_SCORE);
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    fn play(rules: &mut Rummy, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    #[test]
+    fn whoever_goes_out_scores_what_everyone_else_holds() {
+        for players in [2, 4, 6] {
+            let (mut rules, mut table, ids, _receivers) = deal_round(players, 0);
+            let player = ids[0].clone();
+            table.player_mut(&player).unwrap().hand = cards("2C 3C 4C 9H 9S 9D 5C");
+            play(&mut rules, &mut table, &player, GameMove::DrawCard);
+            let run = GameMove::LayMeld {
+                cards: cards("2C 3C 4C 5C"),
+            };
+            play(&mut rules, &mut table, &player, run);
+            let set = GameMove::LayMeld {
+                cards: cards("9H 9S 9D"),
+            };
+            play(&mut rules, &mut table, &player, set);
+            assert!(!rules.is_round_over(&table));
+            let card = hand_of(&table, &player)[0];
+            play(
+                &mut rules,
+                &mut table,
+                &player,
+                GameMove::DiscardCard { card },
+            );
+
+            assert!(rules.is_round_over(&table));
+            let held: u32 = ids
+                .iter()
+                .flat_map(|player_id| hand_of(&table, player_id).iter())
+                .map(card_points)
+                .sum();
+            assert_eq!(rules.round_points[0], held as i64);
+            assert_eq!(rules.totals[&player], held as i64);
+            assert!(rules.round_points[1..].iter().all(|points| *points == 0));
+        }
+    }
+
+    #[test]
+    fn melds_have_to_be_real_and_stay_on_the_table() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(2, 0);
+        let player = ids[0].clone();
+        let not_a_meld = GameMove::LayMeld {
+            cards: cards("2C 3D 4C"),
+        };
+        table.player_mut(&player).unwrap().hand = cards("2C 3D 4C 3C 9H 9S 9D");
+        assert!(rules.validate(&table, &player, &not_a_meld).is_err());
+        let run = GameMove::LayMeld {
+            cards: cards("2C 3C 4C"),
+        };
+        // nothing can be melded before drawing
+        assert!(rules.validate(&table, &player, &run).is_err());
+        assert!(rules
+            .validate(&table, &ids[1], &GameMove::DrawCard)
+            .is_err());
+
+        play(&mut rules, &mut table, &player, GameMove::DrawCard);
+        assert!(rules.validate(&table, &player, &not_a_meld).is_err());
+        let not_held = GameMove::LayMeld {
+            cards: cards("2H 3H 4H"),
+        };
+        assert!(rules.validate(&table, &player, &not_held).is_err());
+        play(&mut rules, &mut table, &player, run);
+        assert_eq!(rules.melds.len(), 1);
+
+        let dropping_a_card = GameMove::RearrangeMelds {
+            melds: vec![cards("9H 9S 9D")],
+        };
+        assert!(rules.validate(&table, &player, &dropping_a_card).is_err());
+        let rearranged = GameMove::RearrangeMelds {
+            melds: vec![cards("2C 3C 4C"), cards("9H 9S 9D")],
+        };
+        play(&mut rules, &mut table, &player, rearranged);
+        assert_eq!(rules.melds.len(), 2);
+        let extend = GameMove::ExtendMeld {
+            meld: 0,
+            cards: cards("3D"),
+        };
+        assert!(rules.validate(&table, &player, &extend).is_err());
+    }
+}
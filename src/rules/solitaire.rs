@@ -0,0 +1,811 @@
+use std::collections::HashMap;
+
+use eyre::{bail, eyre, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    freecell_solver::{Position, Solvability},
+    restore_state, save_state,
+    trick::same_colour,
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Card, CardData, DeckSpec, Suite, Value},
+    table::Table,
+};
+
+/// How many moves can be taken back.
+const MAX_UNDO: usize = 500;
+/// Microsoft FreeCell deal numbers, which players know by heart, run up to here.
+const FREECELL_DEALS: u64 = 32_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+pub enum Variant {
+    Klondike,
+    FreeCell,
+    Spider,
+}
+
+impl Variant {
+    fn deck(&self) -> DeckSpec {
+        match self {
+            Variant::Spider => DeckSpec::new(2),
+            _ => DeckSpec::default(),
+        }
+    }
+
+    fn columns(&self) -> usize {
+        match self {
+            Variant::Klondike => 7,
+            Variant::FreeCell => 8,
+            Variant::Spider => 10,
+        }
+    }
+
+    fn cells(&self) -> usize {
+        match self {
+            Variant::FreeCell => 4,
+            _ => 0,
+        }
+    }
+
+    fn foundations(&self) -> usize {
+        match self {
+            Variant::Spider => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// A place cards can be moved from or to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(tag = "zone")]
+pub enum Zone {
+    Tableau { index: usize },
+    Foundation { index: usize },
+    Cell { index: usize },
+    Waste,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "SolitairePhase")]
+enum Phase {
+    Waiting,
+    Playing,
+    Won,
+}
+
+/// Aces are one and kings thirteen.
+fn rank(value: Value) -> u8 {
+    Value::all()
+        .iter()
+        .position(|other| *other == value)
+        .unwrap_or_default() as u8
+        + 1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct Layout {
+    tableau: Vec<Vec<Card>>,
+    foundations: Vec<Vec<Card>>,
+    cells: Vec<Option<Card>>,
+    stock: Vec<Card>,
+    waste: Vec<Card>,
+}
+
+impl Layout {
+    fn deal(variant: Variant, mut cards: Vec<Card>) -> Self {
+        let mut layout = Self {
+            tableau: vec![vec![]; variant.columns()],
+            foundations: vec![vec![]; variant.foundations()],
+            cells: vec![None; variant.cells()],
+            stock: vec![],
+            waste: vec![],
+        };
+        match variant {
+            Variant::Klondike => {
+                for column in 0..7 {
+                    for _ in 0..=column {
+                        layout.tableau[column].extend(cards.pop());
+                    }
+                }
+            }
+            Variant::FreeCell => {
+                for (index, card) in cards.drain(..).enumerate() {
+                    layout.tableau[index % 8].push(card);
+                }
+            }
+            Variant::Spider => {
+                for index in 0..54 {
+                    layout.tableau[index % 10].extend(cards.pop());
+                }
+            }
+        }
+        for column in &mut layout.tableau {
+            for card in column.iter_mut() {
+                card.set_visible(variant == Variant::FreeCell);
+            }
+            if let Some(top) = column.last_mut() {
+                top.set_visible(true);
+            }
+        }
+        for card in &mut cards {
+            card.set_visible(false);
+        }
+        layout.stock = cards;
+        layout
+    }
+
+    fn cards_on_foundations(&self) -> usize {
+        self.foundations.iter().map(Vec::len).sum()
+    }
+
+    fn empty_columns(&self) -> usize {
+        self.tableau
+            .iter()
+            .filter(|column| column.is_empty())
+            .count()
+    }
+
+    fn free_cells(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.is_none()).count()
+    }
+
+    /// The cards that would be picked up by taking `count` from the zone.
+    fn peek(&self, variant: Variant, from: Zone, count: usize) -> Result<&[Card]> {
+        if count == 0 {
+            bail!("You have to move at least one card");
+        }
+        let pile: &[Card] = match from {
+            Zone::Tableau { index } => self
+                .tableau
+                .get(index)
+                .ok_or_else(|| eyre!("There is no such column"))?,
+            Zone::Foundation { index } => {
+                if variant == Variant::Spider {
+                    bail!("Finished suits stay put in spider");
+                }
+                self.foundations
+                    .get(index)
+                    .ok_or_else(|| eyre!("There is no such foundation"))?
+            }
+            Zone::Cell { index } => match self.cells.get(index) {
+                Some(Some(card)) => std::slice::from_ref(card),
+                Some(None) => bail!("That cell is empty"),
+                None => bail!("There is no such cell"),
+            },
+            Zone::Waste => &self.waste,
+        };
+        if count > pile.len() {
+            bail!("There aren't that many cards there");
+        }
+        if count > 1 && !matches!(from, Zone::Tableau { .. }) {
+            bail!("Only runs of cards in the tableau can be moved together");
+        }
+        let cards = &pile[pile.len() - count..];
+        if cards.iter().any(|card| !card.is_visible()) {
+            bail!("Face down cards can't be moved");
+        }
+        if !cards
+            .windows(2)
+            .all(|pair| builds_on(variant, &pair[1], &pair[0], true))
+        {
+            bail!("Only cards in sequence can be moved together");
+        }
+        Ok(cards)
+    }
+
+    fn check_move(&self, variant: Variant, from: Zone, to: Zone, count: usize) -> Result<()> {
+        if from == to {
+            bail!("The cards are already there");
+        }
+        let cards = self.peek(variant, from, count)?;
+        let first = &cards[0];
+        match to {
+            Zone::Tableau { index } => {
+                let column = self
+                    .tableau
+                    .get(index)
+                    .ok_or_else(|| eyre!("There is no such column"))?;
+                match column.last() {
+                    Some(top) => {
+                        if !builds_on(variant, first, top, false) {
+                            bail!("That card doesn't go on that column");
+                        }
+                    }
+                    None => {
+                        if variant == Variant::Klondike && first.value != Value::King {
+                            bail!("Only a king can start an empty column");
+                        }
+                    }
+                }
+                if variant == Variant::FreeCell {
+                    // runs are really moved one card at a time through free cells and empty
+                    // columns, which limits how long they can be
+                    let empty_columns = self.empty_columns() - usize::from(column.is_empty());
+                    let limit = (self.free_cells() + 1) << empty_columns;
+                    if count > limit {
+                        bail!("There's only room to move {} cards at once", limit);
+                    }
+                }
+            }
+            Zone::Foundation { index } => {
+                if variant == Variant::Spider {
+                    bail!("Suits are moved to the foundations once they are complete");
+                }
+                let foundation = self
+                    .foundations
+                    .get(index)
+                    .ok_or_else(|| eyre!("There is no such foundation"))?;
+                if count != 1 {
+                    bail!("Cards go on the foundations one at a time");
+                }
+                let fits = match foundation.last() {
+                    Some(top) => {
+                        top.suite == first.suite && rank(first.value) == rank(top.value) + 1
+                    }
+                    None => first.value == Value::Ace,
+                };
+                if !fits {
+                    bail!("That card doesn't go on that foundation");
+                }
+            }
+            Zone::Cell { index } => {
+                match self.cells.get(index) {
+                    Some(None) => {}
+                    Some(Some(_)) => bail!("That cell is taken"),
+                    None => bail!("There is no such cell"),
+                }
+                if count != 1 {
+                    bail!("A cell only holds one card");
+                }
+            }
+            Zone::Waste => bail!("Cards can't be put on the waste"),
+        }
+        Ok(())
+    }
+
+    fn move_cards(&mut self, variant: Variant, from: Zone, to: Zone, count: usize) {
+        let cards = match from {
+            Zone::Tableau { index } => {
+                let column = &mut self.tableau[index];
+                let cards = column.split_off(column.len() - count);
+                if let Some(top) = column.last_mut() {
+                    top.set_visible(true);
+                }
+                cards
+            }
+            Zone::Foundation { index } => self.foundations[index].pop().into_iter().collect(),
+            Zone::Cell { index } => self.cells[index].take().into_iter().collect(),
+            Zone::Waste => self.waste.pop().into_iter().collect(),
+        };
+        match to {
+            Zone::Tableau { index } => self.tableau[index].extend(cards),
+            Zone::Foundation { index } => self.foundations[index].extend(cards),
+            Zone::Cell { index } => self.cells[index] = cards.first().copied(),
+            Zone::Waste => {}
+        }
+        if variant == Variant::Spider {
+            self.clear_finished_suits();
+        }
+    }
+
+    fn check_draw(&self, variant: Variant) -> Result<()> {
+        match variant {
+            Variant::Klondike => {
+                if self.stock.is_empty() && self.waste.is_empty() {
+                    bail!("There are no cards left to draw");
+                }
+            }
+            Variant::FreeCell => bail!("FreeCell has no stock"),
+            Variant::Spider => {
+                if self.stock.is_empty() {
+                    bail!("There are no cards left to deal");
+                }
+                if self.empty_columns() > 0 {
+                    bail!("Every column needs a card before dealing another row");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, variant: Variant) {
+        match variant {
+            Variant::Klondike => {
+                if self.stock.is_empty() {
+                    self.stock = self.waste.drain(..).rev().collect();
+                    for card in &mut self.stock {
+                        card.set_visible(false);
+                    }
+                } else if let Some(mut card) = self.stock.pop() {
+                    card.set_visible(true);
+                    self.waste.push(card);
+                }
+            }
+            Variant::FreeCell => {}
+            Variant::Spider => {
+                for column in &mut self.tableau {
+                    if let Some(mut card) = self.stock.pop() {
+                        card.set_visible(true);
+                        column.push(card);
+                    }
+                }
+                self.clear_finished_suits();
+            }
+        }
+    }
+
+    /// Spider moves a full suit from king down to ace off the tableau as soon as it's built.
+    fn clear_finished_suits(&mut self) {
+        for column in &mut self.tableau {
+            if column.len() < 13 {
+                continue;
+            }
+            let run = &column[column.len() - 13..];
+            let finished = run[0].value == Value::King
+                && run.iter().all(|card| card.is_visible())
+                && run
+                    .windows(2)
+                    .all(|pair| builds_on(Variant::Spider, &pair[1], &pair[0], true));
+            if !finished {
+                continue;
+            }
+            let run = column.split_off(column.len() - 13);
+            if let Some(top) = column.last_mut() {
+                top.set_visible(true);
+            }
+            if let Some(foundation) = self.foundations.iter_mut().find(|pile| pile.is_empty()) {
+                *foundation = run;
+            }
+        }
+    }
+}
+
+/// Whether `card` can go on top of `onto` in the tableau. Spider lets any suit build down, but
+/// only cards of one suit move together.
+fn builds_on(variant: Variant, card: &Card, onto: &Card, moving_together: bool) -> bool {
+    if rank(card.value) + 1 != rank(onto.value) {
+        return false;
+    }
+    match variant {
+        Variant::Spider if moving_together => card.suite == onto.suite,
+        Variant::Spider => true,
+        _ => !same_colour(card.suite, onto.suite),
+    }
+}
+
+/// The deal Microsoft FreeCell gives for a deal number, so numbered deals match the ones
+/// players already know.
+fn freecell_deal(deal_number: u64) -> Vec<Card> {
+    let suites = [Suite::Club, Suite::Diamond, Suite::Heart, Suite::Spade];
+    let values = Value::all();
+    let mut cards: Vec<usize> = (0..52).rev().collect();
+    let mut seed = deal_number as u32;
+    for index in 0..52 {
+        seed = (seed.wrapping_mul(214_013).wrapping_add(2_531_011)) & 0x7fff_ffff;
+        let random = (seed >> 16) as usize;
+        let swap = 51 - random % (52 - index);
+        cards.swap(index, swap);
+    }
+    cards
+        .into_iter()
+        .map(|card| Card::new(suites[card % 4], values[card / 4]))
+        .collect()
+}
+
+/// Solitaire for a single player, with the whole layout kept server side so every move can be
+/// checked. Deals are seeded so the same deal can be played again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Solitaire {
+    variant: Variant,
+    /// The seed asked for when the room was made, used for the first deal only.
+    requested_seed: Option<u64>,
+    seed: u64,
+    player_id: String,
+    phase: Phase,
+    layout: Layout,
+    history: Vec<Layout>,
+    moves: usize,
+    solvability: Option<Solvability>,
+    /// What the solver found for positions of this deal, since undoing can come back to them.
+    #[serde(skip)]
+    solved: HashMap<Vec<u8>, Solvability>,
+    /// Whether the solver is looking at this game, it only looks at one position at a time.
+    #[serde(skip)]
+    checking: bool,
+}
+
+impl Solitaire {
+    pub fn new(variant: Variant, seed: Option<u64>) -> Self {
+        Self {
+            variant,
+            requested_seed: seed,
+            seed: 0,
+            player_id: String::new(),
+            phase: Phase::Waiting,
+            layout: Layout::default(),
+            history: vec![],
+            moves: 0,
+            solvability: None,
+            solved: HashMap::new(),
+            checking: false,
+        }
+    }
+
+    fn check_player(&self, player_id: &str) -> Result<()> {
+        if self.phase != Phase::Playing {
+            bail!("There is no game being played");
+        }
+        if self.player_id != player_id {
+            bail!("This isn't your game");
+        }
+        Ok(())
+    }
+
+    fn remember(&mut self) {
+        self.history.push(self.layout.clone());
+        if self.history.len() > MAX_UNDO {
+            self.history.remove(0);
+        }
+        self.moves += 1;
+        self.solvability = None;
+    }
+
+    fn deck_size(&self) -> usize {
+        self.variant.deck().size()
+    }
+
+    fn position(&self) -> Position {
+        let layout = &self.layout;
+        Position::new(&layout.tableau, &layout.cells, &layout.foundations)
+    }
+}
+
+impl GameRules for Solitaire {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        let mut rng = table.rng();
+        self.seed = match (self.requested_seed.take(), self.variant) {
+            (Some(seed), _) => seed,
+            (None, Variant::FreeCell) => rng.gen_range(1..=FREECELL_DEALS),
+            (None, _) => rng.gen(),
+        };
+        let cards = match self.variant {
+            Variant::FreeCell => freecell_deal(self.seed),
+            _ => {
+                let mut cards = self.variant.deck().build();
+                cards.shuffle(&mut StdRng::seed_from_u64(self.seed));
+                cards
+            }
+        };
+        // everything is laid out here rather than on the table
+        table.collect_cards();
+        table.draw_deck.clear();
+        self.layout = Layout::deal(self.variant, cards);
+        self.player_id = table
+            .players
+            .first()
+            .map(|player| player.id.clone())
+            .unwrap_or_default();
+        self.history.clear();
+        self.moves = 0;
+        self.solvability = None;
+        self.solved.clear();
+        self.phase = Phase::Playing;
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if table.players.len() != 1 {
+                    bail!("Solitaire is played alone");
+                }
+            }
+            GameMove::MoveCards { from, to, count } => {
+                self.check_player(player_id)?;
+                self.layout.check_move(self.variant, *from, *to, *count)?;
+            }
+            GameMove::DrawStock => {
+                self.check_player(player_id)?;
+                self.layout.check_draw(self.variant)?;
+            }
+            GameMove::UndoMove => {
+                self.check_player(player_id)?;
+                if self.history.is_empty() {
+                    bail!("There is nothing to undo");
+                }
+            }
+            _ => bail!("That move isn't part of solitaire"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, _table: &mut Table, _player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::MoveCards { from, to, count } => {
+                self.remember();
+                self.layout.move_cards(self.variant, *from, *to, *count);
+            }
+            GameMove::DrawStock => {
+                self.remember();
+                self.layout.draw(self.variant);
+            }
+            GameMove::UndoMove => {
+                if let Some(layout) = self.history.pop() {
+                    self.layout = layout;
+                    self.moves += 1;
+                    self.solvability = None;
+                }
+            }
+            _ => {}
+        }
+        if self.layout.cards_on_foundations() == self.deck_size() {
+            self.phase = Phase::Won;
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        self.phase == Phase::Won
+    }
+
+    /// The number of cards that made it to the foundations.
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        vec![PlayerScore {
+            player_id: self.player_id.clone(),
+            points: self.layout.cards_on_foundations() as i64,
+        }]
+    }
+
+    fn winner(&self, _table: &Table) -> Option<String> {
+        (self.phase == Phase::Won).then(|| self.player_id.clone())
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        self.phase == Phase::Playing
+    }
+
+    fn view(&self, _table: &Table, _player_id: &str) -> Option<GameState> {
+        let pile_data = |pile: &Vec<Card>| pile.iter().map(Card::card_data).collect();
+        let view = SolitaireView {
+            variant: self.variant,
+            phase: self.phase,
+            seed: self.seed,
+            tableau: self.layout.tableau.iter().map(pile_data).collect(),
+            foundations: self.layout.foundations.iter().map(pile_data).collect(),
+            cells: self
+                .layout
+                .cells
+                .iter()
+                .map(|cell| cell.map(|card| card.card_data()))
+                .collect(),
+            stock_size: self.layout.stock.len(),
+            waste: self.layout.waste.last().map(Card::card_data),
+            moves: self.moves,
+            can_undo: !self.history.is_empty(),
+            solvability: self.solvability,
+        };
+        Some(GameState::Solitaire(view))
+    }
+
+    fn player_left(&mut self, _table: &mut Table, player_id: &str) -> Result<()> {
+        if self.player_id == player_id {
+            self.phase = Phase::Waiting;
+        }
+        Ok(())
+    }
+
+    fn solver_position(&mut self, _table: &Table, player_id: &str) -> Result<Option<Position>> {
+        self.check_player(player_id)?;
+        if self.variant != Variant::FreeCell {
+            bail!("Only FreeCell deals can be checked");
+        }
+        if self.solvability.is_some() {
+            return Ok(None);
+        }
+        let position = self.position();
+        if let Some(solvability) = self.solved.get(&position.key()) {
+            self.solvability = Some(*solvability);
+            return Ok(None);
+        }
+        if self.checking {
+            bail!("The solver is already looking at this game");
+        }
+        self.checking = true;
+        Ok(Some(position))
+    }
+
+    fn solved(&mut self, position: &Position, solvability: Solvability) {
+        self.checking = false;
+        let key = position.key();
+        if self.phase == Phase::Playing && self.position().key() == key {
+            self.solvability = Some(solvability);
+        }
+        self.solved.insert(key, solvability);
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SolitaireView {
+    variant: Variant,
+    phase: Phase,
+    seed: u64,
+    tableau: Vec<Vec<CardData>>,
+    foundations: Vec<Vec<CardData>>,
+    cells: Vec<Option<CardData>>,
+    stock_size: usize,
+    waste: Option<CardData>,
+    moves: usize,
+    can_undo: bool,
+    solvability: Option<Solvability>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card::cards, outbox::detached, player::Player, rules::solve};
+
+    fn deal(variant: Variant) -> (Solitaire, Table, String) {
+        let mut table = Table::new(variant.deck(), 0);
+        let player = Player::new("Ada", detached());
+        let player_id = player.id.clone();
+        table.players.push(player);
+        let mut rules = Solitaire::new(variant, Some(1));
+        rules.setup(&mut table).unwrap();
+        (rules, table, player_id)
+    }
+
+    #[test]
+    fn the_solver_looks_at_each_position_once() {
+        let (mut rules, mut table, ada) = deal(Variant::FreeCell);
+        let first = rules.solver_position(&table, &ada).unwrap().unwrap();
+        assert!(rules.solver_position(&table, &ada).is_err());
+        let winnable = Solvability::Winnable { moves: 90 };
+        rules.solved(&first, winnable);
+        assert!(rules.solver_position(&table, &ada).unwrap().is_none());
+        assert_eq!(rules.solvability, Some(winnable));
+
+        let to_cell = GameMove::MoveCards {
+            from: Zone::Tableau { index: 0 },
+            to: Zone::Cell { index: 0 },
+            count: 1,
+        };
+        rules.validate(&table, &ada, &to_cell).unwrap();
+        rules.apply(&mut table, &ada, &to_cell).unwrap();
+        assert_eq!(rules.solvability, None);
+        let second = rules.solver_position(&table, &ada).unwrap().unwrap();
+        rules.solved(&second, Solvability::Unknown);
+
+        // undoing comes back to a position the solver already answered for
+        rules.apply(&mut table, &ada, &GameMove::UndoMove).unwrap();
+        assert!(rules.solver_position(&table, &ada).unwrap().is_none());
+        assert_eq!(rules.solvability, Some(winnable));
+    }
+
+    #[test]
+    fn only_freecell_deals_can_be_checked() {
+        let (mut rules, table, ada) = deal(Variant::Klondike);
+        assert!(rules.solver_position(&table, &ada).is_err());
+    }
+
+    fn play(rules: &mut Solitaire, table: &mut Table, player_id: &str, game_move: GameMove) {
+        rules.validate(table, player_id, &game_move).unwrap();
+        rules.apply(table, player_id, &game_move).unwrap();
+    }
+
+    #[test]
+    fn numbered_freecell_deals_match_the_classic_ones() {
+        // the first row of Microsoft FreeCell deal #1
+        let first_row: Vec<Card> = freecell_deal(1).into_iter().take(8).collect();
+        assert_eq!(first_row, cards("JD 2D 9H JC 5D 7H 7C 5H"));
+    }
+
+    #[test]
+    fn the_game_is_won_once_every_card_is_on_the_foundations() {
+        let (mut rules, mut table, ada) = deal(Variant::Klondike);
+        let mut layout = Layout::deal(Variant::Klondike, vec![]);
+        for (index, suite) in Suite::all().iter().enumerate() {
+            layout.foundations[index] = Value::all()
+                .iter()
+                .filter(|value| **value != Value::King)
+                .map(|value| Card::new(*suite, *value))
+                .collect();
+            let mut king = Card::new(*suite, Value::King);
+            king.set_visible(true);
+            layout.tableau[index].push(king);
+        }
+        rules.layout = layout;
+
+        for index in 0..4 {
+            assert!(!rules.is_round_over(&table));
+            let to_foundation = GameMove::MoveCards {
+                from: Zone::Tableau { index },
+                to: Zone::Foundation { index },
+                count: 1,
+            };
+            play(&mut rules, &mut table, &ada, to_foundation);
+        }
+        assert!(rules.is_round_over(&table));
+        assert_eq!(rules.winner(&table), Some(ada.clone()));
+        assert_eq!(rules.score(&table)[0].points, 52);
+        assert!(rules.validate(&table, &ada, &GameMove::DrawStock).is_err());
+    }
+
+    #[test]
+    fn moves_the_layout_doesnt_allow_are_rejected() {
+        let (mut rules, table, ada) = deal(Variant::Klondike);
+        let face_down = GameMove::MoveCards {
+            from: Zone::Tableau { index: 6 },
+            to: Zone::Cell { index: 0 },
+            count: 2,
+        };
+        assert!(rules.validate(&table, &ada, &face_down).is_err());
+        assert!(rules
+            .validate(&table, "someone else", &GameMove::DrawStock)
+            .is_err());
+        assert!(rules.validate(&table, &ada, &GameMove::UndoMove).is_err());
+
+        rules.layout.tableau[0] = vec![];
+        rules.layout.tableau[1] = cards("5H");
+        rules.layout.tableau[1][0].set_visible(true);
+        let not_a_king = GameMove::MoveCards {
+            from: Zone::Tableau { index: 1 },
+            to: Zone::Tableau { index: 0 },
+            count: 1,
+        };
+        assert!(rules.validate(&table, &ada, &not_a_king).is_err());
+        let not_an_ace = GameMove::MoveCards {
+            from: Zone::Tableau { index: 1 },
+            to: Zone::Foundation { index: 0 },
+            count: 1,
+        };
+        assert!(rules.validate(&table, &ada, &not_an_ace).is_err());
+
+        let (rules, table, ada) = deal(Variant::FreeCell);
+        assert!(rules.validate(&table, &ada, &GameMove::DrawStock).is_err());
+        let too_many = GameMove::MoveCards {
+            from: Zone::Tableau { index: 0 },
+            to: Zone::Tableau { index: 1 },
+            count: 6,
+        };
+        assert!(rules.validate(&table, &ada, &too_many).is_err());
+    }
+
+    #[test]
+    fn the_solver_wins_a_classic_deal() {
+        let (rules, _, _) = deal(Variant::FreeCell);
+        assert!(matches!(
+            solve(&rules.position()),
+            Solvability::Winnable { .. }
+        ));
+    }
+
+    #[test]
+    fn undo_takes_back_the_last_move() {
+        let (mut rules, mut table, ada) = deal(Variant::FreeCell);
+        let dealt = rules.layout.clone();
+        let to_cell = GameMove::MoveCards {
+            from: Zone::Tableau { index: 0 },
+            to: Zone::Cell { index: 0 },
+            count: 1,
+        };
+        play(&mut rules, &mut table, &ada, to_cell);
+        assert_ne!(rules.layout.tableau, dealt.tableau);
+        play(&mut rules, &mut table, &ada, GameMove::UndoMove);
+        assert_eq!(rules.layout.tableau, dealt.tableau);
+        assert_eq!(rules.layout.cells, dealt.cells);
+        assert_eq!(rules.moves, 2);
+        assert!(rules.validate(&table, &ada, &GameMove::UndoMove).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use eyre::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    restore_state, save_state,
+    trick::{ace_high_rank, deal, hand_of, playable_cards, TrickEngine, TrickView},
+    GameMove, GameRules, GameState, PlayerScore,
+};
+use crate::{
+    card::{Suite, Value},
+    table::Table,
+};
+
+const PLAYERS: usize = 4;
+const MAX_BID: u8 = 13;
+const NIL_POINTS: i64 = 100;
+const BAGS_PER_PENALTY: u32 = 10;
+const BAG_PENALTY: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[schemars(rename = "SpadesPhase")]
+enum Phase {
+    Waiting,
+    Bidding,
+    Playing,
+    RoundOver,
+    GameOver,
+}
+
+/// Seats across from each other play as partners.
+fn team_of(seat: usize) -> usize {
+    seat % 2
+}
+
+/// Spades for two partnerships. Everyone bids how many tricks they will take, a bid of zero is
+/// nil. Tricks over the partnership's bid are bags, and every ten bags costs a hundred points.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Spades {
+    target_score: i64,
+    phase: Phase,
+    engine: TrickEngine,
+    dealer: usize,
+    bids: Vec<Option<u8>>,
+    spades_broken: bool,
+    round_points: [i64; 2],
+    team_scores: [i64; 2],
+    bags: [u32; 2],
+}
+
+impl Spades {
+    pub const TARGET_SCORE: i64 = 500;
+
+    pub fn new(target_score: i64) -> Self {
+        Self {
+            target_score,
+            phase: Phase::Waiting,
+            engine: TrickEngine::new(vec![]),
+            dealer: 0,
+            bids: vec![],
+            spades_broken: false,
+            round_points: [0; 2],
+            team_scores: [0; 2],
+            bags: [0; 2],
+        }
+    }
+
+    fn end_round(&mut self) {
+        let mut round_points = [0i64; 2];
+        for (team, points) in round_points.iter_mut().enumerate() {
+            let seats = [team, team + 2];
+            let mut contract = 0;
+            let mut tricks = 0;
+            let mut bags = 0;
+            for seat in seats {
+                let taken = self.engine.tricks_won(seat) as u32;
+                match self.bids[seat] {
+                    Some(0) => {
+                        if taken == 0 {
+                            *points += NIL_POINTS;
+                        } else {
+                            *points -= NIL_POINTS;
+                            bags += taken;
+                        }
+                    }
+                    Some(bid) => {
+                        contract += bid as u32;
+                        tricks += taken;
+                    }
+                    None => {}
+                }
+            }
+            if tricks >= contract {
+                *points += 10 * contract as i64;
+                bags += tricks - contract;
+            } else {
+                *points -= 10 * contract as i64;
+            }
+            *points += bags as i64;
+            self.bags[team] += bags;
+            while self.bags[team] >= BAGS_PER_PENALTY {
+                self.bags[team] -= BAGS_PER_PENALTY;
+                *points -= BAG_PENALTY;
+            }
+            self.team_scores[team] += *points;
+        }
+        self.round_points = round_points;
+        self.dealer = (self.dealer + 1) % PLAYERS;
+        let game_over = self
+            .team_scores
+            .iter()
+            .any(|score| *score >= self.target_score);
+        self.phase = if game_over {
+            Phase::GameOver
+        } else {
+            Phase::RoundOver
+        };
+    }
+}
+
+impl GameRules for Spades {
+    fn setup(&mut self, table: &mut Table) -> Result<()> {
+        if self.phase == Phase::GameOver {
+            self.team_scores = [0; 2];
+            self.bags = [0; 2];
+            self.dealer = 0;
+        }
+        table.collect_cards();
+        let seats: Vec<String> = table
+            .players
+            .iter()
+            .take(PLAYERS)
+            .map(|player| player.id.clone())
+            .collect();
+        deal(table, &seats, 52 / PLAYERS)?;
+        self.engine = TrickEngine::new(seats);
+        self.engine.trump = Some(Suite::Spade);
+        let first = (self.dealer + 1) % PLAYERS;
+        self.engine.start(first);
+        self.bids = vec![None; PLAYERS];
+        self.spades_broken = false;
+        self.round_points = [0; 2];
+        self.phase = Phase::Bidding;
+        Ok(())
+    }
+
+    fn validate(&self, table: &Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::StartRound => {
+                if matches!(self.phase, Phase::Bidding | Phase::Playing) {
+                    bail!("The round isn't over yet");
+                }
+                if table.players.len() < PLAYERS {
+                    bail!("Spades needs four players");
+                }
+            }
+            GameMove::Bid { tricks } => {
+                if self.phase != Phase::Bidding {
+                    bail!("Bidding is over");
+                }
+                if self.engine.seat_of(player_id) != Some(self.engine.turn) {
+                    bail!("It isn't your turn to bid");
+                }
+                if *tricks > MAX_BID {
+                    bail!("You can't bid more than thirteen tricks");
+                }
+            }
+            GameMove::PlayCard { card } => {
+                if self.phase != Phase::Playing {
+                    bail!("There is no hand being played");
+                }
+                self.engine.validate_play(table, player_id, card)?;
+                if self.engine.is_leading()
+                    && card.suite == Suite::Spade
+                    && !self.spades_broken
+                    && hand_of(table, player_id)
+                        .iter()
+                        .any(|held| held.suite != Suite::Spade)
+                {
+                    bail!("Spades haven't been broken yet");
+                }
+            }
+            _ => bail!("That move isn't part of spades"),
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, table: &mut Table, player_id: &str, game_move: &GameMove) -> Result<()> {
+        match game_move {
+            GameMove::Bid { tricks } => {
+                if let Some(seat) = self.engine.seat_of(player_id) {
+                    self.bids[seat] = Some(*tricks);
+                    self.engine.turn = self.engine.next_seat(seat);
+                }
+                if self.bids.iter().all(Option::is_some) {
+                    self.phase = Phase::Playing;
+                }
+            }
+            GameMove::PlayCard { card } => {
+                if card.suite == Suite::Spade {
+                    self.spades_broken = true;
+                }
+                if self.engine.play(table, card)?.is_some() && self.engine.hands_empty(table) {
+                    self.end_round();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_round_over(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::RoundOver | Phase::GameOver)
+    }
+
+    fn target_score(&self) -> Option<i64> {
+        Some(self.target_score)
+    }
+
+    /// Partners share their points, so both of them are given the partnership's score.
+    fn score(&self, _table: &Table) -> Vec<PlayerScore> {
+        self.engine
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(seat, player_id)| PlayerScore {
+                player_id: player_id.clone(),
+                points: self.round_points[team_of(seat)],
+            })
+            .collect()
+    }
+
+    fn in_progress(&self, _table: &Table) -> bool {
+        matches!(self.phase, Phase::Bidding | Phase::Playing)
+    }
+
+    fn view(&self, table: &Table, player_id: &str) -> Option<GameState> {
+        let teams = (0..2)
+            .map(|team| TeamView {
+                player_ids: self
+                    .engine
+                    .seats
+                    .iter()
+                    .enumerate()
+                    .filter(|(seat, _)| team_of(*seat) == team)
+                    .map(|(_, player_id)| player_id.clone())
+                    .collect(),
+                score: self.team_scores[team],
+                bags: self.bags[team],
+            })
+            .collect();
+        let view = SpadesView {
+            phase: self.phase,
+            trick: self.engine.view(table, player_id),
+            dealer: self.engine.seats.get(self.dealer).cloned(),
+            bids: self.bids.clone(),
+            spades_broken: self.spades_broken,
+            teams,
+            round_points: self.score(table),
+            target_score: self.target_score,
+        };
+        Some(GameState::Spades(view))
+    }
+
+    fn player_left(&mut self, table: &mut Table, player_id: &str) -> Result<()> {
+        if self.engine.seat_of(player_id).is_some() && self.in_progress(table) {
+            self.phase = Phase::Waiting;
+        }
+        Ok(())
+    }
+
+    fn hint(&self, table: &Table, player_id: &str) -> Option<GameMove> {
+        let seat = self.engine.seat_of(player_id)?;
+        match self.phase {
+            Phase::Bidding => {
+                // count on the aces and kings, and on the high spades
+                let sure_tricks = hand_of(table, player_id)
+                    .iter()
+                    .filter(|card| {
+                        ace_high_rank(card.value) >= ace_high_rank(Value::King)
+                            || (card.suite == Suite::Spade
+                                && ace_high_rank(card.value) >= ace_high_rank(Value::Queen))
+                    })
+                    .count() as u8;
+                Some(GameMove::Bid {
+                    tricks: sure_tricks.max(1),
+                })
+            }
+            Phase::Playing => {
+                let partner_winning = self
+                    .engine
+                    .winning_seat()
+                    .is_some_and(|winner| team_of(winner) == team_of(seat));
+                self.engine
+                    .pick_card(&playable_cards(self, table, player_id), !partner_winning)
+                    .map(|card| GameMove::PlayCard { card })
+            }
+            _ => None,
+        }
+    }
+
+    fn save(&self) -> Result<serde_json::Value> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        restore_state(self, state)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SpadesView {
+    phase: Phase,
+    #[serde(flatten)]
+    trick: TrickView,
+    dealer: Option<String>,
+    bids: Vec<Option<u8>>,
+    spades_broken: bool,
+    teams: Vec<TeamView>,
+    round_points: Vec<PlayerScore>,
+    target_score: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct TeamView {
+    player_ids: Vec<String>,
+    score: i64,
+    bags: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        outbox::OutboxReceiver,
+        rules::{play_hints, test_table},
+    };
+
+    fn deal_round(seed: u64) -> (Spades, Table, Vec<String>, Vec<OutboxReceiver>) {
+        let (mut table, ids, receivers) = test_table(PLAYERS, 0, seed);
+        let mut rules = Spades::new(Spades::TARGET_SCORE);
+        rules
+            .validate(&table, &ids[0], &GameMove::StartRound)
+            .unwrap();
+        rules.setup(&mut table).unwrap();
+        (rules, table, ids, receivers)
+    }
+
+    #[test]
+    fn a_round_is_bid_and_played_out_for_all_thirteen_tricks() {
+        for seed in 0..5 {
+            let (mut rules, mut table, _, _receivers) = deal_round(seed);
+            assert_eq!(play_hints(&mut rules, &mut table), 4 + 52);
+            assert!(rules.is_round_over(&table));
+            let tricks: usize = (0..PLAYERS).map(|seat| rules.engine.tricks_won(seat)).sum();
+            assert_eq!(tricks, 13);
+            assert_eq!(rules.team_scores, rules.round_points);
+            let scores = rules.score(&table);
+            assert_eq!(scores[0].points, scores[2].points);
+            assert_eq!(scores[1].points, scores[3].points);
+        }
+    }
+
+    #[test]
+    fn bids_and_leads_that_break_the_rules_are_rejected() {
+        let (mut rules, mut table, ids, _receivers) = deal_round(0);
+        // the seat after the dealer bids first
+        let too_many = GameMove::Bid { tricks: 14 };
+        assert!(rules.validate(&table, &ids[1], &too_many).is_err());
+        let bid = GameMove::Bid { tricks: 3 };
+        assert!(rules.validate(&table, &ids[0], &bid).is_err());
+        let early = GameMove::PlayCard {
+            card: hand_of(&table, &ids[1])[0],
+        };
+        assert!(rules.validate(&table, &ids[1], &early).is_err());
+
+        for seat in [1, 2, 3, 0] {
+            rules.validate(&table, &ids[seat], &bid).unwrap();
+            rules.apply(&mut table, &ids[seat], &bid).unwrap();
+        }
+        assert!(rules.validate(&table, &ids[1], &bid).is_err());
+        let hand = hand_of(&table, &ids[1]);
+        if let (Some(spade), true) = (
+            hand.iter().find(|card| card.suite == Suite::Spade),
+            hand.iter().any(|card| card.suite != Suite::Spade),
+        ) {
+            let lead = GameMove::PlayCard { card: *spade };
+            assert!(rules.validate(&table, &ids[1], &lead).is_err());
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use eyre::{bail, eyre, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{GameMove, GameRules};
+use crate::{
+    card::{Card, CardData, Suite, Value},
+    table::Table,
+};
+
+/// The parts every trick-taking game shares: whose turn it is, which suit has to be followed,
+/// who won each trick and the piles of tricks everyone has taken.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrickEngine {
+    pub seats: Vec<String>,
+    pub trump: Option<Suite>,
+    /// Euchre's bowers: the jack of trump ranks highest, followed by the other jack of the same
+    /// colour, which counts as a trump card too.
+    pub bowers: bool,
+    pub sitting_out: Option<usize>,
+    pub leader: usize,
+    pub turn: usize,
+    pub current: Vec<(usize, Card)>,
+    pub last_trick: Vec<(usize, Card)>,
+    pub piles: Vec<Vec<Card>>,
+}
+
+impl TrickEngine {
+    pub fn new(seats: Vec<String>) -> Self {
+        let piles = seats.iter().map(|_| vec![]).collect();
+        Self {
+            seats,
+            trump: None,
+            bowers: false,
+            sitting_out: None,
+            leader: 0,
+            turn: 0,
+            current: vec![],
+            last_trick: vec![],
+            piles,
+        }
+    }
+
+    pub fn seat_of(&self, player_id: &str) -> Option<usize> {
+        self.seats.iter().position(|seat| seat == player_id)
+    }
+
+    /// The seat after `seat` that is taking part in this hand.
+    pub fn next_seat(&self, seat: usize) -> usize {
+        let mut next = (seat + 1) % self.seats.len();
+        if Some(next) == self.sitting_out {
+            next = (next + 1) % self.seats.len();
+        }
+        next
+    }
+
+    pub fn active_seats(&self) -> usize {
+        self.seats.len() - self.sitting_out.map_or(0, |_| 1)
+    }
+
+    pub fn start(&mut self, leader: usize) {
+        self.leader = leader;
+        self.turn = leader;
+        self.current.clear();
+        self.last_trick.clear();
+        for pile in &mut self.piles {
+            pile.clear();
+        }
+    }
+
+    pub fn tricks_won(&self, seat: usize) -> usize {
+        self.piles[seat].len() / self.active_seats()
+    }
+
+    pub fn effective_suit(&self, card: &Card) -> Suite {
+        match self.trump {
+            Some(trump)
+                if self.bowers && card.value == Value::Jack && same_colour(card.suite, trump) =>
+            {
+                trump
+            }
+            _ => card.suite,
+        }
+    }
+
+    fn trick_rank(&self, card: &Card) -> u8 {
+        if self.bowers && card.value == Value::Jack {
+            if let Some(trump) = self.trump {
+                if card.suite == trump {
+                    return 16;
+                }
+                if same_colour(card.suite, trump) {
+                    return 15;
+                }
+            }
+        }
+        ace_high_rank(card.value)
+    }
+
+    pub fn led_suit(&self) -> Option<Suite> {
+        self.current
+            .first()
+            .map(|(_, card)| self.effective_suit(card))
+    }
+
+    pub fn is_leading(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    /// Checks that it's the player's turn, that they hold the card, and that they follow suit
+    /// when they can.
+    pub fn validate_play(&self, table: &Table, player_id: &str, card: &Card) -> Result<()> {
+        let seat = self
+            .seat_of(player_id)
+            .ok_or_else(|| eyre!("You aren't playing this hand"))?;
+        if seat != self.turn {
+            bail!("It isn't your turn");
+        }
+        let hand = hand_of(table, player_id);
+        if !holds(hand, card) {
+            bail!("That card isn't in your hand");
+        }
+        if let Some(led_suit) = self.led_suit() {
+            let can_follow = hand
+                .iter()
+                .any(|held| self.effective_suit(held) == led_suit);
+            if can_follow && self.effective_suit(card) != led_suit {
+                bail!("You have to follow suit");
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the card from the player's hand onto the trick. Once everyone has played, the trick
+    /// goes to the winner's pile and the seat of the winner is returned, who leads next.
+    pub fn play(&mut self, table: &mut Table, card: &Card) -> Result<Option<usize>> {
+        let seat = self.turn;
+        let mut played = table
+            .player_mut(&self.seats[seat])
+            .and_then(|player| player.discard_card(card))
+            .ok_or_else(|| eyre!("That card isn't in your hand"))?;
+        played.set_visible(true);
+        self.current.push((seat, played));
+
+        if self.current.len() < self.active_seats() {
+            self.turn = self.next_seat(seat);
+            return Ok(None);
+        }
+
+        let winner = self.trick_winner();
+        self.last_trick = std::mem::take(&mut self.current);
+        self.piles[winner].extend(self.last_trick.iter().map(|(_, card)| *card));
+        self.leader = winner;
+        self.turn = winner;
+        Ok(Some(winner))
+    }
+
+    /// How strongly a card plays in the current trick, trumps beat the led suit and anything
+    /// else can't win at all.
+    fn strength(&self, card: &Card, led_suit: Option<Suite>) -> (u8, u8) {
+        let suit = self.effective_suit(card);
+        let priority = if Some(suit) == self.trump {
+            2
+        } else if Some(suit) == led_suit {
+            1
+        } else {
+            0
+        };
+        (priority, self.trick_rank(card))
+    }
+
+    fn trick_winner(&self) -> usize {
+        self.winning_seat()
+            .expect("a finished trick has cards in it")
+    }
+
+    /// Who is winning the trick being played so far.
+    pub fn winning_seat(&self) -> Option<usize> {
+        let led_suit = self.led_suit();
+        self.current
+            .iter()
+            .max_by_key(|(_, card)| self.strength(card, led_suit))
+            .map(|(seat, _)| *seat)
+    }
+
+    /// A simple way to play a trick for bots. Wanting the trick means taking it as cheaply as
+    /// possible, otherwise it's ducking under the winning card or throwing away the highest card.
+    pub fn pick_card(&self, playable: &[Card], want_trick: bool) -> Option<Card> {
+        let led_suit = self.led_suit();
+        let mut by_strength = playable.to_vec();
+        by_strength.sort_by_key(|card| {
+            self.strength(card, led_suit.or_else(|| Some(self.effective_suit(card))))
+        });
+        let best = self
+            .current
+            .iter()
+            .map(|(_, card)| self.strength(card, led_suit))
+            .max();
+        let wins = |card: &&Card| best.is_none_or(|best| self.strength(card, led_suit) > best);
+        if want_trick {
+            if self.is_leading() {
+                by_strength.last().copied()
+            } else {
+                by_strength
+                    .iter()
+                    .find(wins)
+                    .or(by_strength.first())
+                    .copied()
+            }
+        } else if self.is_leading() {
+            by_strength.first().copied()
+        } else {
+            by_strength
+                .iter()
+                .rev()
+                .find(|card| !wins(card))
+                .or(by_strength.first())
+                .copied()
+        }
+    }
+
+    /// Whether every player taking part has played out their hand.
+    pub fn hands_empty(&self, table: &Table) -> bool {
+        self.seats
+            .iter()
+            .enumerate()
+            .filter(|(seat, _)| Some(*seat) != self.sitting_out)
+            .all(|(_, player_id)| hand_of(table, player_id).is_empty())
+    }
+
+    pub fn view(&self, table: &Table, player_id: &str) -> TrickView {
+        TrickView {
+            seats: self
+                .seats
+                .iter()
+                .enumerate()
+                .map(|(seat, seat_player_id)| SeatView {
+                    player_id: seat_player_id.clone(),
+                    cards_in_hand: hand_of(table, seat_player_id).len(),
+                    tricks_won: self.tricks_won(seat),
+                    sitting_out: Some(seat) == self.sitting_out,
+                })
+                .collect(),
+            hand: hand_of(table, player_id)
+                .iter()
+                .map(|card| card.revealed_card_data())
+                .collect(),
+            trump: self.trump,
+            turn: self.seats.get(self.turn).cloned(),
+            current_trick: played_cards(&self.seats, &self.current),
+            last_trick: played_cards(&self.seats, &self.last_trick),
+        }
+    }
+}
+
+/// The cards in a player's hand the rules would let them play right now.
+pub fn playable_cards<R: GameRules + ?Sized>(
+    rules: &R,
+    table: &Table,
+    player_id: &str,
+) -> Vec<Card> {
+    hand_of(table, player_id)
+        .iter()
+        .filter(|card| {
+            rules
+                .validate(table, player_id, &GameMove::PlayCard { card: **card })
+                .is_ok()
+        })
+        .copied()
+        .collect()
+}
+
+pub fn same_colour(first: Suite, second: Suite) -> bool {
+    let red = |suite| matches!(suite, Suite::Heart | Suite::Diamond);
+    red(first) == red(second)
+}
+
+pub fn ace_high_rank(value: Value) -> u8 {
+    match value {
+        Value::Two => 2,
+        Value::Three => 3,
+        Value::Four => 4,
+        Value::Five => 5,
+        Value::Six => 6,
+        Value::Seven => 7,
+        Value::Eight => 8,
+        Value::Nine => 9,
+        Value::Ten => 10,
+        Value::Jack => 11,
+        Value::Queen => 12,
+        Value::King => 13,
+        Value::Ace => 14,
+    }
+}
+
+pub fn hand_of<'a>(table: &'a Table, player_id: &str) -> &'a [Card] {
+    table
+        .player(player_id)
+        .map(|player| player.hand.as_slice())
+        .unwrap_or(&[])
+}
+
+pub fn holds(hand: &[Card], card: &Card) -> bool {
+    hand.iter()
+        .any(|held| held.suite == card.suite && held.value == card.value)
+}
+
+/// Deals the draw deck out one card at a time, face down, until everyone has `count` cards.
+pub fn deal(table: &mut Table, seats: &[String], count: usize) -> Result<()> {
+    for _ in 0..count {
+        for player_id in seats {
+            let card = table
+                .draw_deck
+                .pop()
+                .ok_or_else(|| eyre!("The deck ran out of cards"))?;
+            if let Some(player) = table.player_mut(player_id) {
+                player.add_card(card);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn played_cards(seats: &[String], plays: &[(usize, Card)]) -> Vec<PlayedCard> {
+    plays
+        .iter()
+        .map(|(seat, card)| PlayedCard {
+            player_id: seats[*seat].clone(),
+            card: card.card_data(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TrickView {
+    seats: Vec<SeatView>,
+    hand: Vec<CardData>,
+    trump: Option<Suite>,
+    turn: Option<String>,
+    current_trick: Vec<PlayedCard>,
+    last_trick: Vec<PlayedCard>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[schemars(rename = "TrickSeatView")]
+struct SeatView {
+    player_id: String,
+    cards_in_hand: usize,
+    tricks_won: usize,
+    sitting_out: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+struct PlayedCard {
+    player_id: String,
+    card: CardData,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::test_table;
+
+    #[test]
+    fn the_left_bower_follows_trump_and_beats_the_ace() {
+        let (mut table, ids, _receivers) = test_table(2, 0, 0);
+        let nine = Card::new(Suite::Heart, Value::Nine);
+        let ace = Card::new(Suite::Heart, Value::Ace);
+        let left_bower = Card::new(Suite::Diamond, Value::Jack);
+        let ten = Card::new(Suite::Diamond, Value::Ten);
+        for (player, hand) in table
+            .players
+            .iter_mut()
+            .zip([vec![nine, ace], vec![left_bower, ten]])
+        {
+            for card in hand {
+                player.add_card(card);
+            }
+        }
+        let mut engine = TrickEngine::new(ids.clone());
+        engine.trump = Some(Suite::Heart);
+        engine.bowers = true;
+        engine.start(0);
+
+        assert!(engine.validate_play(&table, &ids[1], &left_bower).is_err());
+        engine.validate_play(&table, &ids[0], &nine).unwrap();
+        assert_eq!(engine.play(&mut table, &nine).unwrap(), None);
+        // the jack of diamonds is a heart while hearts are trump, so it has to be played
+        assert!(engine.validate_play(&table, &ids[1], &ten).is_err());
+        assert!(engine.validate_play(&table, &ids[1], &ace).is_err());
+        engine.validate_play(&table, &ids[1], &left_bower).unwrap();
+        assert_eq!(engine.play(&mut table, &left_bower).unwrap(), Some(1));
+        assert_eq!(engine.tricks_won(1), 1);
+        assert_eq!(engine.turn, 1);
+    }
+}
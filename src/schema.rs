@@ -0,0 +1,229 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use eyre::Result;
+use schemars::{schema_for, Schema};
+use serde_json::Value;
+
+use crate::message::{ClientMessage, ServerMessage};
+
+/// Where the generated schema is committed, relative to the repository root.
+pub const SCHEMA_DIR: &str = "schema";
+
+/// The files generated from the message types: a JSON Schema for each direction, and the same
+/// types as TypeScript definitions.
+pub fn schema_files() -> Vec<(&'static str, String)> {
+    let client = schema_for!(ClientMessage);
+    let server = schema_for!(ServerMessage);
+    vec![
+        ("client_message.schema.json", pretty_json(&client)),
+        ("server_message.schema.json", pretty_json(&server)),
+        ("protocol.d.ts", typescript(&[&client, &server])),
+    ]
+}
+
+pub fn write_schema(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, contents) in schema_files() {
+        fs::write(dir.join(name), contents)?;
+        println!("wrote {}", dir.join(name).display());
+    }
+    Ok(())
+}
+
+fn pretty_json(schema: &Schema) -> String {
+    let mut json = serde_json::to_string_pretty(schema).unwrap();
+    json.push('\n');
+    json
+}
+
+/// Writes every definition in the schemas, and the schemas themselves, as TypeScript types.
+fn typescript(roots: &[&Schema]) -> String {
+    let mut definitions = BTreeMap::new();
+    for root in roots {
+        if let Some(Value::Object(defs)) = root.get("$defs") {
+            definitions.extend(
+                defs.iter()
+                    .map(|(name, schema)| (name.clone(), schema.clone())),
+            );
+        }
+        let name = root
+            .get("title")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned();
+        definitions.insert(name, root.as_value().clone());
+    }
+
+    let mut output =
+        String::from("// Generated from the server's message types, don't edit by hand.\n");
+    for (name, schema) in &definitions {
+        output.push('\n');
+        output.push_str(&doc_comment(schema, 0));
+        let ts_type = ts_type(schema, 0);
+        let separator = if ts_type.starts_with('\n') { "" } else { " " };
+        output.push_str(&format!(
+            "export type {} ={}{};\n",
+            name, separator, ts_type
+        ));
+    }
+    output
+}
+
+fn doc_comment(schema: &Value, indent: usize) -> String {
+    let description = match schema.get("description").and_then(Value::as_str) {
+        Some(description) => description,
+        None => return String::new(),
+    };
+    let padding = "  ".repeat(indent);
+    let mut comment = format!("{}/**\n", padding);
+    for line in description.lines() {
+        comment.push_str(&format!("{} * {}\n", padding, line).replace(" * \n", " *\n"));
+    }
+    comment.push_str(&format!("{} */\n", padding));
+    comment
+}
+
+fn ts_type(schema: &Value, indent: usize) -> String {
+    if schema == &Value::Bool(true) {
+        return "unknown".to_owned();
+    }
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.trim_start_matches("#/$defs/").to_owned();
+        // internally tagged variants keep their tag next to the type they wrap
+        return match schema.get("properties") {
+            Some(_) => format!("{} & {}", ts_object(schema, indent), name),
+            None => name,
+        };
+    }
+    if let Some(constant) = schema.get("const") {
+        return constant.to_string();
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        return values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    if let Some(Value::Array(variants)) = schema.get("anyOf") {
+        return union(variants, indent);
+    }
+
+    let object = schema.get("properties").map(|_| ts_object(schema, indent));
+    let variants = match schema.get("oneOf") {
+        Some(Value::Array(variants)) => Some(union(variants, indent)),
+        _ => None,
+    };
+    match (object, variants) {
+        (Some(object), Some(variants)) => return format!("{} & ({})", object, variants),
+        (Some(object), None) => return object,
+        (None, Some(variants)) => return variants,
+        (None, None) => {}
+    }
+
+    match schema.get("type") {
+        Some(Value::Array(types)) => types
+            .iter()
+            .map(|json_type| primitive(json_type.as_str().unwrap_or_default(), schema, indent))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        Some(Value::String(json_type)) => primitive(json_type, schema, indent),
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn primitive(json_type: &str, schema: &Value, indent: usize) -> String {
+    match json_type {
+        "string" => "string".to_owned(),
+        "integer" | "number" => "number".to_owned(),
+        "boolean" => "boolean".to_owned(),
+        "null" => "null".to_owned(),
+        "array" => match schema.get("items") {
+            Some(items) => format!("Array<{}>", ts_type(items, indent)),
+            None => "Array<unknown>".to_owned(),
+        },
+        "object" => match schema.get("additionalProperties") {
+            Some(values) if values.is_object() => {
+                format!("Record<string, {}>", ts_type(values, indent))
+            }
+            _ => "Record<string, unknown>".to_owned(),
+        },
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn union(variants: &[Value], indent: usize) -> String {
+    let padding = "  ".repeat(indent + 1);
+    let members: Vec<String> = variants
+        .iter()
+        .map(|variant| ts_type(variant, indent + 1))
+        .collect();
+    // short unions like `T | null` read better on one line
+    if variants.len() <= 2 && members.iter().all(|member| !member.contains('\n')) {
+        return members.join(" | ");
+    }
+    variants
+        .iter()
+        .zip(members)
+        .map(|(variant, member)| {
+            format!(
+                "\n{}{}| {}",
+                doc_comment(variant, indent + 1),
+                padding,
+                member
+            )
+        })
+        .collect()
+}
+
+fn ts_object(schema: &Value, indent: usize) -> String {
+    let required: Vec<&str> = match schema.get("required") {
+        Some(Value::Array(required)) => required.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    let properties = match schema.get("properties") {
+        Some(Value::Object(properties)) => properties,
+        _ => return "{}".to_owned(),
+    };
+    let padding = "  ".repeat(indent + 1);
+    let mut object = String::from("{\n");
+    for (name, property) in properties {
+        let optional = if required.contains(&name.as_str()) {
+            ""
+        } else {
+            "?"
+        };
+        object.push_str(&doc_comment(property, indent + 1));
+        object.push_str(&format!(
+            "{}{}{}: {};\n",
+            padding,
+            name,
+            optional,
+            ts_type(property, indent + 1)
+        ));
+    }
+    object.push_str(&"  ".repeat(indent));
+    object.push('}');
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn committed_schema_is_up_to_date() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SCHEMA_DIR);
+        for (name, contents) in schema_files() {
+            let committed = fs::read_to_string(dir.join(name)).unwrap_or_default();
+            assert!(
+                committed == contents,
+                "{}/{} is out of date, regenerate it with `cargo run -- --write-schema`",
+                SCHEMA_DIR,
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::rules::PlayerScore;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct RoundScores {
+    pub round: usize,
+    pub deltas: Vec<PlayerScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ScoreboardData {
+    pub target_score: Option<i64>,
+    pub totals: Vec<PlayerScore>,
+    pub rounds: Vec<RoundScores>,
+    pub winners: Vec<String>,
+}
+
+/// Running totals for a match that lasts several rounds. The match ends once anyone reaches
+/// the target score, and the next round recorded after that starts a new match. Everyone tied
+/// on the best total shares the win, which is also how partners win together.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Scoreboard {
+    target_score: Option<i64>,
+    low_score_wins: bool,
+    rounds: Vec<RoundScores>,
+    totals: Vec<PlayerScore>,
+    #[serde(default)]
+    winners: Vec<String>,
+}
+
+impl Scoreboard {
+    pub fn new(target_score: Option<i64>, low_score_wins: bool) -> Self {
+        Self {
+            target_score,
+            low_score_wins,
+            ..Self::default()
+        }
+    }
+
+    pub fn record_round(&mut self, deltas: Vec<PlayerScore>) {
+        if !self.winners.is_empty() {
+            self.rounds.clear();
+            self.totals.clear();
+            self.winners.clear();
+        }
+
+        for delta in &deltas {
+            match self
+                .totals
+                .iter_mut()
+                .find(|total| total.player_id == delta.player_id)
+            {
+                Some(total) => total.points += delta.points,
+                None => self.totals.push(delta.clone()),
+            }
+        }
+        self.rounds.push(RoundScores {
+            round: self.rounds.len() + 1,
+            deltas,
+        });
+
+        if let Some(target_score) = self.target_score {
+            if self.totals.iter().any(|total| total.points >= target_score) {
+                self.winners = self.leaders();
+            }
+        }
+    }
+
+    fn leaders(&self) -> Vec<String> {
+        let points = self.totals.iter().map(|total| total.points);
+        let best = if self.low_score_wins {
+            points.min()
+        } else {
+            points.max()
+        };
+        self.totals
+            .iter()
+            .filter(|total| Some(total.points) == best)
+            .map(|total| total.player_id.clone())
+            .collect()
+    }
+
+    pub fn scoreboard_data(&self) -> ScoreboardData {
+        ScoreboardData {
+            target_score: self.target_score,
+            totals: self.totals.clone(),
+            rounds: self.rounds.clone(),
+            winners: self.winners.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(points: &[(&str, i64)]) -> Vec<PlayerScore> {
+        points
+            .iter()
+            .map(|(player_id, points)| PlayerScore {
+                player_id: player_id.to_string(),
+                points: *points,
+            })
+            .collect()
+    }
+
+    fn total(scoreboard: &Scoreboard, player_id: &str) -> i64 {
+        scoreboard
+            .totals
+            .iter()
+            .find(|total| total.player_id == player_id)
+            .map(|total| total.points)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn a_match_ends_at_the_target_and_the_next_round_starts_another() {
+        let mut scoreboard = Scoreboard::new(Some(100), false);
+        scoreboard.record_round(round(&[("ada", 60), ("bea", 30)]));
+        assert!(scoreboard.winners.is_empty());
+        scoreboard.record_round(round(&[("ada", 20), ("bea", 80)]));
+        assert_eq!(total(&scoreboard, "ada"), 80);
+        assert_eq!(total(&scoreboard, "bea"), 110);
+        assert_eq!(scoreboard.winners, ["bea"]);
+        let data = scoreboard.scoreboard_data();
+        assert_eq!(data.rounds.len(), 2);
+        assert_eq!(data.rounds[1].round, 2);
+
+        scoreboard.record_round(round(&[("ada", 5), ("bea", 0)]));
+        assert!(scoreboard.winners.is_empty());
+        assert_eq!(scoreboard.rounds.len(), 1);
+        assert_eq!(total(&scoreboard, "ada"), 5);
+    }
+
+    #[test]
+    fn a_tie_at_the_target_is_a_shared_win() {
+        let mut scoreboard = Scoreboard::new(Some(100), false);
+        scoreboard.record_round(round(&[("ada", 100), ("bea", 100), ("cy", 40)]));
+        assert_eq!(scoreboard.winners, ["ada", "bea"]);
+
+        let mut scoreboard = Scoreboard::new(Some(100), true);
+        scoreboard.record_round(round(&[("ada", 100), ("bea", 20), ("cy", 20)]));
+        assert_eq!(scoreboard.winners, ["bea", "cy"]);
+    }
+
+    #[test]
+    fn the_lowest_total_wins_games_like_hearts() {
+        let mut scoreboard = Scoreboard::new(Some(100), true);
+        scoreboard.record_round(round(&[("ada", 26), ("bea", 0), ("cy", 0)]));
+        scoreboard.record_round(round(&[("ada", 80), ("bea", 10), ("cy", 16)]));
+        assert_eq!(scoreboard.winners, ["bea"]);
+    }
+
+    #[test]
+    fn without_a_target_nobody_ever_wins() {
+        let mut scoreboard = Scoreboard::new(None, false);
+        for _ in 0..5 {
+            scoreboard.record_round(round(&[("ada", 1000), ("bea", -50)]));
+        }
+        assert!(scoreboard.winners.is_empty());
+        assert_eq!(total(&scoreboard, "ada"), 5000);
+        assert_eq!(total(&scoreboard, "bea"), -250);
+        // players who only join later start from what they score
+        scoreboard.record_round(round(&[("cy", 7)]));
+        assert_eq!(total(&scoreboard, "cy"), 7);
+        assert_eq!(scoreboard.rounds.len(), 6);
+    }
+}
@@ -0,0 +1,234 @@
+use eyre::{bail, Result};
+use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    actions::ServerAction,
+    card::{Card, DeckSpec},
+    event_log::EventLog,
+    message::ServerMessage,
+    player::Player,
+};
+
+/// Everything physically on the table: who is sitting at it, and where the cards are.
+#[derive(Debug)]
+pub struct Table {
+    pub players: Vec<Player>,
+    pub draw_deck: Vec<Card>,
+    pub discard_deck: Vec<Card>,
+    pub deck: DeckSpec,
+    /// The player whose request is being handled and the id they gave it, which direct
+    /// replies to them echo back.
+    requester: Option<(String, Option<String>)>,
+    /// Everything broadcast to the room, numbered for clients that need to catch up.
+    pub events: EventLog,
+    /// Every shuffle is seeded from this and how many shuffles came before it, so the same
+    /// moves always deal the same cards.
+    seed: u64,
+    pub shuffles: u64,
+}
+
+impl Table {
+    pub fn new(deck: DeckSpec, seed: u64) -> Self {
+        let mut table = Self {
+            players: vec![],
+            draw_deck: vec![],
+            discard_deck: vec![],
+            deck,
+            requester: None,
+            events: EventLog::default(),
+            seed,
+            shuffles: 0,
+        };
+        table.reset_draw_deck();
+        table
+    }
+
+    pub fn player(&self, player_id: &str) -> Option<&Player> {
+        self.players.iter().find(|player| player.id == player_id)
+    }
+
+    pub fn player_mut(&mut self, player_id: &str) -> Option<&mut Player> {
+        self.players
+            .iter_mut()
+            .find(|player| player.id == player_id)
+    }
+
+    pub fn chips(&self, player_id: &str) -> u64 {
+        self.player(player_id)
+            .map(|player| player.chips)
+            .unwrap_or_default()
+    }
+
+    pub fn add_chips(&mut self, player_id: &str, amount: u64) {
+        if let Some(player) = self.player_mut(player_id) {
+            player.chips += amount;
+        }
+    }
+
+    pub fn take_chips(&mut self, player_id: &str, amount: u64) -> Result<()> {
+        match self.player_mut(player_id) {
+            Some(player) if player.chips >= amount => {
+                player.chips -= amount;
+                Ok(())
+            }
+            _ => bail!("You don't have that many chips"),
+        }
+    }
+
+    pub fn set_requester(&mut self, player_id: &str, request_id: Option<String>) {
+        self.requester = Some((player_id.to_owned(), request_id));
+    }
+
+    /// For messages that aren't a reply to anything.
+    pub fn clear_requester(&mut self) {
+        self.requester = None;
+    }
+
+    /// Wraps a message for one player, with the request id when it's a reply to their request.
+    pub fn message_for(&self, player_id: &str, action: ServerAction) -> ServerMessage {
+        match &self.requester {
+            Some((requester, request_id)) if requester == player_id => {
+                ServerMessage::reply(request_id.clone(), action)
+            }
+            _ => ServerMessage::from(action),
+        }
+    }
+
+    pub fn send_to_player(&mut self, player_id: &str, action: ServerAction) -> Result<()> {
+        let message = self.message_for(player_id, action);
+        if let Some(player) = self.player_mut(player_id) {
+            // players waiting to reclaim their seat aren't connected to anything
+            player.send(message).ok();
+        }
+        Ok(())
+    }
+
+    pub fn broadcast_to_room(&mut self, action: ServerAction) -> Result<()> {
+        let message = self.events.record(action, None);
+        for player in &mut self.players {
+            // a connection that was dropped for falling behind shouldn't stop everyone else
+            // hearing about it
+            player.send(message.clone()).ok();
+        }
+        Ok(())
+    }
+
+    pub fn broadcast_to_everyone_else(
+        &mut self,
+        action: ServerAction,
+        player_id: &str,
+    ) -> Result<()> {
+        let message = self.events.record(action, Some(player_id));
+        for player in &mut self.players {
+            if player.id != player_id {
+                player.send(message.clone()).ok();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn draw_card(&mut self, player_id: &str) -> Result<()> {
+        let card = if let Some(card) = self.draw_deck.pop() {
+            card
+        } else {
+            return Ok(());
+        };
+
+        let draw_deck_size = self.draw_deck.len();
+        let player = if let Some(player) = self.player_mut(player_id) {
+            player
+        } else {
+            return Ok(());
+        };
+
+        player.add_card(card);
+        self.send_to_player(
+            player_id,
+            ServerAction::DrawCard {
+                card,
+                draw_deck_size,
+            },
+        )?;
+        self.broadcast_to_room(ServerAction::DrawDeckUpdated {
+            player_id: player_id.to_owned(),
+            draw_deck_size,
+        })?;
+        Ok(())
+    }
+
+    pub fn toggle_visibility_of_card(&mut self, player_id: &str, card: &Card) -> Result<()> {
+        let player = if let Some(player) = self.player_mut(player_id) {
+            player
+        } else {
+            return Ok(());
+        };
+
+        if let Some(card) = player.toggle_visibility_of_card(card) {
+            let message_to_all_players = ServerAction::ToggleVisibilityOfCard {
+                player_id: player.id.clone(),
+                card,
+            };
+            self.broadcast_to_room(message_to_all_players)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn discard_card(&mut self, player_id: &str, card: &Card) -> Result<()> {
+        let player = if let Some(player) = self.player_mut(player_id) {
+            player
+        } else {
+            return Ok(());
+        };
+        if let Some(mut discarded_card) = player.discard_card(card) {
+            // everyone sees what's thrown on the pile
+            discarded_card.set_visible(true);
+            let message_to_all_players = ServerAction::DiscardCard {
+                player_id: player.id.clone(),
+                card: discarded_card,
+                hand: player.hand.iter().map(|card| card.card_data()).collect(),
+            };
+            self.discard_deck.push(discarded_card);
+            self.broadcast_to_room(message_to_all_players)?;
+        }
+        Ok(())
+    }
+
+    pub fn reset_deck(&mut self) -> Result<()> {
+        self.collect_cards();
+        let message_to_all_players = ServerAction::ResetDeck {
+            discard_pile: vec![],
+            draw_deck_size: self.draw_deck.len(),
+            message: "Deck reset and shuffled".to_owned(),
+        };
+        self.broadcast_to_room(message_to_all_players)?;
+        Ok(())
+    }
+
+    /// Takes every card back from the players and the discard pile and shuffles a fresh draw
+    /// deck, without telling anyone.
+    pub fn collect_cards(&mut self) {
+        self.reset_draw_deck();
+        self.discard_deck.clear();
+        for player in &mut self.players {
+            player.empty_hand();
+        }
+    }
+
+    pub fn reset_draw_deck(&mut self) {
+        self.draw_deck = self.deck.build();
+        self.shuffle_draw_deck();
+    }
+
+    pub fn shuffle_draw_deck(&mut self) {
+        let mut rng = self.rng();
+        self.draw_deck.shuffle(&mut rng);
+    }
+
+    /// Randomness for the next shuffle or deal.
+    pub fn rng(&mut self) -> StdRng {
+        self.shuffles += 1;
+        StdRng::seed_from_u64(self.seed.wrapping_add(self.shuffles))
+    }
+}
@@ -0,0 +1,235 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::{Card, CardData},
+    player::Player,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TeamData {
+    pub name: String,
+    pub members: Vec<String>,
+    pub pile: Vec<CardData>,
+}
+
+/// A team the host set up, and the pile of cards its members share, like a meld area or the
+/// tricks they've taken.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Team {
+    pub name: String,
+    pub pile: Vec<Card>,
+}
+
+impl Team {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            pile: vec![],
+        }
+    }
+
+    pub fn team_data(&self, players: &[Player]) -> TeamData {
+        TeamData {
+            name: self.name.clone(),
+            members: players
+                .iter()
+                .filter(|player| player.team.as_deref() == Some(self.name.as_str()))
+                .map(|player| player.id.clone())
+                .collect(),
+            pile: self.pile.iter().map(|card| card.card_data()).collect(),
+        }
+    }
+}
+
+/// Orders the players so that teams take turns around the table, which sits partners across
+/// from each other. Anyone without a team ends up at the end.
+pub fn seat_by_team(players: &mut Vec<Player>, teams: &[Team]) {
+    let mut unseated = std::mem::take(players);
+    loop {
+        let mut seated_anyone = false;
+        for team in teams {
+            if let Some(index) = unseated
+                .iter()
+                .position(|player| player.team.as_deref() == Some(team.name.as_str()))
+            {
+                players.push(unseated.remove(index));
+                seated_anyone = true;
+            }
+        }
+        if !seated_anyone {
+            break;
+        }
+    }
+    players.append(&mut unseated);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        card::{Suite, Value},
+        outbox::{outbox, OutboxConfig, OutboxReceiver},
+        room::Room,
+        room_settings::{GameType, RoomSettings},
+        rules::GameMove,
+    };
+
+    fn on_team(name: &str, team: Option<&str>) -> Player {
+        let (sender, _) = outbox(OutboxConfig::default(), &Arc::default());
+        let mut player = Player::new(name, sender);
+        player.team = team.map(str::to_owned);
+        player
+    }
+
+    fn names(players: &[Player]) -> Vec<&str> {
+        players.iter().map(|player| player.name.as_str()).collect()
+    }
+
+    #[test]
+    fn teams_take_turns_around_the_table() {
+        let teams = [Team::new("Red"), Team::new("Blue")];
+        let mut players = vec![
+            on_team("Ada", Some("Red")),
+            on_team("Bea", Some("Red")),
+            on_team("Cy", None),
+            on_team("Di", Some("Blue")),
+            on_team("Ed", Some("Blue")),
+            on_team("Flo", Some("Red")),
+        ];
+        seat_by_team(&mut players, &teams);
+        assert_eq!(names(&players), ["Ada", "Di", "Bea", "Ed", "Flo", "Cy"]);
+    }
+
+    #[test]
+    fn team_data_lists_the_members_and_hides_face_down_cards() {
+        let players = vec![on_team("Ada", Some("Red")), on_team("Bea", Some("Blue"))];
+        let mut team = Team::new("Red");
+        let mut face_up = Card::new(Suite::Heart, Value::Ace);
+        face_up.set_visible(true);
+        let face_down = Card::new(Suite::Spade, Value::Two);
+        team.pile = vec![face_up, face_down];
+
+        let data = team.team_data(&players);
+        assert_eq!(data.members, [players[0].id.clone()]);
+        assert_eq!(data.pile[0].value, Some(Value::Ace));
+        assert_eq!(data.pile[1].value, None);
+    }
+
+    fn four_player_room(game_type: GameType) -> (Room, Vec<(String, OutboxReceiver)>) {
+        let settings = RoomSettings {
+            game_type,
+            ..RoomSettings::default()
+        };
+        let mut seats = vec![];
+        let mut room = None;
+        for name in ["Ada", "Bea", "Cy", "Di"] {
+            let (sender, receiver) = outbox(OutboxConfig::default(), &Arc::default());
+            let player = Player::new(name, sender);
+            seats.push((player.id.clone(), receiver));
+            match room.as_mut() {
+                None => room = Some(Room::new(1234, player, None, settings.clone(), None).unwrap()),
+                Some(room) => room.join(player).unwrap(),
+            }
+        }
+        (room.unwrap(), seats)
+    }
+
+    fn messages(receiver: &mut OutboxReceiver) -> Vec<serde_json::Value> {
+        let mut messages = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(serde_json::to_value(message).unwrap());
+        }
+        messages
+    }
+
+    fn with_action(receiver: &mut OutboxReceiver, action: &str) -> Vec<serde_json::Value> {
+        messages(receiver)
+            .into_iter()
+            .filter(|message| message["action"] == action)
+            .map(|message| message["data"].clone())
+            .collect()
+    }
+
+    fn illegal_moves(receiver: &mut OutboxReceiver) -> Vec<String> {
+        with_action(receiver, "IllegalMove")
+            .iter()
+            .map(|data| data["message"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    fn drawn_card(receiver: &mut OutboxReceiver) -> Card {
+        let drawn = with_action(receiver, "DrawCard");
+        serde_json::from_value(drawn[0]["card"].clone()).unwrap()
+    }
+
+    #[test]
+    fn partners_share_a_pile_in_free_play() {
+        let (mut room, mut seats) = four_player_room(GameType::FreePlay);
+        let ids: Vec<String> = seats.iter().map(|(id, _)| id.clone()).collect();
+        room.define_teams(&ids[0], &["Red".to_owned(), "Blue".to_owned()])
+            .unwrap();
+        for (seat, team) in ["Red", "Blue", "Red", "Blue"].iter().enumerate() {
+            room.assign_team(&ids[0], &ids[seat], Some(team)).unwrap();
+        }
+        messages(&mut seats[0].1);
+        messages(&mut seats[1].1);
+        room.handle_move(&ids[0], GameMove::DrawCard).unwrap();
+        let card = drawn_card(&mut seats[0].1);
+        room.play_to_team_pile(&ids[0], &card).unwrap();
+
+        let updates = with_action(&mut seats[1].1, "TeamsUpdated");
+        let teams: Vec<TeamData> =
+            serde_json::from_value(updates.last().unwrap()["teams"].clone()).unwrap();
+        assert_eq!(teams[0].members, [ids[0].clone(), ids[2].clone()]);
+        assert_eq!(teams[0].pile.len(), 1);
+
+        // the other team can't reach into it, but a partner can
+        room.take_from_team_pile(&ids[1], &card).unwrap();
+        assert_eq!(illegal_moves(&mut seats[1].1).len(), 1);
+        room.take_from_team_pile(&ids[2], &card).unwrap();
+        room.play_to_team_pile(&ids[2], &card).unwrap();
+        assert!(illegal_moves(&mut seats[2].1).is_empty());
+    }
+
+    #[test]
+    fn team_moves_that_arent_allowed_are_rejected() {
+        let (mut room, mut seats) = four_player_room(GameType::FreePlay);
+        let ids: Vec<String> = seats.iter().map(|(id, _)| id.clone()).collect();
+
+        room.define_teams(&ids[1], &["Red".to_owned()]).unwrap();
+        room.assign_team(&ids[1], &ids[1], None).unwrap();
+        assert_eq!(
+            illegal_moves(&mut seats[1].1),
+            [
+                "Only the host can set up teams",
+                "Only the host can put players on teams"
+            ]
+        );
+        room.assign_team(&ids[0], &ids[1], Some("Red")).unwrap();
+        assert_eq!(
+            illegal_moves(&mut seats[0].1),
+            ["There's no team with that name"]
+        );
+
+        room.handle_move(&ids[1], GameMove::DrawCard).unwrap();
+        let card = drawn_card(&mut seats[1].1);
+        room.play_to_team_pile(&ids[1], &card).unwrap();
+        assert_eq!(illegal_moves(&mut seats[1].1), ["You aren't on a team"]);
+
+        let (mut room, mut seats) = four_player_room(GameType::Hearts);
+        let ada = seats[0].0.clone();
+        room.define_teams(&ada, &["Red".to_owned()]).unwrap();
+        room.assign_team(&ada, &ada, Some("Red")).unwrap();
+        room.handle_move(&ada, GameMove::StartRound).unwrap();
+        messages(&mut seats[0].1);
+        room.play_to_team_pile(&ada, &Card::new(Suite::Heart, Value::Ace))
+            .unwrap();
+        assert_eq!(
+            illegal_moves(&mut seats[0].1),
+            ["Team piles are only used in free play"]
+        );
+    }
+}